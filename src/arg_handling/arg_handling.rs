@@ -17,96 +17,244 @@ Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
  */
 
 pub mod arg_handling {
-    const ERROR : i32 = 1;
-    const SUCCESS : i32 = 0;
-    use std::process::exit;
-    use crate::file_encoding_support;
-    use crate::file_encoding_support::file_encoding_support::{FileEncoding, FileEncodingMethod, FileEncodingSupport, ImageSupport, Operation};
+    pub const SUCCESS : i32 = 0;
+    pub const ERROR : i32 = 1;
+    pub const IO_ERROR : i32 = 2;
+    pub const MALFORMED_FILE : i32 = 3;
+    pub const UNSUPPORTED_FORMAT : i32 = 4;
+    pub const UNSUPPORTED_ENCODING : i32 = 5;
+    pub const INSUFFICIENT_CAPACITY : i32 = 6;
+    pub const INTEGRITY_FAILURE : i32 = 7;
+    pub const INTERNAL_ERROR : i32 = 8;
 
+    const USAGE : &str = "Usage: maya encoding(Lsb,Color,Slack,SlackLsb,Chunk) encoding-method(LeftRight, RightLeft, TopBottom, SinWave,CosWave, PolynomialFunc, FractalFunc, DiagonalRight, DiagonalLeft, ZigZagHorizontal, ZigZagVertical, Sinusoidal) operation(embed/extract/capacity/chunks) <optional>'Message to be hidden'</optional> input.ext <optional>output.ext</optional> <optional>--key passphrase</optional> <optional>--bits 1-8</optional> <optional>--rle keep|uncompressed</optional> <optional>--grow-gap bytes</optional> <optional>--compression match|0-9</optional> <optional>--interlace keep|none|adam7</optional> <optional>--palette-twins on|off</optional> <optional>--chunk zTXt|tEXt|iTXt|type</optional> <optional>--chunk-keyword keyword</optional> <optional>--chunk-placement before|after</optional>(embed reads input.ext and writes the result to output.ext, extract, capacity and chunks only need input.ext, chunks lists the chunks of a PNG that are not part of the specification. With --key the bits are scattered over the image in an order only the same passphrase can reproduce. --bits makes Lsb use that many low bits of every sample, for 16 bit images. --rle uncompressed writes an RLE compressed BMP back as plain pixel data, --grow-gap makes the gap in front of the pixel data of a BMP that many bytes larger for Slack, --compression sets the zlib level a PNG is written with instead of matching the original size, --interlace writes it Adam7 interlaced or not, --palette-twins off keeps Lsb from adding entries to the palette of an indexed PNG. The --chunk options pick the PNG chunk the Chunk encoding uses, extract needs the same --chunk and --chunk-keyword)";
 
-    pub fn parse_arguments<T: file_encoding_support::file_encoding_support::FileEncodingAlgorithms + file_encoding_support::file_encoding_support::FileEncodingSupport>(args: Vec<String>) -> ImageSupport<T> {
-        if ( args.len() <= 2 && args[1] == "--help") {
-            println!("Usage: maya encoding(Lsb,PixelValueDifferencing,Hamming) encoding-method(LeftRight, TopBottom, SinWave,CosWave, PolyFunc, FractalFunc) operation(embed/extract) <optional>'Message to be hidden'</optional> filename.ext(either the file to extract or the filename to embed into)");
+    /*
+        Options that belong to one file type, handed to the carrier with set_option once it is parsed
+     */
+    const FILE_OPTIONS : [&str; 8] = ["--rle", "--grow-gap", "--compression", "--interlace", "--palette-twins", "--chunk", "--chunk-keyword", "--chunk-placement"];
+
+    use crate::file_encoding_support::file_encoding_support::{FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod, FileEncodingSupport, ImageSupport, Operation, OperationOutput, WaveFunction};
+    use crate::file_encoding_support::stego_error::StegoError;
+
+    /*
+        Entry point for the binary, returns the exit status for the process
+     */
+    pub fn run(args: Vec<String>) -> i32 {
+        if args.len() == 2 && args[1] == "--help" {
+            println!("{USAGE}");
             println!("This is a stegonagraphy tool for embedding and extracting secret messages within images.");
-            println!("Options: --help, --version");
-            exit(SUCCESS);
+            println!("Options: --help, --version, --key <passphrase>, --bits <low bits per sample>, --rle <keep|uncompressed>, --grow-gap <bytes>, --compression <match|0-9>, --interlace <keep|none|adam7>, --palette-twins <on|off>, --chunk <zTXt|tEXt|iTXt|type>, --chunk-keyword <keyword>, --chunk-placement <before|after>");
+            return SUCCESS;
         }
 
-        if (args.len() <= 2 && args[1] == "--version") {
+        if args.len() == 2 && args[1] == "--version" {
             println!("Maya version {}", env!("CARGO_PKG_VERSION"));
-            exit(SUCCESS);
+            return SUCCESS;
         }
-        if (args.len() < 5 ) {
-            println!("Too few arguments!");
-            println!("Usage: maya encoding(Lsb,PixelValueDifferencing,Hamming) encoding-method(LeftRight, TopBottom, SinWave,CosWave, PolyFunc, FractalFunc) operation(embed/extract) <optional>'Message to be hidden'</optional> filename.ext(either the file to extract or the filename to embed into)");
-            println!("Try --help for help.");
-            exit(ERROR);
+
+        let result = parse_arguments::<Box<dyn FileEncodingSupport>>(args).and_then(|mut image_support| image_support.run());
+
+        match result {
+            Ok(OperationOutput::Extracted(message)) => {
+                println!("{}", String::from_utf8_lossy(&message));
+                SUCCESS
+            }
+            Ok(OperationOutput::Capacity(report)) => {
+                println!("{} bytes available for a message", report.usable_payload_bytes);
+                println!("{} bits total at {} bits per pixel, {} bytes used by the payload header", report.total_bits, report.bits_per_pixel, report.header_overhead_bytes);
+                SUCCESS
+            }
+            Ok(OperationOutput::Chunks(chunk_types)) => {
+                println!("{} non-standard chunks", chunk_types.len());
+                for chunk_type in chunk_types {
+                    println!("{chunk_type}");
+                }
+                SUCCESS
+            }
+            Ok(OperationOutput::Embedded) => SUCCESS,
+            Err(StegoError::Usage(message)) => {
+                eprintln!("maya: {message}");
+                eprintln!("{USAGE}");
+                eprintln!("Try --help for help.");
+                ERROR
+            }
+            Err(e) => {
+                eprintln!("maya: {e}");
+                exit_code(&e)
+            }
         }
+    }
 
-        if (args.len() > 6 ) {
-            println!("Too many arguments!");
-            println!("Usage: maya encoding(Lsb,PixelValueDifferencing,Hamming) encoding-method(LeftRight, TopBottom, SinWave,CosWave, PolyFunc, FractalFunc) operation(embed/extract) <optional>'Message to be hidden'</optional> filename.ext(either the file to extract or the filename to embed into)");
-            println!("Try --help for help.");
-            exit(ERROR);
+    pub fn exit_code(error: &StegoError) -> i32 {
+        match error {
+            StegoError::Io(_) => IO_ERROR,
+            StegoError::MalformedHeader(_) => MALFORMED_FILE,
+            StegoError::UnsupportedFormat(_) => UNSUPPORTED_FORMAT,
+            StegoError::UnsupportedEncoding(_) => UNSUPPORTED_ENCODING,
+            StegoError::InsufficientCapacity { .. } => INSUFFICIENT_CAPACITY,
+            StegoError::IntegrityFailure(_) => INTEGRITY_FAILURE,
+            StegoError::InvalidChannel(_) | StegoError::FileNotReady(_) => INTERNAL_ERROR,
+            StegoError::Usage(_) => ERROR,
         }
+    }
 
+    fn usage_error<T>(message: &str) -> Result<T, StegoError> {
+        Err(StegoError::Usage(message.to_string()))
+    }
 
-        if { args[3] == "embed" &&  args.len() != 6 } {
-            println!("You must specific a message with the embed option!");
-            println!("Usage: maya encoding(Lsb,PixelValueDifferencing,Hamming) encoding-method(LeftRight, TopBottom, SinWave,CosWave, PolyFunc, FractalFunc) operation(embed/extract) <optional>'Message to be hidden'</optional> filename.ext(either the file to extract or the filename to embed into)");
-            println!("Try --help for help.");
-            exit(ERROR);
+    /*
+        Validates the argument count and the operation, returns the input file name
+     */
+    fn check_arguments(args: &[String]) -> Result<&str, StegoError> {
+        if args.len() < 5 {
+            return usage_error("Too few arguments!");
         }
 
-        let encoding = match args[1].as_str() {
-            "Lsb" => {FileEncoding::Lsb},
-            "PixelValueDifferencing" => {FileEncoding::PixelValueDifferencing},
-            "Hamming" => {FileEncoding::HammingMatrix},
-            _ => {
-                println!("Invalid encoding found! : {}", args[1].as_str());
-                exit(1);
+        if args.len() > 7 {
+            return usage_error("Too many arguments!");
+        }
+
+        match args[3].as_str() {
+            "embed" => {
+                if args.len() != 7 {
+                    return usage_error("You must specify a message, an input file and an output file with the embed option!");
+                }
+                if args[5] == args[6] {
+                    return usage_error("The output file must be different from the input file!");
+                }
+                Ok(&args[5])
             }
+            "extract" | "capacity" | "chunks" => {
+                if args.len() != 5 {
+                    return usage_error(&format!("The {} option only takes the input file!", args[3]));
+                }
+                Ok(&args[4])
+            }
+            _ => usage_error(&format!("Invalid operation found! : {}", args[3])),
+        }
+    }
 
+    /*
+        Removes --key <passphrase> from anywhere after the program name, the rest of the arguments are positional
+     */
+    fn take_key(args: &mut Vec<String>) -> Result<FileEncodingFunctionDerivation, StegoError> {
+        let Some(index) = args.iter().skip(1).position(|arg| arg == "--key").map(|index| index + 1) else {
+            return Ok(FileEncodingFunctionDerivation::Unkeyed);
         };
 
-        let encoding_method = match args[2].as_str() {
-            "LeftRight" => {FileEncodingMethod::LeftToRight},
-            "TopBottom" => {FileEncodingMethod::TopToBottom},
-            "RightLeft" => {FileEncodingMethod::RightToLeft},
-            "CosWave" => {FileEncodingMethod::CosWave},
-            "SinWave" => {FileEncodingMethod::SinWave},
-            "FractalFunc" => {FileEncodingMethod::FractalFunction},
-            "PolynomialFunc" => {FileEncodingMethod::PolynomialFunction},
-            _ => {
-                println!("Invalid encoding method found! : {}", args[2].as_str());
-                exit(1);
-            }
+        if index + 1 >= args.len() {
+            return usage_error("--key needs a passphrase!");
+        }
 
-        };
+        let passphrase = args.remove(index + 1);
+        args.remove(index);
 
-        let operation = match args[3].as_str() {
-            "embed" => Operation::Embed,
-            "extract" => Operation::Extract,
-            _ => {
-                println!("Invalid operation found! : {}", args[3]);
-                exit(1);
-            }
+        if passphrase.is_empty() {
+            return usage_error("The passphrase given to --key can not be empty!");
+        }
+
+        Ok(FileEncodingFunctionDerivation::from_passphrase(&passphrase))
+    }
+
+    /*
+        Removes --bits <n> the same way, None when it is not given
+     */
+    fn take_bits(args: &mut Vec<String>) -> Result<Option<u8>, StegoError> {
+        let Some(index) = args.iter().skip(1).position(|arg| arg == "--bits").map(|index| index + 1) else {
+            return Ok(None);
         };
 
-        let mut message = Vec::new();
-        if(operation == Operation::Embed) {
-            message = args[4].as_bytes().to_vec();
+        if index + 1 >= args.len() {
+            return usage_error("--bits needs the number of low bits per sample!");
         }
 
+        let bits = args.remove(index + 1);
+        args.remove(index);
 
+        match bits.parse::<u8>() {
+            Ok(bits @ 1..=8) => Ok(Some(bits)),
+            _ => usage_error(&format!("--bits takes a number from 1 to 8! : {bits}")),
+        }
+    }
 
+    /*
+        Removes every file type option in FILE_OPTIONS with its value, in the order they were given
+     */
+    fn take_options(args: &mut Vec<String>) -> Result<Vec<(String, String)>, StegoError> {
+        let mut options = Vec::new();
+        while let Some(index) = args.iter().skip(1).position(|arg| FILE_OPTIONS.contains(&arg.as_str())).map(|index| index + 1) {
+            if index + 1 >= args.len() {
+                return usage_error(&format!("{} needs a value!", args[index]));
+            }
 
+            let value = args.remove(index + 1);
+            let name = args.remove(index);
+            options.push((name.trim_start_matches("--").to_string(), value));
+        }
+        Ok(options)
+    }
+
+    pub fn parse_arguments<T: FileEncodingSupport>(mut args: Vec<String>) -> Result<ImageSupport<T>, StegoError> {
+        let file_encoding_function_derivation = take_key(&mut args)?;
+        let bits = take_bits(&mut args)?;
+        let options = take_options(&mut args)?;
+        check_arguments(&args)?;
 
-        todo!()
+        let encoding = match args[1].as_str() {
+            "Lsb" => {FileEncoding::Lsb},
+            "Color" => {FileEncoding::ColorParity},
+            "Slack" => {FileEncoding::Slack},
+            "SlackLsb" => {FileEncoding::SlackLsb},
+            "Chunk" => {FileEncoding::Chunk},
+            // No carrier embeds with these yet
+            "PixelValueDifferencing" | "Hamming" => return usage_error(&format!("{} is not implemented yet!", args[1].as_str())),
+            _ => return usage_error(&format!("Invalid encoding found! : {}", args[1].as_str())),
+        };
 
+        let encoding = match (encoding, bits) {
+            (_, None) | (FileEncoding::Lsb, Some(1)) => encoding,
+            (FileEncoding::Lsb, Some(bits)) => FileEncoding::LowBits(bits),
+            _ => return usage_error("--bits can only be used with the Lsb encoding!"),
+        };
 
+        let encoding_method = match args[2].as_str() {
+            "LeftRight" => {FileEncodingMethod::LeftToRight},
+            "TopBottom" => {FileEncodingMethod::TopToBottom},
+            "RightLeft" => {FileEncodingMethod::RightToLeft},
+            "CosWave" => {FileEncodingMethod::CosWave},
+            "SinWave" => {FileEncodingMethod::SinWave},
+            "FractalFunc" => {FileEncodingMethod::FractalFunction},
+            "PolynomialFunc" => {FileEncodingMethod::PolynomialFunction},
+            "DiagonalRight" => {FileEncodingMethod::Wave(WaveFunction::DiagonalRight)},
+            "DiagonalLeft" => {FileEncodingMethod::Wave(WaveFunction::DiagonalLeft)},
+            "ZigZagHorizontal" => {FileEncodingMethod::Wave(WaveFunction::ZigZagHorizontal)},
+            "ZigZagVertical" => {FileEncodingMethod::Wave(WaveFunction::ZigZagVertical)},
+            "Sinusoidal" => {FileEncodingMethod::Wave(WaveFunction::Sinusoidal)},
+            _ => return usage_error(&format!("Invalid encoding method found! : {}", args[2].as_str())),
+        };
 
+        let (operation, message, input_file, output_file) = match args[3].as_str() {
+            "embed" => (Operation::Embed, args[4].as_bytes().to_vec(), &args[5], Some(args[6].clone())),
+            "capacity" => (Operation::Capacity, Vec::new(), &args[4], None),
+            "chunks" => (Operation::Chunks, Vec::new(), &args[4], None),
+            _ => (Operation::Extract, Vec::new(), &args[4], None),
+        };
 
+        let mut encoding_support = T::new(input_file)?;
+        encoding_support.parse_file()?;
+        for (name, value) in &options {
+            encoding_support.set_option(name, value)?;
+        }
 
+        Ok(ImageSupport::new(
+            encoding_support,
+            output_file,
+            encoding,
+            encoding_method,
+            file_encoding_function_derivation,
+            operation,
+            message,
+        ))
     }
-}
\ No newline at end of file
+}
@@ -14,4 +14,861 @@
  * You should have received a copy of the GNU General Public License
  * along with this program; if not, write to the Free Software
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
- */
\ No newline at end of file
+ */
+use crate::file_encoding_support::stego_error::StegoError;
+use crate::mathematics_support::mathematics_support::adler32;
+
+/*
+   zlib (RFC 1950) around raw DEFLATE (RFC 1951), which is what PNG stores its pixel data in. A DEFLATE
+   stream is a list of blocks, each either stored as is or Huffman coded with a fixed or a dynamic code,
+   a coded block is literal bytes and (length, distance) back references into the last 32KiB of output.
+*/
+const MAX_CODE_LENGTH: usize = 15;
+const END_OF_BLOCK: u16 = 256;
+
+// Base value and number of extra bits for the length symbols 257..=285 and the distance symbols 0..=29
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// Order the code length code lengths of a dynamic block are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn malformed(message: &str) -> StegoError {
+    StegoError::MalformedHeader(format!("compression.rs: {message}"))
+}
+
+/*
+   Reads DEFLATE's bit stream, bits are packed starting at the lowest bit of each byte
+*/
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0, buffer: 0, count: 0 }
+    }
+
+    fn refill(&mut self) {
+        while self.count <= 56 && self.pos < self.data.len() {
+            self.buffer |= (self.data[self.pos] as u64) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+    }
+
+    // The next bits without consuming them, past the end of the data they read as 0
+    fn peek(&mut self, bits: u32) -> u32 {
+        if self.count < bits {
+            self.refill();
+        }
+        (self.buffer & ((1u64 << bits) - 1)) as u32
+    }
+
+    fn consume(&mut self, bits: u32) -> Result<(), StegoError> {
+        if self.count < bits {
+            return Err(malformed("DEFLATE stream ends in the middle of a block"));
+        }
+        self.buffer >>= bits;
+        self.count -= bits;
+        Ok(())
+    }
+
+    fn bits(&mut self, bits: u32) -> Result<u32, StegoError> {
+        let value = self.peek(bits);
+        self.consume(bits)?;
+        Ok(value)
+    }
+
+    // Drops whatever is left of the current byte, stored blocks start on a byte boundary
+    fn align_to_byte(&mut self) {
+        let partial = self.count % 8;
+        self.buffer >>= partial;
+        self.count -= partial;
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], StegoError> {
+        // Give back whole bytes still sitting in the buffer, only ever called on a byte boundary
+        self.pos -= (self.count / 8) as usize;
+        self.buffer = 0;
+        self.count = 0;
+
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + length)
+            .ok_or_else(|| malformed("stored DEFLATE block runs past the end of the data"))?;
+        self.pos += length;
+        Ok(bytes)
+    }
+
+    // Bytes of data taken up so far, a partly used byte counts as taken
+    fn consumed(&self) -> usize {
+        self.pos - (self.count / 8) as usize
+    }
+}
+
+/*
+   The codes a list of code lengths stands for, shorter codes come first and codes of the same length
+   go up with the symbol. Codes are written most significant bit first into a stream that is otherwise
+   packed from the lowest bit, so they are reversed on their way in and out.
+*/
+fn canonical_codes(lengths: &[u8]) -> Vec<u32> {
+    let mut counts = [0u32; MAX_CODE_LENGTH + 1];
+    for &length in lengths {
+        counts[length as usize] += 1;
+    }
+    counts[0] = 0;
+
+    let mut next_code = [0u32; MAX_CODE_LENGTH + 1];
+    let mut code = 0u32;
+    for length in 1..=MAX_CODE_LENGTH {
+        code = (code + counts[length - 1]) << 1;
+        next_code[length] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&length| {
+            let code = next_code[length as usize];
+            next_code[length as usize] += 1;
+            code
+        })
+        .collect()
+}
+
+fn reverse_code(code: u32, length: u8) -> u32 {
+    code.reverse_bits() >> (32 - length as u32)
+}
+
+/*
+   A canonical Huffman code as a lookup table indexed by the next max_length bits of the stream, each
+   entry is the symbol and the length of its code (0 for bit patterns no code starts with)
+*/
+struct HuffmanTable {
+    entries: Vec<(u16, u8)>,
+    max_length: u32,
+}
+
+impl HuffmanTable {
+    fn new(lengths: &[u8]) -> Result<Self, StegoError> {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Codes left over at each length, running out means the lengths can not come from a prefix code
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err(malformed("Huffman code lengths are over subscribed"));
+            }
+        }
+
+        let max_length = counts.iter().rposition(|&count| count != 0).unwrap_or(0).max(1) as u32;
+        let mut entries = vec![(0u16, 0u8); 1 << max_length];
+        for (symbol, (&length, code)) in lengths.iter().zip(canonical_codes(lengths)).enumerate() {
+            if length == 0 {
+                continue;
+            }
+            // The table is indexed by stream order
+            let reversed = reverse_code(code, length);
+            for index in (reversed as usize..entries.len()).step_by(1 << length) {
+                entries[index] = (symbol as u16, length);
+            }
+        }
+
+        Ok(HuffmanTable { entries, max_length })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, StegoError> {
+        let (symbol, length) = self.entries[reader.peek(self.max_length) as usize];
+        if length == 0 {
+            return Err(malformed("DEFLATE stream holds a code that is not in its Huffman table"));
+        }
+        reader.consume(length as u32)?;
+        Ok(symbol)
+    }
+}
+
+fn fixed_literal_lengths() -> [u8; 288] {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    lengths
+}
+
+fn fixed_tables() -> Result<(HuffmanTable, HuffmanTable), StegoError> {
+    Ok((HuffmanTable::new(&fixed_literal_lengths())?, HuffmanTable::new(&[5; 30])?))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(HuffmanTable, HuffmanTable), StegoError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(malformed("dynamic DEFLATE block has too many codes"));
+    }
+
+    let mut code_length_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_length_table = HuffmanTable::new(&code_length_lengths)?;
+
+    // Literal/length and distance code lengths are one sequence, repeats may cross from one into the other
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_length_table.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or_else(|| malformed("code length repeat with nothing to repeat"))?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if lengths.len() + repeat > literal_count + distance_count {
+            return Err(malformed("code length repeat runs past the last code"));
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(malformed("dynamic DEFLATE block has no end of block code"));
+    }
+
+    Ok((
+        HuffmanTable::new(&lengths[..literal_count])?,
+        HuffmanTable::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn inflate_block(reader: &mut BitReader, literals: &HuffmanTable, distances: &HuffmanTable, output: &mut Vec<u8>, limit: usize) -> Result<(), StegoError> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            output.push(symbol as u8);
+        } else if symbol == END_OF_BLOCK {
+            return Ok(());
+        } else {
+            let index = (symbol - 257) as usize;
+            if index >= LENGTH_BASES.len() {
+                return Err(malformed("DEFLATE stream holds an invalid length code"));
+            }
+            let length = LENGTH_BASES[index] as usize + reader.bits(LENGTH_EXTRA_BITS[index] as u32)? as usize;
+
+            let index = distances.decode(reader)? as usize;
+            if index >= DISTANCE_BASES.len() {
+                return Err(malformed("DEFLATE stream holds an invalid distance code"));
+            }
+            let distance = DISTANCE_BASES[index] as usize + reader.bits(DISTANCE_EXTRA_BITS[index] as u32)? as usize;
+            if distance > output.len() {
+                return Err(malformed("DEFLATE back reference points before the start of the data"));
+            }
+
+            // Byte by byte, a reference may overlap the bytes it is producing
+            let start = output.len() - distance;
+            for i in 0..length {
+                output.push(output[start + i]);
+            }
+        }
+
+        if output.len() > limit {
+            return Err(malformed("DEFLATE stream inflates to more data than expected"));
+        }
+    }
+}
+
+/*
+   Inflates a raw DEFLATE stream, returns the data and how many bytes of input the stream took up.
+   Anything that would inflate past limit bytes is rejected rather than filling up memory.
+*/
+pub fn inflate(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize), StegoError> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let header = reader.bytes(4)?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                if length != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err(malformed("stored DEFLATE block length does not match its complement"));
+                }
+                output.extend_from_slice(reader.bytes(length as usize)?);
+            }
+            1 => {
+                let (literals, distances) = fixed_tables()?;
+                inflate_block(&mut reader, &literals, &distances, &mut output, limit)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &literals, &distances, &mut output, limit)?;
+            }
+            _ => return Err(malformed("DEFLATE block type 3 is reserved")),
+        }
+
+        if output.len() > limit {
+            return Err(malformed("DEFLATE stream inflates to more data than expected"));
+        }
+        if last {
+            return Ok((output, reader.consumed()));
+        }
+    }
+}
+
+/*
+   Inflates a zlib stream (2 byte header, DEFLATE data, Adler-32 of the inflated data)
+*/
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, StegoError> {
+    if data.len() < 6 {
+        return Err(malformed("zlib stream is too short"));
+    }
+
+    let (method, flags) = (data[0], data[1]);
+    if method & 0x0F != 8 || method >> 4 > 7 {
+        return Err(malformed("zlib stream does not use DEFLATE with a window of 32KiB or less"));
+    }
+    if (u16::from_be_bytes([method, flags])) % 31 != 0 {
+        return Err(malformed("zlib header check bits are wrong"));
+    }
+    if flags & 0x20 != 0 {
+        return Err(StegoError::UnsupportedFormat("compression.rs: zlib streams with a preset dictionary are not supported".to_string()));
+    }
+
+    let (output, used) = inflate(&data[2..], limit)?;
+    let checksum = data
+        .get(2 + used..2 + used + 4)
+        .ok_or_else(|| malformed("zlib stream ends without its Adler-32 checksum"))?;
+    if u32::from_be_bytes(checksum.try_into().unwrap()) != adler32(&output) {
+        return Err(StegoError::IntegrityFailure("compression.rs: zlib Adler-32 checksum does not match the inflated data".to_string()));
+    }
+    Ok(output)
+}
+
+/*
+   The compressing side. Matches are found with hash chains over the last 32KiB, higher levels follow
+   the chains further and look one byte ahead before taking a match (lazy matching) the way zlib does.
+   Every block is written whichever way is smallest, stored, fixed or dynamic Huffman.
+*/
+const WINDOW_SIZE: usize = 1 << 15;
+const HASH_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// A 3 byte match further back than this costs more than the literals it replaces
+const TOO_FAR: usize = 4096;
+const TOKENS_PER_BLOCK: usize = 1 << 14;
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+pub const MAX_LEVEL: u8 = 9;
+
+/*
+   Which block types deflate may write. Smallest picks whichever of stored, fixed and dynamic Huffman is
+   smallest for every block, Fixed always uses the fixed Huffman code. Fixed output is larger, a caller
+   trying to hit a size can use it to land between the levels.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DeflateBlocks {
+    Smallest,
+    Fixed,
+}
+
+struct LevelSettings {
+    max_chain: usize,
+    nice_length: usize,
+    max_lazy: usize, // 0 takes the first match found
+}
+
+// zlib's settings for levels 1 to 9
+const LEVEL_SETTINGS: [LevelSettings; 9] = [
+    LevelSettings { max_chain: 4, nice_length: 8, max_lazy: 0 },
+    LevelSettings { max_chain: 8, nice_length: 16, max_lazy: 0 },
+    LevelSettings { max_chain: 32, nice_length: 32, max_lazy: 0 },
+    LevelSettings { max_chain: 16, nice_length: 16, max_lazy: 4 },
+    LevelSettings { max_chain: 32, nice_length: 32, max_lazy: 16 },
+    LevelSettings { max_chain: 128, nice_length: 128, max_lazy: 16 },
+    LevelSettings { max_chain: 256, nice_length: 128, max_lazy: 32 },
+    LevelSettings { max_chain: 1024, nice_length: 258, max_lazy: 128 },
+    LevelSettings { max_chain: 4096, nice_length: 258, max_lazy: 258 },
+];
+
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+struct MatchFinder<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    previous: Vec<usize>,
+}
+
+impl<'a> MatchFinder<'a> {
+    const NONE: usize = usize::MAX;
+
+    fn new(data: &'a [u8]) -> Self {
+        MatchFinder {
+            data,
+            head: vec![Self::NONE; HASH_SIZE],
+            previous: vec![Self::NONE; WINDOW_SIZE],
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let bytes = &self.data[pos..pos + MIN_MATCH];
+        ((bytes[0] as usize) << 10 ^ (bytes[1] as usize) << 5 ^ bytes[2] as usize) & (HASH_SIZE - 1)
+    }
+
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH <= self.data.len() {
+            let hash = self.hash(pos);
+            self.previous[pos & (WINDOW_SIZE - 1)] = self.head[hash];
+            self.head[hash] = pos;
+        }
+    }
+
+    // The longest match for pos among the positions inserted so far, (length, distance)
+    fn longest_match(&self, pos: usize, settings: &LevelSettings) -> (usize, usize) {
+        if pos + MIN_MATCH > self.data.len() {
+            return (0, 0);
+        }
+
+        let max_length = MAX_MATCH.min(self.data.len() - pos);
+        let (mut best_length, mut best_distance) = (0, 0);
+        let mut candidate = self.head[self.hash(pos)];
+        let mut chain = settings.max_chain;
+
+        while candidate != Self::NONE && pos - candidate <= WINDOW_SIZE && chain > 0 {
+            let length = self.data[candidate..candidate + max_length]
+                .iter()
+                .zip(&self.data[pos..pos + max_length])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best_length {
+                (best_length, best_distance) = (length, pos - candidate);
+                if length >= settings.nice_length.min(max_length) {
+                    break;
+                }
+            }
+
+            // A slot of the window that has been reused points forwards, the chain ends there
+            let next = self.previous[candidate & (WINDOW_SIZE - 1)];
+            if next == Self::NONE || next >= candidate {
+                break;
+            }
+            candidate = next;
+            chain -= 1;
+        }
+
+        if best_length < MIN_MATCH || (best_length == MIN_MATCH && best_distance > TOO_FAR) {
+            return (0, 0);
+        }
+        (best_length, best_distance)
+    }
+}
+
+fn find_tokens(data: &[u8], settings: &LevelSettings) -> Vec<Token> {
+    let mut finder = MatchFinder::new(data);
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let (length, distance) = finder.longest_match(pos, settings);
+        finder.insert(pos);
+
+        // Lazy matching, a longer match starting at the next byte is worth a literal
+        if length >= MIN_MATCH && length < settings.max_lazy && finder.longest_match(pos + 1, settings).0 > length {
+            tokens.push(Token::Literal(data[pos]));
+            pos += 1;
+            continue;
+        }
+
+        if length >= MIN_MATCH {
+            tokens.push(Token::Match { length: length as u16, distance: distance as u16 });
+            for inside in pos + 1..pos + length {
+                finder.insert(inside);
+            }
+            pos += length;
+        } else {
+            tokens.push(Token::Literal(data[pos]));
+            pos += 1;
+        }
+    }
+    tokens
+}
+
+// Index into LENGTH_BASES / DISTANCE_BASES of the last base value not above value
+fn base_index(bases: &[u16], value: usize) -> usize {
+    bases.partition_point(|&base| base as usize <= value) - 1
+}
+
+/*
+   Code lengths of a Huffman code for the given symbol frequencies, no longer than limit bits. A code
+   that comes out too long is built again from halved frequencies until it fits.
+*/
+fn huffman_lengths(frequencies: &[u32], limit: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+    let used: Vec<usize> = (0..frequencies.len()).filter(|&symbol| frequencies[symbol] != 0).collect();
+    match used.len() {
+        0 => return lengths,
+        1 => {
+            lengths[used[0]] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    let mut weights: Vec<u64> = used.iter().map(|&symbol| frequencies[symbol] as u64).collect();
+    loop {
+        // Nodes 0..used.len() are the symbols, every merge adds a node and records it as the parent of two
+        let mut parents = vec![0usize; used.len() * 2 - 1];
+        let mut heap: std::collections::BinaryHeap<std::cmp::Reverse<(u64, usize)>> =
+            weights.iter().enumerate().map(|(node, &weight)| std::cmp::Reverse((weight, node))).collect();
+        let mut next_node = used.len();
+        while heap.len() > 1 {
+            let std::cmp::Reverse((first_weight, first)) = heap.pop().unwrap();
+            let std::cmp::Reverse((second_weight, second)) = heap.pop().unwrap();
+            parents[first] = next_node;
+            parents[second] = next_node;
+            heap.push(std::cmp::Reverse((first_weight + second_weight, next_node)));
+            next_node += 1;
+        }
+
+        // Parents always come after their children, so depths fill in from the root downwards
+        let root = next_node - 1;
+        let mut depths = vec![0u8; next_node];
+        for node in (0..root).rev() {
+            depths[node] = depths[parents[node]] + 1;
+        }
+
+        if depths[..used.len()].iter().all(|&depth| depth <= limit) {
+            for (&symbol, &depth) in used.iter().zip(&depths) {
+                lengths[symbol] = depth;
+            }
+            return lengths;
+        }
+        weights.iter_mut().for_each(|weight| *weight = (*weight / 2).max(1));
+    }
+}
+
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter { output: Vec::new(), buffer: 0, count: 0 }
+    }
+
+    fn bits(&mut self, value: u32, bits: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn code(&mut self, code: u32, length: u8) {
+        self.bits(reverse_code(code, length), length as u32);
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.count > 0 {
+            self.bits(0, 8 - self.count);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.align_to_byte();
+        self.output
+    }
+}
+
+/*
+   One block worth of tokens, with everything needed to work out what each way of writing it costs
+*/
+struct Block<'a> {
+    tokens: &'a [Token],
+    raw: &'a [u8],
+    literal_frequencies: [u32; 286],
+    distance_frequencies: [u32; 30],
+}
+
+impl<'a> Block<'a> {
+    fn new(tokens: &'a [Token], raw: &'a [u8]) -> Self {
+        let mut literal_frequencies = [0u32; 286];
+        let mut distance_frequencies = [0u32; 30];
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => literal_frequencies[byte as usize] += 1,
+                Token::Match { length, distance } => {
+                    literal_frequencies[257 + base_index(&LENGTH_BASES, length as usize)] += 1;
+                    distance_frequencies[base_index(&DISTANCE_BASES, distance as usize)] += 1;
+                }
+            }
+        }
+        literal_frequencies[END_OF_BLOCK as usize] = 1;
+        Block { tokens, raw, literal_frequencies, distance_frequencies }
+    }
+
+    // Bits the tokens take up with the given code lengths, extra bits included
+    fn coded_size(&self, literal_lengths: &[u8], distance_lengths: &[u8]) -> u64 {
+        let literals: u64 = self.literal_frequencies.iter().enumerate().map(|(symbol, &frequency)| {
+            let extra = if symbol > 256 { LENGTH_EXTRA_BITS[symbol - 257] as u64 } else { 0 };
+            frequency as u64 * (literal_lengths[symbol] as u64 + extra)
+        }).sum();
+        let distances: u64 = self.distance_frequencies.iter().enumerate().map(|(symbol, &frequency)| {
+            frequency as u64 * (distance_lengths[symbol] as u64 + DISTANCE_EXTRA_BITS[symbol] as u64)
+        }).sum();
+        literals + distances
+    }
+
+    fn stored_size(&self) -> u64 {
+        let blocks = self.raw.len().div_ceil(MAX_STORED_BLOCK).max(1) as u64;
+        blocks * (3 + 7 + 32) + self.raw.len() as u64 * 8
+    }
+
+    fn write_stored(&self, writer: &mut BitWriter, last: bool) {
+        let pieces: Vec<&[u8]> = if self.raw.is_empty() { vec![&[]] } else { self.raw.chunks(MAX_STORED_BLOCK).collect() };
+        for (i, piece) in pieces.iter().enumerate() {
+            writer.bits((last && i + 1 == pieces.len()) as u32, 1);
+            writer.bits(0, 2);
+            writer.align_to_byte();
+            let length = piece.len() as u16;
+            writer.bits(length as u32, 16);
+            writer.bits(!length as u32, 16);
+            for &byte in *piece {
+                writer.bits(byte as u32, 8);
+            }
+        }
+    }
+
+    fn write_tokens(&self, writer: &mut BitWriter, literal_lengths: &[u8], distance_lengths: &[u8]) {
+        let literal_codes = canonical_codes(literal_lengths);
+        let distance_codes = canonical_codes(distance_lengths);
+
+        for token in self.tokens {
+            match *token {
+                Token::Literal(byte) => writer.code(literal_codes[byte as usize], literal_lengths[byte as usize]),
+                Token::Match { length, distance } => {
+                    let index = base_index(&LENGTH_BASES, length as usize);
+                    writer.code(literal_codes[257 + index], literal_lengths[257 + index]);
+                    writer.bits((length - LENGTH_BASES[index]) as u32, LENGTH_EXTRA_BITS[index] as u32);
+
+                    let index = base_index(&DISTANCE_BASES, distance as usize);
+                    writer.code(distance_codes[index], distance_lengths[index]);
+                    writer.bits((distance - DISTANCE_BASES[index]) as u32, DISTANCE_EXTRA_BITS[index] as u32);
+                }
+            }
+        }
+        let end = END_OF_BLOCK as usize;
+        writer.code(literal_codes[end], literal_lengths[end]);
+    }
+}
+
+/*
+   How a dynamic block describes its two codes, the code lengths run length coded with the code
+   length symbols 16 (repeat the previous length), 17 and 18 (runs of zeros)
+*/
+struct DynamicHeader {
+    literal_lengths: Vec<u8>,
+    distance_lengths: Vec<u8>,
+    symbols: Vec<(u8, u8)>, // (code length symbol, value of its extra bits)
+    code_length_lengths: [u8; 19],
+    code_length_count: usize,
+}
+
+impl DynamicHeader {
+    fn new(block: &Block) -> Self {
+        let mut literal_lengths = huffman_lengths(&block.literal_frequencies, MAX_CODE_LENGTH as u8);
+        let mut distance_lengths = huffman_lengths(&block.distance_frequencies, MAX_CODE_LENGTH as u8);
+        let literal_count = literal_lengths.iter().rposition(|&length| length != 0).map_or(257, |last| (last + 1).max(257));
+        let distance_count = distance_lengths.iter().rposition(|&length| length != 0).map_or(1, |last| last + 1);
+        literal_lengths.truncate(literal_count);
+        distance_lengths.truncate(distance_count);
+
+        let all: Vec<u8> = literal_lengths.iter().chain(&distance_lengths).copied().collect();
+        let mut symbols = Vec::new();
+        let mut i = 0;
+        while i < all.len() {
+            let value = all[i];
+            let run = all[i..].iter().take_while(|&&length| length == value).count();
+            if value == 0 && run >= 11 {
+                let run = run.min(138);
+                symbols.push((18, (run - 11) as u8));
+                i += run;
+            } else if value == 0 && run >= 3 {
+                symbols.push((17, (run - 3) as u8));
+                i += run;
+            } else if value != 0 && run >= 4 {
+                // The first one is written as is, the rest as repeats of it
+                symbols.push((value, 0));
+                let mut left = run - 1;
+                while left >= 3 {
+                    let repeat = left.min(6);
+                    symbols.push((16, (repeat - 3) as u8));
+                    left -= repeat;
+                }
+                i += run - left;
+            } else {
+                symbols.push((value, 0));
+                i += 1;
+            }
+        }
+
+        let mut frequencies = [0u32; 19];
+        symbols.iter().for_each(|&(symbol, _)| frequencies[symbol as usize] += 1);
+        let code_length_lengths: [u8; 19] = huffman_lengths(&frequencies, 7).try_into().unwrap();
+        let code_length_count = CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| code_length_lengths[symbol] != 0)
+            .map_or(4, |last| (last + 1).max(4));
+
+        DynamicHeader { literal_lengths, distance_lengths, symbols, code_length_lengths, code_length_count }
+    }
+
+    fn size(&self) -> u64 {
+        let symbols: u64 = self.symbols.iter().map(|&(symbol, _)| {
+            let extra = match symbol {
+                16 => 2,
+                17 => 3,
+                18 => 7,
+                _ => 0,
+            };
+            self.code_length_lengths[symbol as usize] as u64 + extra
+        }).sum();
+        5 + 5 + 4 + 3 * self.code_length_count as u64 + symbols
+    }
+
+    fn write(&self, writer: &mut BitWriter) {
+        writer.bits((self.literal_lengths.len() - 257) as u32, 5);
+        writer.bits((self.distance_lengths.len() - 1) as u32, 5);
+        writer.bits((self.code_length_count - 4) as u32, 4);
+        for &symbol in &CODE_LENGTH_ORDER[..self.code_length_count] {
+            writer.bits(self.code_length_lengths[symbol] as u32, 3);
+        }
+
+        let codes = canonical_codes(&self.code_length_lengths);
+        for &(symbol, extra) in &self.symbols {
+            writer.code(codes[symbol as usize], self.code_length_lengths[symbol as usize]);
+            match symbol {
+                16 => writer.bits(extra as u32, 2),
+                17 => writer.bits(extra as u32, 3),
+                18 => writer.bits(extra as u32, 7),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn write_block(writer: &mut BitWriter, block: &Block, last: bool, blocks: DeflateBlocks) {
+    let fixed_literals = fixed_literal_lengths();
+    let fixed_distances = [5u8; 30];
+    if blocks == DeflateBlocks::Fixed {
+        writer.bits(last as u32, 1);
+        writer.bits(1, 2);
+        block.write_tokens(writer, &fixed_literals, &fixed_distances);
+        return;
+    }
+
+    let dynamic = DynamicHeader::new(block);
+
+    let mut padded_literals = dynamic.literal_lengths.clone();
+    padded_literals.resize(286, 0);
+    let mut padded_distances = dynamic.distance_lengths.clone();
+    padded_distances.resize(30, 0);
+
+    let stored_size = block.stored_size();
+    let fixed_size = 3 + block.coded_size(&fixed_literals, &fixed_distances);
+    let dynamic_size = 3 + dynamic.size() + block.coded_size(&padded_literals, &padded_distances);
+
+    if stored_size <= fixed_size && stored_size <= dynamic_size {
+        block.write_stored(writer, last);
+    } else if fixed_size <= dynamic_size {
+        writer.bits(last as u32, 1);
+        writer.bits(1, 2);
+        block.write_tokens(writer, &fixed_literals, &fixed_distances);
+    } else {
+        writer.bits(last as u32, 1);
+        writer.bits(2, 2);
+        dynamic.write(writer);
+        block.write_tokens(writer, &padded_literals, &padded_distances);
+    }
+}
+
+/*
+   Compresses data into a raw DEFLATE stream. Level 0 only stores, 1 to 9 trade speed for size like
+   zlib's levels, anything above 9 is treated as 9.
+*/
+pub fn deflate(data: &[u8], level: u8, blocks: DeflateBlocks) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    if level == 0 {
+        Block::new(&[], data).write_stored(&mut writer, true);
+        return writer.finish();
+    }
+
+    let tokens = find_tokens(data, &LEVEL_SETTINGS[level.min(MAX_LEVEL) as usize - 1]);
+    if tokens.is_empty() {
+        write_block(&mut writer, &Block::new(&[], &[]), true, blocks);
+        return writer.finish();
+    }
+
+    let mut raw_start = 0;
+    let token_blocks: Vec<&[Token]> = tokens.chunks(TOKENS_PER_BLOCK).collect();
+    for (i, tokens) in token_blocks.iter().enumerate() {
+        let raw_length: usize = tokens.iter().map(|token| match token {
+            Token::Literal(_) => 1,
+            Token::Match { length, .. } => *length as usize,
+        }).sum();
+        let block = Block::new(tokens, &data[raw_start..raw_start + raw_length]);
+        write_block(&mut writer, &block, i + 1 == token_blocks.len(), blocks);
+        raw_start += raw_length;
+    }
+    writer.finish()
+}
+
+/*
+   deflate wrapped in a zlib header and Adler-32 checksum, the header records the level the same way
+   zlib does
+*/
+pub fn zlib_compress(data: &[u8], level: u8) -> Vec<u8> {
+    zlib_compress_with(data, level, DeflateBlocks::Smallest)
+}
+
+pub fn zlib_compress_with(data: &[u8], level: u8, blocks: DeflateBlocks) -> Vec<u8> {
+    let method = 0x78; // DEFLATE with a 32KiB window
+    let level_flag = match level {
+        0 | 1 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let flags = level_flag << 6;
+    let flags = flags + (31 - (u16::from_be_bytes([method, flags]) % 31) as u8) % 31;
+
+    let mut stream = vec![method, flags];
+    stream.extend(deflate(data, level, blocks));
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
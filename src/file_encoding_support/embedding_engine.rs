@@ -0,0 +1,505 @@
+/*
+ * Copyright (C) 2025 Dustyn Gibb
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License
+ * as published by the Free Software Foundation; either version 2
+ * of the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
+ */
+use crate::file_encoding_support::file_encoding_support::{FileEncoding, FileEncodingFunctionDerivation};
+use crate::file_encoding_support::image::{ChannelLayout, Image};
+use crate::file_encoding_support::palette::PalettePairs;
+use crate::file_encoding_support::stego_error::StegoError;
+use crate::mathematics_support::chacha20::ChaCha20Rng;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+
+/*
+   The engine is split in two independent halves. An ordering is just a list of (row, col) image
+   coordinates, it can come from FileEncodingMethod::traverse, WaveFunction::traverse or anywhere else.
+   A PixelEmbedding knows how to hide single bits in the slots of one pixel of a decoded Image and get
+   them back out. embed_slots and extract_slots walk any ordering over any SlotCarrier, embed_along and
+   extract_along do that for a PixelEmbedding on an Image, so a new traversal, a new primitive or a new
+   file format works with everything else as soon as it exists.
+
+   Every pixel of the ordering has bits_per_pixel slots, slot n is pixel n / bits_per_pixel of the
+   ordering and slot n % bits_per_pixel inside of it. Unkeyed uses the slots in order, KeyBased uses
+   them in the order of a keyed shuffle (see slot_sequence).
+
+   Bits are taken from the data least significant bit first, byte by byte.
+*/
+pub trait PixelEmbedding {
+    fn bits_per_pixel(&self, layout: ChannelLayout) -> u64;
+
+    /*
+        samples are the samples of one pixel in layout order
+     */
+    fn embed_bit(&self, samples: &mut [u16], layout: ChannelLayout, slot: usize, bit: bool) -> Result<(), StegoError>;
+
+    fn extract_bit(&self, samples: &[u16], layout: ChannelLayout, slot: usize) -> Result<bool, StegoError>;
+}
+
+/*
+   One bit in the least significant bit of every sample, the slot is the channel. However many bits a
+   sample has, only its lowest one changes.
+*/
+pub struct LsbEmbedding;
+
+impl PixelEmbedding for LsbEmbedding {
+    fn bits_per_pixel(&self, layout: ChannelLayout) -> u64 {
+        layout.channels() as u64
+    }
+
+    fn embed_bit(&self, samples: &mut [u16], _layout: ChannelLayout, slot: usize, bit: bool) -> Result<(), StegoError> {
+        samples[slot] = (samples[slot] & !1) | bit as u16;
+        Ok(())
+    }
+
+    fn extract_bit(&self, samples: &[u16], _layout: ChannelLayout, slot: usize) -> Result<bool, StegoError> {
+        Ok(samples[slot] & 1 != 0)
+    }
+}
+
+/*
+   The lowest bits bits of every sample, slot n is bit n % bits of channel n / bits. LsbEmbedding is the
+   same thing with a single bit. new checks bits against the image, every sample keeps at least its top
+   bit and never more than 8 bits change, so a 16 bit sample only ever changes in its low byte.
+*/
+pub struct LowBitsEmbedding {
+    bits: u8,
+}
+
+impl LowBitsEmbedding {
+    pub const MAX_BITS: u8 = 8;
+
+    pub fn new(image: &Image, bits: u8) -> Result<Self, StegoError> {
+        let smallest_depth = image.bit_depths.iter().copied().min().unwrap_or(0);
+        if bits == 0 || bits > Self::MAX_BITS || bits >= smallest_depth {
+            return Err(StegoError::UnsupportedEncoding(format!(
+                "embedding_engine.rs: {bits} low bits per sample do not fit samples of {smallest_depth} bits, use 1 to {}",
+                Self::MAX_BITS.min(smallest_depth.saturating_sub(1))
+            )));
+        }
+        Ok(LowBitsEmbedding { bits })
+    }
+}
+
+impl PixelEmbedding for LowBitsEmbedding {
+    fn bits_per_pixel(&self, layout: ChannelLayout) -> u64 {
+        layout.channels() as u64 * self.bits as u64
+    }
+
+    fn embed_bit(&self, samples: &mut [u16], _layout: ChannelLayout, slot: usize, bit: bool) -> Result<(), StegoError> {
+        let (channel, mask) = (slot / self.bits as usize, 1u16 << (slot % self.bits as usize));
+        samples[channel] = (samples[channel] & !mask) | if bit { mask } else { 0 };
+        Ok(())
+    }
+
+    fn extract_bit(&self, samples: &[u16], _layout: ChannelLayout, slot: usize) -> Result<bool, StegoError> {
+        Ok(samples[slot / self.bits as usize] & (1 << (slot % self.bits as usize)) != 0)
+    }
+}
+
+/*
+   One bit per pixel in the parity of the number of set bits over all samples, an even count is a 1.
+   When the parity is wrong the least significant bit of the blue sample (the grey one for grey images)
+   is flipped, the smallest change that fixes it in the channel the eye notices least.
+*/
+pub struct ColorParityEmbedding;
+
+impl ColorParityEmbedding {
+    fn parity_bit(samples: &[u16]) -> bool {
+        let ones: u32 = samples.iter().map(|sample| sample.count_ones()).sum();
+        ones.is_multiple_of(2)
+    }
+
+    fn flipped_channel(layout: ChannelLayout) -> usize {
+        match layout {
+            ChannelLayout::Rgb | ChannelLayout::Rgba => 2,
+            _ => 0,
+        }
+    }
+}
+
+impl PixelEmbedding for ColorParityEmbedding {
+    fn bits_per_pixel(&self, _layout: ChannelLayout) -> u64 {
+        1
+    }
+
+    fn embed_bit(&self, samples: &mut [u16], layout: ChannelLayout, _slot: usize, bit: bool) -> Result<(), StegoError> {
+        if Self::parity_bit(samples) != bit {
+            samples[Self::flipped_channel(layout)] ^= 1;
+        }
+        Ok(())
+    }
+
+    fn extract_bit(&self, samples: &[u16], _layout: ChannelLayout, _slot: usize) -> Result<bool, StegoError> {
+        Ok(Self::parity_bit(samples))
+    }
+}
+
+/*
+   Drops repeated coordinates so a traversal that crosses itself never overwrites bits it already embedded,
+   anything outside of the image is an error since it means the traversal itself is broken
+*/
+pub fn unique_positions(width: usize, height: usize, order: &[(usize, usize)]) -> Result<Vec<(usize, usize)>, StegoError> {
+    let mut visited = vec![false; width * height];
+    let mut positions = Vec::with_capacity(order.len());
+
+    for &(row, col) in order {
+        if row >= height || col >= width {
+            return Err(StegoError::MalformedHeader(format!(
+                "embedding_engine.rs: traversal visits ({row}, {col}) which is outside of the {width}x{height} image"
+            )));
+        }
+
+        let index = row * width + col;
+        if !visited[index] {
+            visited[index] = true;
+            positions.push((row, col));
+        }
+    }
+
+    Ok(positions)
+}
+
+// Keeps this stream apart from anything else that might ever be derived from the same key
+const SLOT_NONCE: [u8; 12] = *b"maya-slots\0\0";
+
+/*
+   The slots that carry bits 0..count. For KeyBased this is the prefix of a Fisher-Yates shuffle of all
+   total_slots slots driven by ChaCha20, only the swapped entries are kept in a map so a short message in a
+   large image stays cheap. The prefix only depends on the key, so extracting the header first and the
+   whole payload afterwards lands on the same slots.
+*/
+pub fn slot_sequence(total_slots: u64, count: u64, derivation: FileEncodingFunctionDerivation) -> Vec<u64> {
+    match derivation {
+        FileEncodingFunctionDerivation::Unkeyed => (0..count).collect(),
+        FileEncodingFunctionDerivation::KeyBased(key) => {
+            let mut rng = ChaCha20Rng::new(&key, &SLOT_NONCE, 0);
+            let mut swapped: HashMap<u64, u64> = HashMap::new();
+            let mut slots = Vec::with_capacity(count as usize);
+
+            for i in 0..count {
+                let j = i + rng.next_below(total_slots - i);
+                let at_i = swapped.get(&i).copied().unwrap_or(i);
+                let at_j = swapped.get(&j).copied().unwrap_or(j);
+                swapped.insert(j, at_i);
+                slots.push(at_j);
+            }
+
+            slots
+        }
+    }
+}
+
+/*
+   Anything the engine can hide bits in. A carrier exposes a width x height image where every pixel has
+   slots_per_pixel slots, ImageSlots puts a PixelEmbedding on a decoded Image and anything that is not
+   an image of pixels can bring its own. SlotCarrier is the writable half.
+*/
+pub trait SlotSource {
+    fn width(&self) -> usize;
+
+    fn height(&self) -> usize;
+
+    fn slots_per_pixel(&self) -> u64;
+
+    fn extract_bit(&self, row: usize, col: usize, slot: usize) -> Result<bool, StegoError>;
+}
+
+pub trait SlotCarrier: SlotSource {
+    fn embed_bit(&mut self, row: usize, col: usize, slot: usize, bit: bool) -> Result<(), StegoError>;
+}
+
+/*
+   A PixelEmbedding on an Image, I is &Image to extract and &mut Image to embed as well
+*/
+pub struct ImageSlots<'e, I: Deref<Target = Image>, E: PixelEmbedding> {
+    image: I,
+    embedding: &'e E,
+}
+
+impl<'e, I: Deref<Target = Image>, E: PixelEmbedding> ImageSlots<'e, I, E> {
+    pub fn new(image: I, embedding: &'e E) -> Self {
+        ImageSlots { image, embedding }
+    }
+}
+
+impl<I: Deref<Target = Image>, E: PixelEmbedding> SlotSource for ImageSlots<'_, I, E> {
+    fn width(&self) -> usize {
+        self.image.width
+    }
+
+    fn height(&self) -> usize {
+        self.image.height
+    }
+
+    fn slots_per_pixel(&self) -> u64 {
+        self.embedding.bits_per_pixel(self.image.layout)
+    }
+
+    fn extract_bit(&self, row: usize, col: usize, slot: usize) -> Result<bool, StegoError> {
+        let samples = self.image.pixel(row, col).ok_or_else(|| self.image.out_of_bounds(row, col))?;
+        self.embedding.extract_bit(samples, self.image.layout, slot)
+    }
+}
+
+impl<I: DerefMut<Target = Image>, E: PixelEmbedding> SlotCarrier for ImageSlots<'_, I, E> {
+    fn embed_bit(&mut self, row: usize, col: usize, slot: usize, bit: bool) -> Result<(), StegoError> {
+        let layout = self.image.layout;
+        let samples = self.image.pixel_mut(row, col).expect("unique_positions only returns points inside the image");
+        self.embedding.embed_bit(samples, layout, slot, bit)
+    }
+}
+
+/*
+   Plain bytes where every bit is a slot, for data that is not pixels at all (the slack space of a file).
+   It is a single row of bytes.len() pixels with 8 slots each, slot n is bit n of the byte.
+*/
+pub struct ByteSlots<B: Deref<Target = [u8]>> {
+    bytes: B,
+}
+
+impl<B: Deref<Target = [u8]>> ByteSlots<B> {
+    pub fn new(bytes: B) -> Self {
+        ByteSlots { bytes }
+    }
+
+    // Every byte once, first to last
+    pub fn order(&self) -> Vec<(usize, usize)> {
+        (0..self.bytes.len()).map(|col| (0, col)).collect()
+    }
+}
+
+impl<B: Deref<Target = [u8]>> SlotSource for ByteSlots<B> {
+    fn width(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn height(&self) -> usize {
+        1
+    }
+
+    fn slots_per_pixel(&self) -> u64 {
+        8
+    }
+
+    fn extract_bit(&self, _row: usize, col: usize, slot: usize) -> Result<bool, StegoError> {
+        Ok(self.bytes[col] & (1 << slot) != 0)
+    }
+}
+
+impl<B: DerefMut<Target = [u8]>> SlotCarrier for ByteSlots<B> {
+    fn embed_bit(&mut self, _row: usize, col: usize, slot: usize, bit: bool) -> Result<(), StegoError> {
+        if bit {
+            self.bytes[col] |= 1 << slot;
+        } else {
+            self.bytes[col] &= !(1 << slot);
+        }
+        Ok(())
+    }
+}
+
+pub fn embed_slots<C: SlotCarrier>(
+    carrier: &mut C,
+    order: &[(usize, usize)],
+    derivation: FileEncodingFunctionDerivation,
+    data: &[u8],
+) -> Result<(), StegoError> {
+    let positions = unique_positions(carrier.width(), carrier.height(), order)?;
+    let slots_per_pixel = carrier.slots_per_pixel();
+    let available_bits = positions.len() as u64 * slots_per_pixel;
+    let required_bits = data.len() as u64 * 8;
+
+    if required_bits > available_bits {
+        return Err(StegoError::InsufficientCapacity {
+            required_bits,
+            available_bits,
+        });
+    }
+
+    for (bit_index, slot) in slot_sequence(available_bits, required_bits, derivation).into_iter().enumerate() {
+        let (row, col) = positions[(slot / slots_per_pixel) as usize];
+        let bit = data[bit_index / 8] & (1 << (bit_index % 8)) != 0;
+        carrier.embed_bit(row, col, (slot % slots_per_pixel) as usize, bit)?;
+    }
+
+    Ok(())
+}
+
+/*
+   Reads back exactly embedded_bits bits, the returned vec is embedded_bits rounded up to whole bytes
+*/
+pub fn extract_slots<C: SlotSource>(
+    carrier: &C,
+    order: &[(usize, usize)],
+    derivation: FileEncodingFunctionDerivation,
+    embedded_bits: u64,
+) -> Result<Vec<u8>, StegoError> {
+    let positions = unique_positions(carrier.width(), carrier.height(), order)?;
+    let slots_per_pixel = carrier.slots_per_pixel();
+    let available_bits = positions.len() as u64 * slots_per_pixel;
+
+    if embedded_bits > available_bits {
+        return Err(StegoError::InsufficientCapacity {
+            required_bits: embedded_bits,
+            available_bits,
+        });
+    }
+
+    let mut extracted_data = vec![0u8; embedded_bits.div_ceil(8) as usize];
+
+    for (bit_index, slot) in slot_sequence(available_bits, embedded_bits, derivation).into_iter().enumerate() {
+        let (row, col) = positions[(slot / slots_per_pixel) as usize];
+        if carrier.extract_bit(row, col, (slot % slots_per_pixel) as usize)? {
+            extracted_data[bit_index / 8] |= 1 << (bit_index % 8);
+        }
+    }
+
+    Ok(extracted_data)
+}
+
+pub fn embed_along<E: PixelEmbedding>(
+    image: &mut Image,
+    order: &[(usize, usize)],
+    embedding: &E,
+    derivation: FileEncodingFunctionDerivation,
+    data: &[u8],
+) -> Result<(), StegoError> {
+    embed_slots(&mut ImageSlots::new(image, embedding), order, derivation, data)
+}
+
+pub fn extract_along<E: PixelEmbedding>(
+    image: &Image,
+    order: &[(usize, usize)],
+    embedding: &E,
+    derivation: FileEncodingFunctionDerivation,
+    embedded_bits: u64,
+) -> Result<Vec<u8>, StegoError> {
+    extract_slots(&ImageSlots::new(image, embedding), order, derivation, embedded_bits)
+}
+
+/*
+   FileEncoding to primitive, encodings without a primitive yet are reported here so no carrier has to.
+   Palette images hide their bits in the choice between two close palette entries (PalettePairs), that is
+   what Lsb means for them. Their pixels whose colour has no close partner are left out of the ordering.
+*/
+pub fn embed_encoded(
+    image: &mut Image,
+    order: &[(usize, usize)],
+    encoding: FileEncoding,
+    derivation: FileEncodingFunctionDerivation,
+    data: &[u8],
+) -> Result<(), StegoError> {
+    let order = &outside_colour_key(image, order, encoding);
+    match (image.layout, encoding) {
+        (ChannelLayout::Indexed, FileEncoding::Lsb) => {
+            let pairs = PalettePairs::new(&image.palette, &image.palette_alpha);
+            let positions = pairs.positions(image, order);
+            embed_along(image, &positions, &pairs, derivation, data)
+        }
+        (ChannelLayout::Indexed, _) => Err(unsupported_on_palette(encoding)),
+        (_, FileEncoding::Lsb) => embed_along(image, order, &LsbEmbedding, derivation, data),
+        (_, FileEncoding::ColorParity) => embed_along(image, order, &ColorParityEmbedding, derivation, data),
+        (_, FileEncoding::LowBits(bits)) => {
+            let low_bits = LowBitsEmbedding::new(image, bits)?;
+            embed_along(image, order, &low_bits, derivation, data)
+        }
+        _ => Err(unsupported_encoding(encoding)),
+    }
+}
+
+pub fn extract_encoded(
+    image: &Image,
+    order: &[(usize, usize)],
+    encoding: FileEncoding,
+    derivation: FileEncodingFunctionDerivation,
+    embedded_bits: u64,
+) -> Result<Vec<u8>, StegoError> {
+    let order = &outside_colour_key(image, order, encoding);
+    match (image.layout, encoding) {
+        (ChannelLayout::Indexed, FileEncoding::Lsb) => {
+            let pairs = PalettePairs::new(&image.palette, &image.palette_alpha);
+            extract_along(image, &pairs.positions(image, order), &pairs, derivation, embedded_bits)
+        }
+        (ChannelLayout::Indexed, _) => Err(unsupported_on_palette(encoding)),
+        (_, FileEncoding::Lsb) => extract_along(image, order, &LsbEmbedding, derivation, embedded_bits),
+        (_, FileEncoding::ColorParity) => extract_along(image, order, &ColorParityEmbedding, derivation, embedded_bits),
+        (_, FileEncoding::LowBits(bits)) => extract_along(image, order, &LowBitsEmbedding::new(image, bits)?, derivation, embedded_bits),
+        _ => Err(unsupported_encoding(encoding)),
+    }
+}
+
+pub fn bits_per_pixel(image: &Image, encoding: FileEncoding) -> Result<u64, StegoError> {
+    match (image.layout, encoding) {
+        (ChannelLayout::Indexed, FileEncoding::Lsb) => Ok(PalettePairs::new(&image.palette, &image.palette_alpha).bits_per_pixel(image.layout)),
+        (ChannelLayout::Indexed, _) => Err(unsupported_on_palette(encoding)),
+        (_, FileEncoding::Lsb) => Ok(LsbEmbedding.bits_per_pixel(image.layout)),
+        (_, FileEncoding::ColorParity) => Ok(ColorParityEmbedding.bits_per_pixel(image.layout)),
+        (_, FileEncoding::LowBits(bits)) => Ok(LowBitsEmbedding::new(image, bits)?.bits_per_pixel(image.layout)),
+        _ => Err(unsupported_encoding(encoding)),
+    }
+}
+
+/*
+   The pixels of order that carry bits for encoding, each of them once. That is every pixel except on
+   palette images, where pixels whose colour has no close partner carry nothing, and on colour keyed
+   images, where pixels at or next to the key carry nothing (see outside_colour_key).
+*/
+pub fn carrier_positions(image: &Image, order: &[(usize, usize)], encoding: FileEncoding) -> Result<Vec<(usize, usize)>, StegoError> {
+    let positions = unique_positions(image.width, image.height, &outside_colour_key(image, order, encoding))?;
+    match (image.layout, encoding) {
+        (ChannelLayout::Indexed, FileEncoding::Lsb) => Ok(PalettePairs::new(&image.palette, &image.palette_alpha).positions(image, &positions)),
+        _ => Ok(positions),
+    }
+}
+
+/*
+   Every pixel of a colour keyed image (PNG tRNS on a grey or truecolour image) that is exactly the key
+   colour is transparent. Pixels that are the key, or could turn into it once the encoding has changed
+   their low bits, are left out of order, so no pixel appears or disappears. Only the bits above the
+   ones the encoding changes decide, extraction leaves out the same pixels.
+*/
+fn outside_colour_key<'a>(image: &Image, order: &'a [(usize, usize)], encoding: FileEncoding) -> Cow<'a, [(usize, usize)]> {
+    let Some(key) = &image.transparent_key else {
+        return Cow::Borrowed(order);
+    };
+    let changed_bits = match encoding {
+        FileEncoding::LowBits(bits) => bits.min(LowBitsEmbedding::MAX_BITS),
+        _ => 1,
+    };
+    // Points outside of the image stay in, unique_positions reports them
+    let outside = |&&(row, col): &&(usize, usize)| {
+        image.pixel(row, col).is_none_or(|samples| samples.iter().zip(key).any(|(sample, key)| sample >> changed_bits != key >> changed_bits))
+    };
+    Cow::Owned(order.iter().filter(outside).copied().collect())
+}
+
+fn unsupported_encoding(encoding: FileEncoding) -> StegoError {
+    match encoding {
+        FileEncoding::Slack | FileEncoding::SlackLsb | FileEncoding::Chunk => StegoError::UnsupportedEncoding(format!(
+            "embedding_engine.rs: {encoding:?} hides data in the file structure, the carrier has to handle it"
+        )),
+        _ => StegoError::UnsupportedEncoding(format!("embedding_engine.rs: {encoding:?} is not implemented")),
+    }
+}
+
+fn unsupported_on_palette(encoding: FileEncoding) -> StegoError {
+    if matches!(encoding, FileEncoding::Slack | FileEncoding::SlackLsb | FileEncoding::Chunk) {
+        return unsupported_encoding(encoding);
+    }
+    StegoError::UnsupportedEncoding(format!(
+        "embedding_engine.rs: {encoding:?} is not available for palette images, use Lsb"
+    ))
+}
@@ -16,10 +16,16 @@ along with this program; if not, write to the Free Software
 Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
  */
 
+use crate::file_encoding_support::payload_header::PAYLOAD_HEADER_SIZE;
+use crate::file_encoding_support::stego_error::StegoError;
+use crate::mathematics_support::mathematics_support::hilbert_point;
+use crate::mathematics_support::sha256::sha256;
+use std::fmt;
 use std::fs::File;
+use std::io::{BufWriter, Read, Write};
 
-pub struct ImageSupport<T: FileEncodingSupport + FileEncodingAlgorithms> {
-    image_file: File,
+pub struct ImageSupport<T: FileEncodingSupport> {
+    output_file: Option<String>,
     encoding: FileEncoding,
     encoding_method: FileEncodingMethod,
     file_encoding_function_derivation: FileEncodingFunctionDerivation,
@@ -28,7 +34,105 @@ pub struct ImageSupport<T: FileEncodingSupport + FileEncodingAlgorithms> {
     encoding_support: T,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl<T: FileEncodingSupport> ImageSupport<T> {
+    /*
+        Takes an already parsed carrier, output_file is only needed when embedding
+     */
+    pub fn new(
+        encoding_support: T,
+        output_file: Option<String>,
+        encoding: FileEncoding,
+        encoding_method: FileEncodingMethod,
+        file_encoding_function_derivation: FileEncodingFunctionDerivation,
+        operation: Operation,
+        data: Vec<u8>,
+    ) -> Self {
+        ImageSupport {
+            output_file,
+            encoding,
+            encoding_method,
+            file_encoding_function_derivation,
+            operation,
+            data,
+            encoding_support,
+        }
+    }
+
+    /*
+        Embed writes the carrier out to output_file, extract and capacity hand back what they found
+     */
+    pub fn run(&mut self) -> Result<OperationOutput, StegoError> {
+        match self.operation {
+            Operation::Embed => {
+                let output_file = match &self.output_file {
+                    Some(output_file) => output_file.clone(),
+                    None => {
+                        return Err(StegoError::Io(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            "no output file given for embed",
+                        )));
+                    }
+                };
+
+                self.encoding_support.embed_data(
+                    &mut self.data,
+                    self.encoding,
+                    self.encoding_method,
+                    self.file_encoding_function_derivation,
+                )?;
+                self.encoding_support.write_file(&output_file)?;
+                Ok(OperationOutput::Embedded)
+            }
+            Operation::Extract => {
+                let data = self.encoding_support.retrieve_data(
+                    self.encoding,
+                    self.encoding_method,
+                    self.file_encoding_function_derivation,
+                )?;
+                Ok(OperationOutput::Extracted(data))
+            }
+            Operation::Capacity => {
+                let report = self.encoding_support.capacity(self.encoding, self.encoding_method)?;
+                Ok(OperationOutput::Capacity(report))
+            }
+            Operation::Chunks => Ok(OperationOutput::Chunks(self.encoding_support.non_standard_chunk_types())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum OperationOutput {
+    Embedded,
+    Extracted(Vec<u8>),
+    Capacity(CapacityReport),
+    Chunks(Vec<String>),
+}
+
+/*
+    How much a carrier can hold for one encoding and method. usable_payload_bytes is what is left for
+    the message once the payload header has been taken out of the raw capacity.
+ */
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct CapacityReport {
+    pub total_bits: u64,
+    pub bits_per_pixel: u64,
+    pub header_overhead_bytes: u64,
+    pub usable_payload_bytes: u64,
+}
+
+impl CapacityReport {
+    pub fn new(total_bits: u64, bits_per_pixel: u64) -> Self {
+        let header_overhead_bytes = PAYLOAD_HEADER_SIZE as u64;
+        CapacityReport {
+            total_bits,
+            bits_per_pixel,
+            header_overhead_bytes,
+            usable_payload_bytes: (total_bits / 8).saturating_sub(header_overhead_bytes),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum WaveFunction {
     Horizontal,
     Vertical,
@@ -42,13 +146,28 @@ pub enum WaveFunction {
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Operation {
     Embed,
-    Extract
+    Extract,
+    Capacity,
+    Chunks,
 }
+/*
+    Slack and SlackLsb do not touch pixels (or not only pixels), they hide data in bytes of the file that
+    viewers skip, such as the gap before the pixel data and the padding at the end of every row. SlackLsb
+    fills the slack first and carries on in the Lsb slots of the pixels. Chunk leaves the pixels alone as
+    well and stores the payload in an ancillary chunk of its own (PNG only). LowBits(n) is Lsb over the n
+    lowest bits of every sample instead of just one, meant for 16 bit samples where the low byte is noise
+    to the eye. n goes from 1 to 8 and has to stay below the bit depth of every channel.
+ */
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum FileEncoding {
     Lsb,
     PixelValueDifferencing,
     HammingMatrix,
+    ColorParity,
+    Slack,
+    SlackLsb,
+    Chunk,
+    LowBits(u8),
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -60,24 +179,87 @@ pub enum FileEncodingMethod {
     CosWave,
     PolynomialFunction,
     FractalFunction,
+    Wave(WaveFunction),
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/*
+    Unkeyed embeds in the plain order of the encoding method. KeyBased holds a 256 bit key that seeds a
+    ChaCha20 stream which shuffles every pixel and channel slot, the bits can only be found again with the
+    same key. The key is never written into the image.
+ */
+#[derive(Copy, Clone, Eq, PartialEq)]
 pub enum FileEncodingFunctionDerivation {
-    KeyBased,
+    Unkeyed,
+    KeyBased([u8; 32]),
 }
 
+impl FileEncodingFunctionDerivation {
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        FileEncodingFunctionDerivation::KeyBased(sha256(passphrase.as_bytes()))
+    }
+}
+
+// Keeps the key out of logs and error messages
+impl fmt::Debug for FileEncodingFunctionDerivation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileEncodingFunctionDerivation::Unkeyed => write!(f, "Unkeyed"),
+            FileEncodingFunctionDerivation::KeyBased(_) => write!(f, "KeyBased(..)"),
+        }
+    }
+}
+
+/*
+    Implementations work entirely on an in-memory copy of the file, from_bytes and write_to are the only
+    required constructor / writer, the file and reader based ones are built on top of them
+ */
 pub trait FileEncodingSupport {
-    fn new(filename : &str) -> Self;
+    fn new(filename : &str) -> Result<Self, StegoError> where Self: Sized {
+        Self::from_reader(File::open(filename)?)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, StegoError> where Self: Sized;
+
+    fn from_reader<R: Read>(mut reader: R) -> Result<Self, StegoError> where Self: Sized {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    fn parse_file(&mut self) -> Result<(), StegoError>;
 
-    fn parse_file(&mut self);
+    fn embed_data(&mut self, data: &mut Vec<u8>, encoding: FileEncoding, encoding_method: FileEncodingMethod, file_encoding_function_derivation: FileEncodingFunctionDerivation) -> Result<(), StegoError>;
 
-    fn embed_data(&mut self, data: &mut Vec<u8>, encoding: FileEncoding, encoding_method: FileEncodingMethod, file_encoding_function_derivation: FileEncodingFunctionDerivation);
+    /*
+        Returns exactly the payload that was embedded, the length comes from the payload header
+     */
+    fn retrieve_data(&mut self, encoding: FileEncoding, encoding_method: FileEncodingMethod, file_encoding_function_derivation: FileEncodingFunctionDerivation) -> Result<Vec<u8>, StegoError>;
 
-    fn retrieve_data(&mut self,data: Vec<u8>,encoding: FileEncoding, encoding_method: FileEncodingMethod, file_encoding_function_derivation: FileEncodingFunctionDerivation);
+    fn capacity(&self, encoding: FileEncoding, encoding_method: FileEncodingMethod) -> Result<CapacityReport, StegoError>;
 
-    fn write_file(&mut self, new_file_location: &str);
-    
+    /*
+        Chunks of the file that no specification defines, by type. Only formats built out of chunks have any.
+     */
+    fn non_standard_chunk_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /*
+        Format specific settings from the command line, --name value arrives as set_option(name, value)
+        after parse_file. Formats without settings of their own turn every option down.
+     */
+    fn set_option(&mut self, name: &str, _value: &str) -> Result<(), StegoError> {
+        Err(StegoError::Usage(format!("--{name} is not an option for this file type!")))
+    }
+
+    fn write_to(&mut self, writer: &mut dyn Write) -> Result<(), StegoError>;
+
+    fn write_file(&mut self, new_file_location: &str) -> Result<(), StegoError> {
+        let mut writer = BufWriter::new(File::create(new_file_location)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
 }
 
 
@@ -127,11 +309,11 @@ impl WaveFunction {
             // DiagonalLeft wave: Traverse diagonally from top-right to bottom-left
             WaveFunction::DiagonalLeft => {
                 let mut row = 0;
-                let mut col = cols - 1;
-                while row < rows && col >= 0 {
-                    positions.push((row, col));
+                let mut col = cols;
+                while row < rows && col > 0 {
+                    positions.push((row, col - 1));
                     row += 1;
-                    col = col.saturating_sub(1);
+                    col -= 1;
                 }
             }
             // ZigZagHorizontal: Traverse rows in a zigzag pattern (left-right, then right-left)
@@ -165,15 +347,97 @@ impl WaveFunction {
             // Sinusoidal wave: Simulate a sine wave pattern over rows and columns
             WaveFunction::Sinusoidal => {
                 // A basic sine-wave-like pattern, with amplitude and frequency scaling
+                if cols == 0 {
+                    return positions;
+                }
                 for row in 0..rows {
                     let sine_wave_offset = ((row as f32 / rows as f32) * 2.0 * std::f32::consts::PI).sin();
-                    // Translate sine value to column index (oscillation over columns)
-                    let col_offset = ((sine_wave_offset + 1.0) * (cols as f32 / 2.0)) as usize;
-                    positions.push((row, col_offset));
+                    // Translate sine value to column index (oscillation over columns), the peak lands on the last column
+                    let col_offset = ((sine_wave_offset + 1.0) * ((cols - 1) as f32 / 2.0)).round() as usize;
+                    positions.push((row, col_offset.min(cols - 1)));
                 }
             }
         }
 
         positions
     }
-}
\ No newline at end of file
+}
+
+/*
+    Every method visits every pixel exactly once so they all have the same capacity, they only differ in
+    the order the pixels are used in. Coordinates are (row, col) with row 0 at the top of the image.
+    Wave goes along a WaveFunction first, the diagonals and the sinusoid only touch a few pixels so
+    whatever they leave out follows left to right.
+ */
+impl FileEncodingMethod {
+    pub(crate) fn traverse(&self, rows: usize, cols: usize) -> Vec<(usize, usize)> {
+        let mut positions = Vec::with_capacity(rows * cols);
+
+        if rows == 0 || cols == 0 {
+            return positions;
+        }
+
+        match self {
+            FileEncodingMethod::LeftToRight => positions = WaveFunction::Horizontal.traverse(rows, cols),
+            FileEncodingMethod::RightToLeft => {
+                positions = WaveFunction::Horizontal.traverse(rows, cols);
+                positions.reverse();
+            }
+            FileEncodingMethod::TopToBottom => positions = WaveFunction::Vertical.traverse(rows, cols),
+            // Every row is rotated by a sine (or cosine) of its position so the used columns snake across the image
+            FileEncodingMethod::SinWave | FileEncodingMethod::CosWave => {
+                for row in 0..rows {
+                    let angle = (row as f64 / rows as f64) * 2.0 * std::f64::consts::PI;
+                    let wave = match self {
+                        FileEncodingMethod::SinWave => angle.sin(),
+                        _ => angle.cos(),
+                    };
+                    let shift = ((wave + 1.0) * ((cols - 1) as f64 / 2.0)).round() as usize;
+                    for col in 0..cols {
+                        positions.push((row, (col + shift) % cols));
+                    }
+                }
+            }
+            // Same idea with a quadratic shift, rows drift apart faster and faster
+            FileEncodingMethod::PolynomialFunction => {
+                for row in 0..rows {
+                    let row = row as u64;
+                    let shift = ((row * row + row) / 2 % cols as u64) as usize;
+                    for col in 0..cols {
+                        positions.push((row as usize, (col + shift) % cols));
+                    }
+                }
+            }
+            /*
+                The image is tiled with squares as large as the shorter side allows (rounded up to a power of two)
+                and each tile is walked along a Hilbert curve, the tiles themselves go left to right, top to bottom.
+                Tiles are never more than twice the shorter side so the points thrown away outside of the image
+                stay bounded.
+             */
+            FileEncodingMethod::FractalFunction => {
+                let side = rows.min(cols).next_power_of_two();
+                for tile_row in (0..rows).step_by(side) {
+                    for tile_col in (0..cols).step_by(side) {
+                        for distance in 0..side * side {
+                            let (x, y) = hilbert_point(side, distance);
+                            let (row, col) = (tile_row + y, tile_col + x);
+                            if row < rows && col < cols {
+                                positions.push((row, col));
+                            }
+                        }
+                    }
+                }
+            }
+            FileEncodingMethod::Wave(wave_function) => {
+                let mut visited = vec![false; rows * cols];
+                for (row, col) in wave_function.traverse(rows, cols).into_iter().chain(WaveFunction::Horizontal.traverse(rows, cols)) {
+                    if !std::mem::replace(&mut visited[row * cols + col], true) {
+                        positions.push((row, col));
+                    }
+                }
+            }
+        }
+
+        positions
+    }
+}
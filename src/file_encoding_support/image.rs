@@ -0,0 +1,145 @@
+/*
+ * Copyright (C) 2025 Dustyn Gibb
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License
+ * as published by the Free Software Foundation; either version 2
+ * of the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
+ */
+use crate::file_encoding_support::stego_error::StegoError;
+
+/*
+   What the samples of one pixel mean. Samples are always stored in this order, so an Rgb pixel is
+   red, green, blue no matter how the file orders its bytes. An Indexed pixel has a single sample that
+   is an index into the palette of the image.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChannelLayout {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    Rgba,
+    Indexed,
+}
+
+impl ChannelLayout {
+    pub fn channels(self) -> usize {
+        match self {
+            ChannelLayout::Gray | ChannelLayout::Indexed => 1,
+            ChannelLayout::GrayAlpha => 2,
+            ChannelLayout::Rgb => 3,
+            ChannelLayout::Rgba => 4,
+        }
+    }
+
+    pub fn from_channels(channels: usize, colour: bool) -> Option<ChannelLayout> {
+        match (channels, colour) {
+            (1, false) => Some(ChannelLayout::Gray),
+            (2, false) => Some(ChannelLayout::GrayAlpha),
+            (3, true) => Some(ChannelLayout::Rgb),
+            (4, true) => Some(ChannelLayout::Rgba),
+            _ => None,
+        }
+    }
+}
+
+/*
+   Something a format wants back when the image is encoded again (headers, colour profiles, ancillary
+   chunks, ...). Only the format that decoded the image knows what a block means, everything else just
+   carries it along untouched.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MetadataBlock {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/*
+   The decoded form every format is turned into before embedding. samples holds width * height pixels
+   of layout.channels() samples each, top row first and left to right. bit_depths has one entry per
+   channel, a sample never goes above 2^depth - 1, so a RGB565 pixel is three samples of 5, 6 and 5 bits.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub layout: ChannelLayout,
+    pub bit_depths: Vec<u8>,
+    pub samples: Vec<u16>,
+    pub palette: Vec<[u8; 3]>, // (red, green, blue), only used by Indexed images
+    pub palette_alpha: Vec<u8>, // Alpha of the first palette entries (PNG tRNS), the others are opaque
+    pub transparent_key: Option<Vec<u16>>, // The one fully transparent colour of a Gray or Rgb image (PNG tRNS)
+    pub metadata: Vec<MetadataBlock>,
+}
+
+impl Image {
+    /*
+        An image with every sample set to 0
+     */
+    pub fn new(width: usize, height: usize, layout: ChannelLayout, bit_depths: Vec<u8>) -> Result<Self, StegoError> {
+        if bit_depths.len() != layout.channels() || bit_depths.iter().any(|&depth| depth == 0 || depth > 16) {
+            return Err(StegoError::UnsupportedFormat(format!(
+                "image.rs: bit depths {bit_depths:?} do not describe a {layout:?} image"
+            )));
+        }
+
+        let sample_count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(layout.channels()))
+            .ok_or_else(|| StegoError::MalformedHeader(format!("image.rs: {width}x{height} image overflows")))?;
+
+        Ok(Image {
+            width,
+            height,
+            layout,
+            bit_depths,
+            samples: vec![0; sample_count],
+            palette: Vec::new(),
+            palette_alpha: Vec::new(),
+            transparent_key: None,
+            metadata: Vec::new(),
+        })
+    }
+
+    pub fn channels(&self) -> usize {
+        self.layout.channels()
+    }
+
+    fn offset(&self, row: usize, col: usize) -> Option<usize> {
+        if row >= self.height || col >= self.width {
+            return None;
+        }
+        Some((row * self.width + col) * self.channels())
+    }
+
+    pub fn pixel(&self, row: usize, col: usize) -> Option<&[u16]> {
+        let offset = self.offset(row, col)?;
+        Some(&self.samples[offset..offset + self.channels()])
+    }
+
+    pub fn pixel_mut(&mut self, row: usize, col: usize) -> Option<&mut [u16]> {
+        let offset = self.offset(row, col)?;
+        let channels = self.channels();
+        Some(&mut self.samples[offset..offset + channels])
+    }
+
+    pub fn out_of_bounds(&self, row: usize, col: usize) -> StegoError {
+        StegoError::MalformedHeader(format!(
+            "image.rs: ({row}, {col}) is outside of the {}x{} image",
+            self.width, self.height
+        ))
+    }
+
+    pub fn metadata(&self, name: &str) -> Option<&[u8]> {
+        self.metadata.iter().find(|block| block.name == name).map(|block| &block.data[..])
+    }
+}
@@ -16,5 +16,11 @@
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
  */
 
+pub mod embedding_engine;
 pub mod file_encoding_support;
-pub mod pixel;
\ No newline at end of file
+pub mod image;
+pub mod palette;
+pub mod payload_header;
+pub mod pixel;
+pub mod pixel_grid;
+pub mod stego_error;
\ No newline at end of file
@@ -0,0 +1,165 @@
+/*
+ * Copyright (C) 2025 Dustyn Gibb
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License
+ * as published by the Free Software Foundation; either version 2
+ * of the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
+ */
+use crate::file_encoding_support::embedding_engine::PixelEmbedding;
+use crate::file_encoding_support::image::{ChannelLayout, Image};
+use crate::file_encoding_support::stego_error::StegoError;
+
+/*
+   Embedding for palette images with a bound on how far a colour can move. Entries are matched into
+   pairs, closest first, and a pixel carries one bit in which entry of its pair it uses, the lower index
+   is a 0. Entries further than MAX_DISTANCE from every free entry stay unpaired and their pixels carry
+   nothing, positions drops them from an ordering. An index only ever moves to its partner, so the pairs
+   and the usable pixels come out the same on extraction. Only pixel indices are ever touched.
+
+   Entries can have an alpha (PNG tRNS), colours are compared premultiplied by it, so two fully
+   transparent entries look the same whatever their colour is.
+*/
+pub struct PalettePairs {
+    partner: Vec<Option<u8>>,
+}
+
+// Red, green, blue and alpha, roughly how much the eye notices each one
+const CHANNEL_WEIGHTS: [u32; 4] = [3, 4, 2, 3];
+
+impl PalettePairs {
+    // As far as a pixel can move, MAX_STEP levels in every channel at once (12 is the sum of the weights)
+    pub const MAX_STEP: u32 = 12;
+    pub const MAX_DISTANCE: u32 = Self::MAX_STEP * Self::MAX_STEP * 12;
+
+    /*
+        alpha holds the alpha of the first entries, the rest are opaque
+     */
+    pub fn new(palette: &[[u8; 3]], alpha: &[u8]) -> Self {
+        let colours: Vec<[u32; 4]> = palette
+            .iter()
+            .enumerate()
+            .map(|(index, &colour)| premultiplied(colour, alpha.get(index).copied().unwrap_or(255)))
+            .collect();
+
+        let mut candidates = Vec::new();
+        for first in 0..colours.len() {
+            for second in first + 1..colours.len() {
+                let distance = distance(colours[first], colours[second]);
+                if distance <= Self::MAX_DISTANCE {
+                    candidates.push((distance, first, second));
+                }
+            }
+        }
+        // Ties go to the lowest indices so the pairs are the same every time the palette is read
+        candidates.sort_unstable();
+
+        let mut partner = vec![None; palette.len()];
+        for (_, first, second) in candidates {
+            if partner[first].is_none() && partner[second].is_none() {
+                partner[first] = Some(second as u8);
+                partner[second] = Some(first as u8);
+            }
+        }
+
+        PalettePairs { partner }
+    }
+
+    pub fn partner(&self, index: u8) -> Option<u8> {
+        self.partner.get(index as usize).copied().flatten()
+    }
+
+    /*
+        order without the pixels that can not carry a bit, points outside of the image are kept for the
+        engine to report
+     */
+    pub fn positions(&self, image: &Image, order: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        order
+            .iter()
+            .copied()
+            .filter(|&(row, col)| {
+                image.pixel(row, col).is_none_or(|samples| u8::try_from(samples[0]).is_ok_and(|index| self.partner(index).is_some()))
+            })
+            .collect()
+    }
+
+    fn pair_of(&self, sample: u16) -> Result<(u8, u8), StegoError> {
+        u8::try_from(sample)
+            .ok()
+            .and_then(|index| Some((index, self.partner(index)?)))
+            .ok_or_else(|| StegoError::MalformedHeader(format!("palette.rs: index {sample} has no partner to carry a bit")))
+    }
+}
+
+fn premultiplied([red, green, blue]: [u8; 3], alpha: u8) -> [u32; 4] {
+    let scale = |value: u8| (value as u32 * alpha as u32 + 127) / 255;
+    [scale(red), scale(green), scale(blue), alpha as u32]
+}
+
+fn distance(first: [u32; 4], second: [u32; 4]) -> u32 {
+    (0..4).map(|channel| CHANNEL_WEIGHTS[channel] * first[channel].abs_diff(second[channel]).pow(2)).sum()
+}
+
+impl PixelEmbedding for PalettePairs {
+    fn bits_per_pixel(&self, _layout: ChannelLayout) -> u64 {
+        1
+    }
+
+    fn embed_bit(&self, samples: &mut [u16], _layout: ChannelLayout, _slot: usize, bit: bool) -> Result<(), StegoError> {
+        let (index, partner) = self.pair_of(samples[0])?;
+        if (index > partner) != bit {
+            samples[0] = partner as u16;
+        }
+        Ok(())
+    }
+
+    fn extract_bit(&self, samples: &[u16], _layout: ChannelLayout, _slot: usize) -> Result<bool, StegoError> {
+        let (index, partner) = self.pair_of(samples[0])?;
+        Ok(index > partner)
+    }
+}
+
+/*
+   Gives the most used entries that have no partner an exact copy to pair with, while the palette has
+   fewer than max_entries entries. A copy has the same colour and alpha, so a pixel moved to it looks
+   exactly like it did. Returns how many entries were added.
+*/
+pub fn add_palette_twins(image: &mut Image, max_entries: usize) -> usize {
+    let pairs = PalettePairs::new(&image.palette, &image.palette_alpha);
+    let mut uses = vec![0u64; image.palette.len()];
+    for &sample in &image.samples {
+        if let Some(count) = uses.get_mut(sample as usize) {
+            *count += 1;
+        }
+    }
+
+    let mut lonely: Vec<usize> = (0..image.palette.len())
+        .filter(|&index| uses[index] > 0 && pairs.partner(index as u8).is_none())
+        .collect();
+    lonely.sort_by_key(|&index| (std::cmp::Reverse(uses[index]), index));
+
+    let max_entries = max_entries.min(256);
+    let mut added = 0;
+    for index in lonely {
+        if image.palette.len() >= max_entries {
+            break;
+        }
+        let alpha = image.palette_alpha.get(index).copied().unwrap_or(255);
+        if alpha != 255 {
+            image.palette_alpha.resize(image.palette.len(), 255);
+            image.palette_alpha.push(alpha);
+        }
+        image.palette.push(image.palette[index]);
+        added += 1;
+    }
+    added
+}
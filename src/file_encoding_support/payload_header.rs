@@ -0,0 +1,245 @@
+/*
+ * Copyright (C) 2025 Dustyn Gibb
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License
+ * as published by the Free Software Foundation; either version 2
+ * of the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
+ */
+use crate::file_encoding_support::file_encoding_support::{FileEncoding, FileEncodingMethod, WaveFunction};
+use crate::file_encoding_support::stego_error::StegoError;
+use crate::mathematics_support::mathematics_support::{crc32, crc32_update};
+
+/*
+   Every payload is embedded with this header in front of it, in the same pixel stream and with the
+   same encoding as the payload itself. That way nothing about the payload lives in the file headers
+   and extraction can work out how much to read on its own.
+
+   Layout (little endian):
+       0..4    magic "MAYA"
+       4       format version
+       5       encoding
+       6       encoding method
+       7       encoding parameter, the number of bits for LowBits, 0 for everything else
+       8..16   payload length in bytes
+       16..20  CRC32 over bytes 0..16 followed by the payload
+*/
+pub const PAYLOAD_MAGIC: [u8; 4] = *b"MAYA";
+pub const PAYLOAD_HEADER_VERSION: u8 = 1;
+pub const PAYLOAD_HEADER_SIZE: usize = 20;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PayloadHeader {
+    pub version: u8,
+    pub encoding: FileEncoding,
+    pub encoding_method: FileEncodingMethod,
+    pub payload_length: u64,
+    pub crc32: u32,
+}
+
+impl PayloadHeader {
+    pub fn new(payload: &[u8], encoding: FileEncoding, encoding_method: FileEncodingMethod) -> Self {
+        let mut header = PayloadHeader {
+            version: PAYLOAD_HEADER_VERSION,
+            encoding,
+            encoding_method,
+            payload_length: payload.len() as u64,
+            crc32: 0,
+        };
+        header.crc32 = header.checksum(payload);
+        header
+    }
+
+    pub fn to_bytes(self) -> [u8; PAYLOAD_HEADER_SIZE] {
+        let mut bytes = [0u8; PAYLOAD_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&PAYLOAD_MAGIC);
+        bytes[4] = self.version;
+        bytes[5] = encoding_id(self.encoding);
+        bytes[6] = encoding_method_id(self.encoding_method);
+        bytes[7] = encoding_parameter(self.encoding);
+        bytes[8..16].copy_from_slice(&self.payload_length.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.crc32.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StegoError> {
+        if bytes.len() < PAYLOAD_HEADER_SIZE || bytes[0..4] != PAYLOAD_MAGIC {
+            return Err(StegoError::IntegrityFailure(
+                "payload_header.rs: no payload header found, wrong encoding or method, or nothing embedded".to_string(),
+            ));
+        }
+
+        if bytes[4] != PAYLOAD_HEADER_VERSION {
+            return Err(StegoError::UnsupportedFormat(format!(
+                "payload_header.rs: payload header version {} is not supported",
+                bytes[4]
+            )));
+        }
+
+        Ok(PayloadHeader {
+            version: bytes[4],
+            encoding: encoding_from_id(bytes[5], bytes[7])?,
+            encoding_method: encoding_method_from_id(bytes[6])?,
+            payload_length: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            crc32: u32::from_le_bytes(bytes[16..20].try_into().unwrap()),
+        })
+    }
+
+    /*
+        Saturates so that a corrupted length just reads as "too large" instead of overflowing
+     */
+    pub fn total_bits(&self) -> u64 {
+        (PAYLOAD_HEADER_SIZE as u64)
+            .saturating_add(self.payload_length)
+            .saturating_mul(8)
+    }
+
+    fn checksum(&self, payload: &[u8]) -> u32 {
+        let bytes = self.to_bytes();
+        crc32_update(crc32(&bytes[0..16]), payload)
+    }
+}
+
+/*
+   Returns the header followed by the payload, this is what actually gets embedded
+*/
+pub fn frame_payload(payload: &[u8], encoding: FileEncoding, encoding_method: FileEncodingMethod) -> Vec<u8> {
+    let header = PayloadHeader::new(payload, encoding, encoding_method);
+    let mut framed = Vec::with_capacity(PAYLOAD_HEADER_SIZE + payload.len());
+    framed.extend_from_slice(&header.to_bytes());
+    framed.extend_from_slice(payload);
+    framed
+}
+
+/*
+   extract is called with a number of bits and must return at least that many bits worth of bytes
+   read from the start of the embedded stream. It is called once for the header and once more for
+   header + payload now that the length is known.
+*/
+pub fn unframe_payload<F>(mut extract: F, encoding: FileEncoding, encoding_method: FileEncodingMethod) -> Result<Vec<u8>, StegoError>
+where
+    F: FnMut(u64) -> Result<Vec<u8>, StegoError>,
+{
+    let header_bytes = extract(PAYLOAD_HEADER_SIZE as u64 * 8)?;
+    let header = PayloadHeader::from_bytes(&header_bytes)?;
+
+    if header.encoding != encoding || header.encoding_method != encoding_method {
+        return Err(StegoError::IntegrityFailure(format!(
+            "payload_header.rs: payload was embedded with {:?} {:?} but extraction asked for {:?} {:?}",
+            header.encoding, header.encoding_method, encoding, encoding_method
+        )));
+    }
+
+    let framed = match extract(header.total_bits()) {
+        Ok(framed) => framed,
+        Err(StegoError::InsufficientCapacity { .. }) => {
+            return Err(StegoError::IntegrityFailure(format!(
+                "payload_header.rs: payload length {} is larger than the image can hold",
+                header.payload_length
+            )));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let payload = framed[PAYLOAD_HEADER_SIZE..PAYLOAD_HEADER_SIZE + header.payload_length as usize].to_vec();
+
+    if header.checksum(&payload) != header.crc32 {
+        return Err(StegoError::IntegrityFailure(
+            "payload_header.rs: CRC32 mismatch, the payload is corrupted".to_string(),
+        ));
+    }
+
+    Ok(payload)
+}
+
+/*
+   The ids are part of the on-disk format, never renumber these, only append
+*/
+fn encoding_id(encoding: FileEncoding) -> u8 {
+    match encoding {
+        FileEncoding::Lsb => 0,
+        FileEncoding::PixelValueDifferencing => 1,
+        FileEncoding::HammingMatrix => 2,
+        FileEncoding::ColorParity => 3,
+        FileEncoding::Slack => 4,
+        FileEncoding::SlackLsb => 5,
+        FileEncoding::Chunk => 6,
+        FileEncoding::LowBits(_) => 7,
+    }
+}
+
+fn encoding_parameter(encoding: FileEncoding) -> u8 {
+    match encoding {
+        FileEncoding::LowBits(bits) => bits,
+        _ => 0,
+    }
+}
+
+fn encoding_from_id(id: u8, parameter: u8) -> Result<FileEncoding, StegoError> {
+    match id {
+        0 => Ok(FileEncoding::Lsb),
+        1 => Ok(FileEncoding::PixelValueDifferencing),
+        2 => Ok(FileEncoding::HammingMatrix),
+        3 => Ok(FileEncoding::ColorParity),
+        4 => Ok(FileEncoding::Slack),
+        5 => Ok(FileEncoding::SlackLsb),
+        6 => Ok(FileEncoding::Chunk),
+        7 if (1..=8).contains(&parameter) => Ok(FileEncoding::LowBits(parameter)),
+        7 => Err(StegoError::IntegrityFailure(format!(
+            "payload_header.rs: LowBits with {parameter} bits per sample is not a valid encoding"
+        ))),
+        _ => Err(StegoError::IntegrityFailure(format!(
+            "payload_header.rs: unknown encoding id {id}"
+        ))),
+    }
+}
+
+fn encoding_method_id(encoding_method: FileEncodingMethod) -> u8 {
+    match encoding_method {
+        FileEncodingMethod::LeftToRight => 0,
+        FileEncodingMethod::RightToLeft => 1,
+        FileEncodingMethod::TopToBottom => 2,
+        FileEncodingMethod::SinWave => 3,
+        FileEncodingMethod::CosWave => 4,
+        FileEncodingMethod::PolynomialFunction => 5,
+        FileEncodingMethod::FractalFunction => 6,
+        FileEncodingMethod::Wave(WaveFunction::Horizontal) => 7,
+        FileEncodingMethod::Wave(WaveFunction::Vertical) => 8,
+        FileEncodingMethod::Wave(WaveFunction::DiagonalRight) => 9,
+        FileEncodingMethod::Wave(WaveFunction::DiagonalLeft) => 10,
+        FileEncodingMethod::Wave(WaveFunction::ZigZagHorizontal) => 11,
+        FileEncodingMethod::Wave(WaveFunction::ZigZagVertical) => 12,
+        FileEncodingMethod::Wave(WaveFunction::Sinusoidal) => 13,
+    }
+}
+
+fn encoding_method_from_id(id: u8) -> Result<FileEncodingMethod, StegoError> {
+    match id {
+        0 => Ok(FileEncodingMethod::LeftToRight),
+        1 => Ok(FileEncodingMethod::RightToLeft),
+        2 => Ok(FileEncodingMethod::TopToBottom),
+        3 => Ok(FileEncodingMethod::SinWave),
+        4 => Ok(FileEncodingMethod::CosWave),
+        5 => Ok(FileEncodingMethod::PolynomialFunction),
+        6 => Ok(FileEncodingMethod::FractalFunction),
+        7 => Ok(FileEncodingMethod::Wave(WaveFunction::Horizontal)),
+        8 => Ok(FileEncodingMethod::Wave(WaveFunction::Vertical)),
+        9 => Ok(FileEncodingMethod::Wave(WaveFunction::DiagonalRight)),
+        10 => Ok(FileEncodingMethod::Wave(WaveFunction::DiagonalLeft)),
+        11 => Ok(FileEncodingMethod::Wave(WaveFunction::ZigZagHorizontal)),
+        12 => Ok(FileEncodingMethod::Wave(WaveFunction::ZigZagVertical)),
+        13 => Ok(FileEncodingMethod::Wave(WaveFunction::Sinusoidal)),
+        _ => Err(StegoError::IntegrityFailure(format!(
+            "payload_header.rs: unknown encoding method id {id}"
+        ))),
+    }
+}
@@ -15,10 +15,50 @@
  * along with this program; if not, write to the Free Software
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
  */
-use crate::file_encoding_support::file_encoding_support::WaveFunction;
-use std::ops::{AddAssign, BitOrAssign, BitXor, BitXorAssign, Sub, SubAssign};
+use crate::file_encoding_support::image::{ChannelLayout, Image};
+use crate::file_encoding_support::pixel_grid::{IndexedPixelGrid, PixelGrid};
+use crate::file_encoding_support::stego_error::StegoError;
 
 pub trait Pixel {
+    // Number of bytes one pixel takes up in the pixel buffer
+    const SIZE: usize;
+
+    /*
+       Whatever has to be known at runtime to decode a pixel, for example the channel masks of a
+       BI_BITFIELDS bitmap. Pixels whose layout is fixed by the type use ()
+    */
+    type Format: Copy;
+
+    /*
+       Decode a pixel from exactly SIZE bytes of the pixel buffer and write it back again,
+       PixelGrid uses these instead of casting the buffer
+    */
+    fn from_bytes(bytes: &[u8], format: Self::Format) -> Self;
+    fn write_bytes(&self, bytes: &mut [u8]);
+
+    /*
+       How many of first, second, third and fourth are real channels in this format
+    */
+    fn channel_count(format: Self::Format) -> usize;
+
+    /*
+       The Image layout the channels decode to, colour unless a pixel says otherwise
+    */
+    fn layout(format: Self::Format) -> Option<ChannelLayout> {
+        ChannelLayout::from_channels(Self::channel_count(format), true)
+    }
+
+    /*
+       Significant bits of every channel in Image order, red, green, blue and then alpha if there is one
+    */
+    fn bit_depths(format: Self::Format) -> Vec<u8>;
+
+    /*
+       Channel values in Image order at their full bit depth, this is what a decoded Image holds
+    */
+    fn sample(&self, channel: usize) -> u16;
+    fn set_sample(&mut self, channel: usize, value: u16);
+
     fn red(&self) -> u8;
     fn green(&self) -> u8;
     fn blue(&self) -> u8;
@@ -45,17 +85,17 @@ pub trait Pixel {
     fn set_second(&mut self, value: u8);
     fn set_third(&mut self, value: u8);
 
-    // This should do nothing or exit / panic on invocation from a 3 byte pixel
-    fn set_fourth(&mut self, value: u8);
+    // This should return an error on invocation from a 3 byte pixel
+    fn set_fourth(&mut self, value: u8) -> Result<(), StegoError>;
     /*
-       This should do nothing on a 3 byte pixel impl
+       This should return an error on a 3 byte pixel impl
     */
-    fn set_alpha(&mut self, value: u8);
+    fn set_alpha(&mut self, value: u8) -> Result<(), StegoError>;
 
     fn pixel_size(&self) -> usize;
 }
 
-pub fn transform_pixels<P, F>(pixel_map: &mut Vec<P>, transform_function: F)
+pub fn transform_pixels<P, F>(pixel_map: &mut [P], transform_function: F)
 where
     P: Pixel,
     F: Fn(&mut P),
@@ -66,7 +106,7 @@ where
 }
 
 pub fn transform_pixel_quadrants<P, F>(
-    pixel_map: &mut Vec<P>,
+    pixel_map: &mut [P],
     transform_function: F,
     coordinates: (u64, u64),
     quadrant_size: u64,
@@ -74,7 +114,7 @@ pub fn transform_pixel_quadrants<P, F>(
     P: Pixel + Sized,
     F: Fn(&mut [P]),
 {
-    let start_index = (coordinates.0 as usize * coordinates.1 as usize) as usize;
+    let start_index = coordinates.0 as usize * coordinates.1 as usize;
     let end_index = start_index + quadrant_size as usize;
 
     let quadrant_slice = &mut pixel_map[start_index..end_index];
@@ -82,676 +122,90 @@ pub fn transform_pixel_quadrants<P, F>(
     transform_function(quadrant_slice);
 }
 
-pub fn increment_bit_and_byte_counters(bit: &mut u32, byte: &mut u32) {
-    *bit += 1;
-    if *bit == 8 {
-        *byte += 1;
-        *bit = 0;
-    }
-}
-
-fn embed_pixel_lsb<P: Pixel>(
-    pixel: &mut P,
-    current_bit: &mut u32,
-    current_byte: &mut u32,
-    data: &Vec<u8>,
-    bits_to_embed: &mut usize,
-) {
-    let mut bit: u8 = data[*current_byte as usize] & (1 << *current_bit);
-
-    if bit == 0 {
-        pixel.set_first(pixel.first() & !1);
-    } else {
-        pixel.set_first(pixel.first() | 1);
-    }
-
-    increment_bit_and_byte_counters(current_bit, current_byte);
-    bits_to_embed.sub_assign(1);
-
-    if *bits_to_embed == 0 {
-        return;
-    }
-
-    bit = data[*current_byte as usize] & (1 << *current_bit);
-
-    if bit == 0 {
-        pixel.set_second(pixel.second() & !1);
-    } else {
-        pixel.set_second(pixel.second() | 1);
-    }
-
-    increment_bit_and_byte_counters(current_bit, current_byte);
-    bits_to_embed.sub_assign(1);
-
-    if *bits_to_embed == 0 {
-        return;
-    }
-
-    bit = data[*current_byte as usize] & (1 << *current_bit);
-
-    if bit == 0 {
-        pixel.set_third(pixel.third() & !1);
-    } else {
-        pixel.set_third(pixel.third() | 1);
-    }
-    increment_bit_and_byte_counters(current_bit, current_byte);
-    bits_to_embed.sub_assign(1);
-
-    if *bits_to_embed == 0 {
-        return;
-    }
-
-    if pixel.pixel_size() == 4 {
-        bit = data[*current_byte as usize] & (1 << *current_bit);
-        if bit == 0 {
-            pixel.set_fourth(pixel.fourth() & !1);
-        } else {
-            pixel.set_fourth(pixel.fourth() | 1);
-        }
-
-        increment_bit_and_byte_counters(current_bit, current_byte);
-        bits_to_embed.sub_assign(1);
-    }
-}
-
-fn extract_pixel_lsb<P: Pixel>(
-    pixel: &P,
-    bits: &mut u32,
-    bytes: &mut u32,
-    extracted_data: &mut Vec<u8>,
-    embedded_bits: usize,
-) {
-    let mut current_bit = pixel.first() & 1;
-
-    if current_bit == 0 {
-        extracted_data[*bytes as usize] &= !(1 << *bits);
-    } else {
-        extracted_data[*bytes as usize] |= 1 << *bits;
-    }
-
-    increment_bit_and_byte_counters(bits, bytes);
-
-    if *bits + (*bytes * 8) == embedded_bits as u32 {
-        return;
-    }
-
-    current_bit = pixel.second() & 1;
-
-    if current_bit == 0 {
-        extracted_data[*bytes as usize] &= !(1 << *bits);
-    } else {
-        extracted_data[*bytes as usize] |= 1 << *bits;
-    }
-
-    increment_bit_and_byte_counters(bits, bytes);
-
-    if *bits + (*bytes * 8) == embedded_bits as u32 {
-        return;
-    }
-
-    current_bit = pixel.third() & 1;
-
-    if current_bit == 0 {
-        extracted_data[*bytes as usize] &= !(1 << *bits);
-    } else {
-        extracted_data[*bytes as usize] |= 1 << *bits;
-    }
-
-    increment_bit_and_byte_counters(&mut *bits, &mut *bytes);
-
-    if *bits + (*bytes * 8) == embedded_bits as u32 {
-        return;
-    }
-
-    if pixel.pixel_size() == 4 {
-        current_bit = pixel.fourth() & 1;
-
-        if current_bit == 0 {
-            extracted_data[*bytes as usize] &= !(1 << *bits);
-        } else {
-            extracted_data[*bytes as usize] |= 1 << *bits;
-        }
-
-        increment_bit_and_byte_counters(bits, bytes);
-
-        if *bits + (*bytes * 8) == embedded_bits as u32 {
-            return;
-        }
-    }
-}
-
-fn embed_pixel_color<P: Pixel>(
-    pixel: &mut P,
-    current_bit: &mut u32,
-    current_byte: &mut u32,
-    data: &Vec<u8>,
-    bits_to_embed: &mut usize,
-) {
-    let bit = data[*current_byte as usize] & (1 << *current_bit);
-    let mut ones = 0;
-
-    let first = pixel.first();
-    let second = pixel.second();
-    let third = pixel.third();
-    let fourth = match pixel.pixel_size() {
-        3 => 0,
-        4 => pixel.fourth(),
-        _ => unreachable!(),
-    };
-
-    let mut changed =false;
-
-    for i in 0..8 {
-        if (first & (1 << i)) == 0 {
-            ones += 1;
-        }
-        if (second & (1 << i)) == 0 {
-            ones += 1;
-        }
-        if (third & (1 << i)) == 0 {
-            ones += 1;
-        }
-
-        if (pixel.pixel_size() ==4 && (fourth & (1 << i)) == 0) {
-            ones += 1;
-        }
-    }
-
-    if ((bit == 0 && (ones % 2 == 0)) || (bit != 0 && (ones % 2 != 0))) {
-        for i in 0..8 {
-            if (first & (1 << i)) == 0 {
-                pixel.set_first(first | (1 << i));
-                changed = true;
-                break;
-            }
-
-            if (second & (1 << i)) == 0 {
-                pixel.set_second(second | (1 << i));
-                changed = true;
-                break;
-            }
-
-            if (third & (1 << i)) == 0 {
-                pixel.set_third(third | (1 << i));
-                changed = true;
-                break;
-            }
-
-            if (pixel.pixel_size() == 4 && (fourth & (1 << i)) == 0) {
-                pixel.set_fourth(fourth | (1 << i));
-                changed = true;
-                break;
-            }
-        }
-
-        if(!changed){
-            for i in 0..8 {
-                if (first & (1 << i)) != 0 {
-                    pixel.set_first(first & !(1 << i));
-                    break;
-                }
-                if (second & (1 << i)) != 0 {
-                    pixel.set_second(second & !(1 << i));
-                    break;
-                }
-                if (third & (1 << i)) != 0 {
-                    pixel.set_third(third & !(1 << i));
-                    break;
-                }
-
-                if (pixel.pixel_size() == 4 && (fourth & (1 << i)) != 0) {
-                    pixel.set_fourth(fourth & !(1 << i));
-                    break;
-                }
-            }
-        }
-    }
-
-
-    increment_bit_and_byte_counters(current_bit, current_byte);
-    bits_to_embed.sub_assign(1);
-}
-
-fn extract_pixel_color<P: Pixel>(
-    pixel: &P,
-    bits: &mut u32,
-    bytes: &mut u32,
-    extracted_data: &mut Vec<u8>,
-    embedded_bits: usize,
-) {
-    let mut ones = 0;
-
-    let first = pixel.first();
-    let second = pixel.second();
-    let third = pixel.third();
-    let fourth = match pixel.pixel_size() {
-        3 => 0,
-        4 => pixel.fourth(),
-        _ => unreachable!(),
-    };
-
-    for i in 0..8 {
-        if (first & (1 << i)) != 0 {
-            ones += 1;
-        }
-        if (second & (1 << i)) != 0 {
-            ones += 1;
-        }
-        if (third & (1 << i)) != 0 {
-            ones += 1;
-        }
-        if (pixel.pixel_size() == 4 && (fourth & (1 << i)) != 0) {
-            ones += 1;
-        }
-    }
-    let mut current_bit = false;
-
-    if (ones % 2 == 0) {
-        current_bit = true;
-    }
-
-    if current_bit {
-        extracted_data[*bytes as usize] |= 1 << *bits;
-    } else {
-        extracted_data[*bytes as usize] &= !(1 << *bits);
-    }
-
-    increment_bit_and_byte_counters(bits, bytes);
-
-
-}
-
-pub fn embed_color_data_left_right<P: Pixel>(
-    data: &Vec<u8>,
-    pixel_map: &mut [u8],
-    width: u64,
-    length: u64,
-    padding: u64,
-    pixel_size_bytes: u64,
-) {
-    let total_length = (width + padding) * length;
-
-    let mut bits_to_embed = data.len() * 8;
-
-    let mut current_byte: u32 = 0;
-    let mut current_bit: u32 = 0;
-
-    if bits_to_embed > (width * length) as usize {
-        panic!(
-            "Not enough space in the image to embed {bits_to_embed} bits! Only have {} bits available!",
-            width * length * pixel_size_bytes
-        )
-    }
-
-    for row in 0..length as usize {
-        let start = (width + padding) * pixel_size_bytes * row as u64;
-        let end = start + (width * pixel_size_bytes);
-        let row_start_ptr = unsafe { pixel_map.as_mut_ptr().add(start as usize) };
-        let row_pixels_ptr = row_start_ptr as *mut P;
-        let row_pixels: &mut [P] =
-            unsafe { std::slice::from_raw_parts_mut(row_pixels_ptr, width as usize) };
-
-        for pixel in row_pixels.iter_mut() {
-            embed_pixel_color(
-                pixel,
-                &mut current_bit,
-                &mut current_byte,
-                &data,
-                &mut bits_to_embed,
-            );
-
-            if bits_to_embed == 0 {
-                return;
-            }
-        }
-    }
-}
-
-pub fn extract_color_data_left_right<P: Pixel>(
-    pixel_map: &mut [u8],
-    width: u64,
-    length: u64,
-    padding: u64,
-    pixel_size_bytes: u64,
-    embedded_bits: u64,
-) -> Vec<u8> {
-    let total_length = (width + padding) * length;
-
-    let mut bytes: u32 = 0;
-    let mut bits: u32 = 0;
-
-    //The plus one is just in case we have a sub byte number of bits , we would lose those few bits or write into an invalid offset
-    let mut extracted_data: Vec<u8> = vec![0u8; ((embedded_bits as usize / 8) + 1) as usize];
-
-    for row in 0..length as usize {
-        let start = (width + padding) * pixel_size_bytes * row as u64;
-        let end = start + (width * pixel_size_bytes);
-        let row_start_ptr = unsafe { pixel_map.as_mut_ptr().add(start as usize) };
-        let row_pixels_ptr = row_start_ptr as *mut P;
-        let row_pixels: &[P] =
-            unsafe { std::slice::from_raw_parts(row_pixels_ptr, width as usize) };
-
-        let mut current_bit: u8 = 0;
-
-        for pixel in row_pixels.iter() {
-            extract_pixel_color(
-                pixel,
-                &mut bits,
-                &mut bytes,
-                &mut extracted_data,
-                embedded_bits as usize,
-            );
-
-            if bits + (bytes * 8) == embedded_bits as u32 {
-                break;
+/*
+   Decoders and encoders of raw pixel rows, formats use these to turn their pixel data into an Image
+   and to write the Image back once it has been embedded into
+*/
+pub fn image_from_grid<P: Pixel>(grid: &PixelGrid<P>) -> Result<Image, StegoError> {
+    let channels = P::channel_count(grid.format());
+    let layout = P::layout(grid.format()).ok_or_else(|| {
+        StegoError::UnsupportedFormat(format!("pixel.rs: {channels} channel pixels have no image layout"))
+    })?;
+
+    let mut image = Image::new(grid.width(), grid.height(), layout, P::bit_depths(grid.format()))?;
+    for (row, pixels) in grid.rows().enumerate() {
+        for (col, pixel) in pixels.enumerate() {
+            let samples = image.pixel_mut(row, col).expect("the image has the size of the grid");
+            for (channel, sample) in samples.iter_mut().enumerate() {
+                *sample = pixel.sample(channel);
             }
         }
-
-        if bits + (bytes * 8) == embedded_bits as u32 {
-            break;
-        }
     }
 
-    extracted_data
+    Ok(image)
 }
 
-pub fn embed_color_data_right_left<P: Pixel>(
-    data: &Vec<u8>,
-    pixel_map: &mut [u8],
-    width: u64,
-    length: u64,
-    padding: u64,
-    pixel_size_bytes: u64,
-) {
-    let total_length = (width + padding) * length;
-
-    let mut bits_to_embed = data.len() * 8;
-
-    let mut current_byte: u32 = 0;
-    let mut current_bit: u32 = 0;
-
-    if bits_to_embed > (width * length) as usize {
-        panic!(
-            "Not enough space in the image to embed {bits_to_embed} bits! Only have {} bits available!",
-            width * length * pixel_size_bytes
-        )
-    }
+pub fn write_image_to_grid<P: Pixel>(image: &Image, grid: &mut PixelGrid<P>) -> Result<(), StegoError> {
+    check_image_size(image, grid.width(), grid.height(), P::channel_count(grid.format()))?;
 
-    for row in (0..length).rev() {
-        let start = (width + padding) * pixel_size_bytes * row as u64;
-        let end = start + (width * pixel_size_bytes);
-        let row_start_ptr = unsafe { pixel_map.as_mut_ptr().add(start as usize) };
-        let row_pixels_ptr = row_start_ptr as *mut P;
-        let row_pixels: &mut [P] =
-            unsafe { std::slice::from_raw_parts_mut(row_pixels_ptr, width as usize) };
-
-        for pixel in row_pixels.iter_mut().rev() {
-            embed_pixel_color(
-                pixel,
-                &mut current_bit,
-                &mut current_byte,
-                &data,
-                &mut bits_to_embed,
-            );
-
-            if bits_to_embed == 0 {
-                return;
+    for row in 0..image.height {
+        for col in 0..image.width {
+            let samples = image.pixel(row, col).expect("the image has the size of the grid");
+            let mut pixel = grid.get_mut(row, col).expect("the grid has the size of the image");
+            for (channel, &sample) in samples.iter().enumerate() {
+                pixel.set_sample(channel, sample);
             }
         }
     }
-}
-
-pub fn extract_color_data_right_left<P: Pixel>(
-    pixel_map: &mut [u8],
-    width: u64,
-    length: u64,
-    padding: u64,
-    pixel_size_bytes: u64,
-    embedded_bits: u64,
-) -> Vec<u8> {
-    let total_length = (width + padding) * length;
-
-    let mut bytes: u32 = 0;
-    let mut bits: u32 = 0;
-
-    //The plus one is just in case we have a sub byte number of bits , we would lose those few bits or write into an invalid offset
-    let mut extracted_data: Vec<u8> = vec![0u8; ((embedded_bits as usize / 8) + 1) as usize];
-
-    for row in (0..length).rev() {
-        let start = (width + padding) * pixel_size_bytes * row as u64;
-        let end = start + (width * pixel_size_bytes);
-        let row_start_ptr = unsafe { pixel_map.as_mut_ptr().add(start as usize) };
-        let row_pixels_ptr = row_start_ptr as *mut P;
-        let row_pixels: &[P] =
-            unsafe { std::slice::from_raw_parts(row_pixels_ptr, width as usize) };
-
-        let mut current_bit: u8 = 0;
-
-        for pixel in row_pixels.iter().rev() {
-            extract_pixel_color(
-                pixel,
-                &mut bits,
-                &mut bytes,
-                &mut extracted_data,
-                embedded_bits as usize,
-            );
-
-            if bits + (bytes * 8) == embedded_bits as u32 {
-                break;
-            }
-        }
-
-        if bits + (bytes * 8) == embedded_bits as u32 {
-            break;
-        }
-    }
 
-    extracted_data
+    Ok(())
 }
-pub fn embed_lsb_data_left_right<P: Pixel>(
-    data: &Vec<u8>,
-    pixel_map: &mut [u8],
-    width: u64,
-    length: u64,
-    padding: u64,
-    pixel_size_bytes: u64,
-) {
-    let total_length = (width + padding) * length;
-
-    let mut bits_to_embed = data.len() * 8;
-
-    let mut current_byte: u32 = 0;
-    let mut current_bit: u32 = 0;
-
-    if bits_to_embed > (width * length * pixel_size_bytes) as usize {
-        panic!(
-            "Not enough space in the image to embed {bits_to_embed} bits! Only have {} bits available!",
-            width * length * pixel_size_bytes
-        )
-    }
 
-    for row in 0..length as usize {
-        let start = (width + padding) * pixel_size_bytes * row as u64;
-        let end = start + (width * pixel_size_bytes);
-        let row_start_ptr = unsafe { pixel_map.as_mut_ptr().add(start as usize) };
-        let row_pixels_ptr = row_start_ptr as *mut P;
-        let row_pixels: &mut [P] =
-            unsafe { std::slice::from_raw_parts_mut(row_pixels_ptr, width as usize) };
-
-        for pixel in row_pixels.iter_mut() {
-            embed_pixel_lsb(
-                pixel,
-                &mut current_bit,
-                &mut current_byte,
-                &data,
-                &mut bits_to_embed,
-            );
-
-            if bits_to_embed == 0 {
-                return;
-            }
-        }
-    }
-}
-
-pub fn extract_lsb_data_left_right<P: Pixel>(
-    pixel_map: &mut [u8],
-    width: u64,
-    length: u64,
-    padding: u64,
-    pixel_size_bytes: u64,
-    embedded_bits: u64,
-) -> Vec<u8> {
-    let total_length = (width + padding) * length;
-
-    let mut bytes: u32 = 0;
-    let mut bits: u32 = 0;
-
-    //The plus one is just in case we have a sub byte number of bits , we would lose those few bits or write into an invalid offset
-    let mut extracted_data: Vec<u8> = vec![0u8; ((embedded_bits as usize / 8) + 1) as usize];
-
-    for row in 0..length as usize {
-        let start = (width + padding) * pixel_size_bytes * row as u64;
-        let end = start + (width * pixel_size_bytes);
-        let row_start_ptr = unsafe { pixel_map.as_mut_ptr().add(start as usize) };
-        let row_pixels_ptr = row_start_ptr as *mut P;
-        let row_pixels: &[P] =
-            unsafe { std::slice::from_raw_parts(row_pixels_ptr, width as usize) };
-
-        let mut current_bit: u8 = 0;
-
-        for pixel in row_pixels.iter() {
-            extract_pixel_lsb(
-                pixel,
-                &mut bits,
-                &mut bytes,
-                &mut extracted_data,
-                embedded_bits as usize,
-            );
-
-            if bits + (bytes * 8) == embedded_bits as u32 {
-                break;
-            }
-        }
+/*
+   Palette images, the palette is (red, green, blue)
+*/
+pub fn image_from_indices(grid: &IndexedPixelGrid, bits_per_index: u8, palette: &[[u8; 3]]) -> Result<Image, StegoError> {
+    let mut image = Image::new(grid.width(), grid.height(), ChannelLayout::Indexed, vec![bits_per_index])?;
+    image.palette = palette.to_vec();
 
-        if bits + (bytes * 8) == embedded_bits as u32 {
-            break;
+    for row in 0..grid.height() {
+        for col in 0..grid.width() {
+            let index = grid.get(row, col).expect("the image has the size of the grid");
+            image.pixel_mut(row, col).expect("the image has the size of the grid")[0] = index as u16;
         }
     }
 
-    extracted_data
+    Ok(image)
 }
 
-pub fn embed_lsb_data_right_left<P: Pixel>(
-    data: &Vec<u8>,
-    pixel_map: &mut [u8],
-    width: u64,
-    length: u64,
-    padding: u64,
-    pixel_size_bytes: u64,
-) {
-    let total_length = (width + padding) * length;
-
-    let mut bits_to_embed = data.len() * 8;
-
-    let mut current_byte: u32 = 0;
-    let mut current_bit: u32 = 0;
-
-    if bits_to_embed > (width * length * pixel_size_bytes) as usize {
-        panic!(
-            "Not enough space in the image to embed {bits_to_embed} bits! Only have {} bits available!",
-            width * length * pixel_size_bytes
-        )
+pub fn write_image_to_indices(image: &Image, grid: &mut IndexedPixelGrid) -> Result<(), StegoError> {
+    check_image_size(image, grid.width(), grid.height(), 1)?;
+    if image.layout != ChannelLayout::Indexed {
+        return Err(StegoError::UnsupportedFormat(format!(
+            "pixel.rs: a {:?} image can not be written as palette indices",
+            image.layout
+        )));
     }
 
-    for row in (0..length).rev() {
-        let start = (width + padding) * pixel_size_bytes * row as u64;
-        let end = start + (width * pixel_size_bytes);
-        let row_start_ptr = unsafe { pixel_map.as_mut_ptr().add(start as usize) };
-        let row_pixels_ptr = row_start_ptr as *mut P;
-        let row_pixels: &mut [P] =
-            unsafe { std::slice::from_raw_parts_mut(row_pixels_ptr, width as usize) };
-
-        for pixel in row_pixels.iter_mut().rev() {
-            embed_pixel_lsb(
-                pixel,
-                &mut current_bit,
-                &mut current_byte,
-                &data,
-                &mut bits_to_embed,
-            );
-
-            if bits_to_embed == 0 {
-                return;
-            }
-        }
-    }
-}
-
-pub fn extract_lsb_data_right_left<P: Pixel>(
-    pixel_map: &mut [u8],
-    width: u64,
-    length: u64,
-    padding: u64,
-    pixel_size_bytes: u64,
-    embedded_bits: u64,
-) -> Vec<u8> {
-    let total_length = (width + padding) * length;
-
-    let mut bytes: u32 = 0;
-    let mut bits: u32 = 0;
-
-    //The plus one is just in case we have a sub byte number of bits , we would lose those few bits or write into an invalid offset
-    let mut extracted_data: Vec<u8> = vec![0u8; ((embedded_bits as usize / 8) + 1) as usize];
-
-    for row in (0..length).rev() {
-        let start = (width + padding) * pixel_size_bytes * row as u64;
-        let end = start + (width * pixel_size_bytes);
-        let row_start_ptr = unsafe { pixel_map.as_mut_ptr().add(start as usize) };
-        let row_pixels_ptr = row_start_ptr as *mut P;
-        let row_pixels: &[P] =
-            unsafe { std::slice::from_raw_parts(row_pixels_ptr, width as usize) };
-
-        for pixel in row_pixels.iter().rev() {
-            extract_pixel_lsb(
-                pixel,
-                &mut bits,
-                &mut bytes,
-                &mut extracted_data,
-                embedded_bits as usize,
-            );
-            if bits + (bytes * 8) == embedded_bits as u32 {
-                break;
-            }
-        }
-
-        if bits + (bytes * 8) == embedded_bits as u32 {
-            break;
+    for row in 0..image.height {
+        for col in 0..image.width {
+            let index = image.pixel(row, col).expect("the image has the size of the grid")[0];
+            let index = u8::try_from(index).map_err(|_| {
+                StegoError::MalformedHeader(format!("pixel.rs: palette index {index} does not fit in a byte"))
+            })?;
+            grid.set(row, col, index)?;
         }
     }
 
-    extracted_data
+    Ok(())
 }
 
-pub fn embed_lsb_wave_function_left_right<P: Pixel>(
-    pixel_map: &mut [u8],
-    width: u64,
-    length: u64,
-    padding: u64,
-    pixel_size_bytes: u64,
-    embedded_bits: u64,
-    wave_function: WaveFunction,
-) {
-    let points = WaveFunction::traverse(&wave_function, width as usize, length as usize);
-
-    for point in points {
-        let x: u64 = point.0 as u64;
-        let y: u64 = point.1 as u64;
-
-        let offset = unsafe {
-            pixel_map
-                .as_mut_ptr()
-                .add((((width + padding) * x) + (y * length)) as usize)
-        };
+fn check_image_size(image: &Image, width: usize, height: usize, channels: usize) -> Result<(), StegoError> {
+    if image.width != width || image.height != height || image.channels() != channels {
+        return Err(StegoError::UnsupportedFormat(format!(
+            "pixel.rs: a {}x{} {:?} image does not fit a {width}x{height} carrier with {channels} channels",
+            image.width, image.height, image.layout
+        )));
     }
+    Ok(())
 }
@@ -0,0 +1,330 @@
+/*
+ * Copyright (C) 2025 Dustyn Gibb
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License
+ * as published by the Free Software Foundation; either version 2
+ * of the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
+ */
+use crate::file_encoding_support::pixel::Pixel;
+use crate::file_encoding_support::stego_error::StegoError;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+/*
+   How rows are laid out in the buffer. BMP is normally bottom up (the first row in the file is the
+   bottom of the image), most everything else is top down.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RowOrder {
+    BottomUp,
+    TopDown,
+}
+
+/*
+   A bounds checked view of a raw pixel buffer. Rows and columns are always in image coordinates,
+   row 0 is the top row no matter how the rows are stored. The buffer length is checked once against
+   stride * height when the grid is built and every access is checked against the grid dimensions.
+*/
+pub struct PixelGrid<'a, P: Pixel> {
+    data: &'a mut [u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    row_order: RowOrder,
+    format: P::Format,
+    _pixel: PhantomData<P>,
+}
+
+/*
+   Handed out by get_mut, the decoded pixel is written back into the buffer when this is dropped
+*/
+pub struct PixelMut<'a, P: Pixel> {
+    bytes: &'a mut [u8],
+    pixel: P,
+}
+
+impl<'a, P: Pixel<Format = ()>> PixelGrid<'a, P> {
+    /*
+        padding is the number of bytes after the last pixel of every row
+     */
+    pub fn new(
+        data: &'a mut [u8],
+        width: usize,
+        height: usize,
+        padding: usize,
+        row_order: RowOrder,
+    ) -> Result<Self, StegoError> {
+        Self::with_format(data, width, height, padding, row_order, ())
+    }
+}
+
+impl<'a, P: Pixel> PixelGrid<'a, P> {
+    /*
+        For pixels that need a runtime format to be decoded, every pixel of the grid shares it
+     */
+    pub fn with_format(
+        data: &'a mut [u8],
+        width: usize,
+        height: usize,
+        padding: usize,
+        row_order: RowOrder,
+        format: P::Format,
+    ) -> Result<Self, StegoError> {
+        let stride = width
+            .checked_mul(P::SIZE)
+            .and_then(|row| row.checked_add(padding))
+            .ok_or_else(|| StegoError::MalformedHeader(format!("pixel_grid.rs: {width} pixel wide rows overflow")))?;
+
+        let required = stride
+            .checked_mul(height)
+            .ok_or_else(|| StegoError::MalformedHeader(format!("pixel_grid.rs: {width}x{height} image overflows")))?;
+
+        if required > data.len() {
+            return Err(StegoError::MalformedHeader(format!(
+                "pixel_grid.rs: {width}x{height} image needs {required} bytes of pixel data but only {} are present",
+                data.len()
+            )));
+        }
+
+        Ok(PixelGrid {
+            data,
+            width,
+            height,
+            stride,
+            row_order,
+            format,
+            _pixel: PhantomData,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn row_order(&self) -> RowOrder {
+        self.row_order
+    }
+
+    pub fn format(&self) -> P::Format {
+        self.format
+    }
+
+    fn offset(&self, row: usize, col: usize) -> Option<usize> {
+        if row >= self.height || col >= self.width {
+            return None;
+        }
+
+        let stored_row = match self.row_order {
+            RowOrder::TopDown => row,
+            RowOrder::BottomUp => self.height - 1 - row,
+        };
+
+        Some(stored_row * self.stride + col * P::SIZE)
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<P> {
+        let offset = self.offset(row, col)?;
+        Some(P::from_bytes(&self.data[offset..offset + P::SIZE], self.format))
+    }
+
+    pub fn get_mut(&mut self, row: usize, col: usize) -> Option<PixelMut<'_, P>> {
+        let offset = self.offset(row, col)?;
+        let bytes = &mut self.data[offset..offset + P::SIZE];
+        let pixel = P::from_bytes(bytes, self.format);
+        Some(PixelMut { bytes, pixel })
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, pixel: &P) -> Result<(), StegoError> {
+        let offset = self.offset(row, col).ok_or_else(|| self.out_of_bounds(row, col))?;
+        pixel.write_bytes(&mut self.data[offset..offset + P::SIZE]);
+        Ok(())
+    }
+
+    pub fn out_of_bounds(&self, row: usize, col: usize) -> StegoError {
+        StegoError::MalformedHeader(format!(
+            "pixel_grid.rs: ({row}, {col}) is outside of the {}x{} image",
+            self.width, self.height
+        ))
+    }
+
+    /*
+        Pixels of one row, left to right
+     */
+    pub fn row(&self, row: usize) -> Option<impl DoubleEndedIterator<Item = P> + '_> {
+        if row >= self.height {
+            return None;
+        }
+        Some((0..self.width).map(move |col| self.get(row, col).unwrap()))
+    }
+
+    /*
+        Pixels of one column, top to bottom
+     */
+    pub fn column(&self, col: usize) -> Option<impl DoubleEndedIterator<Item = P> + '_> {
+        if col >= self.width {
+            return None;
+        }
+        Some((0..self.height).map(move |row| self.get(row, col).unwrap()))
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = impl DoubleEndedIterator<Item = P> + '_> + '_ {
+        (0..self.height).map(move |row| self.row(row).unwrap())
+    }
+
+    pub fn columns(&self) -> impl DoubleEndedIterator<Item = impl DoubleEndedIterator<Item = P> + '_> + '_ {
+        (0..self.width).map(move |col| self.column(col).unwrap())
+    }
+}
+
+impl<P: Pixel> Deref for PixelMut<'_, P> {
+    type Target = P;
+
+    fn deref(&self) -> &P {
+        &self.pixel
+    }
+}
+
+impl<P: Pixel> DerefMut for PixelMut<'_, P> {
+    fn deref_mut(&mut self) -> &mut P {
+        &mut self.pixel
+    }
+}
+
+impl<P: Pixel> Drop for PixelMut<'_, P> {
+    fn drop(&mut self) {
+        self.pixel.write_bytes(self.bytes);
+    }
+}
+
+/*
+   The same view for palette images, every pixel is a bits_per_index wide index into a palette.
+   Indices narrower than a byte are packed most significant bits first like BMP and PNG store them.
+*/
+pub struct IndexedPixelGrid<'a> {
+    data: &'a mut [u8],
+    width: usize,
+    height: usize,
+    bits_per_index: usize,
+    stride: usize,
+    row_order: RowOrder,
+}
+
+impl<'a> IndexedPixelGrid<'a> {
+    /*
+        padding is the number of bytes after the last (possibly partial) byte of indices in every row
+     */
+    pub fn new(
+        data: &'a mut [u8],
+        width: usize,
+        height: usize,
+        bits_per_index: usize,
+        padding: usize,
+        row_order: RowOrder,
+    ) -> Result<Self, StegoError> {
+        if !matches!(bits_per_index, 1 | 2 | 4 | 8) {
+            return Err(StegoError::UnsupportedFormat(format!(
+                "pixel_grid.rs: {bits_per_index} bit palette indices are not supported"
+            )));
+        }
+
+        let stride = width
+            .checked_mul(bits_per_index)
+            .map(|bits| bits.div_ceil(8))
+            .and_then(|row| row.checked_add(padding))
+            .ok_or_else(|| StegoError::MalformedHeader(format!("pixel_grid.rs: {width} pixel wide rows overflow")))?;
+
+        let required = stride
+            .checked_mul(height)
+            .ok_or_else(|| StegoError::MalformedHeader(format!("pixel_grid.rs: {width}x{height} image overflows")))?;
+
+        if required > data.len() {
+            return Err(StegoError::MalformedHeader(format!(
+                "pixel_grid.rs: {width}x{height} image needs {required} bytes of pixel data but only {} are present",
+                data.len()
+            )));
+        }
+
+        Ok(IndexedPixelGrid {
+            data,
+            width,
+            height,
+            bits_per_index,
+            stride,
+            row_order,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn row_order(&self) -> RowOrder {
+        self.row_order
+    }
+
+    /*
+        Byte offset and the shift of the index inside of that byte
+     */
+    fn position(&self, row: usize, col: usize) -> Option<(usize, usize)> {
+        if row >= self.height || col >= self.width {
+            return None;
+        }
+
+        let stored_row = match self.row_order {
+            RowOrder::TopDown => row,
+            RowOrder::BottomUp => self.height - 1 - row,
+        };
+
+        let bit = col * self.bits_per_index;
+        Some((stored_row * self.stride + bit / 8, 8 - self.bits_per_index - bit % 8))
+    }
+
+    fn mask(&self) -> u8 {
+        (0xFFu16 >> (8 - self.bits_per_index)) as u8
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<u8> {
+        let (offset, shift) = self.position(row, col)?;
+        Some((self.data[offset] >> shift) & self.mask())
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, index: u8) -> Result<(), StegoError> {
+        let (offset, shift) = self.position(row, col).ok_or_else(|| self.out_of_bounds(row, col))?;
+
+        if index > self.mask() {
+            return Err(StegoError::MalformedHeader(format!(
+                "pixel_grid.rs: palette index {index} does not fit in {} bits",
+                self.bits_per_index
+            )));
+        }
+
+        let mask = self.mask() << shift;
+        self.data[offset] = (self.data[offset] & !mask) | (index << shift);
+        Ok(())
+    }
+
+    pub fn out_of_bounds(&self, row: usize, col: usize) -> StegoError {
+        StegoError::MalformedHeader(format!(
+            "pixel_grid.rs: ({row}, {col}) is outside of the {}x{} image",
+            self.width, self.height
+        ))
+    }
+}
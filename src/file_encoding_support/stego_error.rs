@@ -0,0 +1,75 @@
+/*
+ * Copyright (C) 2025 Dustyn Gibb
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License
+ * as published by the Free Software Foundation; either version 2
+ * of the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
+ */
+use std::fmt;
+use std::io;
+
+/*
+   Every fallible operation in the library reports one of these instead of exiting,
+   so whoever embeds this code can decide what a failure means for them.
+*/
+#[derive(Debug)]
+pub enum StegoError {
+    Io(io::Error),
+    MalformedHeader(String),
+    UnsupportedFormat(String),
+    UnsupportedEncoding(String),
+    InsufficientCapacity { required_bits: u64, available_bits: u64 },
+    IntegrityFailure(String),
+    InvalidChannel(&'static str),
+    FileNotReady(&'static str),
+    Usage(String),
+}
+
+impl fmt::Display for StegoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StegoError::Io(e) => write!(f, "I/O error: {e}"),
+            StegoError::MalformedHeader(msg) => write!(f, "malformed header: {msg}"),
+            StegoError::UnsupportedFormat(msg) => write!(f, "unsupported format: {msg}"),
+            StegoError::UnsupportedEncoding(msg) => write!(f, "unsupported encoding: {msg}"),
+            StegoError::InsufficientCapacity {
+                required_bits,
+                available_bits,
+            } => write!(
+                f,
+                "not enough space in the image to embed {required_bits} bits, only have {available_bits} bits available"
+            ),
+            StegoError::IntegrityFailure(msg) => write!(f, "integrity check failed: {msg}"),
+            StegoError::InvalidChannel(msg) => write!(f, "invalid channel access: {msg}"),
+            StegoError::FileNotReady(operation) => {
+                write!(f, "{operation} called before the file was parsed")
+            }
+            StegoError::Usage(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for StegoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StegoError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StegoError {
+    fn from(e: io::Error) -> Self {
+        StegoError::Io(e)
+    }
+}
@@ -16,15 +16,48 @@
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
  */
 use crate::file_encoding_support::file_encoding_support::{
-    FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod, FileEncodingSupport,
+    CapacityReport, FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod,
+    FileEncodingSupport,
 };
-use crate::file_encoding_support::pixel::{embed_lsb_data_left_right, extract_lsb_data_left_right, Pixel};
-use std::fs::File;
-use std::io::{Read, Write};
-use std::mem;
-use std::process::exit;
+use crate::file_encoding_support::payload_header::{frame_payload, unframe_payload};
+use crate::file_encoding_support::embedding_engine::{
+    bits_per_pixel, carrier_positions, embed_encoded, embed_slots, extract_encoded, extract_slots, unique_positions, ByteSlots,
+};
+use crate::file_encoding_support::image::{ChannelLayout, Image, MetadataBlock};
+use crate::file_encoding_support::pixel::{
+    image_from_grid, image_from_indices, write_image_to_grid, write_image_to_indices, Pixel,
+};
+use crate::file_encoding_support::pixel_grid::{IndexedPixelGrid, PixelGrid, RowOrder};
+use crate::file_encoding_support::stego_error::StegoError;
+use crate::filetype_support::bmp_rle::{decode_rle, encode_rle, RleCompression};
+use std::io::Write;
+use std::ops::Range;
 
 const BMP_MAGIC: u16 = 0x4D42;
+pub const BMP_FILE_HEADER_SIZE: usize = 14;
+
+const BITMAPCOREHEADER_SIZE: u32 = 12;
+const OS22XBITMAPHEADER_SHORT_SIZE: u32 = 16;
+const OS22XBITMAPHEADER_SIZE: u32 = 64;
+const BITMAPINFOHEADER_SIZE: u32 = 40;
+const BITMAPV2INFOHEADER_SIZE: u32 = 52;
+const BITMAPV3INFOHEADER_SIZE: u32 = 56;
+const BITMAPV4HEADER_SIZE: u32 = 108;
+const BITMAPV5HEADER_SIZE: u32 = 124;
+
+const BI_RGB: u32 = 0;
+const BI_RLE8: u32 = 1;
+const BI_RLE4: u32 = 2;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+// OS/2 2.x reuses 3 and 4 for its own compression types
+const BCA_HUFFMAN1D: u32 = 3;
+const BCA_RLE24: u32 = 4;
+
+// cs_type values that point at an ICC profile, 'MBED' and 'LINK'
+const PROFILE_EMBEDDED: u32 = 0x4D424544;
+const PROFILE_LINKED: u32 = 0x4C494E4B;
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
 pub struct BitmapFileHeader {
@@ -38,7 +71,7 @@ pub struct BitmapFileHeader {
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
 pub struct BitmapDIBHeader {
-    pub bi_size: u32,             // Size of this header (40 bytes, for BITMAPINFOHEADER)
+    pub bi_size: u32,             // Size of this header, 40 for BITMAPINFOHEADER up to 124 for BITMAPV5HEADER
     pub bi_width: i32,            // Width of bitmap in pixels
     pub bi_height: i32, // Height of bitmap in pixels. If positive, bottom-up. If negative, top-down.
     pub bi_planes: u16, // Number of color planes (must be 1 for all bitmaps)
@@ -50,16 +83,167 @@ pub struct BitmapDIBHeader {
     pub bi_clr_used: u32, // Number of colors in the color palette (0 = default, 2^bi_bit_count colors)
     pub bi_clr_important: u32, // Number of important colors (0 = all colors are important)
 }
+impl BitmapFileHeader {
+    // bytes must hold at least the 14 byte file header
+    fn from_bytes(bytes: &[u8]) -> Self {
+        BitmapFileHeader {
+            bf_type: read_u16(bytes, 0),
+            bf_size: read_u32(bytes, 2),
+            bf_reserved1: read_u16(bytes, 6),
+            bf_reserved2: read_u16(bytes, 8),
+            bf_off_bits: read_u32(bytes, 10),
+        }
+    }
+}
+
+impl BitmapDIBHeader {
+    /*
+        bytes is the DIB header, at least the 16 bytes up to the bit count. The OS/2 2.x header has the same
+        layout as a BITMAPINFOHEADER but may stop after any field, the ones it leaves out are 0.
+     */
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let field = |offset: usize| {
+            if bytes.len() >= offset + 4 {
+                read_u32(bytes, offset)
+            } else {
+                0
+            }
+        };
+
+        BitmapDIBHeader {
+            bi_size: read_u32(bytes, 0),
+            bi_width: read_i32(bytes, 4),
+            bi_height: read_i32(bytes, 8),
+            bi_planes: read_u16(bytes, 12),
+            bi_bit_count: read_u16(bytes, 14),
+            bi_compression: field(16),
+            bi_size_image: field(20),
+            bi_x_pels_per_meter: field(24) as i32,
+            bi_y_pels_per_meter: field(28) as i32,
+            bi_clr_used: field(32),
+            bi_clr_important: field(36),
+        }
+    }
+
+    /*
+        The 12 byte OS/2 1.x BITMAPCOREHEADER, width and height are unsigned 16 bit and the rows are always
+        stored bottom up
+     */
+    fn from_core_bytes(bytes: &[u8]) -> Self {
+        BitmapDIBHeader {
+            bi_size: read_u32(bytes, 0),
+            bi_width: read_u16(bytes, 4) as i32,
+            bi_height: read_u16(bytes, 6) as i32,
+            bi_planes: read_u16(bytes, 8),
+            bi_bit_count: read_u16(bytes, 10),
+            bi_compression: BI_RGB,
+            bi_size_image: 0,
+            bi_x_pels_per_meter: 0,
+            bi_y_pels_per_meter: 0,
+            bi_clr_used: 0,
+            bi_clr_important: 0,
+        }
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_i32(bytes: &[u8], offset: usize) -> i32 {
+    read_u32(bytes, offset) as i32
+}
+
 /*
-   Presence is mandatory when bits per pixel is <= 8
+   Everything the V2 to V5 headers add after the BITMAPINFOHEADER fields, in file order. Fields a shorter
+   header does not have are left at 0. These are only read, the header bytes in file_data are written back
+   exactly as they were.
+*/
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct BitmapV5Fields {
+    pub red_mask: u32,         // V2 and up
+    pub green_mask: u32,       // V2 and up
+    pub blue_mask: u32,        // V2 and up
+    pub alpha_mask: u32,       // V3 and up
+    pub cs_type: u32,          // V4 and up, LCS_CALIBRATED_RGB, LCS_sRGB, PROFILE_EMBEDDED, ...
+    pub endpoints: [i32; 9],   // V4 and up, CIEXYZTRIPLE for red, green and blue in 2.30 fixed point
+    pub gamma_red: u32,        // V4 and up, 16.16 fixed point
+    pub gamma_green: u32,      // V4 and up
+    pub gamma_blue: u32,       // V4 and up
+    pub intent: u32,           // V5
+    pub profile_data: u32,     // V5, offset of the profile from the start of the DIB header
+    pub profile_size: u32,     // V5
+    pub reserved: u32,         // V5
+}
+
+impl BitmapV5Fields {
+    // bytes is the whole DIB header, only the fields that fit in it are read
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let field = |offset: usize| {
+            if bytes.len() >= offset + 4 {
+                read_u32(bytes, offset)
+            } else {
+                0
+            }
+        };
+
+        let mut endpoints = [0i32; 9];
+        for (i, endpoint) in endpoints.iter_mut().enumerate() {
+            *endpoint = field(60 + i * 4) as i32;
+        }
 
-   We likely won't need this
+        BitmapV5Fields {
+            red_mask: field(40),
+            green_mask: field(44),
+            blue_mask: field(48),
+            alpha_mask: field(52),
+            cs_type: field(56),
+            endpoints,
+            gamma_red: field(96),
+            gamma_green: field(100),
+            gamma_blue: field(104),
+            intent: field(108),
+            profile_data: field(112),
+            profile_size: field(116),
+            reserved: field(120),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BmpHeaderVersion {
+    Core, // OS/2 1.x BITMAPCOREHEADER, 12 bytes with 3 byte color table entries
+    Os2,  // OS/2 2.x OS22XBITMAPHEADER, 64 bytes or the 16 byte short form
+    Info, // BITMAPINFOHEADER, 40 bytes
+    V2,   // BITMAPV2INFOHEADER, 52 bytes
+    V3,   // BITMAPV3INFOHEADER, 56 bytes
+    V4,   // BITMAPV4HEADER, 108 bytes
+    V5,   // BITMAPV5HEADER, 124 bytes
+}
+
+/*
+   V5 headers can carry an ICC profile, either the profile itself somewhere after the headers or the
+   path of a profile file. Both are located relative to the DIB header and kept as byte ranges of file_data.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BmpColorProfile {
+    Embedded { offset: u64, size: u64 },
+    Linked { offset: u64, size: u64 },
+}
+
+/*
+   Presence is mandatory when bits per pixel is <= 8, it follows the DIB header and every pixel is an
+   index into it
 
    The size of color table entries is 3 bytes if BITMAPCOREHEADER is
-   substituted for BITMAPV5HEADER
+   substituted for BITMAPV5HEADER, reserved is 0 for those
 */
 #[repr(C, packed)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct BitmapColorTable {
     pub blue: u8,     // Blue component (0-255)
     pub green: u8,    // Green component (0-255)
@@ -87,25 +271,106 @@ pub struct RgbaPixel {
 }
 
 pub struct BmpBitmap {
-    pub num_embedded_bits : Option<usize>,
     pub width: u32,
     pub height: u32,
+    pub row_order: RowOrder, // Bottom up unless bi_height is negative
     pub pixel_map_start: u64, // File offset where pixel map begins, will be indexed via file_data
 }
 
+/*
+   Whether an RLE image is written back compressed or as plain BI_RGB pixel data. Embedding breaks up
+   runs, so the recompressed pixel data is usually larger than it was.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RleOutput {
+    Rle,
+    Uncompressed,
+}
+
+pub struct BmpRle {
+    pub compression: RleCompression,
+    pub indices: Vec<u8>,  // Laid out like uncompressed pixel data, this is what gets embedded into
+    pub encoded_end: u64,  // File offset right after the RLE data in file_data
+    pub output: RleOutput, // Rle unless set_rle_output says otherwise
+}
+
+/*
+   Bytes viewers never look at. The header gap lies between the headers, channel masks and color table
+   and bf_off_bits (an ICC profile stored there is left out), the row padding is padding_size bytes after
+   every row. RLE images have no row padding in the file.
+*/
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct SlackReport {
+    pub header_gap_bytes: u64,
+    pub row_padding_bytes: u64,
+}
+
+impl SlackReport {
+    pub fn total_bytes(&self) -> u64 {
+        self.header_gap_bytes + self.row_padding_bytes
+    }
+}
+
 pub struct BmpImageParser {
     pub bmp_header: BitmapFileHeader,
     pub bmp_dib_header: BitmapDIBHeader,
-    pub pixel_size: u8,
+    pub header_version: BmpHeaderVersion,
+    pub v5_fields: BitmapV5Fields,
+    pub color_profile: Option<BmpColorProfile>,
+    pub pixel_type: BmpPixelType,
+    pub color_table: Vec<BitmapColorTable>, // Empty unless the image is palette indexed
+    pub pixel_size: u8, // Bytes per pixel, 0 for palette indexed images
     pub padding_size: u8,
     pub pixel_map: BmpBitmap,
-    pub image_file: File,
-    pub file_data: Box<Vec<u8>>,
+    pub rle: Option<BmpRle>, // Decoded pixel data of BI_RLE8 and BI_RLE4 images
+    pub file_data: Vec<u8>,
     ready: bool,
 }
 
 // For RGB pixel type
 impl Pixel for RgbPixel {
+    const SIZE: usize = 3;
+
+    type Format = ();
+
+    fn from_bytes(bytes: &[u8], _format: ()) -> Self {
+        RgbPixel {
+            blue: bytes[0],
+            green: bytes[1],
+            red: bytes[2],
+        }
+    }
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        bytes[0] = self.blue;
+        bytes[1] = self.green;
+        bytes[2] = self.red;
+    }
+
+    fn channel_count(_format: ()) -> usize {
+        3
+    }
+
+    fn bit_depths(_format: ()) -> Vec<u8> {
+        vec![8; 3]
+    }
+
+    fn sample(&self, channel: usize) -> u16 {
+        match channel {
+            0 => self.red as u16,
+            1 => self.green as u16,
+            _ => self.blue as u16,
+        }
+    }
+
+    fn set_sample(&mut self, channel: usize, value: u16) {
+        match channel {
+            0 => self.red = value as u8,
+            1 => self.green = value as u8,
+            _ => self.blue = value as u8,
+        }
+    }
+
     fn red(&self) -> u8 {
         self.red
     }
@@ -156,14 +421,16 @@ impl Pixel for RgbPixel {
         self.red = value
     }
 
-    fn set_fourth(&mut self, value: u8) {
-        println!("bmp.rs RgbPixel set_fourth called on a 3 byte pixel! This is a bug! Exiting");
-        exit(1)
+    fn set_fourth(&mut self, _value: u8) -> Result<(), StegoError> {
+        Err(StegoError::InvalidChannel(
+            "bmp.rs RgbPixel set_fourth called on a 3 byte pixel",
+        ))
     }
 
-    fn set_alpha(&mut self, value: u8) {
-        println!("bmp.rs RgbPixel set_alpha called on a 3 byte pixel! This is a bug! Exiting");
-        exit(1)
+    fn set_alpha(&mut self, _value: u8) -> Result<(), StegoError> {
+        Err(StegoError::InvalidChannel(
+            "bmp.rs RgbPixel set_alpha called on a 3 byte pixel",
+        ))
     }
 
     fn pixel_size(&self) -> usize {
@@ -173,6 +440,52 @@ impl Pixel for RgbPixel {
 
 // For RGBA pixel type
 impl Pixel for RgbaPixel {
+    const SIZE: usize = 4;
+
+    type Format = ();
+
+    fn from_bytes(bytes: &[u8], _format: ()) -> Self {
+        RgbaPixel {
+            blue: bytes[0],
+            green: bytes[1],
+            red: bytes[2],
+            alpha: bytes[3],
+        }
+    }
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        bytes[0] = self.blue;
+        bytes[1] = self.green;
+        bytes[2] = self.red;
+        bytes[3] = self.alpha;
+    }
+
+    fn channel_count(_format: ()) -> usize {
+        4
+    }
+
+    fn bit_depths(_format: ()) -> Vec<u8> {
+        vec![8; 4]
+    }
+
+    fn sample(&self, channel: usize) -> u16 {
+        match channel {
+            0 => self.red as u16,
+            1 => self.green as u16,
+            2 => self.blue as u16,
+            _ => self.alpha as u16,
+        }
+    }
+
+    fn set_sample(&mut self, channel: usize, value: u16) {
+        match channel {
+            0 => self.red = value as u8,
+            1 => self.green = value as u8,
+            2 => self.blue = value as u8,
+            _ => self.alpha = value as u8,
+        }
+    }
+
     fn red(&self) -> u8 {
         self.red
     }
@@ -223,12 +536,14 @@ impl Pixel for RgbaPixel {
         self.red = value
     }
 
-    fn set_fourth(&mut self, value: u8) {
-        self.alpha = value
+    fn set_fourth(&mut self, value: u8) -> Result<(), StegoError> {
+        self.alpha = value;
+        Ok(())
     }
 
-    fn set_alpha(&mut self, value: u8) {
-        self.alpha = value
+    fn set_alpha(&mut self, value: u8) -> Result<(), StegoError> {
+        self.alpha = value;
+        Ok(())
     }
 
     fn pixel_size(&self) -> usize {
@@ -237,17 +552,260 @@ impl Pixel for RgbaPixel {
 }
 
 /*
-   We will just add support for 24 bit and 32 bit pixel sizes, will likely only encounter 24 bit pixels
+   Where the channels of a 16 or 32 bit BI_BITFIELDS pixel are, every mask is one contiguous run of bits.
+   16 bit BI_RGB images use RGB555.
 */
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BitfieldFormat {
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    pub alpha_mask: u32, // 0 when there is no alpha channel
+}
+
+impl BitfieldFormat {
+    pub const RGB555: BitfieldFormat = BitfieldFormat {
+        red_mask: 0x7C00,
+        green_mask: 0x03E0,
+        blue_mask: 0x001F,
+        alpha_mask: 0,
+    };
+
+    pub fn new(red_mask: u32, green_mask: u32, blue_mask: u32, alpha_mask: u32, bit_count: u16) -> Result<Self, StegoError> {
+        let format = BitfieldFormat { red_mask, green_mask, blue_mask, alpha_mask };
+        let pixel_mask = if bit_count >= 32 { u32::MAX } else { (1u32 << bit_count) - 1 };
+
+        let mut seen = 0u32;
+        for mask in [red_mask, green_mask, blue_mask, alpha_mask] {
+            // A mask shifted down to bit 0 has to be all ones
+            let run = mask.checked_shr(mask.trailing_zeros()).unwrap_or(0);
+            let contiguous = run & run.wrapping_add(1) == 0;
+            if !contiguous || mask & !pixel_mask != 0 || mask & seen != 0 {
+                return Err(StegoError::MalformedHeader(format!(
+                    "bmp.rs: parse_file: channel masks {red_mask:#x} {green_mask:#x} {blue_mask:#x} {alpha_mask:#x} are not valid for {bit_count} bit pixels"
+                )));
+            }
+            seen |= mask;
+        }
+
+        if red_mask == 0 || green_mask == 0 || blue_mask == 0 {
+            return Err(StegoError::MalformedHeader(format!(
+                "bmp.rs: parse_file: channel masks {red_mask:#x} {green_mask:#x} {blue_mask:#x} leave out a colour"
+            )));
+        }
+
+        // Image samples are at most 16 bits
+        if format.image_masks().any(|mask| mask.count_ones() > 16) {
+            return Err(StegoError::UnsupportedFormat(format!(
+                "bmp.rs: parse_file: channel masks {red_mask:#x} {green_mask:#x} {blue_mask:#x} {alpha_mask:#x} have a channel wider than 16 bits"
+            )));
+        }
+
+        Ok(format)
+    }
+
+    // The masks of the channels that exist, in Image order
+    fn image_masks(self) -> impl Iterator<Item = u32> {
+        [self.red_mask, self.green_mask, self.blue_mask, self.alpha_mask]
+            .into_iter()
+            .enumerate()
+            .filter(|&(channel, mask)| channel < 3 || mask != 0)
+            .map(|(_, mask)| mask)
+    }
+}
+
+/*
+   A 16 or 32 bit pixel whose channels sit wherever the masks say. first to fourth are blue, green,
+   red and alpha like the byte aligned pixels, but they are the raw channel values, so a 5 bit channel
+   goes from 0 to 31 and its least significant bit is the lowest bit of the mask. Channels wider than
+   8 bits hand out and replace their low 8 bits only. red, green, blue and alpha are scaled to 0-255.
+*/
+#[derive(Debug, Clone)]
+pub struct BitfieldPixel<const N: usize> {
+    pub value: u32,
+    pub format: BitfieldFormat,
+}
+
+pub type Bitfield16Pixel = BitfieldPixel<2>;
+pub type Bitfield32Pixel = BitfieldPixel<4>;
+
+impl<const N: usize> BitfieldPixel<N> {
+    fn raw(&self, mask: u32) -> u32 {
+        if mask == 0 {
+            return 0;
+        }
+        (self.value & mask) >> mask.trailing_zeros()
+    }
+
+    fn set_raw(&mut self, mask: u32, raw: u32) {
+        if mask == 0 {
+            return;
+        }
+        let shift = mask.trailing_zeros();
+        self.value = (self.value & !mask) | ((raw << shift) & mask);
+    }
+
+    fn low_bits(&self, mask: u32) -> u8 {
+        (self.raw(mask) & 0xFF) as u8
+    }
+
+    fn set_low_bits(&mut self, mask: u32, value: u8) {
+        let raw = (self.raw(mask) & !0xFF) | value as u32;
+        self.set_raw(mask, raw);
+    }
+
+    fn scaled(&self, mask: u32) -> u8 {
+        let width = mask.count_ones();
+        let raw = self.raw(mask) as u64;
+        if width >= 8 {
+            return (raw >> (width - 8)) as u8;
+        }
+        let max = (1u64 << width) - 1;
+        ((raw * 255 + max / 2) / max) as u8
+    }
+
+    fn set_scaled(&mut self, mask: u32, value: u8) {
+        let width = mask.count_ones();
+        if width >= 8 {
+            self.set_raw(mask, (value as u32) << (width - 8));
+            return;
+        }
+        let max = (1u32 << width) - 1;
+        self.set_raw(mask, (value as u32 * max + 127) / 255);
+    }
+}
+
+impl<const N: usize> Pixel for BitfieldPixel<N> {
+    const SIZE: usize = N;
+
+    type Format = BitfieldFormat;
+
+    fn from_bytes(bytes: &[u8], format: BitfieldFormat) -> Self {
+        let value = bytes[..N].iter().rev().fold(0u32, |value, &byte| (value << 8) | byte as u32);
+        BitfieldPixel { value, format }
+    }
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        for (i, byte) in bytes[..N].iter_mut().enumerate() {
+            *byte = (self.value >> (i * 8)) as u8;
+        }
+    }
+
+    fn channel_count(format: BitfieldFormat) -> usize {
+        if format.alpha_mask == 0 { 3 } else { 4 }
+    }
+
+    fn bit_depths(format: BitfieldFormat) -> Vec<u8> {
+        format.image_masks().map(|mask| mask.count_ones() as u8).collect()
+    }
+
+    fn sample(&self, channel: usize) -> u16 {
+        self.format.image_masks().nth(channel).map_or(0, |mask| self.raw(mask) as u16)
+    }
+
+    fn set_sample(&mut self, channel: usize, value: u16) {
+        if let Some(mask) = self.format.image_masks().nth(channel) {
+            self.set_raw(mask, value as u32);
+        }
+    }
+
+    fn red(&self) -> u8 {
+        self.scaled(self.format.red_mask)
+    }
+    fn green(&self) -> u8 {
+        self.scaled(self.format.green_mask)
+    }
+    fn blue(&self) -> u8 {
+        self.scaled(self.format.blue_mask)
+    }
+    fn alpha(&self) -> u8 {
+        if self.format.alpha_mask == 0 {
+            return 255;
+        }
+        self.scaled(self.format.alpha_mask)
+    }
+
+    fn first(&self) -> u8 {
+        self.low_bits(self.format.blue_mask)
+    }
+
+    fn second(&self) -> u8 {
+        self.low_bits(self.format.green_mask)
+    }
+    fn third(&self) -> u8 {
+        self.low_bits(self.format.red_mask)
+    }
+    fn fourth(&self) -> u8 {
+        if self.format.alpha_mask == 0 {
+            return 255;
+        }
+        self.low_bits(self.format.alpha_mask)
+    }
+    fn set_red(&mut self, value: u8) {
+        self.set_scaled(self.format.red_mask, value)
+    }
+
+    fn set_green(&mut self, value: u8) {
+        self.set_scaled(self.format.green_mask, value)
+    }
+
+    fn set_blue(&mut self, value: u8) {
+        self.set_scaled(self.format.blue_mask, value)
+    }
+
+    fn set_first(&mut self, value: u8) {
+        self.set_low_bits(self.format.blue_mask, value)
+    }
+
+    fn set_second(&mut self, value: u8) {
+        self.set_low_bits(self.format.green_mask, value)
+    }
+
+    fn set_third(&mut self, value: u8) {
+        self.set_low_bits(self.format.red_mask, value)
+    }
+
+    fn set_fourth(&mut self, value: u8) -> Result<(), StegoError> {
+        if self.format.alpha_mask == 0 {
+            return Err(StegoError::InvalidChannel(
+                "bmp.rs BitfieldPixel set_fourth called on a pixel without an alpha mask",
+            ));
+        }
+        self.set_low_bits(self.format.alpha_mask, value);
+        Ok(())
+    }
 
+    fn set_alpha(&mut self, value: u8) -> Result<(), StegoError> {
+        if self.format.alpha_mask == 0 {
+            return Err(StegoError::InvalidChannel(
+                "bmp.rs BitfieldPixel set_alpha called on a pixel without an alpha mask",
+            ));
+        }
+        self.set_scaled(self.format.alpha_mask, value);
+        Ok(())
+    }
+
+    fn pixel_size(&self) -> usize {
+        N
+    }
+}
+
+/*
+   24 and 32 bit pixels carry their colour directly, 16 and 32 bit pixels with channel masks are
+   unpacked through them, 1, 4 and 8 bit pixels are indices into the color table
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum BmpPixelType {
     Rgb,
     Rgba,
+    Bitfield16(BitfieldFormat),
+    Bitfield32(BitfieldFormat),
+    Indexed(u8), // Bits per index
 }
 
 impl FileEncodingSupport for BmpImageParser {
-    fn new(filename: &str) -> Self {
-        BmpImageParser {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, StegoError> {
+        Ok(BmpImageParser {
             bmp_header: BitmapFileHeader {
                 bf_type: 0,
                 bf_size: 0,
@@ -268,94 +826,164 @@ impl FileEncodingSupport for BmpImageParser {
                 bi_clr_used: 0,
                 bi_clr_important: 0,
             },
+            header_version: BmpHeaderVersion::Info,
+            v5_fields: BitmapV5Fields::default(),
+            color_profile: None,
+            pixel_type: BmpPixelType::Rgb,
+            color_table: Vec::new(),
             pixel_size: 0,
             padding_size: 0,
             pixel_map: BmpBitmap {
-                num_embedded_bits: None,
                 width: 0,
                 height: 0,
+                row_order: RowOrder::BottomUp,
                 pixel_map_start: 0,
             },
-            image_file: match File::open(filename) {
-                Ok(file) => file,
-                Err(e) => {
-                    println!("bmp.rs: new : failed to open file {}: {}", filename, e);
-                    exit(1);
-                }
-            },
-            file_data: Box::new(vec![]),
+            rle: None,
+            file_data: bytes.to_vec(),
             ready: false,
-        }
+        })
     }
 
-    fn parse_file(&mut self) {
-        let header_size = std::mem::size_of::<BitmapFileHeader>();
-        let dib_header_size = std::mem::size_of::<BitmapDIBHeader>();
+    fn parse_file(&mut self) -> Result<(), StegoError> {
+        if self.file_data.len() < BMP_FILE_HEADER_SIZE + 4 {
+            return Err(StegoError::MalformedHeader(format!(
+                "bmp.rs: parse_file: file is {} bytes, too small to hold the bitmap headers",
+                self.file_data.len()
+            )));
+        }
+
+        self.bmp_header = BitmapFileHeader::from_bytes(&self.file_data);
 
-        let file: &mut File = &mut self.image_file;
+        let bf_type = self.bmp_header.bf_type;
+        if bf_type != BMP_MAGIC {
+            return Err(StegoError::UnsupportedFormat(format!(
+                "bmp.rs: parse_file: bad magic {bf_type:#06x}, not a BMP file"
+            )));
+        }
 
-        let file_size: u32 = match file.metadata() {
-            Ok(metadata) => metadata.len() as u32,
-            Err(_) => {
-                println!("bmp.rs : parse_file: Error reading file metadata! Exiting...",);
-                exit(1);
+        let bi_size = read_u32(&self.file_data, BMP_FILE_HEADER_SIZE);
+        self.header_version = match bi_size {
+            BITMAPCOREHEADER_SIZE => BmpHeaderVersion::Core,
+            OS22XBITMAPHEADER_SHORT_SIZE | OS22XBITMAPHEADER_SIZE => BmpHeaderVersion::Os2,
+            BITMAPINFOHEADER_SIZE => BmpHeaderVersion::Info,
+            BITMAPV2INFOHEADER_SIZE => BmpHeaderVersion::V2,
+            BITMAPV3INFOHEADER_SIZE => BmpHeaderVersion::V3,
+            BITMAPV4HEADER_SIZE => BmpHeaderVersion::V4,
+            BITMAPV5HEADER_SIZE => BmpHeaderVersion::V5,
+            _ => {
+                return Err(StegoError::UnsupportedFormat(format!(
+                    "bmp.rs: parse_file: DIB header size {bi_size} is not supported"
+                )));
             }
         };
 
-        self.file_data = Box::new(Vec::<u8>::with_capacity(file_size as usize));
-
-        match file.read_to_end(&mut self.file_data) {
-            Ok(_) => (),
-            Err(e) => {
-                println!(
-                    "bmp.rs: parse_file: Error reading file to end with err {}",
-                    e
-                );
-                exit(1);
-            }
+        let dib_header_end = BMP_FILE_HEADER_SIZE + bi_size as usize;
+        if self.file_data.len() < dib_header_end {
+            return Err(StegoError::MalformedHeader(format!(
+                "bmp.rs: parse_file: file is {} bytes, too small to hold a {bi_size} byte DIB header",
+                self.file_data.len()
+            )));
         }
 
-        let header_size = std::mem::size_of::<BitmapFileHeader>();
+        let dib_header = &self.file_data[BMP_FILE_HEADER_SIZE..dib_header_end];
+        (self.bmp_dib_header, self.v5_fields) = match self.header_version {
+            BmpHeaderVersion::Core => (BitmapDIBHeader::from_core_bytes(dib_header), BitmapV5Fields::default()),
+            // The OS/2 fields after the first 40 bytes are not masks, nothing here needs them
+            BmpHeaderVersion::Os2 => (BitmapDIBHeader::from_bytes(dib_header), BitmapV5Fields::default()),
+            _ => (BitmapDIBHeader::from_bytes(dib_header), BitmapV5Fields::from_bytes(dib_header)),
+        };
+        self.color_profile = self.parse_color_profile()?;
 
-        unsafe {
-            let header_pointer: *mut BitmapFileHeader = &mut self.bmp_header;
-            std::ptr::copy(
-                &mut self.file_data[0] as *mut u8,
-                header_pointer as *mut u8,
-                header_size,
-            );
+        let bi_compression = self.bmp_dib_header.bi_compression;
+        let bi_bit_count = self.bmp_dib_header.bi_bit_count;
+        if self.header_version == BmpHeaderVersion::Os2 && matches!(bi_compression, BCA_HUFFMAN1D | BCA_RLE24) {
+            return Err(StegoError::UnsupportedFormat(format!(
+                "bmp.rs: parse_file: OS/2 compression type {bi_compression} (Huffman 1D or RLE24) is not supported"
+            )));
         }
-        let dib_header_size = mem::size_of::<BitmapDIBHeader>();
+        self.pixel_type = match (bi_compression, bi_bit_count) {
+            (BI_RGB, 1 | 4 | 8) => BmpPixelType::Indexed(bi_bit_count as u8),
+            (BI_RLE8, 8) | (BI_RLE4, 4) => BmpPixelType::Indexed(bi_bit_count as u8),
+            (BI_RGB, 16) => BmpPixelType::Bitfield16(BitfieldFormat::RGB555),
+            (BI_RGB, 24) => BmpPixelType::Rgb,
+            (BI_RGB, 32) => BmpPixelType::Rgba,
+            (BI_BITFIELDS | BI_ALPHABITFIELDS, 16) => BmpPixelType::Bitfield16(self.parse_bitfields(dib_header_end)?),
+            (BI_BITFIELDS | BI_ALPHABITFIELDS, 32) => BmpPixelType::Bitfield32(self.parse_bitfields(dib_header_end)?),
+            // Only the masks that describe the plain BGR layout, 24 bit pixels have nothing else to offer
+            (BI_BITFIELDS, 24) if self.has_default_bitfields() => BmpPixelType::Rgb,
+            (BI_RGB | BI_RLE8 | BI_RLE4 | BI_BITFIELDS | BI_ALPHABITFIELDS, _) => {
+                return Err(StegoError::UnsupportedFormat(format!(
+                    "bmp.rs: parse_file: {bi_bit_count} bits per pixel is not supported with compression type {bi_compression}"
+                )));
+            }
+            _ => {
+                return Err(StegoError::UnsupportedFormat(format!(
+                    "bmp.rs: parse_file: compression type {bi_compression} is not supported"
+                )));
+            }
+        };
 
-        unsafe {
-            let dib_header_pointer: *mut BitmapDIBHeader = &mut self.bmp_dib_header;
-            std::ptr::copy(
-                &mut self.file_data[14] as *mut u8,
-                dib_header_pointer as *mut u8,
-                dib_header_size,
-            );
+        let bi_width = self.bmp_dib_header.bi_width;
+        if bi_width <= 0 {
+            return Err(StegoError::MalformedHeader(format!(
+                "bmp.rs: parse_file: width {bi_width} is not valid"
+            )));
         }
 
-        self.pixel_map.width = self.bmp_dib_header.bi_width as u32;
-        self.pixel_map.height = self.bmp_dib_header.bi_height as u32;
-
-        if self.bmp_dib_header.bi_size != 40 {
-            /*
-               Bit extreme, we'll keep it for now and see.
-               If V5 headers are common I'll remove this
-            */
-            println!("bmp.rs: parse_file: File Size Mismatch, exiting ...");
-            exit(1);
-        }
+        // A negative height means the rows are stored top row first
+        let bi_height = self.bmp_dib_header.bi_height;
+        self.pixel_map.width = bi_width as u32;
+        self.pixel_map.height = bi_height.unsigned_abs();
+        self.pixel_map.row_order = if bi_height < 0 {
+            RowOrder::TopDown
+        } else {
+            RowOrder::BottomUp
+        };
 
-        self.padding_size = ((self.pixel_map.width as u64 * self.pixel_size as u64) % 4) as u8;
-        self.pixel_size = (self.bmp_dib_header.bi_bit_count / 8) as u8;
+        self.pixel_size = match self.pixel_type {
+            BmpPixelType::Indexed(_) => 0,
+            _ => (bi_bit_count / 8) as u8,
+        };
 
-        self.padding_size = ((self.pixel_map.width * self.pixel_size as u32) % 4) as u8;
+        // Rows are padded out to a multiple of 4 bytes, indices narrower than a byte are packed first
+        let unpadded_row_size = self.unpadded_row_size();
+        self.padding_size = ((4 - unpadded_row_size % 4) % 4) as u8;
 
         self.pixel_map.pixel_map_start = self.bmp_header.bf_off_bits as u64;
+        self.color_table = self.parse_color_table(dib_header_end)?;
+
+        // Embedding only ever writes to the pixel data, it must not share any bytes with the headers or the profile
+        if self.pixel_map.pixel_map_start < dib_header_end as u64 {
+            return Err(StegoError::MalformedHeader(format!(
+                "bmp.rs: parse_file: pixel data starts at offset {} inside of the headers",
+                self.pixel_map.pixel_map_start
+            )));
+        }
+
+        self.rle = self.parse_rle()?;
+        let pixel_map_end = match &self.rle {
+            Some(rle) => rle.encoded_end,
+            None => self.pixel_map.pixel_map_start + (unpadded_row_size + self.padding_size as u64) * self.pixel_map.height as u64,
+        };
+        if pixel_map_end > self.file_data.len() as u64 {
+            return Err(StegoError::MalformedHeader(format!(
+                "bmp.rs: parse_file: pixel data ends at offset {pixel_map_end} but the file is only {} bytes",
+                self.file_data.len()
+            )));
+        }
+
+        if let Some(BmpColorProfile::Embedded { offset, size } | BmpColorProfile::Linked { offset, size }) = self.color_profile
+            && offset < pixel_map_end
+            && self.pixel_map.pixel_map_start < offset + size
+        {
+            return Err(StegoError::MalformedHeader(
+                "bmp.rs: parse_file: the color profile overlaps the pixel data".to_string(),
+            ));
+        }
 
         self.ready = true;
+        Ok(())
     }
 
     fn embed_data(
@@ -364,85 +992,593 @@ impl FileEncodingSupport for BmpImageParser {
         encoding: FileEncoding,
         encoding_method: FileEncodingMethod,
         file_encoding_function_derivation: FileEncodingFunctionDerivation,
-    ) {
+    ) -> Result<(), StegoError> {
         if !self.ready {
-            println!("bmp.rs: embed_data called with File Not Ready");
-            exit(1);
-        }
-        match encoding_method {
-            FileEncodingMethod::LeftToRight => match encoding {
-                FileEncoding::Lsb => {
-                    if(self.pixel_size == 3){
-                        embed_lsb_data_left_right::<RgbPixel>(&data, &mut self.file_data[self.pixel_map.pixel_map_start as usize..], self.pixel_map.width as u64, self.pixel_map.height as u64, self.padding_size as u64, 3)
-                    }else {
-                        embed_lsb_data_left_right::<RgbaPixel>(&data, &mut self.file_data[self.pixel_map.pixel_map_start as usize..], self.pixel_map.width as u64, self.pixel_map.height as u64, self.padding_size as u64, 4)
-                    }
+            return Err(StegoError::FileNotReady("bmp.rs: embed_data"));
+        }
+        let framed = frame_payload(data, encoding, encoding_method);
+        let order = encoding_method.traverse(self.pixel_map.height as usize, self.pixel_map.width as usize);
+
+        // SlackLsb fills the slack first, whatever is left goes into the pixels
+        let (in_slack, in_pixels, pixel_encoding) = match encoding {
+            FileEncoding::Slack => (&framed[..], &[][..], encoding),
+            FileEncoding::SlackLsb => {
+                let available_bits = self.capacity(encoding, encoding_method)?.total_bits;
+                let required_bits = framed.len() as u64 * 8;
+                if required_bits > available_bits {
+                    return Err(StegoError::InsufficientCapacity { required_bits, available_bits });
                 }
 
-                _ => todo!(),
-            },
+                let slack_bytes = (self.slack_report().total_bytes() as usize).min(framed.len());
+                let (in_slack, in_pixels) = framed.split_at(slack_bytes);
+                (in_slack, in_pixels, FileEncoding::Lsb)
+            }
+            _ => (&[][..], &framed[..], encoding),
+        };
 
-            _ => todo!(),
+        if !in_slack.is_empty() {
+            self.embed_slack(in_slack, file_encoding_function_derivation)?;
+        }
+        if in_pixels.is_empty() {
+            return Ok(());
         }
 
-        self.bmp_header.bf_reserved1 = data.len() as u16;
+        let mut image = self.decode_image()?;
+        embed_encoded(&mut image, &order, pixel_encoding, file_encoding_function_derivation, in_pixels)?;
+        self.encode_image(&image)
     }
 
     fn retrieve_data(
         &mut self,
-        data: Vec<u8>,
         encoding: FileEncoding,
         encoding_method: FileEncodingMethod,
         file_encoding_function_derivation: FileEncodingFunctionDerivation,
-    ) {
+    ) -> Result<Vec<u8>, StegoError> {
         if !self.ready {
-            println!("bmp.rs: retrieve_data called with File Not Ready");
-            exit(1);
+            return Err(StegoError::FileNotReady("bmp.rs: retrieve_data"));
         }
 
-        self.pixel_map.num_embedded_bits = Some(self.bmp_header.bf_reserved1 as usize * 8);
-        match encoding_method {
-            FileEncodingMethod::LeftToRight => match encoding {
-                FileEncoding::Lsb => {
-                    if(self.pixel_size == 3){
-                        let data_vec : Vec<u8> = extract_lsb_data_left_right::<RgbPixel>(&mut self.file_data[self.pixel_map.pixel_map_start as usize..], self.pixel_map.width as u64, self.pixel_map.height as u64, self.padding_size as u64, 3, self.pixel_map.num_embedded_bits.unwrap() as u64);
-                    }else {
-                        let data_vec : Vec<u8> = extract_lsb_data_left_right::<RgbaPixel>( &mut self.file_data[self.pixel_map.pixel_map_start as usize..], self.pixel_map.width as u64, self.pixel_map.height as u64, self.padding_size as u64, 4,self.pixel_map.num_embedded_bits.unwrap() as u64);
+        let order = encoding_method.traverse(self.pixel_map.height as usize, self.pixel_map.width as usize);
+        let image = self.decode_image()?;
+        let slack = self.slack_bytes();
+        let slack_bits = slack.len() as u64 * 8;
 
-                    }
+        let extract = |embedded_bits: u64| match encoding {
+            FileEncoding::Slack => extract_slack(&slack, file_encoding_function_derivation, embedded_bits),
+            FileEncoding::SlackLsb => {
+                let in_slack = embedded_bits.min(slack_bits);
+                let mut data = extract_slack(&slack, file_encoding_function_derivation, in_slack)?;
+                if embedded_bits > in_slack {
+                    data.extend(extract_encoded(&image, &order, FileEncoding::Lsb, file_encoding_function_derivation, embedded_bits - in_slack)?);
                 }
+                Ok(data)
+            }
+            _ => extract_encoded(&image, &order, encoding, file_encoding_function_derivation, embedded_bits),
+        };
+
+        unframe_payload(extract, encoding, encoding_method)
+    }
+
+    fn capacity(&self, encoding: FileEncoding, encoding_method: FileEncodingMethod) -> Result<CapacityReport, StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("bmp.rs: capacity"));
+        }
+
+        let slack_bits = self.slack_report().total_bytes() * 8;
+        let order = encoding_method.traverse(self.pixel_map.height as usize, self.pixel_map.width as usize);
+
+        match encoding {
+            FileEncoding::Slack => Ok(CapacityReport::new(slack_bits, 0)),
+            FileEncoding::SlackLsb => {
+                let (pixels, bits_per_pixel) = self.pixel_capacity(FileEncoding::Lsb, &order)?;
+                Ok(CapacityReport::new(slack_bits + pixels * bits_per_pixel, bits_per_pixel))
+            }
+            _ => {
+                let (pixels, bits_per_pixel) = self.pixel_capacity(encoding, &order)?;
+                Ok(CapacityReport::new(pixels * bits_per_pixel, bits_per_pixel))
+            }
+        }
+    }
 
-                _ => todo!(),
+    /*
+        --rle keep|uncompressed picks how an RLE image is written back, see set_rle_output. --grow-gap n
+        makes the header gap n bytes larger before anything is embedded, see grow_header_gap.
+     */
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), StegoError> {
+        match (name, value) {
+            ("rle", "keep") => self.set_rle_output(RleOutput::Rle),
+            ("rle", "uncompressed") => self.set_rle_output(RleOutput::Uncompressed),
+            ("rle", _) => return Err(StegoError::Usage(format!("--rle takes keep or uncompressed! : {value}"))),
+            ("grow-gap", _) => match value.parse::<u32>() {
+                Ok(extra) => self.grow_header_gap(extra)?,
+                Err(_) => return Err(StegoError::Usage(format!("--grow-gap takes a number of bytes! : {value}"))),
             },
+            _ => return Err(StegoError::Usage(format!("--{name} is not an option for BMP files!"))),
+        }
+        Ok(())
+    }
+
+    fn write_to(&mut self, writer: &mut dyn Write) -> Result<(), StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("bmp.rs: write_to"));
+        }
+
+        match &self.rle {
+            Some(rle) => writer.write_all(&self.rle_file_data(rle))?,
+            None => writer.write_all(self.file_data.as_slice())?,
+        }
+
+        Ok(())
+    }
+}
+
+impl BmpImageParser {
+    fn parse_color_profile(&self) -> Result<Option<BmpColorProfile>, StegoError> {
+        if self.header_version != BmpHeaderVersion::V5 {
+            return Ok(None);
+        }
+
+        let offset = BMP_FILE_HEADER_SIZE as u64 + self.v5_fields.profile_data as u64;
+        let size = self.v5_fields.profile_size as u64;
+
+        let profile = match self.v5_fields.cs_type {
+            PROFILE_EMBEDDED => BmpColorProfile::Embedded { offset, size },
+            PROFILE_LINKED => BmpColorProfile::Linked { offset, size },
+            _ => return Ok(None),
+        };
+
+        if offset < (BMP_FILE_HEADER_SIZE as u64 + BITMAPV5HEADER_SIZE as u64) || offset + size > self.file_data.len() as u64 {
+            return Err(StegoError::MalformedHeader(format!(
+                "bmp.rs: parse_file: color profile at {offset} of {size} bytes lies outside of the file"
+            )));
+        }
+
+        Ok(Some(profile))
+    }
+
+    /*
+        The ICC profile, or for a linked profile the path of the profile file, exactly as stored
+     */
+    pub fn color_profile_bytes(&self) -> Option<&[u8]> {
+        match self.color_profile? {
+            BmpColorProfile::Embedded { offset, size } | BmpColorProfile::Linked { offset, size } => {
+                Some(&self.file_data[offset as usize..(offset + size) as usize])
+            }
+        }
+    }
+
+    /*
+        bi_clr_used entries, or one for every possible index when it is 0. Entries past the last index
+        the bit count can express are never referenced and are left out. True colour images may carry
+        an optimisation palette, it is not needed for anything and is skipped.
+     */
+    fn parse_color_table(&self, dib_header_end: usize) -> Result<Vec<BitmapColorTable>, StegoError> {
+        let BmpPixelType::Indexed(bits) = self.pixel_type else {
+            return Ok(Vec::new());
+        };
+
+        let max_entries = 1u64 << bits;
+        let entry_size = self.color_entry_size();
+        let entries = match (self.header_version, self.bmp_dib_header.bi_clr_used as u64) {
+            // OS/2 1.x has no bi_clr_used, writers that store fewer entries start the pixel data right after them
+            (BmpHeaderVersion::Core, _) => max_entries.min(self.pixel_map.pixel_map_start.saturating_sub(dib_header_end as u64) / entry_size),
+            (_, 0) => max_entries,
+            (_, used) => used.min(max_entries),
+        };
+
+        let table_end = dib_header_end as u64 + entries * entry_size;
+        if table_end > self.pixel_map.pixel_map_start {
+            return Err(StegoError::MalformedHeader(format!(
+                "bmp.rs: parse_file: color table of {entries} entries runs into the pixel data at offset {}",
+                self.pixel_map.pixel_map_start
+            )));
+        }
+
+        Ok(self.file_data[dib_header_end..table_end as usize]
+            .chunks_exact(entry_size as usize)
+            .map(|entry| BitmapColorTable {
+                blue: entry[0],
+                green: entry[1],
+                red: entry[2],
+                reserved: entry.get(3).copied().unwrap_or(0),
+            })
+            .collect())
+    }
+
+    fn color_entry_size(&self) -> u64 {
+        if self.header_version == BmpHeaderVersion::Core { 3 } else { 4 }
+    }
+
+    fn palette(&self) -> Vec<[u8; 3]> {
+        self.color_table.iter().map(|entry| [entry.red, entry.green, entry.blue]).collect()
+    }
+
+    fn index_grid(&mut self) -> Result<IndexedPixelGrid<'_>, StegoError> {
+        let BmpPixelType::Indexed(bits) = self.pixel_type else {
+            unreachable!("index_grid is only used for palette images")
+        };
+
+        let pixel_data = match &mut self.rle {
+            Some(rle) => &mut rle.indices[..],
+            None => &mut self.file_data[self.pixel_map.pixel_map_start as usize..],
+        };
+
+        IndexedPixelGrid::new(
+            pixel_data,
+            self.pixel_map.width as usize,
+            self.pixel_map.height as usize,
+            bits as usize,
+            self.padding_size as usize,
+            self.pixel_map.row_order,
+        )
+    }
+
+    /*
+        Only has an effect on RLE images
+     */
+    pub fn set_rle_output(&mut self, output: RleOutput) {
+        if let Some(rle) = &mut self.rle {
+            rle.output = output;
+        }
+    }
+
+    fn parse_rle(&self) -> Result<Option<BmpRle>, StegoError> {
+        let compression = match self.bmp_dib_header.bi_compression {
+            BI_RLE8 => RleCompression::Rle8,
+            BI_RLE4 => RleCompression::Rle4,
+            _ => return Ok(None),
+        };
+
+        if self.pixel_map.row_order == RowOrder::TopDown {
+            return Err(StegoError::UnsupportedFormat(
+                "bmp.rs: parse_file: RLE bitmaps can not be stored top down".to_string(),
+            ));
+        }
+
+        let start = (self.pixel_map.pixel_map_start as usize).min(self.file_data.len());
+        let (indices, encoded_size) = decode_rle(
+            &self.file_data[start..],
+            self.pixel_map.width as usize,
+            self.pixel_map.height as usize,
+            compression,
+        )?;
+
+        Ok(Some(BmpRle {
+            compression,
+            indices,
+            encoded_end: (start + encoded_size) as u64,
+            output: RleOutput::Rle,
+        }))
+    }
+
+    /*
+        file_data with the RLE data swapped for the current pixel data. Whatever followed the old pixel
+        data moves along with it and a profile stored there is pointed at its new offset.
+     */
+    fn rle_file_data(&self, rle: &BmpRle) -> Vec<u8> {
+        let (compression, pixel_data) = match rle.output {
+            RleOutput::Rle => {
+                let compression = match rle.compression {
+                    RleCompression::Rle8 => BI_RLE8,
+                    RleCompression::Rle4 => BI_RLE4,
+                };
+                let width = self.pixel_map.width as usize;
+                let height = self.pixel_map.height as usize;
+                (compression, encode_rle(&rle.indices, width, height, rle.compression))
+            }
+            RleOutput::Uncompressed => (BI_RGB, rle.indices.clone()),
+        };
+
+        let start = self.pixel_map.pixel_map_start as usize;
+        let end = rle.encoded_end as usize;
+        let mut bytes = Vec::with_capacity(start + pixel_data.len() + self.file_data.len() - end);
+        bytes.extend_from_slice(&self.file_data[..start]);
+        bytes.extend_from_slice(&pixel_data);
+        bytes.extend_from_slice(&self.file_data[end..]);
 
-            _ => todo!(),
+        let file_size = bytes.len() as u32;
+        bytes[2..6].copy_from_slice(&file_size.to_le_bytes());
+        bytes[BMP_FILE_HEADER_SIZE + 16..BMP_FILE_HEADER_SIZE + 20].copy_from_slice(&compression.to_le_bytes());
+        bytes[BMP_FILE_HEADER_SIZE + 20..BMP_FILE_HEADER_SIZE + 24].copy_from_slice(&(pixel_data.len() as u32).to_le_bytes());
+
+        if let Some(BmpColorProfile::Embedded { offset, .. } | BmpColorProfile::Linked { offset, .. }) = self.color_profile
+            && offset >= rle.encoded_end
+        {
+            let moved = offset as usize - end + start + pixel_data.len();
+            let profile_data = (moved - BMP_FILE_HEADER_SIZE) as u32;
+            bytes[BMP_FILE_HEADER_SIZE + 112..BMP_FILE_HEADER_SIZE + 116].copy_from_slice(&profile_data.to_le_bytes());
         }
+
+        bytes
+    }
+
+    fn unpadded_row_size(&self) -> u64 {
+        (self.pixel_map.width as u64 * self.bmp_dib_header.bi_bit_count as u64).div_ceil(8)
+    }
+
+    /*
+        Where the headers, channel masks and color table end. Every entry bi_clr_used claims counts, for
+        true colour images as well, nothing here reads those entries but a viewer might.
+     */
+    fn structure_end(&self) -> u64 {
+        let dib_header_end = BMP_FILE_HEADER_SIZE as u64 + self.bmp_dib_header.bi_size as u64;
+        let masks = match (self.header_version, self.bmp_dib_header.bi_compression) {
+            (BmpHeaderVersion::Info, BI_BITFIELDS) => 12,
+            (BmpHeaderVersion::Info, BI_ALPHABITFIELDS) => 16,
+            _ => 0,
+        };
+        let entries = match (self.header_version, self.bmp_dib_header.bi_clr_used, self.pixel_type) {
+            (BmpHeaderVersion::Core, _, _) => self.color_table.len() as u64,
+            (_, 0, BmpPixelType::Indexed(bits)) => 1u64 << bits,
+            (_, used, _) => used as u64,
+        };
+
+        (dib_header_end + masks + entries * self.color_entry_size()).min(self.pixel_map.pixel_map_start)
     }
 
-    fn write_file(&mut self, file_location: &str) {
+    fn header_gap(&self) -> Vec<Range<usize>> {
+        let start = self.structure_end() as usize;
+        let end = self.pixel_map.pixel_map_start as usize;
+
+        // Split around the profile, when there is none the second half is empty
+        let (before_profile, after_profile) = match self.color_profile {
+            Some(BmpColorProfile::Embedded { offset, size } | BmpColorProfile::Linked { offset, size }) => {
+                ((offset as usize).min(end), ((offset + size) as usize).max(start))
+            }
+            None => (end, end),
+        };
+        [start..before_profile, after_profile..end].into_iter().filter(|range| !range.is_empty()).collect()
+    }
+
+    fn row_padding(&self) -> Vec<Range<usize>> {
+        if self.rle.is_some() || self.padding_size == 0 {
+            return Vec::new();
+        }
+
+        let padding = self.padding_size as usize;
+        let stride = self.unpadded_row_size() as usize + padding;
+        let start = self.pixel_map.pixel_map_start as usize;
+        (1..=self.pixel_map.height as usize).map(|row| start + row * stride - padding..start + row * stride).collect()
+    }
+
+    /*
+        The slack of the file as ranges of file_data, header gap first and then the rows in file order
+     */
+    pub fn slack_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges = self.header_gap();
+        ranges.extend(self.row_padding());
+        ranges
+    }
+
+    pub fn slack_report(&self) -> SlackReport {
+        let bytes = |ranges: Vec<Range<usize>>| ranges.iter().map(|range| range.len() as u64).sum();
+        SlackReport {
+            header_gap_bytes: bytes(self.header_gap()),
+            row_padding_bytes: bytes(self.row_padding()),
+        }
+    }
+
+    fn slack_bytes(&self) -> Vec<u8> {
+        self.slack_ranges().into_iter().flat_map(|range| self.file_data[range].iter().copied()).collect()
+    }
+
+    fn embed_slack(&mut self, framed: &[u8], file_encoding_function_derivation: FileEncodingFunctionDerivation) -> Result<(), StegoError> {
+        let mut slack = self.slack_bytes();
+        let mut slots = ByteSlots::new(&mut slack[..]);
+        let order = slots.order();
+        embed_slots(&mut slots, &order, file_encoding_function_derivation, framed)?;
+
+        let mut bytes = slack.into_iter();
+        for range in self.slack_ranges() {
+            for byte in &mut self.file_data[range] {
+                *byte = bytes.next().expect("slack_bytes reads the same ranges");
+            }
+        }
+        Ok(())
+    }
+
+    /*
+        Makes the header gap extra bytes larger so Slack has room. Zeros are inserted right in front of the
+        pixel data, bf_off_bits and bf_size grow with them and a profile stored after the pixel data is
+        pointed at its new offset. Pixels already embedded into are kept.
+     */
+    pub fn grow_header_gap(&mut self, extra: u32) -> Result<(), StegoError> {
         if !self.ready {
-            println!("bmp.rs: write_file called with File Not Ready");
-            exit(1);
-        }
-
-        match File::create(file_location.to_string()) {
-            Ok(_) => {}
-            Err(e) => {
-                println!(
-                    "bmp.rs: write_file Error opening file {} {}",
-                    file_location, e
-                );
-                exit(1);
+            return Err(StegoError::FileNotReady("bmp.rs: grow_header_gap"));
+        }
+
+        let too_large = || StegoError::UnsupportedFormat(format!("bmp.rs: grow_header_gap: {extra} more bytes do not fit in a BMP"));
+        let off_bits = self.bmp_header.bf_off_bits.checked_add(extra).ok_or_else(too_large)?;
+        let file_size = self.bmp_header.bf_size.checked_add(extra).ok_or_else(too_large)?;
+
+        let start = self.pixel_map.pixel_map_start as usize;
+        let profile_data = match self.color_profile {
+            Some(BmpColorProfile::Embedded { offset, .. } | BmpColorProfile::Linked { offset, .. }) if offset >= start as u64 => {
+                Some(self.v5_fields.profile_data.checked_add(extra).ok_or_else(too_large)?)
+            }
+            _ => None,
+        };
+
+        self.file_data.splice(start..start, std::iter::repeat_n(0u8, extra as usize));
+        self.file_data[2..6].copy_from_slice(&file_size.to_le_bytes());
+        self.file_data[10..14].copy_from_slice(&off_bits.to_le_bytes());
+        if let Some(profile_data) = profile_data {
+            self.file_data[BMP_FILE_HEADER_SIZE + 112..BMP_FILE_HEADER_SIZE + 116].copy_from_slice(&profile_data.to_le_bytes());
+        }
+
+        // The decoded indices of an RLE image are newer than the RLE data in file_data
+        let rle = self.rle.take();
+        self.parse_file()?;
+        if let (Some(mut rle), Some(reparsed)) = (rle, &self.rle) {
+            rle.encoded_end = reparsed.encoded_end;
+            self.rle = Some(rle);
+        }
+        Ok(())
+    }
+
+    fn has_default_bitfields(&self) -> bool {
+        let masks = (self.v5_fields.red_mask, self.v5_fields.green_mask, self.v5_fields.blue_mask);
+        self.header_version != BmpHeaderVersion::Info && masks == (0x00FF0000, 0x0000FF00, 0x000000FF)
+    }
+
+    /*
+        V2 and later headers hold the masks themselves (alpha from V3 on), a BITMAPINFOHEADER is followed
+        by three masks, or four for BI_ALPHABITFIELDS, which the pixel data has to start after
+     */
+    fn parse_bitfields(&self, dib_header_end: usize) -> Result<BitfieldFormat, StegoError> {
+        let bit_count = self.bmp_dib_header.bi_bit_count;
+
+        if self.header_version != BmpHeaderVersion::Info {
+            let masks = self.v5_fields;
+            let alpha_mask = if self.header_version == BmpHeaderVersion::V2 { 0 } else { masks.alpha_mask };
+            return BitfieldFormat::new(masks.red_mask, masks.green_mask, masks.blue_mask, alpha_mask, bit_count);
+        }
+
+        let mask_count = if self.bmp_dib_header.bi_compression == BI_ALPHABITFIELDS { 4 } else { 3 };
+        let masks_end = dib_header_end + mask_count * 4;
+        if masks_end as u64 > self.bmp_header.bf_off_bits as u64 || masks_end > self.file_data.len() {
+            return Err(StegoError::MalformedHeader(format!(
+                "bmp.rs: parse_file: the {mask_count} channel masks after the DIB header run into the pixel data"
+            )));
+        }
+
+        let mask = |index: usize| read_u32(&self.file_data, dib_header_end + index * 4);
+        let alpha_mask = if mask_count == 4 { mask(3) } else { 0 };
+        BitfieldFormat::new(mask(0), mask(1), mask(2), alpha_mask, bit_count)
+    }
+
+    fn pixel_grid<P: Pixel>(&mut self, format: P::Format) -> Result<PixelGrid<'_, P>, StegoError> {
+        PixelGrid::with_format(
+            &mut self.file_data[self.pixel_map.pixel_map_start as usize..],
+            self.pixel_map.width as usize,
+            self.pixel_map.height as usize,
+            self.padding_size as usize,
+            self.pixel_map.row_order,
+            format,
+        )
+    }
+
+    /*
+        An empty image with the layout, bit depths and palette decode_image would give, enough to know
+        how many bits a pixel can hold
+     */
+    pub fn image_layout(&self) -> Result<Image, StegoError> {
+        let (layout, bit_depths) = match self.pixel_type {
+            BmpPixelType::Rgb => (ChannelLayout::Rgb, RgbPixel::bit_depths(())),
+            BmpPixelType::Rgba => (ChannelLayout::Rgba, RgbaPixel::bit_depths(())),
+            BmpPixelType::Bitfield16(format) | BmpPixelType::Bitfield32(format) => {
+                let layout = if format.alpha_mask == 0 { ChannelLayout::Rgb } else { ChannelLayout::Rgba };
+                (layout, Bitfield32Pixel::bit_depths(format))
             }
+            BmpPixelType::Indexed(bits) => (ChannelLayout::Indexed, vec![bits]),
+        };
+
+        let mut image = Image::new(0, 0, layout, bit_depths)?;
+        image.palette = self.palette();
+        Ok(image)
+    }
+
+    /*
+        How many pixels of order carry bits and how many bits each of them holds. Palette images are
+        decoded from a copy of their indices, only pixels whose colour has a close partner count.
+     */
+    pub fn pixel_capacity(&self, encoding: FileEncoding, order: &[(usize, usize)]) -> Result<(u64, u64), StegoError> {
+        let width = self.pixel_map.width as usize;
+        let height = self.pixel_map.height as usize;
+
+        let image = self.image_layout()?;
+        let bits_per_pixel = bits_per_pixel(&image, encoding)?;
+        let BmpPixelType::Indexed(bits) = self.pixel_type else {
+            return Ok((unique_positions(width, height, order)?.len() as u64, bits_per_pixel));
+        };
+
+        let mut pixel_data = match &self.rle {
+            Some(rle) => rle.indices.clone(),
+            None => self.file_data[self.pixel_map.pixel_map_start as usize..].to_vec(),
+        };
+        let grid = IndexedPixelGrid::new(&mut pixel_data, width, height, bits as usize, self.padding_size as usize, self.pixel_map.row_order)?;
+        let image = image_from_indices(&grid, bits, &image.palette)?;
+        Ok((carrier_positions(&image, order, encoding)?.len() as u64, bits_per_pixel))
+    }
+
+    /*
+        The pixel data as an Image, palette images keep their indices and carry the color table as palette
+     */
+    pub fn decode_image(&mut self) -> Result<Image, StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("bmp.rs: decode_image"));
         }
-        let mut file = File::create(file_location.to_string()).expect("bmp.rs: write_file:  Error opening file");
 
-        match file.write_all(self.file_data.as_slice()) {
-            Ok(_) => {}
-            Err(e) => {
-                println!("bmp.rs: write_file called with File Not Ready {e}");
-                exit(1);
+        match self.pixel_type {
+            BmpPixelType::Rgb => image_from_grid(&self.pixel_grid::<RgbPixel>(())?),
+            BmpPixelType::Rgba => image_from_grid(&self.pixel_grid::<RgbaPixel>(())?),
+            BmpPixelType::Bitfield16(format) => image_from_grid(&self.pixel_grid::<Bitfield16Pixel>(format)?),
+            BmpPixelType::Bitfield32(format) => image_from_grid(&self.pixel_grid::<Bitfield32Pixel>(format)?),
+            BmpPixelType::Indexed(bits) => {
+                let palette = self.palette();
+                image_from_indices(&self.index_grid()?, bits, &palette)
             }
         }
     }
+
+    /*
+        Writes the samples of image back into the pixel data. The image has to be the one decode_image
+        gave, changed only in its samples, the headers and the color table are kept as they are.
+     */
+    pub fn encode_image(&mut self, image: &Image) -> Result<(), StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("bmp.rs: encode_image"));
+        }
+
+        let layout = self.image_layout()?;
+        if image.layout != layout.layout || image.bit_depths != layout.bit_depths || image.palette != layout.palette {
+            return Err(StegoError::UnsupportedFormat(format!(
+                "bmp.rs: encode_image: a {:?} image with bit depths {:?} does not match the {:?} bitmap",
+                image.layout, image.bit_depths, self.pixel_type
+            )));
+        }
+
+        match self.pixel_type {
+            BmpPixelType::Rgb => write_image_to_grid(image, &mut self.pixel_grid::<RgbPixel>(())?),
+            BmpPixelType::Rgba => write_image_to_grid(image, &mut self.pixel_grid::<RgbaPixel>(())?),
+            BmpPixelType::Bitfield16(format) => write_image_to_grid(image, &mut self.pixel_grid::<Bitfield16Pixel>(format)?),
+            BmpPixelType::Bitfield32(format) => write_image_to_grid(image, &mut self.pixel_grid::<Bitfield32Pixel>(format)?),
+            BmpPixelType::Indexed(_) => write_image_to_indices(image, &mut self.index_grid()?),
+        }
+    }
+}
+
+fn extract_slack(slack: &[u8], file_encoding_function_derivation: FileEncodingFunctionDerivation, embedded_bits: u64) -> Result<Vec<u8>, StegoError> {
+    let slots = ByteSlots::new(slack);
+    extract_slots(&slots, &slots.order(), file_encoding_function_derivation, embedded_bits)
+}
+
+/*
+   The BMP decoder and encoder. decode_bmp keeps the whole file as the "BMP" metadata block, encode_bmp
+   writes the samples back into it, so everything besides the pixel values survives the round trip.
+*/
+pub fn decode_bmp(bytes: &[u8]) -> Result<Image, StegoError> {
+    let mut parser = BmpImageParser::from_bytes(bytes)?;
+    parser.parse_file()?;
+
+    let mut image = parser.decode_image()?;
+    image.metadata.push(MetadataBlock {
+        name: "BMP".to_string(),
+        data: parser.file_data,
+    });
+    Ok(image)
+}
+
+pub fn encode_bmp(image: &Image) -> Result<Vec<u8>, StegoError> {
+    let container = image.metadata("BMP").ok_or_else(|| {
+        StegoError::UnsupportedFormat("bmp.rs: encode_bmp: the image was not decoded from a BMP file".to_string())
+    })?;
+
+    let mut parser = BmpImageParser::from_bytes(container)?;
+    parser.parse_file()?;
+    parser.encode_image(image)?;
+
+    let mut bytes = Vec::new();
+    parser.write_to(&mut bytes)?;
+    Ok(bytes)
 }
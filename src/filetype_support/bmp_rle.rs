@@ -0,0 +1,244 @@
+/*
+ * Copyright (C) 2025 Dustyn Gibb
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License
+ * as published by the Free Software Foundation; either version 2
+ * of the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
+ */
+use crate::file_encoding_support::stego_error::StegoError;
+
+/*
+   BI_RLE8 and BI_RLE4 pixel data. The stream is a list of two byte records, (n, value) repeats value
+   n times (for RLE4 value holds two indices that alternate), (0, 0) ends a row, (0, 1) ends the bitmap,
+   (0, 2, dx, dy) skips ahead and (0, n) is followed by n literal indices padded to a 16 bit boundary.
+   Rows go bottom up, RLE bitmaps can not be stored top down.
+
+   Decoding produces the pixel data an uncompressed bitmap of the same size would have, rows bottom up
+   and padded to 4 bytes, so everything that works on uncompressed palette images works on it. Pixels a
+   delta or an early end of row skip over get index 0, the background most decoders draw there.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RleCompression {
+    Rle8,
+    Rle4,
+}
+
+impl RleCompression {
+    pub fn bits_per_index(self) -> usize {
+        match self {
+            RleCompression::Rle8 => 8,
+            RleCompression::Rle4 => 4,
+        }
+    }
+}
+
+/*
+   Bytes per row of the decoded pixel data, including the padding to 4 bytes
+*/
+pub fn decoded_row_size(width: usize, compression: RleCompression) -> usize {
+    (width * compression.bits_per_index()).div_ceil(32) * 4
+}
+
+struct DecodedRows {
+    indices: Vec<u8>,
+    width: usize,
+    height: usize,
+    row_size: usize,
+    compression: RleCompression,
+}
+
+impl DecodedRows {
+    fn put(&mut self, x: usize, y: usize, index: u8) -> Result<(), StegoError> {
+        if x >= self.width || y >= self.height {
+            return Err(StegoError::MalformedHeader(format!(
+                "bmp_rle.rs: RLE data writes pixel ({x}, {y}) outside of the {}x{} bitmap",
+                self.width, self.height
+            )));
+        }
+
+        let offset = y * self.row_size;
+        match self.compression {
+            RleCompression::Rle8 => self.indices[offset + x] = index,
+            RleCompression::Rle4 => {
+                let shift = if x.is_multiple_of(2) { 4 } else { 0 };
+                let byte = &mut self.indices[offset + x / 2];
+                *byte = (*byte & !(0x0F << shift)) | ((index & 0x0F) << shift);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn truncated() -> StegoError {
+    StegoError::MalformedHeader("bmp_rle.rs: RLE data ends without an end of bitmap marker".to_string())
+}
+
+/*
+   Largest decoded pixel data decode_rle allocates. A delta can skip 255 rows in four bytes, so the size
+   of the RLE stream says nothing about the size of the bitmap and the header alone would let a few
+   bytes of file ask for gigabytes.
+*/
+pub const MAX_DECODED_SIZE: usize = 1 << 28;
+
+/*
+   data runs from the start of the pixel data to the end of the file, returns the decoded pixel data and
+   how many bytes of data the RLE stream took up
+*/
+pub fn decode_rle(data: &[u8], width: usize, height: usize, compression: RleCompression) -> Result<(Vec<u8>, usize), StegoError> {
+    let row_size = decoded_row_size(width, compression);
+    let size = row_size.checked_mul(height).filter(|&size| size <= MAX_DECODED_SIZE).ok_or_else(|| {
+        StegoError::UnsupportedFormat(format!(
+            "bmp_rle.rs: a {width}x{height} RLE bitmap decodes to more than {MAX_DECODED_SIZE} bytes"
+        ))
+    })?;
+
+    let mut rows = DecodedRows {
+        indices: vec![0; size],
+        width,
+        height,
+        row_size,
+        compression,
+    };
+
+    let (mut x, mut y, mut pos) = (0usize, 0usize, 0usize);
+    loop {
+        let record = data.get(pos..pos + 2).ok_or_else(truncated)?;
+        let (count, value) = (record[0], record[1]);
+        pos += 2;
+
+        if count > 0 {
+            for i in 0..count as usize {
+                let index = match compression {
+                    RleCompression::Rle8 => value,
+                    RleCompression::Rle4 if i.is_multiple_of(2) => value >> 4,
+                    RleCompression::Rle4 => value & 0x0F,
+                };
+                rows.put(x, y, index)?;
+                x += 1;
+            }
+            continue;
+        }
+
+        match value {
+            0 => {
+                x = 0;
+                y += 1;
+            }
+            1 => return Ok((rows.indices, pos)),
+            2 => {
+                let delta = data.get(pos..pos + 2).ok_or_else(truncated)?;
+                x += delta[0] as usize;
+                y += delta[1] as usize;
+                pos += 2;
+            }
+            literal => {
+                let literal = literal as usize;
+                let length = match compression {
+                    RleCompression::Rle8 => literal,
+                    RleCompression::Rle4 => literal.div_ceil(2),
+                };
+                let bytes = data.get(pos..pos + length).ok_or_else(truncated)?;
+
+                for i in 0..literal {
+                    let index = match compression {
+                        RleCompression::Rle8 => bytes[i],
+                        RleCompression::Rle4 if i.is_multiple_of(2) => bytes[i / 2] >> 4,
+                        RleCompression::Rle4 => bytes[i / 2] & 0x0F,
+                    };
+                    rows.put(x, y, index)?;
+                    x += 1;
+                }
+                pos += length + length % 2;
+            }
+        }
+    }
+}
+
+/*
+   Plain runs of one index and literal stretches, every row ends with an end of row record and the
+   last one with an end of bitmap record. indices is laid out the way decode_rle returns it.
+*/
+pub fn encode_rle(indices: &[u8], width: usize, height: usize, compression: RleCompression) -> Vec<u8> {
+    let row_size = decoded_row_size(width, compression);
+    let mut encoded = Vec::new();
+
+    for y in 0..height {
+        let row: Vec<u8> = (0..width)
+            .map(|x| match compression {
+                RleCompression::Rle8 => indices[y * row_size + x],
+                RleCompression::Rle4 if x.is_multiple_of(2) => indices[y * row_size + x / 2] >> 4,
+                RleCompression::Rle4 => indices[y * row_size + x / 2] & 0x0F,
+            })
+            .collect();
+
+        encode_row(&row, compression, &mut encoded);
+
+        if y + 1 < height {
+            encoded.extend_from_slice(&[0, 0]);
+        }
+    }
+
+    encoded.extend_from_slice(&[0, 1]);
+    encoded
+}
+
+fn run_length(row: &[u8], start: usize) -> usize {
+    row[start..].iter().take(255).take_while(|&&index| index == row[start]).count()
+}
+
+fn encode_row(row: &[u8], compression: RleCompression, encoded: &mut Vec<u8>) {
+    let mut x = 0;
+    while x < row.len() {
+        let run = run_length(row, x);
+        if run >= 3 {
+            let value = match compression {
+                RleCompression::Rle8 => row[x],
+                RleCompression::Rle4 => (row[x] << 4) | row[x],
+            };
+            encoded.extend_from_slice(&[run as u8, value]);
+            x += run;
+            continue;
+        }
+
+        // Literal stretch up to the next run worth encoding
+        let start = x;
+        while x < row.len() && x - start < 255 && (x == start || run_length(row, x) < 3) {
+            x += 1;
+        }
+        let literal = &row[start..x];
+
+        // Absolute mode needs at least 3 indices, anything shorter goes out as runs of one
+        if literal.len() < 3 {
+            for &index in literal {
+                let value = match compression {
+                    RleCompression::Rle8 => index,
+                    RleCompression::Rle4 => index << 4,
+                };
+                encoded.extend_from_slice(&[1, value]);
+            }
+            continue;
+        }
+
+        encoded.extend_from_slice(&[0, literal.len() as u8]);
+        let length_before = encoded.len();
+        match compression {
+            RleCompression::Rle8 => encoded.extend_from_slice(literal),
+            RleCompression::Rle4 => encoded.extend(
+                literal.chunks(2).map(|pair| (pair[0] << 4) | pair.get(1).copied().unwrap_or(0)),
+            ),
+        }
+        if !(encoded.len() - length_before).is_multiple_of(2) {
+            encoded.push(0);
+        }
+    }
+}
@@ -16,9 +16,20 @@ along with this program; if not, write to the Free Software
 Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
  */
 
+use crate::file_encoding_support::file_encoding_support::{
+    CapacityReport, FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod, FileEncodingSupport,
+};
+use crate::file_encoding_support::stego_error::StegoError;
+use crate::filetype_support::bmp::BmpImageParser;
+use crate::filetype_support::ico::IcoImageParser;
+use crate::filetype_support::png::{PngImageParser, PNG_SIGNATURE};
+use std::io::Write;
+
 #[derive(Copy,Clone,Debug,Eq,PartialEq)]
 pub enum FileType{
     Bmp,
+    Ico,
+    Cur,
     Png,
     Jpeg,
     Svg,
@@ -26,5 +37,125 @@ pub enum FileType{
     Mp4
 }
 
+const JPEG_MAGIC: [u8; 3] = [0xFF, 0xD8, 0xFF];
+const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+
+// How far into an XML file to look for the svg root element
+const SVG_SNIFF_LENGTH: usize = 1024;
+
+// Top level QuickTime atoms that can start a .mov written before the ftyp atom existed
+const QUICKTIME_ATOMS: [&[u8; 4]; 6] = [b"moov", b"mdat", b"wide", b"free", b"skip", b"pnot"];
+
+impl FileType {
+    /*
+        Works out the file type from the first bytes of the file, the extension is never looked at
+     */
+    pub fn detect(bytes: &[u8]) -> Result<FileType, StegoError> {
+        if bytes.starts_with(b"BM") {
+            return Ok(FileType::Bmp);
+        }
+
+        if bytes.starts_with(&PNG_SIGNATURE) {
+            return Ok(FileType::Png);
+        }
+
+        if bytes.starts_with(&JPEG_MAGIC) {
+            return Ok(FileType::Jpeg);
+        }
+
+        // ISO base media files (mp4) and QuickTime files both start with a size followed by an atom type
+        if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" {
+            return match &bytes[8..12] {
+                b"qt  " => Ok(FileType::Mov),
+                _ => Ok(FileType::Mp4),
+            };
+        }
+
+        if bytes.len() >= 8 && QUICKTIME_ATOMS.iter().any(|atom| &bytes[4..8] == *atom) {
+            return Ok(FileType::Mov);
+        }
 
+        // Icon directory, reserved 0, type 1 (icon) or 2 (cursor) and at least one entry
+        if bytes.len() >= 6 && bytes[0..2] == [0, 0] && bytes[3] == 0 && bytes[4..6] != [0, 0] {
+            match bytes[2] {
+                1 => return Ok(FileType::Ico),
+                2 => return Ok(FileType::Cur),
+                _ => {}
+            }
+        }
+
+        if is_svg(bytes) {
+            return Ok(FileType::Svg);
+        }
+
+        Err(StegoError::UnsupportedFormat(
+            "filetype_support.rs: the file is not a supported image type".to_string(),
+        ))
+    }
+}
+
+fn is_svg(bytes: &[u8]) -> bool {
+    let bytes = bytes.strip_prefix(&UTF8_BOM).unwrap_or(bytes);
+    let start = bytes.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(bytes.len());
+    let text = &bytes[start..bytes.len().min(start + SVG_SNIFF_LENGTH)];
+
+    if text.starts_with(b"<svg") {
+        return true;
+    }
+
+    (text.starts_with(b"<?xml") || text.starts_with(b"<!DOCTYPE svg") || text.starts_with(b"<!--"))
+        && text.windows(4).any(|window| window == b"<svg")
+}
 
+/*
+    Picks the parser for whatever is in bytes. The carrier comes back unparsed, just like T::from_bytes,
+    file types that are recognised but have no parser yet are reported as unsupported.
+ */
+pub fn carrier_from_bytes(bytes: &[u8]) -> Result<Box<dyn FileEncodingSupport>, StegoError> {
+    match FileType::detect(bytes)? {
+        FileType::Bmp => Ok(Box::new(BmpImageParser::from_bytes(bytes)?)),
+        FileType::Ico | FileType::Cur => Ok(Box::new(IcoImageParser::from_bytes(bytes)?)),
+        FileType::Png => Ok(Box::new(PngImageParser::from_bytes(bytes)?)),
+        file_type => Err(StegoError::UnsupportedFormat(format!(
+            "filetype_support.rs: {file_type:?} files are not supported as carriers yet"
+        ))),
+    }
+}
+
+/*
+    Lets Box<dyn FileEncodingSupport> stand in for a concrete parser anywhere a T: FileEncodingSupport is
+    expected, constructing one runs the format detection above
+ */
+impl FileEncodingSupport for Box<dyn FileEncodingSupport> {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, StegoError> {
+        carrier_from_bytes(bytes)
+    }
+
+    fn parse_file(&mut self) -> Result<(), StegoError> {
+        (**self).parse_file()
+    }
+
+    fn embed_data(&mut self, data: &mut Vec<u8>, encoding: FileEncoding, encoding_method: FileEncodingMethod, file_encoding_function_derivation: FileEncodingFunctionDerivation) -> Result<(), StegoError> {
+        (**self).embed_data(data, encoding, encoding_method, file_encoding_function_derivation)
+    }
+
+    fn retrieve_data(&mut self, encoding: FileEncoding, encoding_method: FileEncodingMethod, file_encoding_function_derivation: FileEncodingFunctionDerivation) -> Result<Vec<u8>, StegoError> {
+        (**self).retrieve_data(encoding, encoding_method, file_encoding_function_derivation)
+    }
+
+    fn capacity(&self, encoding: FileEncoding, encoding_method: FileEncodingMethod) -> Result<CapacityReport, StegoError> {
+        (**self).capacity(encoding, encoding_method)
+    }
+
+    fn non_standard_chunk_types(&self) -> Vec<String> {
+        (**self).non_standard_chunk_types()
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), StegoError> {
+        (**self).set_option(name, value)
+    }
+
+    fn write_to(&mut self, writer: &mut dyn Write) -> Result<(), StegoError> {
+        (**self).write_to(writer)
+    }
+}
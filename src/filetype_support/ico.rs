@@ -0,0 +1,417 @@
+/*
+ * Copyright (C) 2025 Dustyn Gibb
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License
+ * as published by the Free Software Foundation; either version 2
+ * of the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
+ */
+use crate::file_encoding_support::embedding_engine::{bits_per_pixel, carrier_positions, embed_encoded, extract_encoded};
+use crate::file_encoding_support::file_encoding_support::{
+    CapacityReport, FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod, FileEncodingSupport,
+};
+use crate::file_encoding_support::image::Image;
+use crate::file_encoding_support::payload_header::{frame_payload, unframe_payload};
+use crate::file_encoding_support::stego_error::StegoError;
+use crate::filetype_support::bmp::{BmpImageParser, BMP_FILE_HEADER_SIZE};
+use crate::filetype_support::png::PNG_SIGNATURE;
+use std::io::Write;
+
+const ICONDIR_SIZE: usize = 6;
+const ICONDIRENTRY_SIZE: usize = 16;
+
+const ICO_TYPE_ICON: u16 = 1;
+const ICO_TYPE_CURSOR: u16 = 2;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum IcoKind {
+    Icon,   // .ico
+    Cursor, // .cur, planes and bit_count of every entry are the hotspot instead
+}
+
+/*
+   One ICONDIRENTRY. The sizes in here are only hints, a width or height of 0 means 256, the image data
+   itself is what counts.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct IconDirEntry {
+    pub width: u8,
+    pub height: u8,
+    pub color_count: u8,
+    pub reserved: u8,
+    pub planes: u16,    // Hotspot x for cursors
+    pub bit_count: u16, // Hotspot y for cursors
+    pub bytes_in_res: u32,
+    pub image_offset: u32,
+}
+
+impl IconDirEntry {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        IconDirEntry {
+            width: bytes[0],
+            height: bytes[1],
+            color_count: bytes[2],
+            reserved: bytes[3],
+            planes: u16::from_le_bytes([bytes[4], bytes[5]]),
+            bit_count: u16::from_le_bytes([bytes[6], bytes[7]]),
+            bytes_in_res: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            image_offset: u32::from_le_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+}
+
+/*
+   A DIB entry is a BMP without its file header whose height counts the XOR image and the AND mask
+   together. It is parsed as a BMP of the XOR image, the mask is 1 bit per pixel, rows bottom up and
+   padded to 4 bytes, right after the XOR pixel data. A set mask bit makes the pixel transparent, those
+   pixels are never carriers since some editors clear them and some viewers show them.
+*/
+pub struct IcoDib {
+    pub bmp: BmpImageParser, // file_data is the entry with a file header in front and the height halved
+    pub mask_start: usize,   // Offset of the AND mask in bmp.file_data
+    pub mask_row_size: usize,
+}
+
+impl IcoDib {
+    fn parse(data: &[u8]) -> Result<Self, StegoError> {
+        if data.len() < 40 {
+            return Err(StegoError::MalformedHeader(format!(
+                "ico.rs: parse_file: a DIB entry of {} bytes is too small to hold a BITMAPINFOHEADER",
+                data.len()
+            )));
+        }
+
+        let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        let bi_size = read_u32(0);
+        if bi_size < 40 {
+            return Err(StegoError::UnsupportedFormat(format!(
+                "ico.rs: parse_file: icon images need a BITMAPINFOHEADER or later, not a {bi_size} byte header"
+            )));
+        }
+        let height = read_u32(8) as i32;
+        if height <= 0 || height % 2 != 0 {
+            return Err(StegoError::MalformedHeader(format!(
+                "ico.rs: parse_file: DIB height {height} does not hold an image and its mask"
+            )));
+        }
+
+        // The headers, channel masks and color table, the pixel data follows straight after them
+        let bit_count = u16::from_le_bytes([data[14], data[15]]);
+        let compression = read_u32(16);
+        let masks = match (bi_size, compression) {
+            (40, 3) => 12,
+            (40, 6) => 16,
+            _ => 0,
+        };
+        let entries = match read_u32(32) {
+            0 if bit_count <= 8 => 1u64 << bit_count,
+            used => used as u64,
+        };
+        let pixel_map_start = BMP_FILE_HEADER_SIZE as u64 + bi_size as u64 + masks + entries * 4;
+        let pixel_map_start = u32::try_from(pixel_map_start).map_err(|_| {
+            StegoError::MalformedHeader("ico.rs: parse_file: the DIB color table does not fit in the entry".to_string())
+        })?;
+
+        let mut bytes = Vec::with_capacity(BMP_FILE_HEADER_SIZE + data.len());
+        bytes.extend_from_slice(b"BM");
+        bytes.extend_from_slice(&((BMP_FILE_HEADER_SIZE + data.len()) as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        bytes.extend_from_slice(&pixel_map_start.to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes[BMP_FILE_HEADER_SIZE + 8..BMP_FILE_HEADER_SIZE + 12].copy_from_slice(&(height / 2).to_le_bytes());
+
+        let mut bmp = BmpImageParser::from_bytes(&bytes)?;
+        bmp.parse_file()?;
+        if bmp.rle.is_some() {
+            return Err(StegoError::UnsupportedFormat(
+                "ico.rs: parse_file: RLE compressed icon images are not supported".to_string(),
+            ));
+        }
+
+        let width = bmp.pixel_map.width as usize;
+        let rows = bmp.pixel_map.height as usize;
+        let stride = (width * bit_count as usize).div_ceil(8) + bmp.padding_size as usize;
+        let mask_start = bmp.pixel_map.pixel_map_start as usize + stride * rows;
+        let mask_row_size = width.div_ceil(32) * 4;
+        if mask_start + mask_row_size * rows > bmp.file_data.len() {
+            return Err(StegoError::MalformedHeader(format!(
+                "ico.rs: parse_file: the AND mask of a {width}x{rows} icon image runs past the end of its entry"
+            )));
+        }
+
+        Ok(IcoDib { bmp, mask_start, mask_row_size })
+    }
+
+    /*
+        Whether the AND mask hides the pixel, (row, col) is visual like everywhere else
+     */
+    pub fn transparent(&self, row: usize, col: usize) -> Option<bool> {
+        let rows = self.bmp.pixel_map.height as usize;
+        if row >= rows || col >= self.bmp.pixel_map.width as usize {
+            return None;
+        }
+
+        let byte = self.bmp.file_data[self.mask_start + (rows - 1 - row) * self.mask_row_size + col / 8];
+        Some(byte & (0x80 >> (col % 8)) != 0)
+    }
+
+    /*
+        order without the pixels the AND mask hides, points outside of the image are kept for the
+        engine to report
+     */
+    fn visible(&self, mut order: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+        order.retain(|&(row, col)| self.transparent(row, col) != Some(true));
+        order
+    }
+
+    // The entry as it is stored in the icon, the inverse of parse
+    fn entry_data(&self) -> Vec<u8> {
+        let mut data = self.bmp.file_data[BMP_FILE_HEADER_SIZE..].to_vec();
+        let height = self.bmp.pixel_map.height as i32 * 2;
+        data[8..12].copy_from_slice(&height.to_le_bytes());
+        data
+    }
+}
+
+/*
+   PNG entries are kept exactly as they are and carry nothing
+*/
+pub enum IcoImage {
+    Dib(Box<IcoDib>),
+    Png,
+}
+
+/*
+   Icons and cursors hold several sizes of the same picture. The payload is framed once and spread over
+   every DIB entry in directory order, each entry takes as many whole bytes as it has room for.
+*/
+pub struct IcoImageParser {
+    pub kind: IcoKind,
+    pub entries: Vec<IconDirEntry>,
+    pub images: Vec<IcoImage>,
+    pub file_data: Vec<u8>,
+    ready: bool,
+}
+
+impl FileEncodingSupport for IcoImageParser {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, StegoError> {
+        Ok(IcoImageParser {
+            kind: IcoKind::Icon,
+            entries: Vec::new(),
+            images: Vec::new(),
+            file_data: bytes.to_vec(),
+            ready: false,
+        })
+    }
+
+    fn parse_file(&mut self) -> Result<(), StegoError> {
+        if self.file_data.len() < ICONDIR_SIZE {
+            return Err(StegoError::MalformedHeader(format!(
+                "ico.rs: parse_file: file is {} bytes, too small to hold the icon directory",
+                self.file_data.len()
+            )));
+        }
+
+        let read_u16 = |offset: usize| u16::from_le_bytes([self.file_data[offset], self.file_data[offset + 1]]);
+        let (reserved, kind, count) = (read_u16(0), read_u16(2), read_u16(4) as usize);
+        self.kind = match (reserved, kind) {
+            (0, ICO_TYPE_ICON) => IcoKind::Icon,
+            (0, ICO_TYPE_CURSOR) => IcoKind::Cursor,
+            _ => {
+                return Err(StegoError::UnsupportedFormat(format!(
+                    "ico.rs: parse_file: reserved {reserved} and type {kind} are not an icon or a cursor"
+                )));
+            }
+        };
+
+        let directory_end = ICONDIR_SIZE + count * ICONDIRENTRY_SIZE;
+        if count == 0 || self.file_data.len() < directory_end {
+            return Err(StegoError::MalformedHeader(format!(
+                "ico.rs: parse_file: a directory of {count} entries does not fit in {} bytes",
+                self.file_data.len()
+            )));
+        }
+
+        self.entries = self.file_data[ICONDIR_SIZE..directory_end]
+            .chunks_exact(ICONDIRENTRY_SIZE)
+            .map(IconDirEntry::from_bytes)
+            .collect();
+
+        let mut used: Vec<(usize, usize)> = Vec::with_capacity(count);
+        self.images = Vec::with_capacity(count);
+        for entry in &self.entries {
+            let start = entry.image_offset as usize;
+            let end = start + entry.bytes_in_res as usize;
+            if start < directory_end || end > self.file_data.len() {
+                return Err(StegoError::MalformedHeader(format!(
+                    "ico.rs: parse_file: image data at {start}..{end} lies outside of the file"
+                )));
+            }
+
+            // Embedding writes into the entries, two of them must never share bytes
+            if used.iter().any(|&(other_start, other_end)| start < other_end && other_start < end) {
+                return Err(StegoError::MalformedHeader(format!(
+                    "ico.rs: parse_file: image data at {start}..{end} overlaps another entry"
+                )));
+            }
+            used.push((start, end));
+
+            let data = &self.file_data[start..end];
+            self.images.push(match data.starts_with(&PNG_SIGNATURE) {
+                true => IcoImage::Png,
+                false => IcoImage::Dib(Box::new(IcoDib::parse(data)?)),
+            });
+        }
+
+        self.ready = true;
+        Ok(())
+    }
+
+    fn embed_data(
+        &mut self,
+        data: &mut Vec<u8>,
+        encoding: FileEncoding,
+        encoding_method: FileEncodingMethod,
+        file_encoding_function_derivation: FileEncodingFunctionDerivation,
+    ) -> Result<(), StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("ico.rs: embed_data"));
+        }
+
+        let framed = frame_payload(data, encoding, encoding_method);
+        let mut carriers = self.carriers(encoding, encoding_method)?;
+
+        let available_bits = carriers.iter().map(|carrier| carrier.capacity_bytes * 8).sum();
+        let required_bits = framed.len() as u64 * 8;
+        if required_bits > available_bits {
+            return Err(StegoError::InsufficientCapacity { required_bits, available_bits });
+        }
+
+        let mut rest = &framed[..];
+        for carrier in &mut carriers {
+            let (chunk, remaining) = rest.split_at((carrier.capacity_bytes as usize).min(rest.len()));
+            rest = remaining;
+            if chunk.is_empty() {
+                break;
+            }
+
+            embed_encoded(&mut carrier.image, &carrier.order, encoding, file_encoding_function_derivation, chunk)?;
+            let IcoImage::Dib(dib) = &mut self.images[carrier.entry] else {
+                unreachable!("carriers are only made for DIB entries")
+            };
+            dib.bmp.encode_image(&carrier.image)?;
+
+            let start = self.entries[carrier.entry].image_offset as usize;
+            let entry_data = dib.entry_data();
+            self.file_data[start..start + entry_data.len()].copy_from_slice(&entry_data);
+        }
+
+        Ok(())
+    }
+
+    fn retrieve_data(
+        &mut self,
+        encoding: FileEncoding,
+        encoding_method: FileEncodingMethod,
+        file_encoding_function_derivation: FileEncodingFunctionDerivation,
+    ) -> Result<Vec<u8>, StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("ico.rs: retrieve_data"));
+        }
+
+        let carriers = self.carriers(encoding, encoding_method)?;
+        let available_bits: u64 = carriers.iter().map(|carrier| carrier.capacity_bytes * 8).sum();
+
+        unframe_payload(
+            |embedded_bits| {
+                if embedded_bits > available_bits {
+                    return Err(StegoError::InsufficientCapacity { required_bits: embedded_bits, available_bits });
+                }
+
+                let mut extracted = Vec::with_capacity(embedded_bits.div_ceil(8) as usize);
+                let mut remaining = embedded_bits;
+                for carrier in &carriers {
+                    if remaining == 0 {
+                        break;
+                    }
+                    let bits = remaining.min(carrier.capacity_bytes * 8);
+                    extracted.extend(extract_encoded(&carrier.image, &carrier.order, encoding, file_encoding_function_derivation, bits)?);
+                    remaining -= bits;
+                }
+                Ok(extracted)
+            },
+            encoding,
+            encoding_method,
+        )
+    }
+
+    fn capacity(&self, encoding: FileEncoding, encoding_method: FileEncodingMethod) -> Result<CapacityReport, StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("ico.rs: capacity"));
+        }
+
+        let mut total_bits = 0;
+        let mut most_bits_per_pixel = 0;
+        for image in &self.images {
+            let IcoImage::Dib(dib) = image else { continue };
+            let order = dib.visible(encoding_method.traverse(dib.bmp.pixel_map.height as usize, dib.bmp.pixel_map.width as usize));
+            let (pixels, bits_per_pixel) = dib.bmp.pixel_capacity(encoding, &order)?;
+
+            total_bits += pixels * bits_per_pixel / 8 * 8;
+            most_bits_per_pixel = most_bits_per_pixel.max(bits_per_pixel);
+        }
+
+        Ok(CapacityReport::new(total_bits, most_bits_per_pixel))
+    }
+
+    fn write_to(&mut self, writer: &mut dyn Write) -> Result<(), StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("ico.rs: write_to"));
+        }
+
+        writer.write_all(&self.file_data)?;
+        Ok(())
+    }
+}
+
+/*
+   A decoded DIB entry with the ordering of the encoding method over it and the whole bytes it can hold
+*/
+struct IcoCarrier {
+    entry: usize,
+    image: Image,
+    order: Vec<(usize, usize)>,
+    capacity_bytes: u64,
+}
+
+impl IcoImageParser {
+    fn carriers(&mut self, encoding: FileEncoding, encoding_method: FileEncodingMethod) -> Result<Vec<IcoCarrier>, StegoError> {
+        let mut carriers = Vec::new();
+
+        for (entry, image) in self.images.iter_mut().enumerate() {
+            let IcoImage::Dib(dib) = image else { continue };
+            let image = dib.bmp.decode_image()?;
+            let order = dib.visible(encoding_method.traverse(image.height, image.width));
+            let bits = carrier_positions(&image, &order, encoding)?.len() as u64 * bits_per_pixel(&image, encoding)?;
+
+            carriers.push(IcoCarrier { entry, image, order, capacity_bytes: bits / 8 });
+        }
+
+        if carriers.is_empty() {
+            return Err(StegoError::UnsupportedFormat(
+                "ico.rs: only PNG entries, there is no DIB image to embed into".to_string(),
+            ));
+        }
+
+        Ok(carriers)
+    }
+}
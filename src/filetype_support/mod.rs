@@ -18,6 +18,9 @@ Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
 pub mod filetype_support;
 pub mod svg;
 pub mod bmp;
+pub mod bmp_rle;
+pub mod ico;
 mod test;
-mod png;
+pub mod png;
+pub mod png_chunks;
 mod jpg;
\ No newline at end of file
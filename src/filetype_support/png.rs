@@ -16,3 +16,1295 @@
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
  */
 
+use crate::compression::compression::{zlib_compress, zlib_compress_with, zlib_decompress, DeflateBlocks, MAX_LEVEL};
+use crate::file_encoding_support::embedding_engine::{bits_per_pixel, carrier_positions, embed_encoded, extract_encoded};
+use crate::file_encoding_support::file_encoding_support::{
+    CapacityReport, FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod, FileEncodingSupport,
+};
+use crate::file_encoding_support::image::{ChannelLayout, Image, MetadataBlock};
+use crate::file_encoding_support::palette::add_palette_twins;
+use crate::file_encoding_support::payload_header::{frame_payload, unframe_payload};
+use crate::file_encoding_support::pixel::{image_from_grid, write_image_to_grid, Pixel};
+use crate::file_encoding_support::pixel_grid::{PixelGrid, RowOrder};
+use crate::file_encoding_support::stego_error::StegoError;
+use crate::filetype_support::png_chunks::{embed_in_chunks, extract_from_chunks, non_standard_chunks, ChunkEmbedding, ChunkPlacement, ChunkStyle, DEFAULT_KEYWORD};
+use crate::mathematics_support::mathematics_support::{crc32, crc32_update};
+use std::io::Write;
+
+pub const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+// The spec limits width, height and chunk lengths to 2^31 - 1
+pub(crate) const PNG_MAX_VALUE: u32 = 0x7FFF_FFFF;
+
+const FILTER_NONE: u8 = 0;
+const FILTER_SUB: u8 = 1;
+const FILTER_UP: u8 = 2;
+const FILTER_AVERAGE: u8 = 3;
+const FILTER_PAETH: u8 = 4;
+
+/*
+   One chunk of the file, the length and CRC are only checked on the way in and worked out again
+   when the chunk is written
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PngChunk {
+    pub chunk_type: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl PngChunk {
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.chunk_type).into_owned()
+    }
+
+    // Bit 5 of the first letter, upper case means a decoder has to understand the chunk to show the image
+    pub fn is_critical(&self) -> bool {
+        self.chunk_type[0] & 0x20 == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PngColorType {
+    Gray,
+    Rgb,
+    Indexed,
+    GrayAlpha,
+    Rgba,
+}
+
+impl PngColorType {
+    fn from_byte(color_type: u8) -> Option<PngColorType> {
+        match color_type {
+            0 => Some(PngColorType::Gray),
+            2 => Some(PngColorType::Rgb),
+            3 => Some(PngColorType::Indexed),
+            4 => Some(PngColorType::GrayAlpha),
+            6 => Some(PngColorType::Rgba),
+            _ => None,
+        }
+    }
+
+    pub fn layout(self) -> ChannelLayout {
+        match self {
+            PngColorType::Gray => ChannelLayout::Gray,
+            PngColorType::Rgb => ChannelLayout::Rgb,
+            PngColorType::Indexed => ChannelLayout::Indexed,
+            PngColorType::GrayAlpha => ChannelLayout::GrayAlpha,
+            PngColorType::Rgba => ChannelLayout::Rgba,
+        }
+    }
+
+    fn allowed_bit_depths(self) -> &'static [u8] {
+        match self {
+            PngColorType::Gray => &[1, 2, 4, 8, 16],
+            PngColorType::Indexed => &[1, 2, 4, 8],
+            PngColorType::Rgb | PngColorType::GrayAlpha | PngColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PngHeader {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: PngColorType,
+    pub interlaced: bool,
+}
+
+impl PngHeader {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, StegoError> {
+        if data.len() != 13 {
+            return Err(StegoError::MalformedHeader(format!("png.rs: IHDR is {} bytes instead of 13", data.len())));
+        }
+
+        let width = u32::from_be_bytes(data[0..4].try_into().unwrap());
+        let height = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        if width == 0 || height == 0 || width > PNG_MAX_VALUE || height > PNG_MAX_VALUE {
+            return Err(StegoError::MalformedHeader(format!("png.rs: {width}x{height} is not a valid PNG size")));
+        }
+
+        let (bit_depth, color_type) = (data[8], data[9]);
+        let color_type = PngColorType::from_byte(color_type)
+            .filter(|color_type| color_type.allowed_bit_depths().contains(&bit_depth))
+            .ok_or_else(|| StegoError::MalformedHeader(format!(
+                "png.rs: color type {color_type} with bit depth {bit_depth} is not a valid combination"
+            )))?;
+
+        if data[10] != 0 || data[11] != 0 {
+            return Err(StegoError::UnsupportedFormat(format!(
+                "png.rs: compression method {} and filter method {} are not defined",
+                data[10], data[11]
+            )));
+        }
+        let interlaced = match data[12] {
+            0 => false,
+            1 => true,
+            method => {
+                return Err(StegoError::UnsupportedFormat(format!("png.rs: interlace method {method} is not defined")));
+            }
+        };
+
+        Ok(PngHeader { width, height, bit_depth, color_type, interlaced })
+    }
+
+    pub fn channels(&self) -> usize {
+        self.color_type.layout().channels()
+    }
+
+    // Bytes a filter looks back to find the same byte of the previous pixel, at least 1
+    pub fn filter_stride(&self) -> usize {
+        (self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    // Bytes of one row of a width pixels wide image, without the filter type byte
+    pub fn row_size(&self, width: usize) -> usize {
+        (width * self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+}
+
+/*
+   Splits a file into its chunks, checking the signature and every CRC. The list runs from IHDR to IEND
+   in file order, anything after IEND is not part of the image and is dropped.
+*/
+pub fn read_chunks(bytes: &[u8]) -> Result<Vec<PngChunk>, StegoError> {
+    if !bytes.starts_with(&PNG_SIGNATURE) {
+        return Err(StegoError::MalformedHeader("png.rs: the file does not start with the PNG signature".to_string()));
+    }
+
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    loop {
+        let header = bytes.get(pos..pos + 8).ok_or_else(|| {
+            StegoError::MalformedHeader("png.rs: the file ends without an IEND chunk".to_string())
+        })?;
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap());
+        let chunk_type: [u8; 4] = header[4..8].try_into().unwrap();
+        if length > PNG_MAX_VALUE || !chunk_type.iter().all(u8::is_ascii_alphabetic) {
+            return Err(StegoError::MalformedHeader(format!("png.rs: bad chunk header at offset {pos}")));
+        }
+
+        let data_start = pos + 8;
+        let data_end = data_start + length as usize;
+        let data = bytes.get(data_start..data_end).ok_or_else(|| {
+            StegoError::MalformedHeader(format!(
+                "png.rs: {} chunk runs past the end of the file",
+                String::from_utf8_lossy(&chunk_type)
+            ))
+        })?;
+        let stored_crc = bytes
+            .get(data_end..data_end + 4)
+            .map(|crc| u32::from_be_bytes(crc.try_into().unwrap()))
+            .ok_or_else(|| StegoError::MalformedHeader("png.rs: the last chunk has no CRC".to_string()))?;
+
+        let chunk = PngChunk { chunk_type, data: data.to_vec() };
+        if crc32_update(crc32(&chunk.chunk_type), &chunk.data) != stored_crc {
+            return Err(StegoError::IntegrityFailure(format!("png.rs: CRC of the {} chunk at offset {pos} does not match", chunk.name())));
+        }
+
+        pos = data_end + 4;
+        let end = &chunk.chunk_type == b"IEND";
+        chunks.push(chunk);
+        if end {
+            return Ok(chunks);
+        }
+    }
+}
+
+struct ImageChunks {
+    header: PngHeader,
+    palette: Vec<[u8; 3]>,
+    palette_alpha: Vec<u8>, // tRNS of an indexed image
+    transparent_key: Option<Vec<u16>>, // tRNS of a grey or truecolour image
+    compressed: Vec<u8>, // every IDAT chunk joined together
+}
+
+/*
+   Reads IHDR, PLTE, tRNS and the IDAT data out of the chunks and checks they come in the order the spec
+   asks for. Unknown critical chunks are refused, an image that depends on them can not be shown right.
+*/
+fn image_chunks(chunks: &[PngChunk]) -> Result<ImageChunks, StegoError> {
+    let first = chunks.first().filter(|chunk| &chunk.chunk_type == b"IHDR").ok_or_else(|| {
+        StegoError::MalformedHeader("png.rs: the first chunk is not IHDR".to_string())
+    })?;
+    let header = PngHeader::from_bytes(&first.data)?;
+
+    let mut palette = None;
+    let mut transparency = None;
+    let mut compressed = Vec::new();
+    // 0 before the IDAT chunks, 1 inside them and 2 once they ended
+    let mut idat_state = 0;
+
+    for chunk in &chunks[1..] {
+        if &chunk.chunk_type == b"IDAT" {
+            if idat_state == 2 {
+                return Err(StegoError::MalformedHeader("png.rs: IDAT chunks are not consecutive".to_string()));
+            }
+            idat_state = 1;
+            compressed.extend_from_slice(&chunk.data);
+            continue;
+        }
+        if idat_state == 1 {
+            idat_state = 2;
+        }
+
+        match &chunk.chunk_type {
+            b"PLTE" => {
+                if palette.is_some() || idat_state != 0 {
+                    return Err(StegoError::MalformedHeader("png.rs: PLTE is repeated or comes after IDAT".to_string()));
+                }
+                if chunk.data.is_empty() || chunk.data.len() % 3 != 0 || chunk.data.len() > 256 * 3 {
+                    return Err(StegoError::MalformedHeader(format!("png.rs: PLTE holds {} bytes", chunk.data.len())));
+                }
+                palette = Some(chunk.data.chunks_exact(3).map(|entry| [entry[0], entry[1], entry[2]]).collect::<Vec<_>>());
+            }
+            b"tRNS" => {
+                if transparency.is_some() || idat_state != 0 || (header.color_type == PngColorType::Indexed && palette.is_none()) {
+                    return Err(StegoError::MalformedHeader("png.rs: tRNS is repeated, comes before PLTE or after IDAT".to_string()));
+                }
+                transparency = Some(chunk.data.clone());
+            }
+            b"IHDR" => return Err(StegoError::MalformedHeader("png.rs: IHDR is repeated".to_string())),
+            b"IEND" => {}
+            _ if chunk.is_critical() => {
+                return Err(StegoError::UnsupportedFormat(format!("png.rs: unknown critical chunk {}", chunk.name())));
+            }
+            _ => {}
+        }
+    }
+
+    if idat_state == 0 {
+        return Err(StegoError::MalformedHeader("png.rs: the file has no IDAT chunk".to_string()));
+    }
+
+    // For grey and truecolour images tRNS is a single colour key, one 16 bit sample per channel
+    let transparent_key = match (header.color_type, transparency.as_deref()) {
+        (PngColorType::Gray | PngColorType::Rgb, Some(key)) => {
+            let channels = if header.color_type == PngColorType::Gray { 1 } else { 3 };
+            if key.len() != channels * 2 {
+                return Err(StegoError::MalformedHeader(format!(
+                    "png.rs: tRNS of {} bytes is no colour key for {channels} channels",
+                    key.len()
+                )));
+            }
+            let max = ((1u32 << header.bit_depth) - 1) as u16;
+            Some(key.chunks_exact(2).map(|sample| u16::from_be_bytes([sample[0], sample[1]]) & max).collect())
+        }
+        _ => None,
+    };
+
+    // A palette is only a suggestion for truecolour images, the samples do not refer to it
+    let (palette, palette_alpha) = match header.color_type {
+        PngColorType::Indexed => {
+            let palette = palette.ok_or_else(|| StegoError::MalformedHeader("png.rs: indexed image without PLTE".to_string()))?;
+            if palette.len() > 1 << header.bit_depth {
+                return Err(StegoError::MalformedHeader(format!(
+                    "png.rs: {} palette entries do not fit {} bit indices",
+                    palette.len(),
+                    header.bit_depth
+                )));
+            }
+            let palette_alpha = transparency.unwrap_or_default();
+            if palette_alpha.len() > palette.len() {
+                return Err(StegoError::MalformedHeader(format!(
+                    "png.rs: tRNS has {} entries for a palette of {}",
+                    palette_alpha.len(),
+                    palette.len()
+                )));
+            }
+            (palette, palette_alpha)
+        }
+        PngColorType::Gray | PngColorType::GrayAlpha if palette.is_some() => {
+            return Err(StegoError::MalformedHeader("png.rs: greyscale image with a PLTE chunk".to_string()));
+        }
+        _ => (Vec::new(), Vec::new()),
+    };
+
+    Ok(ImageChunks { header, palette, palette_alpha, transparent_key, compressed })
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (to_left, to_up, to_up_left) = (
+        (estimate - left as i16).abs(),
+        (estimate - up as i16).abs(),
+        (estimate - up_left as i16).abs(),
+    );
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+/*
+   Undoes the filter on every row. filtered holds height rows of a filter type byte followed by
+   row_size bytes, the result is the bare rows back to back.
+*/
+fn unfilter(filtered: &[u8], row_size: usize, height: usize, stride: usize) -> Result<Vec<u8>, StegoError> {
+    let mut rows = vec![0u8; row_size * height];
+    let zero_row = vec![0u8; row_size];
+
+    for (row, line) in filtered.chunks_exact(row_size + 1).take(height).enumerate() {
+        let (filter, line) = (line[0], &line[1..]);
+        let (done, rest) = rows.split_at_mut(row * row_size);
+        let previous = if row == 0 { &zero_row[..] } else { &done[(row - 1) * row_size..] };
+        let current = &mut rest[..row_size];
+
+        for i in 0..row_size {
+            let left = if i >= stride { current[i - stride] } else { 0 };
+            let up_left = if i >= stride { previous[i - stride] } else { 0 };
+            let up = previous[i];
+            let predictor = match filter {
+                FILTER_NONE => 0,
+                FILTER_SUB => left,
+                FILTER_UP => up,
+                FILTER_AVERAGE => ((left as u16 + up as u16) / 2) as u8,
+                FILTER_PAETH => paeth(left, up, up_left),
+                _ => {
+                    return Err(StegoError::MalformedHeader(format!("png.rs: row {row} uses unknown filter type {filter}")));
+                }
+            };
+            current[i] = line[i].wrapping_add(predictor);
+        }
+    }
+    Ok(rows)
+}
+
+/*
+   16 bit samples the way PNG stores them, N big endian samples in Image order (grey or red, green and
+   blue, then alpha when there is one). sample and set_sample work on all 16 bits. The 8 bit accessors
+   behave like the ones of BitfieldPixel, red, green, blue and alpha are the high byte, which is the
+   value scaled to 0-255, and first to fourth hand out and replace the low byte.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Png16Pixel<const N: usize> {
+    pub samples: [u16; N],
+}
+
+pub type Gray16Pixel = Png16Pixel<1>;
+pub type GrayAlpha32Pixel = Png16Pixel<2>;
+pub type Rgb48Pixel = Png16Pixel<3>;
+pub type Rgba64Pixel = Png16Pixel<4>;
+
+impl<const N: usize> Png16Pixel<N> {
+    const HAS_ALPHA: bool = N == 2 || N == 4;
+
+    // Grey pixels answer every colour with their one grey sample
+    fn colour_channel(colour: usize) -> usize {
+        if N >= 3 { colour } else { 0 }
+    }
+
+    fn high_byte(&self, channel: usize) -> u8 {
+        (self.samples[channel] >> 8) as u8
+    }
+
+    fn set_high_byte(&mut self, channel: usize, value: u8) {
+        self.samples[channel] = value as u16 * 257;
+    }
+
+    fn low_byte(&self, channel: usize) -> u8 {
+        self.samples.get(channel).map_or(255, |&sample| sample as u8)
+    }
+
+    fn set_low_byte(&mut self, channel: usize, value: u8) {
+        if let Some(sample) = self.samples.get_mut(channel) {
+            *sample = (*sample & 0xFF00) | value as u16;
+        }
+    }
+}
+
+impl<const N: usize> Pixel for Png16Pixel<N> {
+    const SIZE: usize = 2 * N;
+
+    type Format = ();
+
+    fn from_bytes(bytes: &[u8], _format: ()) -> Self {
+        let mut samples = [0u16; N];
+        for (sample, bytes) in samples.iter_mut().zip(bytes.chunks_exact(2)) {
+            *sample = u16::from_be_bytes([bytes[0], bytes[1]]);
+        }
+        Png16Pixel { samples }
+    }
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        for (bytes, sample) in bytes.chunks_exact_mut(2).zip(&self.samples) {
+            bytes.copy_from_slice(&sample.to_be_bytes());
+        }
+    }
+
+    fn channel_count(_format: ()) -> usize {
+        N
+    }
+
+    fn layout(_format: ()) -> Option<ChannelLayout> {
+        ChannelLayout::from_channels(N, N >= 3)
+    }
+
+    fn bit_depths(_format: ()) -> Vec<u8> {
+        vec![16; N]
+    }
+
+    fn sample(&self, channel: usize) -> u16 {
+        self.samples.get(channel).copied().unwrap_or(0)
+    }
+
+    fn set_sample(&mut self, channel: usize, value: u16) {
+        if let Some(sample) = self.samples.get_mut(channel) {
+            *sample = value;
+        }
+    }
+
+    fn red(&self) -> u8 {
+        self.high_byte(Self::colour_channel(0))
+    }
+    fn green(&self) -> u8 {
+        self.high_byte(Self::colour_channel(1))
+    }
+    fn blue(&self) -> u8 {
+        self.high_byte(Self::colour_channel(2))
+    }
+    fn alpha(&self) -> u8 {
+        if !Self::HAS_ALPHA {
+            return 255;
+        }
+        self.high_byte(N - 1)
+    }
+
+    fn first(&self) -> u8 {
+        self.low_byte(0)
+    }
+    fn second(&self) -> u8 {
+        self.low_byte(1)
+    }
+    fn third(&self) -> u8 {
+        self.low_byte(2)
+    }
+    fn fourth(&self) -> u8 {
+        self.low_byte(3)
+    }
+
+    fn set_red(&mut self, value: u8) {
+        self.set_high_byte(Self::colour_channel(0), value)
+    }
+    fn set_green(&mut self, value: u8) {
+        self.set_high_byte(Self::colour_channel(1), value)
+    }
+    fn set_blue(&mut self, value: u8) {
+        self.set_high_byte(Self::colour_channel(2), value)
+    }
+
+    fn set_first(&mut self, value: u8) {
+        self.set_low_byte(0, value)
+    }
+    fn set_second(&mut self, value: u8) {
+        self.set_low_byte(1, value)
+    }
+    fn set_third(&mut self, value: u8) {
+        self.set_low_byte(2, value)
+    }
+
+    fn set_fourth(&mut self, value: u8) -> Result<(), StegoError> {
+        if N < 4 {
+            return Err(StegoError::InvalidChannel("png.rs Png16Pixel set_fourth called on a pixel with less than 4 samples"));
+        }
+        self.set_low_byte(3, value);
+        Ok(())
+    }
+
+    fn set_alpha(&mut self, value: u8) -> Result<(), StegoError> {
+        if !Self::HAS_ALPHA {
+            return Err(StegoError::InvalidChannel("png.rs Png16Pixel set_alpha called on a pixel without alpha"));
+        }
+        self.set_high_byte(N - 1, value);
+        Ok(())
+    }
+
+    fn pixel_size(&self) -> usize {
+        2 * N
+    }
+}
+
+/*
+   Turns unfiltered rows of a width x height image (or pass) into an Image. 16 bit rows are read through
+   a PixelGrid of Png16Pixel, samples below 8 bits are packed from the highest bit of each byte.
+*/
+fn unpack_samples(rows: &mut [u8], header: &PngHeader, width: usize, height: usize) -> Result<Image, StegoError> {
+    let depth = header.bit_depth as usize;
+    if depth == 16 {
+        return match header.channels() {
+            1 => image_from_grid(&PixelGrid::<Gray16Pixel>::new(rows, width, height, 0, RowOrder::TopDown)?),
+            2 => image_from_grid(&PixelGrid::<GrayAlpha32Pixel>::new(rows, width, height, 0, RowOrder::TopDown)?),
+            3 => image_from_grid(&PixelGrid::<Rgb48Pixel>::new(rows, width, height, 0, RowOrder::TopDown)?),
+            _ => image_from_grid(&PixelGrid::<Rgba64Pixel>::new(rows, width, height, 0, RowOrder::TopDown)?),
+        };
+    }
+
+    let layout = header.color_type.layout();
+    let mut image = Image::new(width, height, layout, vec![header.bit_depth; layout.channels()])?;
+    let row_size = header.row_size(width);
+    let samples_per_row = width * header.channels();
+
+    for (row, samples) in rows.chunks_exact(row_size).zip(image.samples.chunks_exact_mut(samples_per_row)) {
+        if depth == 8 {
+            for (sample, byte) in samples.iter_mut().zip(row) {
+                *sample = *byte as u16;
+            }
+        } else {
+            let mask = (1u16 << depth) - 1;
+            for (i, sample) in samples.iter_mut().enumerate() {
+                let bit = i * depth;
+                *sample = (row[bit / 8] as u16 >> (8 - depth - bit % 8)) & mask;
+            }
+        }
+    }
+    Ok(image)
+}
+
+/*
+   The other way around, samples packed into rows the way unpack_samples reads them
+*/
+fn pack_samples(image: &Image, header: &PngHeader) -> Result<Vec<u8>, StegoError> {
+    let (width, height) = (image.width, image.height);
+    let row_size = header.row_size(width);
+    let depth = header.bit_depth as usize;
+    let mut rows = vec![0u8; row_size * height];
+
+    if depth == 16 {
+        match header.channels() {
+            1 => write_image_to_grid(image, &mut PixelGrid::<Gray16Pixel>::new(&mut rows, width, height, 0, RowOrder::TopDown)?)?,
+            2 => write_image_to_grid(image, &mut PixelGrid::<GrayAlpha32Pixel>::new(&mut rows, width, height, 0, RowOrder::TopDown)?)?,
+            3 => write_image_to_grid(image, &mut PixelGrid::<Rgb48Pixel>::new(&mut rows, width, height, 0, RowOrder::TopDown)?)?,
+            _ => write_image_to_grid(image, &mut PixelGrid::<Rgba64Pixel>::new(&mut rows, width, height, 0, RowOrder::TopDown)?)?,
+        }
+        return Ok(rows);
+    }
+
+    let samples_per_row = width * header.channels();
+    for (row, samples) in rows.chunks_exact_mut(row_size).zip(image.samples.chunks_exact(samples_per_row)) {
+        if depth == 8 {
+            for (byte, sample) in row.iter_mut().zip(samples) {
+                *byte = *sample as u8;
+            }
+        } else {
+            for (i, sample) in samples.iter().enumerate() {
+                let bit = i * depth;
+                row[bit / 8] |= (*sample as u8) << (8 - depth - bit % 8);
+            }
+        }
+    }
+    Ok(rows)
+}
+
+fn filter_row(filter: u8, current: &[u8], previous: &[u8], stride: usize, output: &mut Vec<u8>) {
+    output.push(filter);
+    for i in 0..current.len() {
+        let left = if i >= stride { current[i - stride] } else { 0 };
+        let up_left = if i >= stride { previous[i - stride] } else { 0 };
+        let up = previous[i];
+        let predictor = match filter {
+            FILTER_SUB => left,
+            FILTER_UP => up,
+            FILTER_AVERAGE => ((left as u16 + up as u16) / 2) as u8,
+            FILTER_PAETH => paeth(left, up, up_left),
+            _ => 0,
+        };
+        output.push(current[i].wrapping_sub(predictor));
+    }
+}
+
+/*
+   Filters every row. With filters every row gets the filter type given for it, the ones the original
+   file used. Otherwise rows of palette images and of samples below 8 bits are left unfiltered as the
+   spec recommends, every other row gets whichever filter gives the smallest sum of its bytes read as
+   signed values, the heuristic libpng and most other encoders use.
+*/
+fn filter_rows(rows: &[u8], row_size: usize, header: &PngHeader, filters: Option<&[u8]>) -> Vec<u8> {
+    let stride = header.filter_stride();
+    let adaptive = header.color_type != PngColorType::Indexed && header.bit_depth >= 8;
+    let zero_row = vec![0u8; row_size];
+    let mut filtered = Vec::with_capacity(rows.len() + rows.len() / row_size.max(1));
+    let mut candidate = Vec::with_capacity(row_size + 1);
+
+    for (row, current) in rows.chunks_exact(row_size).enumerate() {
+        let previous = if row == 0 { &zero_row[..] } else { &rows[(row - 1) * row_size..row * row_size] };
+        if let Some(filters) = filters {
+            filter_row(filters[row], current, previous, stride, &mut filtered);
+            continue;
+        }
+        if !adaptive {
+            filter_row(FILTER_NONE, current, previous, stride, &mut filtered);
+            continue;
+        }
+
+        let mut best: Option<(u64, u8)> = None;
+        for filter in [FILTER_NONE, FILTER_SUB, FILTER_UP, FILTER_AVERAGE, FILTER_PAETH] {
+            candidate.clear();
+            filter_row(filter, current, previous, stride, &mut candidate);
+            let score: u64 = candidate[1..].iter().map(|&byte| (byte as i8).unsigned_abs() as u64).sum();
+            if best.is_none_or(|(best_score, _)| score < best_score) {
+                best = Some((score, filter));
+            }
+        }
+        filter_row(best.map_or(FILTER_NONE, |(_, filter)| filter), current, previous, stride, &mut filtered);
+    }
+    filtered
+}
+
+/*
+   Adam7 splits the image into 7 passes, pass n holds the pixels at (first_row + i * row_step,
+   first_col + j * col_step). Each pass is filtered like a small image of its own and passes that get
+   no pixels at all (in images narrower or shorter than 8) leave nothing in the data.
+*/
+const ADAM7: [(usize, usize, usize, usize); 7] = [(0, 0, 8, 8), (0, 4, 8, 8), (4, 0, 8, 4), (0, 2, 4, 4), (2, 0, 4, 2), (0, 1, 2, 2), (1, 0, 2, 1)];
+
+#[derive(Debug, Clone, Copy)]
+struct Adam7Pass {
+    first_row: usize,
+    first_col: usize,
+    row_step: usize,
+    col_step: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Adam7Pass {
+    // Where pixel (row, col) of the pass is in the full image
+    fn position(&self, row: usize, col: usize) -> (usize, usize) {
+        (self.first_row + row * self.row_step, self.first_col + col * self.col_step)
+    }
+}
+
+fn adam7_passes(width: usize, height: usize) -> Vec<Adam7Pass> {
+    ADAM7
+        .iter()
+        .map(|&(first_row, first_col, row_step, col_step)| Adam7Pass {
+            first_row,
+            first_col,
+            row_step,
+            col_step,
+            width: width.saturating_sub(first_col).div_ceil(col_step),
+            height: height.saturating_sub(first_row).div_ceil(row_step),
+        })
+        .filter(|pass| pass.width > 0 && pass.height > 0)
+        .collect()
+}
+
+/*
+   Bytes of filtered image data, a filter type byte in front of every row (of every pass)
+*/
+fn filtered_size(header: &PngHeader) -> Result<usize, StegoError> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    let sizes = match header.interlaced {
+        true => adam7_passes(width, height).iter().map(|pass| (pass.width, pass.height)).collect(),
+        false => vec![(width, height)],
+    };
+    sizes
+        .into_iter()
+        .try_fold(0usize, |total, (width, height)| (header.row_size(width) + 1).checked_mul(height).and_then(|size| total.checked_add(size)))
+        .ok_or_else(|| StegoError::MalformedHeader(format!("png.rs: {width}x{height} image overflows")))
+}
+
+/*
+   The filter type byte of every row (of every pass) of filtered image data, in the order they are stored
+*/
+fn row_filters(filtered: &[u8], header: &PngHeader) -> Vec<u8> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    let sizes = match header.interlaced {
+        true => adam7_passes(width, height).iter().map(|pass| (pass.width, pass.height)).collect(),
+        false => vec![(width, height)],
+    };
+
+    let mut filters = Vec::new();
+    let mut offset = 0;
+    for (width, height) in sizes {
+        let row_size = header.row_size(width) + 1;
+        filters.extend(filtered[offset..offset + row_size * height].chunks_exact(row_size).map(|row| row[0]));
+        offset += row_size * height;
+    }
+    filters
+}
+
+/*
+   Unfilters the image data and unpacks it into an Image, putting the pixels of every Adam7 pass where
+   they belong so the Image is always in final image coordinates
+*/
+fn decode_rows(filtered: &[u8], header: &PngHeader) -> Result<Image, StegoError> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    let stride = header.filter_stride();
+    if !header.interlaced {
+        let mut rows = unfilter(filtered, header.row_size(width), height, stride)?;
+        return unpack_samples(&mut rows, header, width, height);
+    }
+
+    let layout = header.color_type.layout();
+    let mut image = Image::new(width, height, layout, vec![header.bit_depth; layout.channels()])?;
+    let mut offset = 0;
+    for pass in adam7_passes(width, height) {
+        let row_size = header.row_size(pass.width);
+        let size = (row_size + 1) * pass.height;
+        let mut rows = unfilter(&filtered[offset..offset + size], row_size, pass.height, stride)?;
+        offset += size;
+
+        let pass_image = unpack_samples(&mut rows, header, pass.width, pass.height)?;
+        for row in 0..pass.height {
+            for col in 0..pass.width {
+                let (image_row, image_col) = pass.position(row, col);
+                let samples = pass_image.pixel(row, col).expect("the pass image has the size of the pass");
+                image.pixel_mut(image_row, image_col).expect("passes stay inside the image").copy_from_slice(samples);
+            }
+        }
+    }
+    Ok(image)
+}
+
+/*
+   The other way around, packs and filters image either as one image or as the 7 Adam7 passes. filters
+   holds one filter type per row in the order row_filters gives them, for the same interlacing.
+*/
+fn encode_rows(image: &Image, header: &PngHeader, interlaced: bool, filters: Option<&[u8]>) -> Result<Vec<u8>, StegoError> {
+    if !interlaced {
+        return Ok(filter_rows(&pack_samples(image, header)?, header.row_size(image.width), header, filters));
+    }
+
+    let mut filtered = Vec::new();
+    let mut first_row = 0;
+    for pass in adam7_passes(image.width, image.height) {
+        let mut pass_image = Image::new(pass.width, pass.height, image.layout, image.bit_depths.clone())?;
+        for row in 0..pass.height {
+            for col in 0..pass.width {
+                let (image_row, image_col) = pass.position(row, col);
+                let samples = image.pixel(image_row, image_col).expect("passes stay inside the image");
+                pass_image.pixel_mut(row, col).expect("the pass image has the size of the pass").copy_from_slice(samples);
+            }
+        }
+        let pass_filters = filters.map(|filters| &filters[first_row..first_row + pass.height]);
+        filtered.extend(filter_rows(&pack_samples(&pass_image, header)?, header.row_size(pass.width), header, pass_filters));
+        first_row += pass.height;
+    }
+    Ok(filtered)
+}
+
+/*
+   Whether the pixel data is written back interlaced. MatchOriginal does what the file did, Adam7 and
+   NotInterlaced convert the file to that, IHDR is changed to match.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PngInterlace {
+    MatchOriginal,
+    NotInterlaced,
+    Adam7,
+}
+
+// How far MatchOriginal may land from the original IDAT size, 1 / SIZE_TOLERANCE of it but at least a few bytes
+const SIZE_TOLERANCE: usize = 100;
+const MIN_SIZE_TOLERANCE: usize = 16;
+
+/*
+   How the pixel data is compressed when it is written again. MatchOriginal reuses the filter type of
+   every row and looks for the level and block types that land within SIZE_TOLERANCE of the size of the
+   original IDAT data, so the file size does not give away that the pixels changed. Level always uses
+   the one given (0 to 9, like zlib) with the adaptive filters.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PngCompression {
+    MatchOriginal,
+    Level(u8),
+}
+
+pub struct PngImageParser {
+    pub header: PngHeader,
+    pub chunks: Vec<PngChunk>, // IHDR to IEND, the IDAT chunks are replaced when the image is encoded
+    pub palette: Vec<[u8; 3]>, // Only set for indexed images
+    pub palette_alpha: Vec<u8>, // tRNS of an indexed image, entries past its end are opaque
+    pub compression: PngCompression, // MatchOriginal unless set_compression says otherwise
+    pub interlace: PngInterlace, // MatchOriginal unless set_interlace says otherwise
+    pub chunk_embedding: ChunkEmbedding, // Where the Chunk encoding puts the payload
+    pub palette_twins: bool, // Whether Lsb on an indexed image may add palette entries, see set_palette_twins
+    image: Option<Image>,
+    original_idat_size: usize,
+    original_filters: Option<Vec<u8>>, // Filter type of every stored row, until the interlacing changes
+    idat_chunk_size: usize, // Length of the first of several IDAT chunks, new IDAT data is split the same way
+    file_data: Vec<u8>,
+    chunks_end: usize, // Offset right after IEND, whatever follows is written back untouched
+    ready: bool,
+}
+
+impl FileEncodingSupport for PngImageParser {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, StegoError> {
+        Ok(PngImageParser {
+            header: PngHeader {
+                width: 0,
+                height: 0,
+                bit_depth: 0,
+                color_type: PngColorType::Gray,
+                interlaced: false,
+            },
+            chunks: Vec::new(),
+            palette: Vec::new(),
+            palette_alpha: Vec::new(),
+            compression: PngCompression::MatchOriginal,
+            interlace: PngInterlace::MatchOriginal,
+            chunk_embedding: ChunkEmbedding::default(),
+            palette_twins: true,
+            image: None,
+            original_idat_size: 0,
+            original_filters: None,
+            idat_chunk_size: 0,
+            file_data: bytes.to_vec(),
+            chunks_end: 0,
+            ready: false,
+        })
+    }
+
+    fn parse_file(&mut self) -> Result<(), StegoError> {
+        let chunks = read_chunks(&self.file_data)?;
+        let ImageChunks { header, palette, palette_alpha, transparent_key, compressed } = image_chunks(&chunks)?;
+
+        let expected = filtered_size(&header)?;
+        let filtered = zlib_decompress(&compressed, expected)?;
+        if filtered.len() != expected {
+            return Err(StegoError::MalformedHeader(format!(
+                "png.rs: parse_file: image data inflates to {} bytes instead of {expected}",
+                filtered.len()
+            )));
+        }
+        let mut image = decode_rows(&filtered, &header)?;
+        if image.layout == ChannelLayout::Indexed {
+            check_indices(&image, &palette)?;
+            image.palette = palette.clone();
+            image.palette_alpha = palette_alpha.clone();
+        }
+        image.transparent_key = transparent_key;
+
+        let idat_sizes: Vec<usize> = chunks.iter().filter(|chunk| &chunk.chunk_type == b"IDAT").map(|chunk| chunk.data.len()).collect();
+        self.idat_chunk_size = if idat_sizes.len() > 1 { idat_sizes[0].max(1) } else { usize::MAX };
+        self.original_idat_size = compressed.len();
+        self.original_filters = Some(row_filters(&filtered, &header));
+        self.chunks_end = PNG_SIGNATURE.len() + chunks.iter().map(|chunk| chunk.data.len() + 12).sum::<usize>();
+        self.header = header;
+        self.palette = palette;
+        self.palette_alpha = palette_alpha;
+        self.chunks = chunks;
+        self.image = Some(image);
+        self.ready = true;
+        Ok(())
+    }
+
+    fn embed_data(
+        &mut self,
+        data: &mut Vec<u8>,
+        encoding: FileEncoding,
+        encoding_method: FileEncodingMethod,
+        file_encoding_function_derivation: FileEncodingFunctionDerivation,
+    ) -> Result<(), StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("png.rs: embed_data"));
+        }
+
+        if encoding == FileEncoding::Chunk {
+            return embed_in_chunks(&mut self.chunks, &self.chunk_embedding, data, encoding_method, file_encoding_function_derivation);
+        }
+
+        let framed = frame_payload(data, encoding, encoding_method);
+
+        let mut image = self.decode_image()?;
+        let order = encoding_method.traverse(image.height, image.width);
+        if self.palette_twins && uses_palette_pairs(&image, encoding) {
+            add_palette_twins(&mut image, 1 << self.header.bit_depth);
+        }
+        embed_encoded(&mut image, &order, encoding, file_encoding_function_derivation, &framed)?;
+        self.encode_image(&image)
+    }
+
+    fn retrieve_data(
+        &mut self,
+        encoding: FileEncoding,
+        encoding_method: FileEncodingMethod,
+        file_encoding_function_derivation: FileEncodingFunctionDerivation,
+    ) -> Result<Vec<u8>, StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("png.rs: retrieve_data"));
+        }
+
+        if encoding == FileEncoding::Chunk {
+            return extract_from_chunks(&self.chunks, &self.chunk_embedding.style, encoding_method, file_encoding_function_derivation);
+        }
+
+        let image = self.image.as_ref().ok_or(StegoError::FileNotReady("png.rs: retrieve_data"))?;
+        let order = encoding_method.traverse(image.height, image.width);
+        unframe_payload(
+            |embedded_bits| extract_encoded(image, &order, encoding, file_encoding_function_derivation, embedded_bits),
+            encoding,
+            encoding_method,
+        )
+    }
+
+    fn capacity(&self, encoding: FileEncoding, encoding_method: FileEncodingMethod) -> Result<CapacityReport, StegoError> {
+        let image = match (&self.image, self.ready) {
+            (Some(image), true) => image,
+            _ => return Err(StegoError::FileNotReady("png.rs: capacity")),
+        };
+
+        if encoding == FileEncoding::Chunk {
+            self.chunk_embedding.style.validate()?;
+            return Ok(CapacityReport::new(self.chunk_embedding.style.max_payload_bytes() * 8, 0));
+        }
+
+        let order = encoding_method.traverse(image.height, image.width);
+        let mut twinned = None;
+        if self.palette_twins && uses_palette_pairs(image, encoding) {
+            let twinned = twinned.insert(image.clone());
+            add_palette_twins(twinned, 1 << self.header.bit_depth);
+        }
+        let image = twinned.as_ref().unwrap_or(image);
+
+        let pixels = carrier_positions(image, &order, encoding)?.len() as u64;
+        let bits_per_pixel = bits_per_pixel(image, encoding)?;
+        Ok(CapacityReport::new(pixels * bits_per_pixel, bits_per_pixel))
+    }
+
+    fn non_standard_chunk_types(&self) -> Vec<String> {
+        self.non_standard_chunks().iter().map(|chunk| chunk.name()).collect()
+    }
+
+    /*
+        --compression match|0-9 picks how the pixel data is compressed again, see set_compression, and
+        --interlace keep|none|adam7 how it is laid out, see set_interlace.
+        --palette-twins on|off allows or forbids new palette entries for Lsb, see set_palette_twins.
+        --chunk zTXt|tEXt|iTXt|<private type>, --chunk-keyword and --chunk-placement before|after change
+        one part of the chunk embedding each, extraction needs the same type and keyword.
+     */
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), StegoError> {
+        let keyword = match &self.chunk_embedding.style {
+            ChunkStyle::Text(keyword) | ChunkStyle::CompressedText(keyword) | ChunkStyle::InternationalText(keyword) => keyword.clone(),
+            ChunkStyle::Private(_) => DEFAULT_KEYWORD.to_string(),
+        };
+        let style = match (name, value) {
+            ("chunk", "tEXt") => Some(ChunkStyle::Text(keyword)),
+            ("chunk", "zTXt") => Some(ChunkStyle::CompressedText(keyword)),
+            ("chunk", "iTXt") => Some(ChunkStyle::InternationalText(keyword)),
+            ("chunk", _) => match <[u8; 4]>::try_from(value.as_bytes()) {
+                Ok(chunk_type) => Some(ChunkStyle::Private(chunk_type)),
+                Err(_) => return Err(StegoError::Usage(format!("--chunk takes zTXt, tEXt, iTXt or a private chunk type! : {value}"))),
+            },
+            ("chunk-keyword", _) => Some(match &self.chunk_embedding.style {
+                ChunkStyle::Text(_) => ChunkStyle::Text(value.to_string()),
+                ChunkStyle::CompressedText(_) => ChunkStyle::CompressedText(value.to_string()),
+                ChunkStyle::InternationalText(_) => ChunkStyle::InternationalText(value.to_string()),
+                ChunkStyle::Private(_) => return Err(StegoError::Usage("--chunk-keyword only goes with text chunks!".to_string())),
+            }),
+            _ => None,
+        };
+        if let Some(style) = style {
+            style.validate().map_err(|e| StegoError::Usage(e.to_string()))?;
+            self.set_chunk_embedding(ChunkEmbedding { style, placement: self.chunk_embedding.placement });
+            return Ok(());
+        }
+
+        let placement = |placement| ChunkEmbedding { style: self.chunk_embedding.style.clone(), placement };
+        match (name, value) {
+            ("interlace", "keep") => self.set_interlace(PngInterlace::MatchOriginal),
+            ("interlace", "none") => self.set_interlace(PngInterlace::NotInterlaced),
+            ("interlace", "adam7") => self.set_interlace(PngInterlace::Adam7),
+            ("interlace", _) => return Err(StegoError::Usage(format!("--interlace takes keep, none or adam7! : {value}"))),
+            ("palette-twins", "on") => self.set_palette_twins(true),
+            ("palette-twins", "off") => self.set_palette_twins(false),
+            ("palette-twins", _) => return Err(StegoError::Usage(format!("--palette-twins takes on or off! : {value}"))),
+            ("chunk-placement", "before") => self.set_chunk_embedding(placement(ChunkPlacement::BeforeIdat)),
+            ("chunk-placement", "after") => self.set_chunk_embedding(placement(ChunkPlacement::AfterIdat)),
+            ("chunk-placement", _) => return Err(StegoError::Usage(format!("--chunk-placement takes before or after! : {value}"))),
+            ("compression", "match") => self.set_compression(PngCompression::MatchOriginal),
+            ("compression", _) => match value.parse::<u8>() {
+                Ok(level) if level <= MAX_LEVEL => self.set_compression(PngCompression::Level(level)),
+                _ => return Err(StegoError::Usage(format!("--compression takes match or a level from 0 to 9! : {value}"))),
+            },
+            _ => return Err(StegoError::Usage(format!("--{name} is not an option for PNG files!"))),
+        }
+        Ok(())
+    }
+
+    fn write_to(&mut self, writer: &mut dyn Write) -> Result<(), StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("png.rs: write_to"));
+        }
+
+        writer.write_all(&PNG_SIGNATURE)?;
+        for chunk in &self.chunks {
+            writer.write_all(&(chunk.data.len() as u32).to_be_bytes())?;
+            writer.write_all(&chunk.chunk_type)?;
+            writer.write_all(&chunk.data)?;
+            writer.write_all(&crc32_update(crc32(&chunk.chunk_type), &chunk.data).to_be_bytes())?;
+        }
+        writer.write_all(&self.file_data[self.chunks_end..])?;
+        Ok(())
+    }
+}
+
+/*
+   Lsb on an indexed image moves indices between pairs of close colours (PalettePairs), a PNG can give
+   its lonely colours a twin to pair with first
+*/
+fn uses_palette_pairs(image: &Image, encoding: FileEncoding) -> bool {
+    image.layout == ChannelLayout::Indexed && encoding == FileEncoding::Lsb
+}
+
+fn check_indices(image: &Image, palette: &[[u8; 3]]) -> Result<(), StegoError> {
+    match image.samples.iter().find(|&&index| index as usize >= palette.len()) {
+        Some(index) => Err(StegoError::MalformedHeader(format!(
+            "png.rs: palette index {index} is past the {} entries of PLTE",
+            palette.len()
+        ))),
+        None => Ok(()),
+    }
+}
+
+impl PngImageParser {
+    pub fn set_compression(&mut self, compression: PngCompression) {
+        self.compression = compression;
+    }
+
+    pub fn set_interlace(&mut self, interlace: PngInterlace) {
+        self.interlace = interlace;
+    }
+
+    pub fn set_chunk_embedding(&mut self, chunk_embedding: ChunkEmbedding) {
+        self.chunk_embedding = chunk_embedding;
+    }
+
+    /*
+        On by default, Lsb on an indexed image adds an exact copy of every used colour that has no close
+        partner while the bit depth leaves room in PLTE. Those pixels can then carry a bit without
+        changing colour at all. Off keeps PLTE and tRNS exactly as they are.
+     */
+    pub fn set_palette_twins(&mut self, palette_twins: bool) {
+        self.palette_twins = palette_twins;
+    }
+
+    /*
+        Chunks that are not part of the PNG specification, whoever wrote them and for whatever reason
+     */
+    pub fn non_standard_chunks(&self) -> Vec<&PngChunk> {
+        non_standard_chunks(&self.chunks)
+    }
+
+    /*
+        The pixels as an Image, indexed images carry PLTE as their palette
+     */
+    pub fn decode_image(&self) -> Result<Image, StegoError> {
+        match (&self.image, self.ready) {
+            (Some(image), true) => Ok(image.clone()),
+            _ => Err(StegoError::FileNotReady("png.rs: decode_image")),
+        }
+    }
+
+    /*
+        Filters and compresses image into new IDAT chunks that take the place of the old ones, every other
+        chunk stays where it was. The image has to match IHDR, only the samples may differ from what
+        decode_image gave. Indexed images may also add palette entries after the ones PLTE has, those
+        are written to PLTE and tRNS (and hIST if there is one).
+     */
+    pub fn encode_image(&mut self, image: &Image) -> Result<(), StegoError> {
+        if !self.ready {
+            return Err(StegoError::FileNotReady("png.rs: encode_image"));
+        }
+
+        let header = self.header;
+        let layout = header.color_type.layout();
+        if image.width != header.width as usize
+            || image.height != header.height as usize
+            || image.layout != layout
+            || image.bit_depths != vec![header.bit_depth; layout.channels()]
+            || (layout == ChannelLayout::Indexed && !self.palette_grows_into(image))
+        {
+            return Err(StegoError::UnsupportedFormat(format!(
+                "png.rs: encode_image: a {}x{} {:?} image with bit depths {:?} does not match the {}x{} {:?} PNG",
+                image.width, image.height, image.layout, image.bit_depths, header.width, header.height, header.color_type
+            )));
+        }
+        if image.samples.iter().any(|&sample| sample as u32 >= 1 << header.bit_depth) {
+            return Err(StegoError::UnsupportedFormat(format!(
+                "png.rs: encode_image: a sample does not fit in {} bits",
+                header.bit_depth
+            )));
+        }
+        if layout == ChannelLayout::Indexed {
+            check_indices(image, &image.palette)?;
+        }
+
+        let interlaced = match self.interlace {
+            PngInterlace::MatchOriginal => header.interlaced,
+            PngInterlace::NotInterlaced => false,
+            PngInterlace::Adam7 => true,
+        };
+        let compressed = match self.compression {
+            PngCompression::Level(level) => zlib_compress(&encode_rows(image, &header, interlaced, None)?, level),
+            PngCompression::MatchOriginal => {
+                let filters = self.original_filters.as_deref().filter(|_| interlaced == header.interlaced);
+                self.compress_like_original(&encode_rows(image, &header, interlaced, filters)?)
+            }
+        };
+
+        let first_idat = self.chunks.iter().position(|chunk| &chunk.chunk_type == b"IDAT").ok_or(
+            StegoError::FileNotReady("png.rs: encode_image"),
+        )?;
+        let idat_chunks = compressed.chunks(self.idat_chunk_size).map(|data| PngChunk {
+            chunk_type: *b"IDAT",
+            data: data.to_vec(),
+        });
+        let after_idat: Vec<PngChunk> = self.chunks.drain(first_idat..).filter(|chunk| &chunk.chunk_type != b"IDAT").collect();
+        self.chunks.extend(idat_chunks);
+        self.chunks.extend(after_idat);
+        if layout == ChannelLayout::Indexed {
+            self.write_palette(image);
+        }
+        if interlaced != header.interlaced {
+            self.original_filters = None;
+            self.header.interlaced = interlaced;
+            self.chunks[0].data[12] = interlaced as u8;
+        }
+
+        self.image = Some(image.clone());
+        Ok(())
+    }
+
+    /*
+        Entries already in PLTE keep their colour and alpha, new ones only go after them as far as the
+        bit depth allows
+     */
+    fn palette_grows_into(&self, image: &Image) -> bool {
+        let alpha = |palette_alpha: &[u8], index: usize| palette_alpha.get(index).copied().unwrap_or(255);
+        image.palette.starts_with(&self.palette)
+            && image.palette.len() <= 1 << self.header.bit_depth
+            && image.palette_alpha.len() <= image.palette.len()
+            && (0..self.palette.len()).all(|index| alpha(&image.palette_alpha, index) == alpha(&self.palette_alpha, index))
+    }
+
+    /*
+        Brings PLTE, tRNS and hIST in line with the palette of image. A new tRNS goes right after PLTE,
+        hIST is counted again from the pixels since it needs one entry per palette entry.
+     */
+    fn write_palette(&mut self, image: &Image) {
+        if image.palette != self.palette {
+            if let Some(plte) = self.chunks.iter_mut().find(|chunk| &chunk.chunk_type == b"PLTE") {
+                plte.data = image.palette.iter().flatten().copied().collect();
+            }
+
+            if let Some(hist) = self.chunks.iter_mut().find(|chunk| &chunk.chunk_type == b"hIST") {
+                let mut uses = vec![0u64; image.palette.len()];
+                image.samples.iter().for_each(|&index| uses[index as usize] += 1);
+                // Scaled down to 16 bits when needed, a colour that is used at all never rounds down to 0
+                let most = uses.iter().copied().max().unwrap_or(0).max(65_535) as u128;
+                let scaled = |count: u64| if count == 0 { 0 } else { (count as u128 * 65_535 / most).max(1) as u16 };
+                hist.data = uses.iter().flat_map(|&count| scaled(count).to_be_bytes()).collect();
+            }
+        }
+
+        if image.palette_alpha != self.palette_alpha {
+            match self.chunks.iter().position(|chunk| &chunk.chunk_type == b"tRNS") {
+                Some(position) if image.palette_alpha.is_empty() => {
+                    self.chunks.remove(position);
+                }
+                Some(position) => self.chunks[position].data = image.palette_alpha.clone(),
+                None if image.palette_alpha.is_empty() => {}
+                None => {
+                    let plte = self.chunks.iter().position(|chunk| &chunk.chunk_type == b"PLTE").expect("indexed images always have PLTE");
+                    self.chunks.insert(plte + 1, PngChunk { chunk_type: *b"tRNS", data: image.palette_alpha.clone() });
+                }
+            }
+        }
+
+        self.palette = image.palette.clone();
+        self.palette_alpha = image.palette_alpha.clone();
+    }
+
+    /*
+        Levels only ever get slower going up and nearly always smaller, so the search stops at the first
+        one that is not above the target range. When that one undershoots, fixed Huffman blocks at the
+        levels up to it and stored blocks are larger and may land closer. The original zlib header is
+        kept when it allows the full 32KiB window, it only records roughly how hard the encoder tried.
+     */
+    fn compress_like_original(&self, filtered: &[u8]) -> Vec<u8> {
+        let target = self.original_idat_size;
+        let tolerance = (target / SIZE_TOLERANCE).max(MIN_SIZE_TOLERANCE);
+        let in_range = |compressed: &[u8]| compressed.len().abs_diff(target) <= tolerance;
+        let closer = |compressed: &[u8], best: &[u8]| compressed.len().abs_diff(target) < best.len().abs_diff(target);
+
+        let mut level = 1;
+        let mut best = zlib_compress(filtered, level);
+        let mut last_size = best.len();
+        while level < MAX_LEVEL && last_size > target + tolerance {
+            level += 1;
+            let compressed = zlib_compress(filtered, level);
+            last_size = compressed.len();
+            if closer(&compressed, &best) {
+                best = compressed;
+            }
+        }
+        if in_range(&best) || last_size > target {
+            return self.with_original_header(best);
+        }
+
+        let larger = (1..=level)
+            .rev()
+            .map(|level| zlib_compress_with(filtered, level, DeflateBlocks::Fixed))
+            .chain(std::iter::once_with(|| zlib_compress(filtered, 0)));
+        for compressed in larger {
+            let overshot = compressed.len() > target;
+            if closer(&compressed, &best) {
+                best = compressed;
+            }
+            // The rest are larger still
+            if in_range(&best) || overshot {
+                break;
+            }
+        }
+        self.with_original_header(best)
+    }
+
+    fn with_original_header(&self, mut best: Vec<u8>) -> Vec<u8> {
+        let original_header = self
+            .chunks
+            .iter()
+            .find(|chunk| &chunk.chunk_type == b"IDAT")
+            .and_then(|chunk| chunk.data.get(0..2));
+        if let Some(original_header) = original_header
+            && original_header[0] == best[0]
+        {
+            best[1] = original_header[1];
+        }
+        best
+    }
+}
+
+/*
+   Decodes a PNG into an Image, every colour type at every bit depth PNG allows, interlaced or not. The
+   whole file is kept as the "PNG" metadata block so encode_png can write the chunks around the pixels back.
+*/
+pub fn decode_png(bytes: &[u8]) -> Result<Image, StegoError> {
+    let mut parser = PngImageParser::from_bytes(bytes)?;
+    parser.parse_file()?;
+
+    let mut image = parser.decode_image()?;
+    image.metadata.push(MetadataBlock {
+        name: "PNG".to_string(),
+        data: parser.file_data,
+    });
+    Ok(image)
+}
+
+pub fn encode_png(image: &Image) -> Result<Vec<u8>, StegoError> {
+    let container = image.metadata("PNG").ok_or_else(|| {
+        StegoError::UnsupportedFormat("png.rs: encode_png: the image was not decoded from a PNG file".to_string())
+    })?;
+
+    let mut parser = PngImageParser::from_bytes(container)?;
+    parser.parse_file()?;
+    parser.encode_image(image)?;
+
+    let mut bytes = Vec::new();
+    parser.write_to(&mut bytes)?;
+    Ok(bytes)
+}
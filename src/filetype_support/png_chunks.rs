@@ -0,0 +1,389 @@
+/*
+ * Copyright (C) 2025 Dustyn Gibb
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License
+ * as published by the Free Software Foundation; either version 2
+ * of the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
+ */
+
+use crate::compression::compression::{zlib_compress, zlib_decompress};
+use crate::file_encoding_support::file_encoding_support::{FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod};
+use crate::file_encoding_support::payload_header::{frame_payload, unframe_payload};
+use crate::file_encoding_support::stego_error::StegoError;
+use crate::filetype_support::png::{PngChunk, PNG_MAX_VALUE};
+use crate::mathematics_support::chacha20::ChaCha20Rng;
+use std::collections::hash_map::RandomState;
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*
+   Chunk types defined by the PNG specification (third edition) and the registered extensions, plus
+   the APNG chunks. Anything else in a file was put there by some program for its own use.
+*/
+const STANDARD_CHUNKS: [&[u8; 4]; 33] = [
+    b"IHDR", b"PLTE", b"IDAT", b"IEND", b"tRNS", b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"cICP",
+    b"mDCV", b"cLLI", b"tEXt", b"zTXt", b"iTXt", b"bKGD", b"hIST", b"pHYs", b"sPLT", b"eXIf", b"tIME",
+    b"acTL", b"fcTL", b"fdAT", b"oFFs", b"pCAL", b"sCAL", b"gIFg", b"gIFx", b"gIFt", b"sTER", b"dSIG",
+];
+
+const NONCE_SIZE: usize = 12;
+pub const DEFAULT_KEYWORD: &str = "Comment";
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn is_standard_chunk(chunk_type: &[u8; 4]) -> bool {
+    STANDARD_CHUNKS.contains(&chunk_type)
+}
+
+pub fn non_standard_chunks(chunks: &[PngChunk]) -> Vec<&PngChunk> {
+    chunks.iter().filter(|chunk| !is_standard_chunk(&chunk.chunk_type)).collect()
+}
+
+/*
+   What the Chunk encoding stores the payload in. Private is a chunk of our own type holding the bytes
+   as they are, the text styles write the payload base64 encoded (text chunks only hold Latin-1) under
+   keyword, CompressedText as zTXt and InternationalText as uncompressed iTXt.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ChunkStyle {
+    Private([u8; 4]),
+    Text(String),
+    CompressedText(String),
+    InternationalText(String),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChunkPlacement {
+    BeforeIdat,
+    AfterIdat,
+}
+
+/*
+   A zTXt "Comment" after the image data unless set_chunk_embedding says otherwise, the kind of chunk
+   plenty of tools leave behind
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ChunkEmbedding {
+    pub style: ChunkStyle,
+    pub placement: ChunkPlacement,
+}
+
+impl Default for ChunkEmbedding {
+    fn default() -> Self {
+        ChunkEmbedding {
+            style: ChunkStyle::CompressedText(DEFAULT_KEYWORD.to_string()),
+            placement: ChunkPlacement::AfterIdat,
+        }
+    }
+}
+
+impl ChunkStyle {
+    /*
+        Private types have to be ancillary, private and have the reserved bit clear (lower, lower,
+        upper case letters) and must not clash with a standard chunk. Keywords are 1 to 79 printable
+        characters without leading, trailing or double spaces.
+     */
+    pub fn validate(&self) -> Result<(), StegoError> {
+        match self {
+            ChunkStyle::Private(chunk_type) => {
+                let valid = chunk_type.iter().all(u8::is_ascii_alphabetic)
+                    && chunk_type[0].is_ascii_lowercase()
+                    && chunk_type[1].is_ascii_lowercase()
+                    && chunk_type[2].is_ascii_uppercase()
+                    && !is_standard_chunk(chunk_type);
+                if !valid {
+                    return Err(StegoError::UnsupportedFormat(format!(
+                        "png_chunks.rs: {} is not an ancillary private chunk type",
+                        String::from_utf8_lossy(chunk_type)
+                    )));
+                }
+            }
+            ChunkStyle::Text(keyword) | ChunkStyle::CompressedText(keyword) | ChunkStyle::InternationalText(keyword) => {
+                let valid = (1..=79).contains(&keyword.len())
+                    && keyword.bytes().all(|byte| (b' '..=b'~').contains(&byte))
+                    && !keyword.starts_with(' ')
+                    && !keyword.ends_with(' ')
+                    && !keyword.contains("  ");
+                if !valid {
+                    return Err(StegoError::UnsupportedFormat(format!(
+                        "png_chunks.rs: \"{keyword}\" is not a valid text chunk keyword"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn chunk_type(&self) -> [u8; 4] {
+        match self {
+            ChunkStyle::Private(chunk_type) => *chunk_type,
+            ChunkStyle::Text(_) => *b"tEXt",
+            ChunkStyle::CompressedText(_) => *b"zTXt",
+            ChunkStyle::InternationalText(_) => *b"iTXt",
+        }
+    }
+
+    /*
+        The most a single chunk can carry. Compressed text is held to the same limit as plain text, base64
+        of random bytes does not get much smaller.
+     */
+    pub fn max_payload_bytes(&self) -> u64 {
+        match self {
+            ChunkStyle::Private(_) => PNG_MAX_VALUE as u64,
+            ChunkStyle::Text(keyword) | ChunkStyle::CompressedText(keyword) | ChunkStyle::InternationalText(keyword) => {
+                // keyword, separator and for iTXt the flags and two empty strings
+                (PNG_MAX_VALUE as u64 - keyword.len() as u64 - 5) / 4 * 3
+            }
+        }
+    }
+
+    fn to_chunk(&self, payload: &[u8]) -> PngChunk {
+        let mut data = Vec::new();
+        match self {
+            ChunkStyle::Private(_) => data.extend_from_slice(payload),
+            ChunkStyle::Text(keyword) => {
+                data.extend_from_slice(keyword.as_bytes());
+                data.push(0);
+                data.extend(base64_encode(payload));
+            }
+            ChunkStyle::CompressedText(keyword) => {
+                data.extend_from_slice(keyword.as_bytes());
+                data.extend_from_slice(&[0, 0]);
+                data.extend(zlib_compress(&base64_encode(payload), 9));
+            }
+            ChunkStyle::InternationalText(keyword) => {
+                // Not compressed, no language tag and no translated keyword
+                data.extend_from_slice(keyword.as_bytes());
+                data.extend_from_slice(&[0, 0, 0, 0, 0]);
+                data.extend(base64_encode(payload));
+            }
+        }
+        PngChunk { chunk_type: self.chunk_type(), data }
+    }
+
+    fn matches(&self, chunk: &PngChunk) -> bool {
+        if chunk.chunk_type != self.chunk_type() {
+            return false;
+        }
+        match self {
+            ChunkStyle::Private(_) => true,
+            ChunkStyle::Text(keyword) | ChunkStyle::CompressedText(keyword) | ChunkStyle::InternationalText(keyword) => {
+                chunk.data.split(|&byte| byte == 0).next() == Some(keyword.as_bytes())
+            }
+        }
+    }
+
+    // The payload of a chunk that matches accepted
+    fn payload(&self, chunk: &PngChunk) -> Result<Vec<u8>, StegoError> {
+        let malformed = || StegoError::MalformedHeader(format!("png_chunks.rs: {} chunk is cut short", chunk.name()));
+        let text_start = match self {
+            ChunkStyle::Private(_) => return Ok(chunk.data.clone()),
+            ChunkStyle::Text(keyword) | ChunkStyle::CompressedText(keyword) | ChunkStyle::InternationalText(keyword) => keyword.len() + 1,
+        };
+        let text = chunk.data.get(text_start..).ok_or_else(malformed)?;
+
+        match self {
+            ChunkStyle::CompressedText(_) => {
+                let (method, compressed) = text.split_first().ok_or_else(malformed)?;
+                if *method != 0 {
+                    return Err(StegoError::UnsupportedFormat(format!("png_chunks.rs: zTXt compression method {method}")));
+                }
+                base64_decode(&zlib_decompress(compressed, PNG_MAX_VALUE as usize)?)
+            }
+            ChunkStyle::InternationalText(_) => {
+                let (flags, rest) = text.split_at_checked(2).ok_or_else(malformed)?;
+                // Language tag and translated keyword, both null terminated
+                let mut parts = rest.splitn(3, |&byte| byte == 0);
+                let text = parts.nth(2).ok_or_else(malformed)?;
+                match flags {
+                    [0, _] => base64_decode(text),
+                    [1, 0] => base64_decode(&zlib_decompress(text, PNG_MAX_VALUE as usize)?),
+                    _ => Err(StegoError::UnsupportedFormat(format!("png_chunks.rs: iTXt compression flags {flags:?}"))),
+                }
+            }
+            _ => base64_decode(text),
+        }
+    }
+}
+
+/*
+   With a key the payload is XORed with a ChaCha20 stream before it goes into the chunk, so the chunk
+   looks like noise to anyone without the passphrase. Every embed draws a fresh nonce and stores it in
+   front of the encrypted payload, two chunks written with the same key never share a keystream.
+*/
+fn encrypt(mut payload: Vec<u8>, derivation: FileEncodingFunctionDerivation) -> Vec<u8> {
+    let FileEncodingFunctionDerivation::KeyBased(key) = derivation else {
+        return payload;
+    };
+
+    let nonce = fresh_nonce();
+    apply_keystream(&mut payload, &key, &nonce);
+    let mut stored = nonce.to_vec();
+    stored.extend(payload);
+    stored
+}
+
+fn decrypt(mut stored: Vec<u8>, derivation: FileEncodingFunctionDerivation) -> Option<Vec<u8>> {
+    let FileEncodingFunctionDerivation::KeyBased(key) = derivation else {
+        return Some(stored);
+    };
+
+    let nonce: [u8; NONCE_SIZE] = stored.get(..NONCE_SIZE)?.try_into().unwrap();
+    let mut payload = stored.split_off(NONCE_SIZE);
+    apply_keystream(&mut payload, &key, &nonce);
+    Some(payload)
+}
+
+fn apply_keystream(data: &mut [u8], key: &[u8; 32], nonce: &[u8; NONCE_SIZE]) {
+    let mut keystream = vec![0u8; data.len()];
+    ChaCha20Rng::new(key, nonce, 0).fill_bytes(&mut keystream);
+    data.iter_mut().zip(keystream).for_each(|(byte, key_byte)| *byte ^= key_byte);
+}
+
+/*
+   A nonce only has to be unique per key. It is read from /dev/urandom where there is one. Elsewhere
+   RandomState hashes the clock, its keys differ between threads and between calls in one thread so
+   two embeds get different nonces, but that is no source of randomness and is only the fallback.
+*/
+fn fresh_nonce() -> [u8; NONCE_SIZE] {
+    let mut nonce = [0u8; NONCE_SIZE];
+    if File::open("/dev/urandom").and_then(|mut urandom| urandom.read_exact(&mut nonce)).is_ok() {
+        return nonce;
+    }
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos());
+    for (part, half) in nonce.chunks_mut(NONCE_SIZE / 2).zip(0u8..) {
+        part.copy_from_slice(&RandomState::new().hash_one((time, half)).to_le_bytes()[..NONCE_SIZE / 2]);
+    }
+    nonce
+}
+
+/*
+   The payload a chunk of our style holds once it is unframed, None for chunks that hold something else
+   (someone's real comment, or a payload under another key)
+*/
+fn unframe_chunk(
+    style: &ChunkStyle,
+    chunk: &PngChunk,
+    encoding_method: FileEncodingMethod,
+    derivation: FileEncodingFunctionDerivation,
+) -> Result<Vec<u8>, StegoError> {
+    let stored = decrypt(style.payload(chunk)?, derivation).ok_or_else(|| {
+        StegoError::IntegrityFailure(format!("png_chunks.rs: {} chunk is too short to hold a nonce", chunk.name()))
+    })?;
+    unframe_payload(
+        |embedded_bits| {
+            stored.get(..embedded_bits.div_ceil(8) as usize).map(<[u8]>::to_vec).ok_or_else(|| {
+                StegoError::IntegrityFailure(format!("png_chunks.rs: {} chunk is shorter than its payload header says", chunk.name()))
+            })
+        },
+        FileEncoding::Chunk,
+        encoding_method,
+    )
+}
+
+/*
+   Frames data into a chunk of the embedding's style, right before the first IDAT chunk or right after the
+   last one. A payload embedded earlier with the same style and key is replaced, every other chunk of
+   that type (a real "Comment" for instance) stays.
+*/
+pub fn embed_in_chunks(
+    chunks: &mut Vec<PngChunk>,
+    embedding: &ChunkEmbedding,
+    data: &[u8],
+    encoding_method: FileEncodingMethod,
+    derivation: FileEncodingFunctionDerivation,
+) -> Result<(), StegoError> {
+    embedding.style.validate()?;
+    let stored = encrypt(frame_payload(data, FileEncoding::Chunk, encoding_method), derivation);
+    let available_bits = embedding.style.max_payload_bytes() * 8;
+    let required_bits = stored.len() as u64 * 8;
+    if required_bits > available_bits {
+        return Err(StegoError::InsufficientCapacity { required_bits, available_bits });
+    }
+
+    let chunk = embedding.style.to_chunk(&stored);
+
+    chunks.retain(|existing| {
+        !(embedding.style.matches(existing) && unframe_chunk(&embedding.style, existing, encoding_method, derivation).is_ok())
+    });
+    let position = match embedding.placement {
+        ChunkPlacement::BeforeIdat => chunks.iter().position(|chunk| &chunk.chunk_type == b"IDAT"),
+        ChunkPlacement::AfterIdat => chunks.iter().rposition(|chunk| &chunk.chunk_type == b"IDAT").map(|last| last + 1),
+    }
+    .ok_or_else(|| StegoError::MalformedHeader("png_chunks.rs: the file has no IDAT chunk".to_string()))?;
+    chunks.insert(position, chunk);
+    Ok(())
+}
+
+/*
+   The payload of the first chunk of the given style that holds one, wherever it is in the file
+*/
+pub fn extract_from_chunks(
+    chunks: &[PngChunk],
+    style: &ChunkStyle,
+    encoding_method: FileEncodingMethod,
+    derivation: FileEncodingFunctionDerivation,
+) -> Result<Vec<u8>, StegoError> {
+    let mut first_error = None;
+    for chunk in chunks.iter().filter(|chunk| style.matches(chunk)) {
+        match unframe_chunk(style, chunk, encoding_method, derivation) {
+            Ok(payload) => return Ok(payload),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    Err(first_error.unwrap_or_else(|| StegoError::IntegrityFailure(format!("png_chunks.rs: no {style:?} chunk in the file"))))
+}
+
+fn base64_encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len().div_ceil(3) * 4);
+    for group in data.chunks(3) {
+        let bits = group.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize]);
+            } else {
+                encoded.push(b'=');
+            }
+        }
+    }
+    encoded
+}
+
+fn base64_decode(text: &[u8]) -> Result<Vec<u8>, StegoError> {
+    let invalid = || StegoError::IntegrityFailure("png_chunks.rs: chunk text is not base64".to_string());
+    if !text.len().is_multiple_of(4) {
+        return Err(invalid());
+    }
+
+    let mut decoded = Vec::with_capacity(text.len() / 4 * 3);
+    for (index, group) in text.chunks(4).enumerate() {
+        let padding = group.iter().rev().take_while(|&&byte| byte == b'=').count();
+        if padding > 2 || (padding > 0 && (index + 1) * 4 != text.len()) {
+            return Err(invalid());
+        }
+
+        let mut bits = 0u32;
+        for &byte in &group[..4 - padding] {
+            let value = BASE64_ALPHABET.iter().position(|&symbol| symbol == byte).ok_or_else(invalid)?;
+            bits = bits << 6 | value as u32;
+        }
+        bits <<= 6 * padding;
+        decoded.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(decoded)
+}
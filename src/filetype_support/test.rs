@@ -15,25 +15,77 @@
  * along with this program; if not, write to the Free Software
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
  */
-use crate::file_encoding_support::pixel::embed_lsb_data_right_left;
-
 #[cfg(test)]
 mod svg_tests{
 
 }
 
+#[cfg(test)]
+mod filetype_tests{
+    use crate::file_encoding_support::file_encoding_support::{FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod, FileEncodingSupport};
+    use crate::file_encoding_support::stego_error::StegoError;
+    use crate::filetype_support::filetype_support::{carrier_from_bytes, FileType};
+
+    const SAMPLE: &str = "src/filetype_support/assets/sample-1024x1024.bmp";
+
+    #[test]
+    fn test_detect_file_type_from_magic(){
+        assert_eq!(FileType::detect(&std::fs::read(SAMPLE).unwrap()).unwrap(), FileType::Bmp);
+        assert_eq!(FileType::detect(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0]).unwrap(), FileType::Png);
+        assert_eq!(FileType::detect(&[0xFF, 0xD8, 0xFF, 0xE0]).unwrap(), FileType::Jpeg);
+        assert_eq!(FileType::detect(b"\0\0\0\x18ftypisom\0\0\x02\0").unwrap(), FileType::Mp4);
+        assert_eq!(FileType::detect(b"\0\0\0\x14ftypqt  \0\0\x02\0").unwrap(), FileType::Mov);
+        assert_eq!(FileType::detect(b"\0\0\0\x08wide\0\0\0\0").unwrap(), FileType::Mov);
+        assert_eq!(FileType::detect(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>").unwrap(), FileType::Svg);
+        assert_eq!(FileType::detect(b"\xEF\xBB\xBF  <?xml version=\"1.0\"?>\n<svg/>").unwrap(), FileType::Svg);
+    }
+
+    #[test]
+    fn test_detect_file_type_rejects_unknown(){
+        for bytes in [&b""[..], b"B", b"<?xml version=\"1.0\"?><html/>", b"[package]\nname = \"x\""] {
+            assert!(matches!(FileType::detect(bytes), Err(StegoError::UnsupportedFormat(_))));
+        }
+    }
+
+    #[test]
+    fn test_carrier_factory(){
+        let mut carrier = carrier_from_bytes(&std::fs::read(SAMPLE).unwrap()).unwrap();
+        carrier.parse_file().unwrap();
+
+        let message = "picked at runtime".as_bytes().to_vec();
+        carrier.embed_data(&mut message.clone(), FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+        assert_eq!(carrier.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap(), message);
+
+        // The boxed trait object is itself a FileEncodingSupport, constructing it runs the detection
+        let mut carrier = <Box<dyn FileEncodingSupport>>::new(SAMPLE).unwrap();
+        carrier.parse_file().unwrap();
+        assert_eq!(carrier.capacity(FileEncoding::ColorParity, FileEncodingMethod::LeftToRight).unwrap().total_bits, 1024 * 1024);
+
+        let result = carrier_from_bytes(&[0xFF, 0xD8, 0xFF, 0xE0]);
+        assert!(matches!(result, Err(StegoError::UnsupportedFormat(message)) if message.contains("Jpeg")));
+    }
+}
+
 #[cfg(test)]
 mod bmp_tests{
-    use std::process::exit;
+    use crate::arg_handling::arg_handling::arg_handling::parse_arguments;
     use crate::file_encoding_support::file_encoding_support::FileEncodingSupport;
-    use crate::file_encoding_support::pixel::{embed_color_data_left_right, embed_color_data_right_left, embed_lsb_data_left_right, embed_lsb_data_right_left, extract_color_data_left_right, extract_color_data_right_left, extract_lsb_data_left_right, extract_lsb_data_right_left};
-    use crate::filetype_support::bmp::{BmpImageParser, RgbPixel, RgbaPixel};
+    use crate::file_encoding_support::file_encoding_support::{FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod, OperationOutput};
+    use crate::file_encoding_support::payload_header::PAYLOAD_HEADER_SIZE;
+    use crate::file_encoding_support::pixel::Pixel;
+    use crate::file_encoding_support::pixel_grid::RowOrder;
+    use crate::file_encoding_support::stego_error::StegoError;
+    use crate::file_encoding_support::image::ChannelLayout;
+    use crate::file_encoding_support::palette::PalettePairs;
+    use crate::filetype_support::bmp::{decode_bmp, encode_bmp, BitfieldFormat, Bitfield16Pixel, BmpColorProfile, BmpHeaderVersion, BmpImageParser, BmpPixelType, RgbPixel, RgbaPixel, RleOutput, SlackReport};
+    use crate::filetype_support::bmp_rle::{decode_rle, encode_rle, RleCompression};
 
     #[test]
     fn test_bmp_object_creation(){
-        let bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp");
+        let bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
 
-        assert_eq!(bmp_image_parser.file_data.len(), 0);
+        // The whole file is read in up front, nothing is parsed out of it until parse_file
+        assert_eq!(bmp_image_parser.file_data.len(), 3145782);
         
         assert_eq!(bmp_image_parser.pixel_size, 0);
         
@@ -86,9 +138,9 @@ mod bmp_tests{
 
     #[test]
     fn test_bmp_image_parsing(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp");
+        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
 
-        bmp_image_parser.parse_file();
+        bmp_image_parser.parse_file().unwrap();
        assert_ne!(bmp_image_parser.file_data.len(), 0);
 
         assert_ne!(bmp_image_parser.pixel_size, 0);
@@ -107,6 +159,8 @@ mod bmp_tests{
         // Check the bmp_header fields via local variables
         assert_ne!(bf_type, 0);
         assert_ne!(bf_size, 0);
+        assert_eq!(bf_reserved1, 0);
+        assert_eq!(bf_reserved2, 0);
         assert_eq!(bf_off_bits, 54);
 
         let bi_size = bmp_image_parser.bmp_dib_header.bi_size;
@@ -129,6 +183,9 @@ mod bmp_tests{
         assert_ne!(bi_size_image, 0);
         assert_ne!(bi_x_pels_per_meter, 0);
         assert_ne!(bi_y_pels_per_meter, 0);
+        assert_eq!(bi_compression, 0);
+        assert_eq!(bi_clr_used, 0);
+        assert_eq!(bi_clr_important, 0);
 
         assert_eq!(bmp_image_parser.padding_size, 0);
     }
@@ -136,109 +193,67 @@ mod bmp_tests{
     /*
         Left right LSB
      */
-    #[test]
-    fn test_bmp_lsb_embed_left_right(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp");
-        bmp_image_parser.parse_file();
+    fn embedded_sample_lsb_left_right() -> Vec<u8> {
+        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        bmp_image_parser.parse_file().unwrap();
 
         let data_vec : Vec<u8> = "This is a test embedding for testing purposes".as_bytes().to_vec();
-        match bmp_image_parser.pixel_size {
-            3 => {
-                embed_lsb_data_left_right::<RgbPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
-            }
+        bmp_image_parser.embed_data(&mut data_vec.clone(), FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
 
-            4 => {
-                embed_lsb_data_left_right::<RgbaPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
-            }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
-            }
-        }
+        let mut embedded: Vec<u8> = Vec::new();
+        bmp_image_parser.write_to(&mut embedded).unwrap();
 
-        bmp_image_parser.write_file("src/filetype_support/assets/sample-1024x1024-TEST_EMBED.bmp");
+        embedded
+    }
 
+    #[test]
+    fn test_bmp_lsb_embed_left_right(){
+        let embedded = embedded_sample_lsb_left_right();
+        assert_eq!(embedded.len(), std::fs::metadata("src/filetype_support/assets/sample-1024x1024.bmp").unwrap().len() as usize);
     }
 
     #[test]
     fn test_bmp_lsb_retrieve_left_right(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024-TEST_EMBED.bmp");
-        bmp_image_parser.parse_file();
-
-        let mut data_vec: Vec<u8> = vec![0];
-        match bmp_image_parser.pixel_size {
-            3 => {
-               data_vec = extract_lsb_data_left_right::<RgbPixel>( &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64,360);
-            }
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&embedded_sample_lsb_left_right()).unwrap();
+        bmp_image_parser.parse_file().unwrap();
 
-            4 => {
-                data_vec = extract_lsb_data_left_right::<RgbaPixel>(&mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64, 360);
-            }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
-            }
-        }
+        let data_vec = bmp_image_parser.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
 
-        /*
-            the data vec will have an extra byte. the slice is just to remove that extra byte so that the test will pass
-         */
-            assert_eq!(data_vec[0..(360/8)], "This is a test embedding for testing purposes".as_bytes().to_vec());
+            assert_eq!(data_vec, "This is a test embedding for testing purposes".as_bytes().to_vec());
 
     }
 
-    #[test]
-    fn test_bmp_lsb_embed_large_message_left_right(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp");
-        bmp_image_parser.parse_file();
+    fn embedded_sample_lsb_large_message_left_right() -> Vec<u8> {
+        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        bmp_image_parser.parse_file().unwrap();
 
         let data_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
-        match bmp_image_parser.pixel_size {
-            3 => {
-                embed_lsb_data_left_right::<RgbPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
-            }
+        bmp_image_parser.embed_data(&mut data_vec.clone(), FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
 
-            4 => {
-                embed_lsb_data_left_right::<RgbaPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
-            }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
-            }
-        }
-
-        bmp_image_parser.write_file("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_LARGE.bmp");
+        let mut embedded: Vec<u8> = Vec::new();
+        bmp_image_parser.write_to(&mut embedded).unwrap();
 
         //ensure we shoved more than a full pixel row of bits in there so that we can test multi row embedding
         assert!(data_vec.len() > 384);
 
+        embedded
+    }
+
+    #[test]
+    fn test_bmp_lsb_embed_large_message_left_right(){
+        let embedded = embedded_sample_lsb_large_message_left_right();
+        assert_eq!(embedded.len(), std::fs::metadata("src/filetype_support/assets/sample-1024x1024.bmp").unwrap().len() as usize);
     }
 
     #[test]
     fn test_bmp_lsb_retrieve_large_message_left_right(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_LARGE.bmp");
-        bmp_image_parser.parse_file();
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&embedded_sample_lsb_large_message_left_right()).unwrap();
+        bmp_image_parser.parse_file().unwrap();
         let message_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
 
-        let mut data_vec: Vec<u8> = vec![0];
-        match bmp_image_parser.pixel_size {
-            3 => {
-                data_vec = extract_lsb_data_left_right::<RgbPixel>( &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64,(message_vec.len() * 8) as u64);
-            }
-
-            4 => {
-                data_vec = extract_lsb_data_left_right::<RgbaPixel>(&mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64, (message_vec.len() * 8) as u64);
-            }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
-            }
-        }
+        let data_vec = bmp_image_parser.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
 
-        /*
-            the data vec will have an extra byte. the slice is just to remove that extra byte so that the test will pass
-         */
-        assert_eq!(String::from_utf8(data_vec[0..(message_vec.len())].to_owned()), String::from_utf8(message_vec));
+        assert_eq!(String::from_utf8(data_vec), String::from_utf8(message_vec));
     }
 
 
@@ -248,339 +263,2482 @@ mod bmp_tests{
 /*
     Right left LSB
  */
-#[test]
-fn test_bmp_lsb_embed_right_left(){
-    let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp");
-    bmp_image_parser.parse_file();
+fn embedded_sample_lsb_right_left() -> Vec<u8> {
+    let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+    bmp_image_parser.parse_file().unwrap();
 
     let data_vec : Vec<u8> = "This is a test embedding for testing purposes".as_bytes().to_vec();
-    match bmp_image_parser.pixel_size {
-        3 => {
-            embed_lsb_data_right_left::<RgbPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
-        }
+    bmp_image_parser.embed_data(&mut data_vec.clone(), FileEncoding::Lsb, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed).unwrap();
 
-        4 => {
-            embed_lsb_data_right_left::<RgbaPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
-        }
-        _ => {
-            println!("bmp test.rs Got bad value for pixel size, exiting ...");
-            exit(1);
-        }
-    }
+    let mut embedded: Vec<u8> = Vec::new();
+    bmp_image_parser.write_to(&mut embedded).unwrap();
 
-    bmp_image_parser.write_file("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_RIGHT_LEFT.bmp");
+    embedded
+}
 
+#[test]
+fn test_bmp_lsb_embed_right_left(){
+    let embedded = embedded_sample_lsb_right_left();
+    assert_eq!(embedded.len(), std::fs::metadata("src/filetype_support/assets/sample-1024x1024.bmp").unwrap().len() as usize);
 }
 
 #[test]
 fn test_bmp_lsb_retrieve_right_left(){
-    let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_RIGHT_LEFT.bmp");
-    bmp_image_parser.parse_file();
+    let mut bmp_image_parser = BmpImageParser::from_bytes(&embedded_sample_lsb_right_left()).unwrap();
+    bmp_image_parser.parse_file().unwrap();
 
-    let mut data_vec: Vec<u8> = vec![0];
-    match bmp_image_parser.pixel_size {
-        3 => {
-            data_vec = extract_lsb_data_right_left::<RgbPixel>( &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64,360);
-        }
+    let data_vec = bmp_image_parser.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+    assert_eq!(data_vec, "This is a test embedding for testing purposes".as_bytes().to_vec());
+
+}
+
+fn embedded_sample_lsb_large_message_right_left() -> Vec<u8> {
+    let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+    bmp_image_parser.parse_file().unwrap();
+
+    let data_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
+    bmp_image_parser.embed_data(&mut data_vec.clone(), FileEncoding::Lsb, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+    let mut embedded: Vec<u8> = Vec::new();
+    bmp_image_parser.write_to(&mut embedded).unwrap();
+
+    //ensure we shoved more than a full pixel row of bits in there so that we can test multi row embedding
+    assert!(data_vec.len() > 384);
+
+    embedded
+}
+
+#[test]
+fn test_bmp_lsb_embed_large_message_right_left(){
+    let embedded = embedded_sample_lsb_large_message_right_left();
+    assert_eq!(embedded.len(), std::fs::metadata("src/filetype_support/assets/sample-1024x1024.bmp").unwrap().len() as usize);
+}
 
-        4 => {
-            data_vec = extract_lsb_data_right_left::<RgbaPixel>(&mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64, 360);
+#[test]
+fn test_bmp_lsb_retrieve_large_message_right_left(){
+    let mut bmp_image_parser = BmpImageParser::from_bytes(&embedded_sample_lsb_large_message_right_left()).unwrap();
+    bmp_image_parser.parse_file().unwrap();
+    let message_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
+
+    let data_vec = bmp_image_parser.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+    assert_eq!(String::from_utf8(data_vec), String::from_utf8(message_vec));
+}
+
+
+    fn embedded_sample_color_left_right() -> Vec<u8> {
+        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        bmp_image_parser.parse_file().unwrap();
+
+        let data_vec : Vec<u8> = "This is a test embedding for testing purposes".as_bytes().to_vec();
+        bmp_image_parser.embed_data(&mut data_vec.clone(), FileEncoding::ColorParity, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+        let mut embedded: Vec<u8> = Vec::new();
+        bmp_image_parser.write_to(&mut embedded).unwrap();
+
+        embedded
+    }
+
+    #[test]
+    fn test_bmp_color_embed_left_right(){
+        let embedded = embedded_sample_color_left_right();
+        assert_eq!(embedded.len(), std::fs::metadata("src/filetype_support/assets/sample-1024x1024.bmp").unwrap().len() as usize);
+    }
+
+    #[test]
+    fn test_bmp_color_retrieve_left_right(){
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&embedded_sample_color_left_right()).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+
+        let data_vec = bmp_image_parser.retrieve_data(FileEncoding::ColorParity, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+        assert_eq!(data_vec, "This is a test embedding for testing purposes".as_bytes().to_vec());
+
+    }
+
+
+
+    fn embedded_sample_color_large_message_left_right() -> Vec<u8> {
+        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        bmp_image_parser.parse_file().unwrap();
+
+        let data_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
+        bmp_image_parser.embed_data(&mut data_vec.clone(), FileEncoding::ColorParity, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+        let mut embedded: Vec<u8> = Vec::new();
+        bmp_image_parser.write_to(&mut embedded).unwrap();
+
+        //ensure we shoved more than a full pixel row of bits in there so that we can test multi row embedding
+        assert!(data_vec.len() > 384);
+
+        embedded
+    }
+
+    #[test]
+    fn test_bmp_color_embed_large_message_left_right(){
+        let embedded = embedded_sample_color_large_message_left_right();
+        assert_eq!(embedded.len(), std::fs::metadata("src/filetype_support/assets/sample-1024x1024.bmp").unwrap().len() as usize);
+    }
+
+    #[test]
+    fn test_bmp_color_retrieve_large_message_left_right(){
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&embedded_sample_color_large_message_left_right()).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        let message_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
+
+        let data_vec = bmp_image_parser.retrieve_data(FileEncoding::ColorParity, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+        assert_eq!(String::from_utf8(data_vec), String::from_utf8(message_vec));
+    }
+
+
+
+
+
+    /*
+        Right left color
+     */
+    fn embedded_sample_color_right_left() -> Vec<u8> {
+        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        bmp_image_parser.parse_file().unwrap();
+
+        let data_vec : Vec<u8> = "This is a test embedding for testing purposes".as_bytes().to_vec();
+        bmp_image_parser.embed_data(&mut data_vec.clone(), FileEncoding::ColorParity, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+        let mut embedded: Vec<u8> = Vec::new();
+        bmp_image_parser.write_to(&mut embedded).unwrap();
+
+        embedded
+    }
+
+    #[test]
+    fn test_bmp_color_embed_right_left(){
+        let embedded = embedded_sample_color_right_left();
+        assert_eq!(embedded.len(), std::fs::metadata("src/filetype_support/assets/sample-1024x1024.bmp").unwrap().len() as usize);
+    }
+
+    #[test]
+    fn test_bmp_color_retrieve_right_left(){
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&embedded_sample_color_right_left()).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+
+        let data_vec = bmp_image_parser.retrieve_data(FileEncoding::ColorParity, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+        assert_eq!(data_vec, "This is a test embedding for testing purposes".as_bytes().to_vec());
+
+    }
+
+    fn embedded_sample_color_large_message_right_left() -> Vec<u8> {
+        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        bmp_image_parser.parse_file().unwrap();
+
+        let data_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
+        bmp_image_parser.embed_data(&mut data_vec.clone(), FileEncoding::ColorParity, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+        let mut embedded: Vec<u8> = Vec::new();
+        bmp_image_parser.write_to(&mut embedded).unwrap();
+
+        //ensure we shoved more than a full pixel row of bits in there so that we can test multi row embedding
+        assert!(data_vec.len() > 384);
+
+        embedded
+    }
+
+    #[test]
+    fn test_bmp_color_embed_large_message_right_left(){
+        let embedded = embedded_sample_color_large_message_right_left();
+        assert_eq!(embedded.len(), std::fs::metadata("src/filetype_support/assets/sample-1024x1024.bmp").unwrap().len() as usize);
+    }
+
+    #[test]
+    fn test_bmp_color_retrieve_large_message_right_left(){
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&embedded_sample_color_large_message_right_left()).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        let message_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
+
+        let data_vec = bmp_image_parser.retrieve_data(FileEncoding::ColorParity, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+        assert_eq!(String::from_utf8(data_vec), String::from_utf8(message_vec));
+    }
+
+    /*
+        Failure paths, these must come back as errors rather than taking the process down
+     */
+    #[test]
+    fn test_bmp_missing_file_is_io_error(){
+        let result = BmpImageParser::new("src/filetype_support/assets/does-not-exist.bmp");
+        assert!(matches!(result, Err(StegoError::Io(_))));
+    }
+
+    #[test]
+    fn test_bmp_truncated_file_is_malformed(){
+        let sample = std::fs::read("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&sample[0..20]).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::MalformedHeader(_))));
+
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&sample[0..4096]).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::MalformedHeader(_))));
+    }
+
+    #[test]
+    fn test_bmp_bad_magic_is_unsupported(){
+        let mut sample = std::fs::read("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        sample[0] = b'X';
+
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&sample).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_bmp_embed_before_parse_is_not_ready(){
+        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        let mut data_vec : Vec<u8> = "This is a test embedding for testing purposes".as_bytes().to_vec();
+
+        let result = bmp_image_parser.embed_data(&mut data_vec, FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed);
+        assert!(matches!(result, Err(StegoError::FileNotReady(_))));
+    }
+
+    #[test]
+    fn test_bmp_embed_too_large_is_insufficient_capacity(){
+        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        bmp_image_parser.parse_file().unwrap();
+
+        let width = bmp_image_parser.pixel_map.width as u64;
+        let height = bmp_image_parser.pixel_map.height as u64;
+        let mut data_vec = vec![0xAAu8; (width * height) as usize];
+
+        let result = bmp_image_parser.embed_data(&mut data_vec, FileEncoding::ColorParity, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed);
+        match result {
+            Err(StegoError::InsufficientCapacity { required_bits, available_bits }) => {
+                assert_eq!(required_bits, (width * height + PAYLOAD_HEADER_SIZE as u64) * 8);
+                assert_eq!(available_bits, width * height);
+            }
+            _ => panic!("expected InsufficientCapacity"),
         }
-        _ => {
-            println!("bmp test.rs Got bad value for pixel size, exiting ...");
-            exit(1);
+    }
+
+    #[test]
+    fn test_rgb_pixel_fourth_channel_is_error(){
+        let mut pixel = RgbPixel::default();
+        assert!(matches!(pixel.set_fourth(1), Err(StegoError::InvalidChannel(_))));
+        assert!(matches!(pixel.set_alpha(1), Err(StegoError::InvalidChannel(_))));
+
+        let mut pixel = RgbaPixel::default();
+        assert!(pixel.set_fourth(1).is_ok());
+        assert_eq!(pixel.alpha(), 1);
+    }
+
+    #[test]
+    fn test_bmp_retrieve_data_every_supported_pair(){
+        let message = "Every implemented encoding and method should come back exactly".as_bytes().to_vec();
+
+        for encoding in [FileEncoding::Lsb, FileEncoding::ColorParity] {
+            for encoding_method in [
+                FileEncodingMethod::LeftToRight,
+                FileEncodingMethod::RightToLeft,
+                FileEncodingMethod::TopToBottom,
+                FileEncodingMethod::SinWave,
+                FileEncodingMethod::CosWave,
+                FileEncodingMethod::PolynomialFunction,
+                FileEncodingMethod::FractalFunction,
+            ] {
+                let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+                bmp_image_parser.parse_file().unwrap();
+
+                bmp_image_parser.embed_data(&mut message.clone(), encoding, encoding_method, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+                let retrieved = bmp_image_parser.retrieve_data(encoding, encoding_method, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+                assert_eq!(retrieved, message, "{encoding:?} {encoding_method:?}");
+            }
         }
     }
 
+    #[test]
+    fn test_bmp_unimplemented_pair_is_unsupported(){
+        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        bmp_image_parser.parse_file().unwrap();
+
+        let result = bmp_image_parser.embed_data(&mut vec![1, 2, 3], FileEncoding::HammingMatrix, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed);
+        assert!(matches!(result, Err(StegoError::UnsupportedEncoding(_))));
+
+        let result = bmp_image_parser.retrieve_data(FileEncoding::PixelValueDifferencing, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed);
+        assert!(matches!(result, Err(StegoError::UnsupportedEncoding(_))));
+    }
+
+    #[test]
+    fn test_bmp_in_memory_round_trip(){
+        let sample = std::fs::read("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        let message = "Straight from a reader and back out to a writer".as_bytes().to_vec();
+
+        let mut bmp_image_parser = BmpImageParser::from_reader(std::io::Cursor::new(&sample)).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        bmp_image_parser.embed_data(&mut message.clone(), FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+
+        let mut embedded: Vec<u8> = Vec::new();
+        bmp_image_parser.write_to(&mut embedded).unwrap();
+        assert_eq!(embedded.len(), sample.len());
+        assert_ne!(embedded, sample);
+
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&embedded).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        let retrieved = bmp_image_parser.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+        assert_eq!(retrieved, message);
+    }
+
+    #[test]
+    fn test_bmp_capacity_report(){
+        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp").unwrap();
+        bmp_image_parser.parse_file().unwrap();
+
+        let report = bmp_image_parser.capacity(FileEncoding::Lsb, FileEncodingMethod::LeftToRight).unwrap();
+        assert_eq!(report.total_bits, 1024 * 1024 * 3);
+        assert_eq!(report.bits_per_pixel, 3);
+        assert_eq!(report.header_overhead_bytes, PAYLOAD_HEADER_SIZE as u64);
+        assert_eq!(report.usable_payload_bytes, (1024 * 1024 * 3) / 8 - PAYLOAD_HEADER_SIZE as u64);
+
+        let report = bmp_image_parser.capacity(FileEncoding::ColorParity, FileEncodingMethod::RightToLeft).unwrap();
+        assert_eq!(report.total_bits, 1024 * 1024);
+        assert_eq!(report.bits_per_pixel, 1);
+
+        // Exactly usable_payload_bytes must fit and one more byte must not
+        let mut data_vec = vec![0x55u8; report.usable_payload_bytes as usize];
+        bmp_image_parser.embed_data(&mut data_vec, FileEncoding::ColorParity, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+        data_vec.push(0x55);
+        let result = bmp_image_parser.embed_data(&mut data_vec, FileEncoding::ColorParity, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed);
+        assert!(matches!(result, Err(StegoError::InsufficientCapacity { .. })));
+
+        let result = bmp_image_parser.capacity(FileEncoding::HammingMatrix, FileEncodingMethod::LeftToRight);
+        assert!(matches!(result, Err(StegoError::UnsupportedEncoding(_))));
+    }
+
     /*
-        the data vec will have an extra byte. the slice is just to remove that extra byte so that the test will pass
+        Builds a small uncompressed BMP in memory with a DIB header of dib_header_size bytes. Headers past
+        BITMAPINFOHEADER get the plain BGRA masks, sRGB and some non zero colour space values so it is easy to
+        see whether they survive.
      */
-    assert_eq!(data_vec[0..(360/8)], "This is a test embedding for testing purposes".as_bytes().to_vec());
+    fn synthetic_bmp(width: u32, height: i32, bit_count: u16, dib_header_size: u32) -> Vec<u8> {
+        let row_size = (width as usize * bit_count as usize / 8).div_ceil(4) * 4;
+        let pixel_data_size = row_size * height.unsigned_abs() as usize;
+        let pixel_map_start = 14 + dib_header_size as usize;
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"BM");
+        bytes.extend_from_slice(&((pixel_map_start + pixel_data_size) as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        bytes.extend_from_slice(&(pixel_map_start as u32).to_le_bytes());
+
+        let mut dib = vec![0u8; dib_header_size as usize];
+        dib[0..4].copy_from_slice(&dib_header_size.to_le_bytes());
+        dib[4..8].copy_from_slice(&width.to_le_bytes());
+        dib[8..12].copy_from_slice(&height.to_le_bytes());
+        dib[12..14].copy_from_slice(&1u16.to_le_bytes());
+        dib[14..16].copy_from_slice(&bit_count.to_le_bytes());
+        dib[20..24].copy_from_slice(&(pixel_data_size as u32).to_le_bytes());
+        dib[24..28].copy_from_slice(&2835u32.to_le_bytes());
+        dib[28..32].copy_from_slice(&2835u32.to_le_bytes());
+
+        let masks: [u32; 4] = [0x00FF0000, 0x0000FF00, 0x000000FF, 0xFF000000];
+        for (i, mask) in masks.iter().enumerate() {
+            if dib.len() >= 44 + i * 4 {
+                dib[40 + i * 4..44 + i * 4].copy_from_slice(&mask.to_le_bytes());
+            }
+        }
+        if dib.len() >= 108 {
+            dib[56..60].copy_from_slice(b"BGRs");
+            for (i, value) in dib[60..108].iter_mut().enumerate() {
+                *value = (i * 11 + 1) as u8;
+            }
+        }
+        if dib.len() >= 124 {
+            dib[108..112].copy_from_slice(&4u32.to_le_bytes());
+        }
+        bytes.extend_from_slice(&dib);
 
-}
+        bytes.extend((0..pixel_data_size).map(|i| (i * 7 % 251) as u8));
+        bytes
+    }
 
-#[test]
-fn test_bmp_lsb_embed_large_message_right_left(){
-    let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp");
-    bmp_image_parser.parse_file();
+    /*
+        Appends profile after the pixel data and points the V5 header at it
+     */
+    fn with_color_profile(mut bytes: Vec<u8>, cs_type: u32, profile: &[u8]) -> Vec<u8> {
+        let profile_data = (bytes.len() - 14) as u32;
+        bytes[70..74].copy_from_slice(&cs_type.to_le_bytes());
+        bytes[126..130].copy_from_slice(&profile_data.to_le_bytes());
+        bytes[130..134].copy_from_slice(&(profile.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(profile);
+        let file_size = bytes.len() as u32;
+        bytes[2..6].copy_from_slice(&file_size.to_le_bytes());
+        bytes
+    }
 
-    let data_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
-    match bmp_image_parser.pixel_size {
-        3 => {
-            embed_lsb_data_right_left::<RgbPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
-        }
+    /*
+        BITMAPINFOHEADER palette image, pixel n uses index n * 7 mod the palette size
+     */
+    fn indexed_bmp(width: u32, height: i32, bit_count: u16, palette: &[[u8; 3]]) -> Vec<u8> {
+        indexed_bmp_with_header(width, height, bit_count, palette, 40)
+    }
 
-        4 => {
-            embed_lsb_data_right_left::<RgbaPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
+    fn indexed_bmp_with_header(width: u32, height: i32, bit_count: u16, palette: &[[u8; 3]], dib_header_size: u32) -> Vec<u8> {
+        let row_size = (width as usize * bit_count as usize).div_ceil(32) * 4;
+        let rows = height.unsigned_abs() as usize;
+        let pixel_map_start = 14 + dib_header_size as usize + palette.len() * 4;
+
+        let mut bytes = synthetic_bmp(width, height, bit_count, dib_header_size);
+        bytes.truncate(14 + dib_header_size as usize);
+        bytes[10..14].copy_from_slice(&(pixel_map_start as u32).to_le_bytes());
+        bytes[46..50].copy_from_slice(&(palette.len() as u32).to_le_bytes());
+        for [red, green, blue] in palette {
+            bytes.extend_from_slice(&[*blue, *green, *red, 0]);
         }
-        _ => {
-            println!("bmp test.rs Got bad value for pixel size, exiting ...");
-            exit(1);
+
+        let mut pixel_data = vec![0u8; row_size * rows];
+        for row in 0..rows {
+            for col in 0..width as usize {
+                let index = ((row * width as usize + col) * 7 % palette.len()) as u8;
+                let bit = col * bit_count as usize;
+                pixel_data[row * row_size + bit / 8] |= index << (8 - bit_count as usize - bit % 8);
+            }
         }
+        bytes.extend_from_slice(&pixel_data);
+
+        let file_size = bytes.len() as u32;
+        bytes[2..6].copy_from_slice(&file_size.to_le_bytes());
+        bytes
     }
 
-    bmp_image_parser.write_file("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_LARGE_RIGHT_LEFT.bmp");
+    fn grey_palette(colours: usize) -> Vec<[u8; 3]> {
+        // Shuffled so palette order and brightness order are not the same thing, neighbouring greys are at
+        // most 8 levels apart so every one of them has a close partner
+        let step = (255 / (colours - 1).max(1)).min(8);
+        (0..colours).map(|i| {
+            let level = ((i * 37) % colours * step) as u8;
+            [level, level, level]
+        }).collect()
+    }
 
-    //ensure we shoved more than a full pixel row of bits in there so that we can test multi row embedding
-    assert!(data_vec.len() > 384);
+    // The 16 colours of the VGA palette, no two of them are anywhere near each other
+    const VGA_PALETTE: [[u8; 3]; 16] = [
+        [0, 0, 0], [0, 0, 170], [0, 170, 0], [0, 170, 170], [170, 0, 0], [170, 0, 170], [170, 85, 0], [170, 170, 170],
+        [85, 85, 85], [85, 85, 255], [85, 255, 85], [85, 255, 255], [255, 85, 85], [255, 85, 255], [255, 255, 85], [255, 255, 255],
+    ];
 
-}
+    /*
+        Channel masks (red, green, blue, alpha) go into the header for V2 and up, after a BITMAPINFOHEADER
+        they are inserted in front of the pixel data, three of them for BI_BITFIELDS (3) and four for
+        BI_ALPHABITFIELDS (6)
+     */
+    fn bitfield_bmp(width: u32, height: i32, bit_count: u16, dib_header_size: u32, compression: u32, masks: [u32; 4]) -> Vec<u8> {
+        let mut bytes = synthetic_bmp(width, height, bit_count, dib_header_size);
+        bytes[30..34].copy_from_slice(&compression.to_le_bytes());
+
+        let mask_bytes: Vec<u8> = masks.iter().flat_map(|mask| mask.to_le_bytes()).collect();
+        if dib_header_size == 40 {
+            let count = if compression == 6 { 16 } else { 12 };
+            bytes.splice(54..54, mask_bytes[..count].iter().copied());
+            let pixel_map_start = (54 + count) as u32;
+            bytes[10..14].copy_from_slice(&pixel_map_start.to_le_bytes());
+        } else {
+            let count = (dib_header_size as usize - 40).min(16);
+            bytes[54..54 + count].copy_from_slice(&mask_bytes[..count]);
+        }
 
-#[test]
-fn test_bmp_lsb_retrieve_large_message_right_left(){
-    let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_LARGE_RIGHT_LEFT.bmp");
-    bmp_image_parser.parse_file();
-    let message_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
+        let file_size = bytes.len() as u32;
+        bytes[2..6].copy_from_slice(&file_size.to_le_bytes());
+        bytes
+    }
 
-    let mut data_vec: Vec<u8> = vec![0];
-    match bmp_image_parser.pixel_size {
-        3 => {
-            data_vec = extract_lsb_data_right_left::<RgbPixel>( &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64,(message_vec.len() * 8) as u64);
+    /*
+        indexed_bmp with its pixel data run length encoded, compression 1 for 8 bit and 2 for 4 bit
+     */
+    fn rle_bmp(width: u32, height: i32, bit_count: u16, palette: &[[u8; 3]], dib_header_size: u32) -> Vec<u8> {
+        let compression = if bit_count == 8 { RleCompression::Rle8 } else { RleCompression::Rle4 };
+        let mut bytes = indexed_bmp_with_header(width, height, bit_count, palette, dib_header_size);
+        let pixel_map_start = 14 + dib_header_size as usize + palette.len() * 4;
+
+        // Stretch the generated indices into runs so there is something to compress
+        let mut indices = bytes.split_off(pixel_map_start);
+        for (i, index) in indices.iter_mut().enumerate() {
+            if (i / 6) % 2 == 0 {
+                *index = 0;
+            }
         }
+        let encoded = encode_rle(&indices, width as usize, height as usize, compression);
+
+        bytes.extend_from_slice(&encoded);
+        bytes[30..34].copy_from_slice(&(if bit_count == 8 { 1u32 } else { 2 }).to_le_bytes());
+        bytes[34..38].copy_from_slice(&(encoded.len() as u32).to_le_bytes());
+        let file_size = bytes.len() as u32;
+        bytes[2..6].copy_from_slice(&file_size.to_le_bytes());
+        bytes
+    }
 
-        4 => {
-            data_vec = extract_lsb_data_right_left::<RgbaPixel>(&mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64, (message_vec.len() * 8) as u64);
+    /*
+        OS/2 bitmap, a 12 byte BITMAPCOREHEADER with 3 byte color table entries or a 16 or 64 byte OS/2 2.x
+        header. Palette images use index n * 7 mod the palette size for pixel n like indexed_bmp.
+     */
+    fn os2_bmp(width: u32, height: u32, bit_count: u16, dib_header_size: u32, palette: &[[u8; 3]]) -> Vec<u8> {
+        let entry_size = if dib_header_size == 12 { 3 } else { 4 };
+        let row_size = (width as usize * bit_count as usize).div_ceil(32) * 4;
+        let pixel_map_start = 14 + dib_header_size as usize + palette.len() * entry_size;
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"BM");
+        bytes.extend_from_slice(&((pixel_map_start + row_size * height as usize) as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        bytes.extend_from_slice(&(pixel_map_start as u32).to_le_bytes());
+
+        let mut dib = vec![0u8; dib_header_size as usize];
+        dib[0..4].copy_from_slice(&dib_header_size.to_le_bytes());
+        if dib_header_size == 12 {
+            dib[4..6].copy_from_slice(&(width as u16).to_le_bytes());
+            dib[6..8].copy_from_slice(&(height as u16).to_le_bytes());
+            dib[8..10].copy_from_slice(&1u16.to_le_bytes());
+            dib[10..12].copy_from_slice(&bit_count.to_le_bytes());
+        } else {
+            dib[4..8].copy_from_slice(&width.to_le_bytes());
+            dib[8..12].copy_from_slice(&height.to_le_bytes());
+            dib[12..14].copy_from_slice(&1u16.to_le_bytes());
+            dib[14..16].copy_from_slice(&bit_count.to_le_bytes());
+            if dib.len() >= 36 {
+                dib[32..36].copy_from_slice(&(palette.len() as u32).to_le_bytes());
+                // Units, reserved, recording and rendering, none of them describe the pixels
+                dib[42..44].copy_from_slice(&4u16.to_le_bytes());
+            }
         }
-        _ => {
-            println!("bmp test.rs Got bad value for pixel size, exiting ...");
-            exit(1);
+        bytes.extend_from_slice(&dib);
+
+        for [red, green, blue] in palette {
+            bytes.extend_from_slice(&[*blue, *green, *red, 0][..entry_size]);
+        }
+
+        let mut pixel_data = vec![0u8; row_size * height as usize];
+        for row in 0..height as usize {
+            for col in 0..width as usize {
+                if palette.is_empty() {
+                    for channel in 0..3 {
+                        pixel_data[row * row_size + col * 3 + channel] = ((row * width as usize + col) * 3 + channel) as u8;
+                    }
+                    continue;
+                }
+                let index = ((row * width as usize + col) * 7 % palette.len()) as u8;
+                let bit = col * bit_count as usize;
+                pixel_data[row * row_size + bit / 8] |= index << (8 - bit_count as usize - bit % 8);
+            }
         }
+        bytes.extend_from_slice(&pixel_data);
+        bytes
     }
 
     /*
-        the data vec will have an extra byte. the slice is just to remove that extra byte so that the test will pass
+        Moves the pixel data gap bytes further back, the gap is filled with 0xEE
      */
-    assert_eq!(String::from_utf8(data_vec[0..(message_vec.len())].to_owned()), String::from_utf8(message_vec));
-}
+    fn with_header_gap(mut bytes: Vec<u8>, gap: usize) -> Vec<u8> {
+        let pixel_map_start = u32::from_le_bytes(bytes[10..14].try_into().unwrap());
+        bytes.splice(pixel_map_start as usize..pixel_map_start as usize, vec![0xEE; gap]);
+        bytes[10..14].copy_from_slice(&(pixel_map_start + gap as u32).to_le_bytes());
+        let file_size = bytes.len() as u32;
+        bytes[2..6].copy_from_slice(&file_size.to_le_bytes());
+        bytes
+    }
 
+    fn embed_and_write(bytes: &[u8], message: &[u8]) -> Vec<u8> {
+        let mut bmp_image_parser = BmpImageParser::from_bytes(bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        bmp_image_parser.embed_data(&mut message.to_vec(), FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
 
-    #[test]
-    fn test_bmp_color_embed_left_right(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp");
-        bmp_image_parser.parse_file();
+        let mut written = Vec::new();
+        bmp_image_parser.write_to(&mut written).unwrap();
+        written
+    }
 
-        let data_vec : Vec<u8> = "This is a test embedding for testing purposes".as_bytes().to_vec();
-        match bmp_image_parser.pixel_size {
-            3 => {
-                embed_color_data_left_right::<RgbPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
+    #[test]
+    fn test_bmp_every_header_version_round_trip(){
+        let message = "any header version".as_bytes();
+
+        for (dib_header_size, header_version) in [
+            (40, BmpHeaderVersion::Info),
+            (52, BmpHeaderVersion::V2),
+            (56, BmpHeaderVersion::V3),
+            (108, BmpHeaderVersion::V4),
+            (124, BmpHeaderVersion::V5),
+        ] {
+            for bit_count in [24, 32] {
+                let original = synthetic_bmp(37, 21, bit_count, dib_header_size);
+                let written = embed_and_write(&original, message);
+
+                let pixel_map_start = 14 + dib_header_size as usize;
+                assert_eq!(written.len(), original.len());
+                assert_eq!(written[..pixel_map_start], original[..pixel_map_start], "{header_version:?} {bit_count}");
+
+                let mut bmp_image_parser = BmpImageParser::from_bytes(&written).unwrap();
+                bmp_image_parser.parse_file().unwrap();
+                assert_eq!(bmp_image_parser.header_version, header_version);
+                assert_eq!(bmp_image_parser.pixel_size as u16, bit_count / 8);
+                let retrieved = bmp_image_parser.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+                assert_eq!(retrieved, message, "{header_version:?} {bit_count}");
             }
+        }
+    }
 
-            4 => {
-                embed_color_data_left_right::<RgbaPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
-            }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
+    #[test]
+    fn test_bmp_v5_fields_are_parsed(){
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&synthetic_bmp(8, 8, 32, 124)).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+
+        let v5_fields = bmp_image_parser.v5_fields;
+        assert_eq!(v5_fields.red_mask, 0x00FF0000);
+        assert_eq!(v5_fields.alpha_mask, 0xFF000000);
+        assert_eq!(v5_fields.cs_type, u32::from_le_bytes(*b"BGRs"));
+        assert_eq!(v5_fields.endpoints[0], i32::from_le_bytes([1, 12, 23, 34]));
+        assert_eq!(v5_fields.gamma_blue, u32::from_le_bytes([229, 240, 251, 6]));
+        assert_eq!(v5_fields.intent, 4);
+        assert_eq!(bmp_image_parser.color_profile, None);
+
+        // A V3 header stops after the alpha mask
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&synthetic_bmp(8, 8, 32, 56)).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert_eq!(bmp_image_parser.v5_fields.alpha_mask, 0xFF000000);
+        assert_eq!(bmp_image_parser.v5_fields.cs_type, 0);
+    }
+
+    #[test]
+    fn test_bmp_v5_color_profiles_survive_embedding(){
+        let icc_profile: Vec<u8> = (0..300u32).map(|i| (i * 13 % 256) as u8).collect();
+        let message = "profile stays put".as_bytes();
+
+        // PROFILE_EMBEDDED ('MBED') and PROFILE_LINKED ('LINK')
+        for (cs_type, profile) in [(0x4D424544, icc_profile.as_slice()), (0x4C494E4B, "C:\\profiles\\display.icm\0".as_bytes())] {
+            let original = with_color_profile(synthetic_bmp(16, 16, 24, 124), cs_type, profile);
+
+            let mut bmp_image_parser = BmpImageParser::from_bytes(&original).unwrap();
+            bmp_image_parser.parse_file().unwrap();
+            assert_eq!(bmp_image_parser.color_profile_bytes().unwrap(), profile);
+            match (cs_type, bmp_image_parser.color_profile.unwrap()) {
+                (0x4D424544, BmpColorProfile::Embedded { size, .. }) | (0x4C494E4B, BmpColorProfile::Linked { size, .. }) => assert_eq!(size, profile.len() as u64),
+                (_, other) => panic!("unexpected profile {other:?}"),
             }
+
+            let written = embed_and_write(&original, message);
+            let pixel_map_start = 14 + 124;
+            let pixel_map_end = pixel_map_start + 16 * 16 * 3;
+            assert_eq!(written[..pixel_map_start], original[..pixel_map_start]);
+            assert_eq!(written[pixel_map_end..], original[pixel_map_end..]);
+            assert_ne!(written[pixel_map_start..pixel_map_end], original[pixel_map_start..pixel_map_end]);
         }
+    }
 
-        bmp_image_parser.write_file("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_COLOR_LEFT_RIGHT.bmp");
+    fn flip_rows(pixel_data: &[u8], row_size: usize) -> Vec<u8> {
+        pixel_data.chunks_exact(row_size).rev().flatten().copied().collect()
+    }
 
+    #[test]
+    fn test_bmp_top_down_matches_bottom_up_visually(){
+        let (width, height) = (19u32, 11i32);
+        let message = "same picture either way".as_bytes();
+        let pixel_map_start = 14 + 40;
+
+        for bit_count in [24u16, 32] {
+            let row_size = (width as usize * bit_count as usize / 8).div_ceil(4) * 4;
+            let bottom_up = synthetic_bmp(width, height, bit_count, 40);
+
+            // Same image stored the other way up
+            let mut top_down = synthetic_bmp(width, -height, bit_count, 40);
+            top_down[pixel_map_start..].copy_from_slice(&flip_rows(&bottom_up[pixel_map_start..], row_size));
+
+            let mut bmp_image_parser = BmpImageParser::from_bytes(&top_down).unwrap();
+            bmp_image_parser.parse_file().unwrap();
+            assert_eq!(bmp_image_parser.pixel_map.height, height as u32);
+            assert_eq!(bmp_image_parser.pixel_map.row_order, RowOrder::TopDown);
+
+            for encoding_method in [FileEncodingMethod::LeftToRight, FileEncodingMethod::TopToBottom, FileEncodingMethod::FractalFunction] {
+                let mut embedded = Vec::new();
+                for original in [&bottom_up, &top_down] {
+                    let mut bmp_image_parser = BmpImageParser::from_bytes(original).unwrap();
+                    bmp_image_parser.parse_file().unwrap();
+                    bmp_image_parser.embed_data(&mut message.to_vec(), FileEncoding::Lsb, encoding_method, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+                    assert_eq!(bmp_image_parser.retrieve_data(FileEncoding::Lsb, encoding_method, FileEncodingFunctionDerivation::Unkeyed).unwrap(), message);
+                    embedded.push(bmp_image_parser.file_data);
+                }
+
+                assert_eq!(
+                    embedded[0][pixel_map_start..],
+                    flip_rows(&embedded[1][pixel_map_start..], row_size),
+                    "{bit_count} {encoding_method:?}"
+                );
+            }
+        }
     }
 
     #[test]
-    fn test_bmp_color_retrieve_left_right(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_COLOR_LEFT_RIGHT.bmp");
-        bmp_image_parser.parse_file();
+    fn test_bmp_bad_dimensions_are_rejected(){
+        // A top down header whose pixel data is cut short must not be read as a 4 billion row image
+        let mut bytes = synthetic_bmp(8, -8, 24, 40);
+        bytes.truncate(bytes.len() - 10);
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::MalformedHeader(_))));
+
+        for width in [0i32, -8] {
+            let mut bytes = synthetic_bmp(8, 8, 24, 40);
+            bytes[18..22].copy_from_slice(&width.to_le_bytes());
+            let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+            assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::MalformedHeader(_))));
+        }
+    }
 
-        let mut data_vec: Vec<u8> = vec![0];
-        match bmp_image_parser.pixel_size {
-            3 => {
-                data_vec = extract_color_data_left_right::<RgbPixel>( &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64,360);
+    #[test]
+    fn test_bmp_bad_headers_are_rejected(){
+        // Profile claims to run past the end of the file
+        let mut bytes = with_color_profile(synthetic_bmp(4, 4, 24, 124), 0x4D424544, &[1, 2, 3, 4]);
+        bytes.truncate(bytes.len() - 1);
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::MalformedHeader(_))));
+
+        // Pixel data offset pointing into the DIB header
+        let mut bytes = synthetic_bmp(4, 4, 24, 108);
+        bytes[10..14].copy_from_slice(&60u32.to_le_bytes());
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::MalformedHeader(_))));
+
+        // Unknown DIB header size
+        let mut bytes = synthetic_bmp(4, 4, 24, 64);
+        bytes[14..18].copy_from_slice(&100u32.to_le_bytes());
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::UnsupportedFormat(_))));
+
+        // Compressed pixel data is not something the parser can embed into
+        let mut bytes = synthetic_bmp(4, 4, 24, 40);
+        bytes[30..34].copy_from_slice(&4u32.to_le_bytes());
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_bmp_indexed_round_trip(){
+        let message = "Palette images carry one bit per pixel".as_bytes().to_vec();
+        let key = FileEncodingFunctionDerivation::from_passphrase("indexed");
+
+        for (bit_count, colours) in [(1u16, 2usize), (4, 16), (4, 11), (8, 256), (8, 37)] {
+            for height in [41i32, -41] {
+                let bytes = indexed_bmp(37, height, bit_count, &grey_palette(colours));
+                // Greys left without a close partner carry nothing
+                let image = decode_bmp(&bytes).unwrap();
+                let pairs = PalettePairs::new(&image.palette, &[]);
+                let carrying_pixels = image.samples.iter().filter(|&&index| pairs.partner(index as u8).is_some()).count() as u64;
+                assert!(carrying_pixels > 0 && carrying_pixels <= 37 * 41);
+
+                for encoding_method in [
+                    FileEncodingMethod::LeftToRight,
+                    FileEncodingMethod::RightToLeft,
+                    FileEncodingMethod::TopToBottom,
+                    FileEncodingMethod::SinWave,
+                    FileEncodingMethod::CosWave,
+                    FileEncodingMethod::PolynomialFunction,
+                    FileEncodingMethod::FractalFunction,
+                ] {
+                    for derivation in [FileEncodingFunctionDerivation::Unkeyed, key] {
+                        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+                        bmp_image_parser.parse_file().unwrap();
+                        assert_eq!(bmp_image_parser.pixel_size, 0);
+                        assert_eq!(bmp_image_parser.color_table.len(), colours);
+                        assert_eq!(bmp_image_parser.capacity(FileEncoding::Lsb, encoding_method).unwrap().total_bits, carrying_pixels);
+
+                        bmp_image_parser.embed_data(&mut message.clone(), FileEncoding::Lsb, encoding_method, derivation).unwrap();
+
+                        let mut written = Vec::new();
+                        bmp_image_parser.write_to(&mut written).unwrap();
+                        let mut reread = BmpImageParser::from_bytes(&written).unwrap();
+                        reread.parse_file().unwrap();
+                        let retrieved = reread.retrieve_data(FileEncoding::Lsb, encoding_method, derivation).unwrap();
+
+                        assert_eq!(retrieved, message, "{bit_count} bit {colours} colours {height} {encoding_method:?} {derivation:?}");
+                    }
+                }
             }
+        }
+    }
 
-            4 => {
-                data_vec = extract_color_data_left_right::<RgbaPixel>(&mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64, 360);
+    #[test]
+    fn test_bmp_indexed_only_moves_to_close_colours(){
+        let palette = grey_palette(256);
+        let bytes = indexed_bmp(64, 64, 8, &palette);
+        let pixel_map_start = 14 + 40 + 256 * 4;
+
+        let message = vec![0xA5u8; 400];
+        let written = embed_and_write(&bytes, &message);
+
+        // Header and palette untouched, every changed index is a close grey
+        assert_eq!(written[..pixel_map_start], bytes[..pixel_map_start]);
+        let mut changed = 0;
+        for (before, after) in bytes[pixel_map_start..].iter().zip(&written[pixel_map_start..]) {
+            if before != after {
+                changed += 1;
+                let before = palette[*before as usize][0] as i32;
+                let after = palette[*after as usize][0] as i32;
+                assert!(before != after && 9 * (before - after).pow(2) <= PalettePairs::MAX_DISTANCE as i32);
             }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
+        }
+        assert!(changed > 0);
+    }
+
+    #[test]
+    fn test_bmp_indexed_never_swaps_distant_colours(){
+        // Neighbours in brightness, but green and purple, red and teal and so on
+        let bytes = indexed_bmp(32, 32, 4, &VGA_PALETTE);
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert_eq!(bmp_image_parser.capacity(FileEncoding::Lsb, FileEncodingMethod::LeftToRight).unwrap().total_bits, 0);
+        let result = bmp_image_parser.embed_data(&mut vec![1u8; 4], FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed);
+        assert!(matches!(result, Err(StegoError::InsufficientCapacity { available_bits: 0, .. })));
+
+        // Give half the colours a near copy, only pixels of those colours carry bits and only ever swap with the copy
+        let mut palette = VGA_PALETTE.to_vec();
+        palette.extend(VGA_PALETTE[..8].iter().map(|&[red, green, blue]| [red + 3, green + 3, blue + 3]));
+        let bytes = indexed_bmp(32, 32, 8, &palette);
+        let pixel_map_start = 14 + 40 + palette.len() * 4;
+        let capacity = {
+            let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+            bmp_image_parser.parse_file().unwrap();
+            bmp_image_parser.capacity(FileEncoding::Lsb, FileEncodingMethod::LeftToRight).unwrap().total_bits
+        };
+        let paired = bytes[pixel_map_start..].iter().filter(|&&index| !(8..16).contains(&index)).count() as u64;
+        assert_eq!(capacity, paired);
+
+        let written = embed_and_write(&bytes, &[0x5Au8; 40]);
+        let mut changed = 0;
+        for (&before, &after) in bytes[pixel_map_start..].iter().zip(&written[pixel_map_start..]) {
+            if before != after {
+                changed += 1;
+                assert_eq!(before % 16, after % 16, "{before} {after}");
+                assert!(!(8..16).contains(&before));
             }
         }
+        assert!(changed > 0);
+    }
 
-        /*
-            the data vec will have an extra byte. the slice is just to remove that extra byte so that the test will pass
-         */
-        assert_eq!(data_vec[0..(360/8)], "This is a test embedding for testing purposes".as_bytes().to_vec());
+    #[test]
+    fn test_bmp_indexed_bad_input_is_rejected(){
+        // Parity of the palette index is the only primitive for palette images
+        let bytes = indexed_bmp(8, 8, 8, &grey_palette(16));
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        let result = bmp_image_parser.embed_data(&mut vec![1, 2, 3], FileEncoding::ColorParity, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed);
+        assert!(matches!(result, Err(StegoError::UnsupportedEncoding(_))));
+
+        // A single colour has no partner to move to
+        let bytes = indexed_bmp(8, 8, 8, &[[10, 20, 30]]);
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert_eq!(bmp_image_parser.capacity(FileEncoding::Lsb, FileEncodingMethod::LeftToRight).unwrap().total_bits, 0);
+
+        // Color table running into the pixel data
+        let mut bytes = indexed_bmp(8, 8, 8, &grey_palette(16));
+        bytes[46..50].copy_from_slice(&64u32.to_le_bytes());
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::MalformedHeader(_))));
+
+        // Pixels pointing past the end of the palette have no colour to pair with and carry nothing
+        let mut bytes = indexed_bmp(16, 16, 8, &grey_palette(16));
+        bytes[14 + 40 + 16 * 4..].fill(200);
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert_eq!(bmp_image_parser.capacity(FileEncoding::Lsb, FileEncodingMethod::RightToLeft).unwrap().total_bits, 0);
+        let result = bmp_image_parser.embed_data(&mut vec![0u8; 8], FileEncoding::Lsb, FileEncodingMethod::RightToLeft, FileEncodingFunctionDerivation::Unkeyed);
+        assert!(matches!(result, Err(StegoError::InsufficientCapacity { available_bits: 0, .. })));
+    }
 
+    #[test]
+    fn test_bmp_bitfield_pixels(){
+        let rgb565 = BitfieldFormat::new(0xF800, 0x07E0, 0x001F, 0, 16).unwrap();
+        // red 31, green 0b100000, blue 1
+        let mut pixel = Bitfield16Pixel::from_bytes(&0xFC01u16.to_le_bytes(), rgb565);
+        assert_eq!((pixel.third(), pixel.second(), pixel.first(), pixel.fourth()), (31, 32, 1, 255));
+        assert_eq!((pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()), (255, 130, 8, 255));
+
+        // Setting the low bit of a channel only ever touches the lowest bit of its mask
+        pixel.set_second(33);
+        pixel.set_first(0);
+        let mut bytes = [0u8; 2];
+        pixel.write_bytes(&mut bytes);
+        assert_eq!(u16::from_le_bytes(bytes), 0xFC20);
+        assert!(pixel.set_fourth(1).is_err());
+
+        pixel.set_green(255);
+        assert_eq!(pixel.second(), 63);
     }
 
+    #[test]
+    fn test_bmp_bitfield_round_trip(){
+        let message = "Packed pixels keep their layout".as_bytes().to_vec();
+        let cases = [
+            // 16 bit BI_RGB is RGB555
+            (16, 40, 0, [0, 0, 0, 0], 3),
+            (16, 40, 3, [0xF800, 0x07E0, 0x001F, 0], 3),
+            (16, 40, 6, [0x0F00, 0x00F0, 0x000F, 0xF000], 4),
+            (16, 52, 3, [0x001F, 0x07E0, 0xF800, 0], 3),
+            (16, 124, 3, [0x7C00, 0x03E0, 0x001F, 0x8000], 4),
+            (32, 108, 3, [0xFF000000, 0x00FF0000, 0x0000FF00, 0x000000FF], 4),
+            (32, 56, 3, [0x3FF00000, 0x000FFC00, 0x000003FF, 0xC0000000], 4),
+            (32, 40, 3, [0x00FF0000, 0x0000FF00, 0x000000FF, 0], 3),
+        ];
+
+        for (bit_count, dib_header_size, compression, masks, channels) in cases {
+            for encoding in [FileEncoding::Lsb, FileEncoding::ColorParity] {
+                let bytes = bitfield_bmp(33, -29, bit_count, dib_header_size, compression, masks);
+                let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+                bmp_image_parser.parse_file().unwrap();
+                assert_eq!(bmp_image_parser.pixel_size as u16, bit_count / 8);
+
+                let bits_per_pixel = if encoding == FileEncoding::Lsb { channels } else { 1 };
+                let capacity = bmp_image_parser.capacity(encoding, FileEncodingMethod::SinWave).unwrap();
+                assert_eq!(capacity.total_bits, 33 * 29 * bits_per_pixel, "{masks:x?}");
+
+                bmp_image_parser.embed_data(&mut message.clone(), encoding, FileEncodingMethod::SinWave, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+                let mut written = Vec::new();
+                bmp_image_parser.write_to(&mut written).unwrap();
+
+                let mut reread = BmpImageParser::from_bytes(&written).unwrap();
+                reread.parse_file().unwrap();
+                let retrieved = reread.retrieve_data(encoding, FileEncodingMethod::SinWave, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+                assert_eq!(retrieved, message, "{bit_count} {dib_header_size} {compression} {masks:x?} {encoding:?}");
+            }
+        }
+    }
 
+    #[test]
+    fn test_bmp_bitfield_lsb_only_touches_channel_lsbs(){
+        let bytes = bitfield_bmp(16, 16, 16, 40, 3, [0xF800, 0x07E0, 0x001F, 0]);
+        let pixel_map_start = 14 + 40 + 12;
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert!(matches!(bmp_image_parser.pixel_type, BmpPixelType::Bitfield16(_)));
+
+        let written = embed_and_write(&bytes, &[0x5Au8; 60]);
+        let before = bytes[pixel_map_start..].chunks(2).map(|pixel| u16::from_le_bytes([pixel[0], pixel[1]]));
+        let after = written[pixel_map_start..].chunks(2).map(|pixel| u16::from_le_bytes([pixel[0], pixel[1]]));
+        let changed: u16 = before.zip(after).fold(0, |changed, (before, after)| changed | (before ^ after));
+        // Lowest bit of blue, green and red
+        assert_eq!(changed, (1 << 11) | (1 << 5) | 1);
+    }
 
     #[test]
-    fn test_bmp_color_embed_large_message_left_right(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp");
-        bmp_image_parser.parse_file();
+    fn test_bmp_bad_bitfields_are_rejected(){
+        for masks in [
+            [0xF800, 0x0FE0, 0x001F, 0],    // green overlaps red
+            [0xF800, 0x07E0, 0x0015, 0],    // blue is not contiguous
+            [0xF800, 0x07E0, 0, 0],         // no blue at all
+            [0x1F0000, 0x07E0, 0x001F, 0],  // red does not fit in 16 bits
+        ] {
+            let bytes = bitfield_bmp(8, 8, 16, 40, 3, masks);
+            let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+            assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::MalformedHeader(_))), "{masks:x?}");
+        }
 
-        let data_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
-        match bmp_image_parser.pixel_size {
-            3 => {
-                embed_color_data_left_right::<RgbPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
-            }
+        // Masks after a BITMAPINFOHEADER that run into the pixel data
+        let mut bytes = bitfield_bmp(8, 8, 16, 40, 3, [0xF800, 0x07E0, 0x001F, 0]);
+        bytes[10..14].copy_from_slice(&60u32.to_le_bytes());
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::MalformedHeader(_))));
 
-            4 => {
-                embed_color_data_left_right::<RgbaPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
+        // 24 bit pixels only take the plain BGR masks
+        let bytes = bitfield_bmp(8, 8, 24, 108, 3, [0x0000FF, 0x00FF00, 0xFF0000, 0]);
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_bmp_rle_decode_reference_streams(){
+        // The RLE8 and RLE4 examples from the BMP format documentation, both with a delta and an end of row
+        let rle8 = [0x03, 0x04, 0x05, 0x06, 0x00, 0x03, 0x45, 0x56, 0x67, 0x00, 0x02, 0x78, 0x00, 0x02, 0x05, 0x01,
+                    0x02, 0x78, 0x00, 0x00, 0x09, 0x1E, 0x00, 0x01, 0xEE];
+        let (indices, used) = decode_rle(&rle8, 20, 3, RleCompression::Rle8).unwrap();
+        assert_eq!(used, 24);
+        assert_eq!(indices[..13], [4, 4, 4, 6, 6, 6, 6, 6, 0x45, 0x56, 0x67, 0x78, 0x78]);
+        assert_eq!(indices[20..40], [&[0u8; 18][..], &[0x78; 2]].concat()[..]);
+        assert_eq!(indices[40..60], [&[0x1Eu8; 9][..], &[0; 11]].concat()[..]);
+
+        let rle4 = [0x03, 0x04, 0x05, 0x06, 0x00, 0x06, 0x45, 0x56, 0x67, 0x00, 0x04, 0x78, 0x00, 0x02, 0x05, 0x01,
+                    0x04, 0x78, 0x00, 0x00, 0x09, 0x1E, 0x00, 0x01];
+        let (indices, used) = decode_rle(&rle4, 27, 3, RleCompression::Rle4).unwrap();
+        assert_eq!(used, 24);
+        // 0 4 0 0 6 0 6 0 4 5 5 6 6 7 7 8 7 8 packed two per byte
+        assert_eq!(indices[..9], [0x04, 0x00, 0x60, 0x60, 0x45, 0x56, 0x67, 0x78, 0x78]);
+        // Skipped to x 23 of the second row, rows are 16 bytes
+        assert_eq!(indices[16 + 11..16 + 14], [0x07, 0x87, 0x80]);
+        assert_eq!(indices[32..37], [0x1E, 0x1E, 0x1E, 0x1E, 0x10]);
+
+        // Missing end of bitmap and pixels outside of the bitmap
+        assert!(matches!(decode_rle(&rle8[..22], 20, 3, RleCompression::Rle8), Err(StegoError::MalformedHeader(_))));
+        assert!(matches!(decode_rle(&rle8, 12, 3, RleCompression::Rle8), Err(StegoError::MalformedHeader(_))));
+
+        // A header asking for more than MAX_DECODED_SIZE is turned down before anything is allocated
+        assert!(matches!(decode_rle(&rle8, 1 << 20, 1 << 20, RleCompression::Rle8), Err(StegoError::UnsupportedFormat(_))));
+        let mut bytes = rle_bmp(8, 8, 8, &grey_palette(16), 40);
+        bytes[18..22].copy_from_slice(&0x7FFF_FFFFu32.to_le_bytes());
+        bytes[22..26].copy_from_slice(&0x7FFF_FFFFu32.to_le_bytes());
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_bmp_rle_encode_round_trip(){
+        for (compression, width) in [(RleCompression::Rle8, 301usize), (RleCompression::Rle4, 301), (RleCompression::Rle4, 6)] {
+            let row_size = (width * compression.bits_per_index()).div_ceil(32) * 4;
+            let mask = if compression == RleCompression::Rle8 { 0xFF } else { 0xFF >> 4 };
+            let mut indices = vec![0u8; row_size * 5];
+            for row in 0..5 {
+                for x in 0..width {
+                    // Long runs, short runs and noise
+                    let index = match (x / 40) % 3 {
+                        0 => 3,
+                        1 => (x / 2) as u8,
+                        _ => ((x * 31 + row * 7) % 13) as u8,
+                    } & mask;
+                    match compression {
+                        RleCompression::Rle8 => indices[row * row_size + x] = index,
+                        RleCompression::Rle4 => indices[row * row_size + x / 2] |= index << if x % 2 == 0 { 4 } else { 0 },
+                    }
+                }
             }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
+
+            let encoded = encode_rle(&indices, width, 5, compression);
+            let (decoded, used) = decode_rle(&encoded, width, 5, compression).unwrap();
+            assert_eq!(used, encoded.len());
+            assert_eq!(decoded, indices, "{compression:?} {width}");
+        }
+    }
+
+    #[test]
+    fn test_bmp_rle_embed_and_write_back(){
+        let message = "Runs get broken up but the message survives".as_bytes().to_vec();
+
+        for (bit_count, colours) in [(8u16, 200usize), (4, 16)] {
+            for output in [RleOutput::Rle, RleOutput::Uncompressed] {
+                let bytes = rle_bmp(45, 39, bit_count, &grey_palette(colours), 40);
+                let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+                bmp_image_parser.parse_file().unwrap();
+                assert!(bmp_image_parser.rle.is_some());
+
+                bmp_image_parser.set_rle_output(output);
+                bmp_image_parser.embed_data(&mut message.clone(), FileEncoding::Lsb, FileEncodingMethod::CosWave, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+                let mut written = Vec::new();
+                bmp_image_parser.write_to(&mut written).unwrap();
+
+                let mut reread = BmpImageParser::from_bytes(&written).unwrap();
+                reread.parse_file().unwrap();
+                let compression = reread.bmp_dib_header.bi_compression;
+                let size_image = reread.bmp_dib_header.bi_size_image;
+                let file_size = reread.bmp_header.bf_size;
+                assert_eq!(file_size as usize, written.len());
+                match output {
+                    RleOutput::Rle => assert_eq!(compression, if bit_count == 8 { 1 } else { 2 }),
+                    RleOutput::Uncompressed => {
+                        assert_eq!(compression, 0);
+                        assert_eq!(size_image as usize, bmp_image_parser.rle.as_ref().unwrap().indices.len());
+                    }
+                }
+
+                let retrieved = reread.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::CosWave, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+                assert_eq!(retrieved, message, "{bit_count} {output:?}");
             }
         }
+    }
 
-        bmp_image_parser.write_file("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_COLOR_LEFT_RIGHT_LARGE.bmp");
+    #[test]
+    fn test_bmp_rle_output_from_the_command_line(){
+        let input = std::env::temp_dir().join("veritasobscura-rle-option.bmp").to_str().unwrap().to_string();
+        let output = std::env::temp_dir().join("veritasobscura-rle-option-out.bmp").to_str().unwrap().to_string();
+        std::fs::write(&input, rle_bmp(45, 39, 8, &grey_palette(200), 40)).unwrap();
+        let args = |list: &[&str]| std::iter::once("maya").chain(list.iter().copied()).map(String::from).collect::<Vec<_>>();
+
+        for (value, compression) in [("keep", 1u32), ("uncompressed", 0)] {
+            let mut image_support = parse_arguments::<BmpImageParser>(args(&["Lsb", "LeftRight", "embed", "runs", &input, &output, "--rle", value])).unwrap();
+            assert_eq!(image_support.run().unwrap(), OperationOutput::Embedded);
+
+            let mut reread = BmpImageParser::new(&output).unwrap();
+            reread.parse_file().unwrap();
+            let bi_compression = reread.bmp_dib_header.bi_compression;
+            assert_eq!(bi_compression, compression, "{value}");
+
+            // Extraction does not care how the pixels were stored
+            let mut image_support = parse_arguments::<BmpImageParser>(args(&["Lsb", "LeftRight", "extract", &output, "--rle", value])).unwrap();
+            assert_eq!(image_support.run().unwrap(), OperationOutput::Extracted(b"runs".to_vec()));
+        }
 
-        //ensure we shoved more than a full pixel row of bits in there so that we can test multi row embedding
-        assert!(data_vec.len() > 384);
+        for list in [
+            &["Lsb", "LeftRight", "extract", &input, "--rle", "smaller"][..],
+            &["Lsb", "LeftRight", "extract", &input, "--rle"][..],
+        ] {
+            assert!(matches!(parse_arguments::<BmpImageParser>(args(list)), Err(StegoError::Usage(_))), "{list:?}");
+        }
+    }
 
+    #[test]
+    fn test_bmp_rle_profile_follows_the_pixel_data(){
+        let profile: Vec<u8> = (0..300).map(|i| (i % 97) as u8).collect();
+        let bytes = with_color_profile(rle_bmp(64, 64, 8, &grey_palette(256), 124), 0x4D424544, &profile);
+
+        for output in [RleOutput::Rle, RleOutput::Uncompressed] {
+            let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+            bmp_image_parser.parse_file().unwrap();
+            bmp_image_parser.set_rle_output(output);
+            bmp_image_parser.embed_data(&mut vec![0x3C; 300], FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+            let mut written = Vec::new();
+            bmp_image_parser.write_to(&mut written).unwrap();
+            assert_ne!(written.len(), bytes.len());
+
+            let mut reread = BmpImageParser::from_bytes(&written).unwrap();
+            reread.parse_file().unwrap();
+            assert_eq!(reread.color_profile_bytes().unwrap(), &profile[..]);
+            assert_eq!(reread.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap(), vec![0x3C; 300]);
+        }
+
+        // RLE can not be stored top down
+        let mut bytes = rle_bmp(8, 8, 8, &grey_palette(16), 40);
+        bytes[22..26].copy_from_slice(&(-8i32).to_le_bytes());
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::UnsupportedFormat(_))));
     }
 
     #[test]
-    fn test_bmp_color_retrieve_large_message_left_right(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_COLOR_LEFT_RIGHT_LARGE.bmp");
-        bmp_image_parser.parse_file();
-        let message_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
+    fn test_bmp_decode_encode_image_round_trip(){
+        let profile: Vec<u8> = (0..64).collect();
+        for (bytes, layout, bit_depths) in [
+            (synthetic_bmp(9, -7, 24, 40), ChannelLayout::Rgb, vec![8, 8, 8]),
+            (with_color_profile(synthetic_bmp(9, 7, 32, 124), 0x4D424544, &profile), ChannelLayout::Rgba, vec![8, 8, 8, 8]),
+            (bitfield_bmp(9, 7, 16, 40, 3, [0xF800, 0x07E0, 0x001F, 0]), ChannelLayout::Rgb, vec![5, 6, 5]),
+            (indexed_bmp(9, 7, 4, &grey_palette(16)), ChannelLayout::Indexed, vec![4]),
+            (rle_bmp(9, 7, 8, &grey_palette(40), 40), ChannelLayout::Indexed, vec![8]),
+        ] {
+            let mut image = decode_bmp(&bytes).unwrap();
+            assert_eq!((image.width, image.height, image.layout), (9, 7, layout));
+            assert_eq!(image.bit_depths, bit_depths);
+            assert_eq!(image.palette.is_empty(), layout != ChannelLayout::Indexed);
+
+            // Nothing changed, nothing moves
+            assert_eq!(encode_bmp(&image).unwrap(), bytes, "{layout:?}");
+
+            let max = (1u16 << bit_depths[0]) - 1;
+            let pixel = image.pixel_mut(6, 8).unwrap();
+            pixel[0] = if pixel[0] == max { 0 } else { max };
+            let changed = image.pixel(6, 8).unwrap().to_vec();
+
+            let written = encode_bmp(&image).unwrap();
+            assert_ne!(written, bytes, "{layout:?}");
+            let reread = decode_bmp(&written).unwrap();
+            assert_eq!(reread.pixel(6, 8).unwrap(), &changed[..], "{layout:?}");
+            assert_eq!(reread.samples, image.samples, "{layout:?}");
+        }
 
-        let mut data_vec: Vec<u8> = vec![0];
-        match bmp_image_parser.pixel_size {
-            3 => {
-                data_vec = extract_color_data_left_right::<RgbPixel>( &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64,(message_vec.len() * 8) as u64);
-            }
+        // The decoded image knows where it came from, anything else can not be written as BMP
+        let mut image = decode_bmp(&synthetic_bmp(4, 4, 24, 40)).unwrap();
+        image.metadata.clear();
+        assert!(matches!(encode_bmp(&image), Err(StegoError::UnsupportedFormat(_))));
 
-            4 => {
-                data_vec = extract_color_data_left_right::<RgbaPixel>(&mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64, (message_vec.len() * 8) as u64);
+        // The layout has to stay the one the bitmap was decoded with
+        let mut image = decode_bmp(&indexed_bmp(4, 4, 8, &grey_palette(16))).unwrap();
+        image.palette.pop();
+        assert!(matches!(encode_bmp(&image), Err(StegoError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_bmp_slack_is_found(){
+        // 5 pixels of 3 bytes leave 1 byte of padding per row
+        let bytes = with_header_gap(synthetic_bmp(5, 4, 24, 40), 20);
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert_eq!(bmp_image_parser.slack_report(), SlackReport { header_gap_bytes: 20, row_padding_bytes: 4 });
+        assert_eq!(bmp_image_parser.slack_ranges(), vec![54..74, 89..90, 105..106, 121..122, 137..138]);
+
+        let report = bmp_image_parser.capacity(FileEncoding::Slack, FileEncodingMethod::LeftToRight).unwrap();
+        assert_eq!((report.total_bits, report.bits_per_pixel), (24 * 8, 0));
+        let report = bmp_image_parser.capacity(FileEncoding::SlackLsb, FileEncodingMethod::LeftToRight).unwrap();
+        assert_eq!((report.total_bits, report.bits_per_pixel), (24 * 8 + 5 * 4 * 3, 3));
+
+        // Masks and the color table are not slack, neither is a profile stored in the gap
+        let bytes = with_header_gap(bitfield_bmp(4, 4, 16, 40, 3, [0xF800, 0x07E0, 0x001F, 0]), 8);
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert_eq!(bmp_image_parser.slack_ranges(), vec![66..74]);
+
+        let bytes = with_header_gap(indexed_bmp(8, 4, 8, &grey_palette(16)), 12);
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert_eq!(bmp_image_parser.slack_ranges(), vec![118..130]);
+
+        let mut bytes = with_header_gap(synthetic_bmp(4, 4, 24, 124), 40);
+        bytes[70..74].copy_from_slice(&0x4D424544u32.to_le_bytes());
+        bytes[126..130].copy_from_slice(&(124 + 10u32).to_le_bytes());
+        bytes[130..134].copy_from_slice(&16u32.to_le_bytes());
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert_eq!(bmp_image_parser.slack_ranges(), vec![138..148, 164..178]);
+    }
+
+    #[test]
+    fn test_bmp_slack_round_trip(){
+        let bytes = with_header_gap(synthetic_bmp(31, 17, 24, 40), 64);
+        let short = "only the gaps".as_bytes().to_vec();
+        let long: Vec<u8> = (0..150).map(|i| (i * 13) as u8).collect();
+
+        for derivation in [FileEncodingFunctionDerivation::Unkeyed, FileEncodingFunctionDerivation::from_passphrase("slack")] {
+            for (encoding, message) in [(FileEncoding::Slack, &short), (FileEncoding::SlackLsb, &short), (FileEncoding::SlackLsb, &long)] {
+                let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+                bmp_image_parser.parse_file().unwrap();
+                let slack = bmp_image_parser.slack_ranges();
+                bmp_image_parser.embed_data(&mut message.clone(), encoding, FileEncodingMethod::SinWave, derivation).unwrap();
+                let mut written = Vec::new();
+                bmp_image_parser.write_to(&mut written).unwrap();
+
+                // Pixels are only touched once the slack is full
+                let pixels_changed = written.iter().zip(&bytes).enumerate().any(|(i, (a, b))| a != b && !slack.iter().any(|range| range.contains(&i)));
+                assert_eq!(pixels_changed, message.len() > 60, "{encoding:?} {}", message.len());
+
+                let mut reread = BmpImageParser::from_bytes(&written).unwrap();
+                reread.parse_file().unwrap();
+                assert_eq!(&reread.retrieve_data(encoding, FileEncodingMethod::SinWave, derivation).unwrap(), message, "{encoding:?} {derivation:?}");
             }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
+        }
+
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert!(matches!(
+            bmp_image_parser.embed_data(&mut long.clone(), FileEncoding::Slack, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed),
+            Err(StegoError::InsufficientCapacity { .. })
+        ));
+        assert!(matches!(
+            bmp_image_parser.embed_data(&mut vec![0; 1000], FileEncoding::SlackLsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed),
+            Err(StegoError::InsufficientCapacity { .. })
+        ));
+        // Nothing was written by the failed attempts
+        let mut written = Vec::new();
+        bmp_image_parser.write_to(&mut written).unwrap();
+        assert_eq!(written, bytes);
+    }
+
+    #[test]
+    fn test_bmp_grow_header_gap(){
+        let message = "the gap grew to fit this".as_bytes().to_vec();
+        let profile: Vec<u8> = (0..40).collect();
+
+        for bytes in [
+            synthetic_bmp(16, 16, 32, 40),
+            with_color_profile(synthetic_bmp(16, 16, 24, 124), 0x4D424544, &profile),
+            rle_bmp(16, 16, 8, &grey_palette(32), 40),
+        ] {
+            let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+            bmp_image_parser.parse_file().unwrap();
+            assert_eq!(bmp_image_parser.slack_report(), SlackReport::default());
+            assert!(matches!(
+                bmp_image_parser.embed_data(&mut message.clone(), FileEncoding::Slack, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed),
+                Err(StegoError::InsufficientCapacity { .. })
+            ));
+
+            bmp_image_parser.grow_header_gap(64).unwrap();
+            assert_eq!(bmp_image_parser.slack_report().header_gap_bytes, 64);
+            bmp_image_parser.embed_data(&mut message.clone(), FileEncoding::Slack, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+            let mut written = Vec::new();
+            bmp_image_parser.write_to(&mut written).unwrap();
+
+            let mut reread = BmpImageParser::from_bytes(&written).unwrap();
+            reread.parse_file().unwrap();
+            let (off_bits, file_size) = (reread.bmp_header.bf_off_bits, reread.bmp_header.bf_size);
+            assert_eq!(off_bits, u32::from_le_bytes(bytes[10..14].try_into().unwrap()) + 64);
+            assert_eq!(file_size as usize, written.len());
+            assert_eq!(decode_bmp(&written).unwrap().samples, decode_bmp(&bytes).unwrap().samples);
+            if reread.color_profile.is_some() {
+                assert_eq!(reread.color_profile_bytes().unwrap(), &profile[..]);
             }
+            assert_eq!(reread.retrieve_data(FileEncoding::Slack, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap(), message);
         }
 
-        /*
-            the data vec will have an extra byte. the slice is just to remove that extra byte so that the test will pass
-         */
-        assert_eq!(String::from_utf8(data_vec[0..(message_vec.len())].to_owned()), String::from_utf8(message_vec));
+        // Pixels embedded into before growing survive, RLE ones included
+        let bytes = rle_bmp(32, 32, 8, &grey_palette(32), 40);
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        bmp_image_parser.embed_data(&mut message.clone(), FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+        bmp_image_parser.grow_header_gap(16).unwrap();
+        let mut written = Vec::new();
+        bmp_image_parser.write_to(&mut written).unwrap();
+        let mut reread = BmpImageParser::from_bytes(&written).unwrap();
+        reread.parse_file().unwrap();
+        assert_eq!(reread.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap(), message);
     }
 
+    #[test]
+    fn test_bmp_os2_round_trip(){
+        let message = "from an old archive".as_bytes().to_vec();
+
+        for (dib_header_size, header_version) in [(12, BmpHeaderVersion::Core), (16, BmpHeaderVersion::Os2), (64, BmpHeaderVersion::Os2)] {
+            for (bit_count, colours) in [(1u16, 2usize), (4, 16), (8, 256), (24, 0)] {
+                let palette = if colours == 0 { Vec::new() } else { grey_palette(colours) };
+                let bytes = os2_bmp(40, 30, bit_count, dib_header_size, &palette);
+                let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+                bmp_image_parser.parse_file().unwrap();
+
+                assert_eq!(bmp_image_parser.header_version, header_version);
+                assert_eq!((bmp_image_parser.pixel_map.width, bmp_image_parser.pixel_map.height), (40, 30));
+                assert_eq!(bmp_image_parser.pixel_map.row_order, RowOrder::BottomUp);
+                assert_eq!(bmp_image_parser.color_table.len(), colours);
+                assert!(bmp_image_parser.color_table.iter().zip(&palette).all(|(entry, [red, green, blue])| {
+                    (entry.red, entry.green, entry.blue, entry.reserved) == (*red, *green, *blue, 0)
+                }));
+                assert_eq!(bmp_image_parser.slack_report().header_gap_bytes, 0);
+
+                let pixel_map_start = bmp_image_parser.pixel_map.pixel_map_start as usize;
+                let written = embed_and_write(&bytes, &message);
+                assert_eq!(written.len(), bytes.len());
+                assert_eq!(&written[..pixel_map_start], &bytes[..pixel_map_start], "{dib_header_size} {bit_count}");
+
+                let mut reread = BmpImageParser::from_bytes(&written).unwrap();
+                reread.parse_file().unwrap();
+                assert_eq!(reread.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap(), message, "{dib_header_size} {bit_count}");
+            }
+        }
+    }
 
+    #[test]
+    fn test_bmp_os2_color_tables_and_compression(){
+        // An OS/2 1.x table with fewer than 2^bits entries ends where the pixel data starts
+        let bytes = os2_bmp(16, 16, 8, 12, &grey_palette(20));
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert_eq!(bmp_image_parser.color_table.len(), 20);
+        assert_eq!(decode_bmp(&bytes).unwrap().palette, grey_palette(20));
+
+        // Gap slack starts after the 3 byte entries
+        let bytes = with_header_gap(os2_bmp(16, 16, 4, 12, &grey_palette(16)), 10);
+        let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+        bmp_image_parser.parse_file().unwrap();
+        assert_eq!(bmp_image_parser.slack_ranges(), vec![74..84]);
+
+        // 3 and 4 are Huffman 1D and RLE24 in OS/2 2.x, not bitfields
+        for compression in [3u32, 4] {
+            let mut bytes = os2_bmp(16, 16, 24, 64, &[]);
+            bytes[30..34].copy_from_slice(&compression.to_le_bytes());
+            let mut bmp_image_parser = BmpImageParser::from_bytes(&bytes).unwrap();
+            assert!(matches!(bmp_image_parser.parse_file(), Err(StegoError::UnsupportedFormat(_))), "{compression}");
+        }
 
+        // OS/2 2.x RLE8 goes through the same decoder
+        let mut bytes = os2_bmp(16, 16, 8, 64, &grey_palette(16));
+        let pixel_map_start = 14 + 64 + 16 * 4;
+        let indices = bytes.split_off(pixel_map_start);
+        bytes.extend_from_slice(&encode_rle(&indices, 16, 16, RleCompression::Rle8));
+        bytes[30..34].copy_from_slice(&1u32.to_le_bytes());
+        let written = embed_and_write(&bytes, "rle".as_bytes());
+        let mut reread = BmpImageParser::from_bytes(&written).unwrap();
+        reread.parse_file().unwrap();
+        assert_eq!(reread.header_version, BmpHeaderVersion::Os2);
+        assert_eq!(reread.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap(), "rle".as_bytes());
+    }
+}
 
+#[cfg(test)]
+mod ico_tests{
+    use crate::file_encoding_support::file_encoding_support::{FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod, FileEncodingSupport};
+    use crate::file_encoding_support::stego_error::StegoError;
+    use crate::filetype_support::filetype_support::{carrier_from_bytes, FileType};
+    use crate::filetype_support::ico::{IcoImage, IcoImageParser, IcoKind};
+
+    const PNG_ENTRY: &[u8] = b"\x89PNG\r\n\x1a\n not really decoded";
 
     /*
-        Right left color
+        A DIB icon image, BITMAPINFOHEADER with the doubled height, a grey palette for 8 bits and less,
+        the XOR pixels and an AND mask that makes every pixel with row + col divisible by 5 transparent
      */
-    #[test]
-    fn test_bmp_color_embed_right_left(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp");
-        bmp_image_parser.parse_file();
+    fn dib_entry(size: u32, bit_count: u16) -> Vec<u8> {
+        let colours = if bit_count <= 8 { 1usize << bit_count } else { 0 };
+        let mut data = vec![0u8; 40];
+        data[0..4].copy_from_slice(&40u32.to_le_bytes());
+        data[4..8].copy_from_slice(&size.to_le_bytes());
+        data[8..12].copy_from_slice(&(size * 2).to_le_bytes());
+        data[12..14].copy_from_slice(&1u16.to_le_bytes());
+        data[14..16].copy_from_slice(&bit_count.to_le_bytes());
+        for i in 0..colours {
+            let level = (i * 255 / (colours - 1)) as u8;
+            data.extend_from_slice(&[level, level, level, 0]);
+        }
 
-        let data_vec : Vec<u8> = "This is a test embedding for testing purposes".as_bytes().to_vec();
-        match bmp_image_parser.pixel_size {
-            3 => {
-                embed_color_data_right_left::<RgbPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
-            }
+        let size = size as usize;
+        let row_size = (size * bit_count as usize).div_ceil(32) * 4;
+        data.extend((0..row_size * size).map(|i| (i * 11 % 251) as u8));
 
-            4 => {
-                embed_color_data_right_left::<RgbaPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
-            }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
+        let mask_row_size = size.div_ceil(32) * 4;
+        let mut mask = vec![0u8; mask_row_size * size];
+        for stored_row in 0..size {
+            let row = size - 1 - stored_row;
+            for col in (0..size).filter(|col| (row + col).is_multiple_of(5)) {
+                mask[stored_row * mask_row_size + col / 8] |= 0x80 >> (col % 8);
             }
         }
+        data.extend_from_slice(&mask);
+        data
+    }
+
+    fn icon_file(kind: u16, images: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(&kind.to_le_bytes());
+        bytes.extend_from_slice(&(images.len() as u16).to_le_bytes());
+
+        let mut offset = 6 + 16 * images.len();
+        for image in images {
+            bytes.extend_from_slice(&[16, 16, 0, 0, 1, 0, 32, 0]);
+            bytes.extend_from_slice(&(image.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += image.len();
+        }
+        for image in images {
+            bytes.extend_from_slice(image);
+        }
+        bytes
+    }
 
-        bmp_image_parser.write_file("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_COLOR_RIGHT_LEFT.bmp");
+    fn parsed(bytes: &[u8]) -> IcoImageParser {
+        let mut ico_image_parser = IcoImageParser::from_bytes(bytes).unwrap();
+        ico_image_parser.parse_file().unwrap();
+        ico_image_parser
+    }
 
+    #[test]
+    fn test_ico_entries_and_masks(){
+        let bytes = icon_file(1, &[dib_entry(16, 8), dib_entry(32, 24), PNG_ENTRY.to_vec(), dib_entry(48, 32), dib_entry(16, 4)]);
+        assert_eq!(FileType::detect(&bytes).unwrap(), FileType::Ico);
+        assert_eq!(FileType::detect(&icon_file(2, &[dib_entry(16, 8)])).unwrap(), FileType::Cur);
+
+        let ico_image_parser = parsed(&bytes);
+        assert_eq!(ico_image_parser.kind, IcoKind::Icon);
+        assert_eq!(ico_image_parser.entries.len(), 5);
+        assert!(matches!(ico_image_parser.images[2], IcoImage::Png));
+
+        for (index, size) in [(0, 16), (1, 32), (3, 48), (4, 16)] {
+            let IcoImage::Dib(dib) = &ico_image_parser.images[index] else { panic!("entry {index} is a DIB") };
+            assert_eq!((dib.bmp.pixel_map.width, dib.bmp.pixel_map.height), (size, size));
+            for (row, col) in [(0, 0), (0, 1), (2, 3), (size as usize - 1, 4), (7, 9)] {
+                assert_eq!(dib.transparent(row, col), Some((row + col).is_multiple_of(5)), "{size} ({row}, {col})");
+            }
+            assert_eq!(dib.transparent(size as usize, 0), None);
+        }
+
+        // Only pixels the AND mask leaves visible carry bits. Palette entries hold one bit per pixel with a
+        // close partner colour, the others one per channel; the 16 greys of the 4 bit entry are too far
+        // apart to pair. Every entry holds whole bytes.
+        let visible = |size: u64| size * size - (0..size).flat_map(|row| (0..size).map(move |col| row + col)).filter(|sum| sum % 5 == 0).count() as u64;
+        let report = ico_image_parser.capacity(FileEncoding::Lsb, FileEncodingMethod::LeftToRight).unwrap();
+        assert_eq!(report.total_bits, visible(16) / 8 * 8 + visible(32) * 3 / 8 * 8 + visible(48) * 4 / 8 * 8);
+        assert_eq!(report.bits_per_pixel, 4);
+        assert!(matches!(ico_image_parser.capacity(FileEncoding::ColorParity, FileEncodingMethod::LeftToRight), Err(StegoError::UnsupportedEncoding(_))));
     }
 
     #[test]
-    fn test_bmp_color_retrieve_right_left(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_COLOR_RIGHT_LEFT.bmp");
-        bmp_image_parser.parse_file();
+    fn test_ico_payload_spans_every_entry(){
+        let images = [dib_entry(16, 24), PNG_ENTRY.to_vec(), dib_entry(16, 32), dib_entry(32, 24)];
+        let bytes = icon_file(2, &images);
+        // Larger than the first two DIB entries together
+        let message: Vec<u8> = (0..250).map(|i| (i * 7) as u8).collect();
+
+        for derivation in [FileEncodingFunctionDerivation::Unkeyed, FileEncodingFunctionDerivation::from_passphrase("icons")] {
+            let mut ico_image_parser = parsed(&bytes);
+            assert_eq!(ico_image_parser.kind, IcoKind::Cursor);
+            ico_image_parser.embed_data(&mut message.clone(), FileEncoding::Lsb, FileEncodingMethod::CosWave, derivation).unwrap();
+            let mut written = Vec::new();
+            ico_image_parser.write_to(&mut written).unwrap();
+            assert_eq!(written.len(), bytes.len());
+
+            // Every DIB entry changed, the directory, the headers, the PNG entry and the AND masks did not
+            let mut offset = 6 + 16 * images.len();
+            assert_eq!(&written[..offset], &bytes[..offset]);
+            for image in &images {
+                let (original, embedded) = (&bytes[offset..offset + image.len()], &written[offset..offset + image.len()]);
+                if image.starts_with(b"\x89PNG") {
+                    assert_eq!(original, embedded);
+                } else {
+                    let size = u32::from_le_bytes(image[4..8].try_into().unwrap()) as usize;
+                    let mask_size = size.div_ceil(32) * 4 * size;
+                    assert_ne!(original, embedded);
+                    assert_eq!(&original[..40], &embedded[..40]);
+                    assert_eq!(&original[image.len() - mask_size..], &embedded[image.len() - mask_size..]);
+                }
+                offset += image.len();
+            }
 
-        let mut data_vec: Vec<u8> = vec![0];
-        match bmp_image_parser.pixel_size {
-            3 => {
-                data_vec = extract_color_data_right_left::<RgbPixel>( &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64,360);
+            // Pixels under the AND mask keep their colour
+            let (mut original, mut embedded) = (parsed(&bytes), parsed(&written));
+            for (before, after) in original.images.iter_mut().zip(embedded.images.iter_mut()) {
+                let (IcoImage::Dib(before), IcoImage::Dib(after)) = (before, after) else { continue };
+                let (before_image, after_image) = (before.bmp.decode_image().unwrap(), after.bmp.decode_image().unwrap());
+                for row in 0..before_image.height {
+                    for col in (0..before_image.width).filter(|&col| before.transparent(row, col) == Some(true)) {
+                        assert_eq!(before_image.pixel(row, col), after_image.pixel(row, col), "({row}, {col})");
+                    }
+                }
             }
 
-            4 => {
-                data_vec = extract_color_data_right_left::<RgbaPixel>(&mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64, 360);
+            let mut carrier = carrier_from_bytes(&written).unwrap();
+            carrier.parse_file().unwrap();
+            assert_eq!(carrier.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::CosWave, derivation).unwrap(), message, "{derivation:?}");
+        }
+
+        let mut ico_image_parser = parsed(&bytes);
+        assert!(matches!(
+            ico_image_parser.embed_data(&mut vec![0; 2000], FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed),
+            Err(StegoError::InsufficientCapacity { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ico_bad_input_is_rejected(){
+        let parse = |bytes: &[u8]| {
+            let mut ico_image_parser = IcoImageParser::from_bytes(bytes).unwrap();
+            ico_image_parser.parse_file()
+        };
+
+        // Type 3 is not an icon, no entries at all, an entry past the end of the file
+        let mut bytes = icon_file(1, &[dib_entry(16, 24)]);
+        bytes[2] = 3;
+        assert!(matches!(parse(&bytes), Err(StegoError::UnsupportedFormat(_))));
+        assert!(matches!(parse(&icon_file(1, &[])), Err(StegoError::MalformedHeader(_))));
+        let bytes = icon_file(1, &[dib_entry(16, 24)]);
+        assert!(matches!(parse(&bytes[..bytes.len() - 1]), Err(StegoError::MalformedHeader(_))));
+
+        // Two entries pointing at the same data
+        let mut bytes = icon_file(1, &[dib_entry(16, 24), dib_entry(16, 24)]);
+        let first_offset = bytes[18..22].to_vec();
+        bytes[34..38].copy_from_slice(&first_offset);
+        assert!(matches!(parse(&bytes), Err(StegoError::MalformedHeader(_))));
+
+        // A height that is not image plus mask, a mask cut short
+        let mut entry = dib_entry(16, 24);
+        entry[8..12].copy_from_slice(&17u32.to_le_bytes());
+        assert!(matches!(parse(&icon_file(1, &[entry])), Err(StegoError::MalformedHeader(_))));
+        let mut entry = dib_entry(16, 24);
+        entry.truncate(entry.len() - 4);
+        assert!(matches!(parse(&icon_file(1, &[entry])), Err(StegoError::MalformedHeader(_))));
+
+        // Nothing to embed into when every entry is a PNG
+        let mut ico_image_parser = parsed(&icon_file(1, &[PNG_ENTRY.to_vec()]));
+        assert!(matches!(
+            ico_image_parser.embed_data(&mut vec![1], FileEncoding::Lsb, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed),
+            Err(StegoError::UnsupportedFormat(_))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod png_tests{
+    use crate::file_encoding_support::embedding_engine::bits_per_pixel;
+    use crate::file_encoding_support::image::ChannelLayout;
+    use crate::file_encoding_support::stego_error::StegoError;
+    use crate::file_encoding_support::file_encoding_support::{FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod, FileEncodingSupport, OperationOutput};
+    use crate::filetype_support::filetype_support::{carrier_from_bytes, FileType};
+    use crate::file_encoding_support::palette::PalettePairs;
+    use crate::file_encoding_support::pixel::Pixel;
+    use crate::filetype_support::png::{decode_png, encode_png, read_chunks, Gray16Pixel, PngCompression, PngImageParser, PngInterlace, Rgb48Pixel, Rgba64Pixel, PNG_SIGNATURE};
+    use crate::filetype_support::png_chunks::{ChunkEmbedding, ChunkPlacement, ChunkStyle};
+    use crate::compression::compression::zlib_decompress;
+    use crate::arg_handling::arg_handling::arg_handling::parse_arguments;
+    use crate::mathematics_support::mathematics_support::{adler32, crc32};
+
+    fn chunk(chunk_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut bytes = (data.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(chunk_type);
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&crc32(&bytes[4..]).to_be_bytes());
+        bytes
+    }
+
+    // zlib stream of stored blocks, small ones so the data spans several
+    fn zlib_stored(data: &[u8]) -> Vec<u8> {
+        let mut stream = vec![0x78, 0x01];
+        let blocks: Vec<&[u8]> = data.chunks(100).collect();
+        for (i, block) in blocks.iter().enumerate() {
+            let length = block.len() as u16;
+            stream.push((i + 1 == blocks.len()) as u8);
+            stream.extend_from_slice(&length.to_le_bytes());
+            stream.extend_from_slice(&(!length).to_le_bytes());
+            stream.extend_from_slice(block);
+        }
+        stream.extend_from_slice(&adler32(data).to_be_bytes());
+        stream
+    }
+
+    fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+        let estimate = left as i16 + up as i16 - up_left as i16;
+        let distances = [left, up, up_left].map(|value| (estimate - value as i16).abs());
+        if distances[0] <= distances[1] && distances[0] <= distances[2] {
+            left
+        } else if distances[1] <= distances[2] {
+            up
+        } else {
+            up_left
+        }
+    }
+
+    // Filters row n with filter type n % 5 so every filter gets used
+    fn filter_rows(rows: &[u8], row_size: usize, stride: usize) -> Vec<u8> {
+        let mut filtered = Vec::new();
+        let zero_row = vec![0u8; row_size];
+        for (row, current) in rows.chunks_exact(row_size).enumerate() {
+            let previous = if row == 0 { &zero_row[..] } else { &rows[(row - 1) * row_size..row * row_size] };
+            filtered.push((row % 5) as u8);
+            for i in 0..row_size {
+                let left = if i >= stride { current[i - stride] } else { 0 };
+                let up_left = if i >= stride { previous[i - stride] } else { 0 };
+                let predictor = match row % 5 {
+                    0 => 0,
+                    1 => left,
+                    2 => previous[i],
+                    3 => ((left as u16 + previous[i] as u16) / 2) as u8,
+                    _ => paeth(left, previous[i], up_left),
+                };
+                filtered.push(current[i].wrapping_sub(predictor));
             }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
+        }
+        filtered
+    }
+
+    fn pack_rows(samples: &[u16], samples_per_row: usize, depth: usize) -> Vec<u8> {
+        let mut rows = Vec::new();
+        for row in samples.chunks_exact(samples_per_row) {
+            match depth {
+                16 => row.iter().for_each(|sample| rows.extend_from_slice(&sample.to_be_bytes())),
+                8 => rows.extend(row.iter().map(|&sample| sample as u8)),
+                _ => {
+                    let mut packed = vec![0u8; (samples_per_row * depth).div_ceil(8)];
+                    for (i, &sample) in row.iter().enumerate() {
+                        packed[i * depth / 8] |= (sample as u8) << (8 - depth - i * depth % 8);
+                    }
+                    rows.extend_from_slice(&packed);
+                }
             }
         }
+        rows
+    }
+
+    fn ihdr(width: u32, height: u32, depth: u8, color_type: u8) -> Vec<u8> {
+        let mut data = width.to_be_bytes().to_vec();
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+        data
+    }
 
-        /*
-            the data vec will have an extra byte. the slice is just to remove that extra byte so that the test will pass
-         */
-        assert_eq!(data_vec[0..(360/8)], "This is a test embedding for testing purposes".as_bytes().to_vec());
+    /*
+        IHDR, the chunks in before_idat, the filtered and compressed rows split over two IDAT chunks, a
+        tEXt chunk and IEND
+     */
+    fn png_file(header: &[u8], before_idat: &[Vec<u8>], filtered: &[u8]) -> Vec<u8> {
+        let compressed = zlib_stored(filtered);
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        bytes.extend(chunk(b"IHDR", header));
+        before_idat.iter().for_each(|extra| bytes.extend_from_slice(extra));
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        bytes.extend(chunk(b"IDAT", first));
+        bytes.extend(chunk(b"IDAT", second));
+        bytes.extend(chunk(b"tEXt", b"Comment\0a test image"));
+        bytes.extend(chunk(b"IEND", b""));
+        bytes
+    }
 
+    fn test_samples(count: usize, depth: u8) -> Vec<u16> {
+        (0..count).map(|i| ((i * 2_654_435_761) >> 7) as u16 & ((1u32 << depth) - 1) as u16).collect()
     }
 
     #[test]
-    fn test_bmp_color_embed_large_message_right_left(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024.bmp");
-        bmp_image_parser.parse_file();
+    fn test_png_decodes_every_color_type_and_depth(){
+        let (width, height) = (13usize, 11usize);
+        let combinations: [(u8, ChannelLayout, &[u8]); 5] = [
+            (0, ChannelLayout::Gray, &[1, 2, 4, 8, 16]),
+            (2, ChannelLayout::Rgb, &[8, 16]),
+            (3, ChannelLayout::Indexed, &[1, 2, 4, 8]),
+            (4, ChannelLayout::GrayAlpha, &[8, 16]),
+            (6, ChannelLayout::Rgba, &[8, 16]),
+        ];
+
+        for (color_type, layout, depths) in combinations {
+            for &depth in depths {
+                let channels = layout.channels();
+                let samples = test_samples(width * height * channels, depth);
+                let rows = pack_rows(&samples, width * channels, depth as usize);
+                let stride = (channels * depth as usize).div_ceil(8);
+                let filtered = filter_rows(&rows, rows.len() / height, stride);
+
+                let palette: Vec<[u8; 3]> = (0..1usize << depth).map(|i| [i as u8, 255 - i as u8, (i * 3) as u8]).collect();
+                let plte: Vec<u8> = palette.iter().flatten().copied().collect();
+                let before_idat = if layout == ChannelLayout::Indexed { vec![chunk(b"PLTE", &plte)] } else { Vec::new() };
+                let bytes = png_file(&ihdr(width as u32, height as u32, depth, color_type), &before_idat, &filtered);
+                assert_eq!(FileType::detect(&bytes).unwrap(), FileType::Png);
+
+                let image = decode_png(&bytes).unwrap();
+                assert_eq!((image.width, image.height, image.layout), (width, height, layout), "{layout:?} {depth}");
+                assert_eq!(image.bit_depths, vec![depth; channels]);
+                assert_eq!(image.samples, samples, "{layout:?} {depth}");
+                assert_eq!(image.metadata("PNG"), Some(&bytes[..]));
+                if layout == ChannelLayout::Indexed {
+                    assert_eq!(image.palette, palette);
+                } else {
+                    assert!(image.palette.is_empty());
+                }
+            }
+        }
+    }
 
-        let data_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
-        match bmp_image_parser.pixel_size {
-            3 => {
-                embed_color_data_right_left::<RgbPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
+    #[test]
+    fn test_png_chunks_are_read_in_order(){
+        let filtered = filter_rows(&[7; 12], 3, 3);
+        let bytes = png_file(&ihdr(1, 4, 8, 2), &[chunk(b"gAMA", &[0, 0, 0xB1, 0x8F]), chunk(b"prVt", b"private")], &filtered);
+        let mut with_trailer = bytes.clone();
+        with_trailer.extend_from_slice(b"trailing bytes");
+
+        let chunks = read_chunks(&with_trailer).unwrap();
+        let names: Vec<String> = chunks.iter().map(|chunk| chunk.name()).collect();
+        assert_eq!(names, ["IHDR", "gAMA", "prVt", "IDAT", "IDAT", "tEXt", "IEND"]);
+        assert!(chunks[0].is_critical() && !chunks[2].is_critical());
+        assert_eq!(chunks[2].data, b"private");
+        assert_eq!(decode_png(&with_trailer).unwrap().samples, vec![7; 12]);
+    }
+
+    #[test]
+    fn test_png_bad_files_are_rejected(){
+        let filtered = filter_rows(&[1, 2, 3, 4, 5, 6], 3, 3);
+        let good = png_file(&ihdr(1, 2, 8, 2), &[], &filtered);
+        decode_png(&good).unwrap();
+
+        // A flipped bit anywhere in a chunk fails its CRC, no IEND, a cut off chunk
+        let mut bytes = good.clone();
+        bytes[PNG_SIGNATURE.len() + 10] ^= 0x10;
+        assert!(matches!(decode_png(&bytes), Err(StegoError::IntegrityFailure(_))));
+        assert!(matches!(decode_png(&good[..good.len() - 12]), Err(StegoError::MalformedHeader(_))));
+        assert!(matches!(decode_png(&good[..good.len() - 20]), Err(StegoError::MalformedHeader(_))));
+
+        // Colour type and depth that do not go together, a zero width, an unknown interlace method
+        assert!(matches!(decode_png(&png_file(&ihdr(1, 2, 4, 2), &[], &filtered)), Err(StegoError::MalformedHeader(_))));
+        assert!(matches!(decode_png(&png_file(&ihdr(0, 2, 8, 2), &[], &filtered)), Err(StegoError::MalformedHeader(_))));
+        let mut unknown_interlace = ihdr(1, 2, 8, 2);
+        unknown_interlace[12] = 2;
+        assert!(matches!(decode_png(&png_file(&unknown_interlace, &[], &filtered)), Err(StegoError::UnsupportedFormat(_))));
+
+        // Unknown critical chunk, indexed without PLTE, an index past the palette
+        assert!(matches!(decode_png(&png_file(&ihdr(1, 2, 8, 2), &[chunk(b"ABCD", b"")], &filtered)), Err(StegoError::UnsupportedFormat(_))));
+        let indices = filter_rows(&[0, 2], 1, 1);
+        assert!(matches!(decode_png(&png_file(&ihdr(1, 2, 8, 3), &[], &indices)), Err(StegoError::MalformedHeader(_))));
+        let plte = chunk(b"PLTE", &[0; 6]);
+        assert!(matches!(decode_png(&png_file(&ihdr(1, 2, 8, 3), std::slice::from_ref(&plte), &indices)), Err(StegoError::MalformedHeader(_))));
+        decode_png(&png_file(&ihdr(1, 2, 8, 3), &[chunk(b"PLTE", &[0; 9])], &indices)).unwrap();
+
+        // Too little image data, an unknown filter type
+        assert!(matches!(decode_png(&png_file(&ihdr(1, 3, 8, 2), &[], &filtered)), Err(StegoError::MalformedHeader(_))));
+        let mut bad_filter = filtered.clone();
+        bad_filter[4] = 5;
+        assert!(matches!(decode_png(&png_file(&ihdr(1, 2, 8, 2), &[], &bad_filter)), Err(StegoError::MalformedHeader(_))));
+    }
+
+    // A smooth photo-like RGB image with some noise, written at level 9 split over 1000 byte IDAT chunks
+    fn photo_png() -> Vec<u8> {
+        let (width, height) = (64usize, 48usize);
+        let mut state = 0x2545_F491_u32;
+        let mut noise = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state % 9) as usize
+        };
+        let mut samples = Vec::new();
+        for row in 0..height {
+            for col in 0..width {
+                samples.extend([(row * 4 + noise()) as u16, (col * 3 + noise()) as u16, ((row + col) * 2 + noise()) as u16]);
             }
+        }
+        let rows = pack_rows(&samples, width * 3, 8);
+        let stored = png_file(&ihdr(width as u32, height as u32, 8, 2), &[chunk(b"gAMA", &[0, 0, 0xB1, 0x8F])], &filter_rows(&rows, width * 3, 3));
+
+        let mut parser = PngImageParser::from_bytes(&stored).unwrap();
+        parser.parse_file().unwrap();
+        parser.set_compression(PngCompression::Level(9));
+        parser.encode_image(&parser.decode_image().unwrap()).unwrap();
+
+        // Split the IDAT data the way an encoder with a small buffer would
+        let chunks = read_chunks(&{
+            let mut bytes = Vec::new();
+            parser.write_to(&mut bytes).unwrap();
+            bytes
+        }).unwrap();
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        let idat: Vec<u8> = chunks.iter().filter(|c| &c.chunk_type == b"IDAT").flat_map(|c| c.data.clone()).collect();
+        for c in chunks.iter().filter(|c| &c.chunk_type != b"IDAT") {
+            if &c.chunk_type == b"tEXt" {
+                idat.chunks(1000).for_each(|data| bytes.extend(chunk(b"IDAT", data)));
+            }
+            bytes.extend(chunk(&c.chunk_type, &c.data));
+        }
+        bytes
+    }
 
-            4 => {
-                embed_color_data_right_left::<RgbaPixel>(&data_vec, &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64);
+    #[test]
+    fn test_png_encode_keeps_every_other_chunk(){
+        let cases: [(u8, u8, usize); 6] = [(0, 1, 1), (0, 16, 1), (2, 8, 3), (3, 4, 1), (4, 16, 2), (6, 8, 4)];
+        for (color_type, depth, channels) in cases {
+            let (width, height) = (9usize, 7usize);
+            let samples = test_samples(width * height * channels, depth);
+            let rows = pack_rows(&samples, width * channels, depth as usize);
+            let mut before_idat = vec![chunk(b"gAMA", &[0, 0, 0xB1, 0x8F]), chunk(b"prVt", b"private")];
+            if color_type == 3 {
+                before_idat.insert(0, chunk(b"PLTE", &[7; 48]));
             }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
+            let mut bytes = png_file(&ihdr(width as u32, height as u32, depth, color_type), &before_idat, &filter_rows(&rows, rows.len() / height, (channels * depth as usize).div_ceil(8)));
+            bytes.extend_from_slice(b"trailing bytes");
+
+            let mut image = decode_png(&bytes).unwrap();
+            for sample in image.samples.iter_mut().step_by(3) {
+                *sample ^= 1;
             }
+            let encoded = encode_png(&image).unwrap();
+            assert!(encoded.ends_with(b"trailing bytes"));
+
+            let without_idat = |bytes: &[u8]| read_chunks(bytes).unwrap().into_iter().filter(|c| &c.chunk_type != b"IDAT").collect::<Vec<_>>();
+            assert_eq!(without_idat(&encoded), without_idat(&bytes), "{color_type} {depth}");
+            let names: Vec<String> = read_chunks(&encoded).unwrap().iter().map(|c| c.name()).collect();
+            let first_idat = names.iter().position(|name| name == "IDAT").unwrap();
+            let last_idat = names.iter().rposition(|name| name == "IDAT").unwrap();
+            assert!(names[first_idat..=last_idat].iter().all(|name| name == "IDAT"));
+            assert_eq!(names[first_idat - 1], "prVt");
+            assert_eq!(names[last_idat + 1..], ["tEXt", "IEND"]);
+            assert_eq!(decode_png(&encoded).unwrap().samples, image.samples, "{color_type} {depth}");
         }
+    }
 
-        bmp_image_parser.write_file("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_COLOR_LARGE_RIGHT_LEFT.bmp");
+    #[test]
+    fn test_png_embed_round_trip_keeps_the_size(){
+        let original = photo_png();
+        let message = b"hidden in the low bits of a png".to_vec();
+
+        for derivation in [FileEncodingFunctionDerivation::Unkeyed, FileEncodingFunctionDerivation::from_passphrase("png")] {
+            let mut carrier = carrier_from_bytes(&original).unwrap();
+            carrier.parse_file().unwrap();
+            carrier.embed_data(&mut message.clone(), FileEncoding::Lsb, FileEncodingMethod::SinWave, derivation).unwrap();
+            let mut written = Vec::new();
+            carrier.write_to(&mut written).unwrap();
+
+            // Within a few percent of the original, split into IDAT chunks of the same size
+            assert!(written.len().abs_diff(original.len()) * 20 < original.len(), "{} vs {}", written.len(), original.len());
+            let idat_sizes: Vec<usize> = read_chunks(&written).unwrap().iter().filter(|c| &c.chunk_type == b"IDAT").map(|c| c.data.len()).collect();
+            assert!(idat_sizes.len() > 1 && idat_sizes[..idat_sizes.len() - 1].iter().all(|&size| size == 1000));
+            assert_eq!(&written[8..41], &original[8..41]);
+
+            let mut carrier = carrier_from_bytes(&written).unwrap();
+            carrier.parse_file().unwrap();
+            assert_eq!(carrier.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::SinWave, derivation).unwrap(), message);
+        }
 
-        //ensure we shoved more than a full pixel row of bits in there so that we can test multi row embedding
-        assert!(data_vec.len() > 384);
+        let mut parser = PngImageParser::from_bytes(&original).unwrap();
+        parser.parse_file().unwrap();
+        let report = parser.capacity(FileEncoding::ColorParity, FileEncodingMethod::LeftToRight).unwrap();
+        assert_eq!(report.total_bits, 64 * 48);
+        parser.set_compression(PngCompression::Level(0));
+        parser.embed_data(&mut message.clone(), FileEncoding::ColorParity, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+        let mut stored = Vec::new();
+        parser.write_to(&mut stored).unwrap();
+        assert!(stored.len() > 64 * 48 * 3);
+        assert_eq!(decode_png(&stored).unwrap().samples.len(), 64 * 48 * 3);
+    }
+
+    #[test]
+    fn test_png_embed_keeps_the_size_of_an_outside_encoder(){
+        // Written by zlib at level 6, the first row Sub filtered, every third Up and the rest Paeth
+        let original = std::fs::read("src/filetype_support/assets/zlib-paeth-48x32.png").unwrap();
+        let image_data = |bytes: &[u8]| {
+            let idat: Vec<u8> = read_chunks(bytes).unwrap().iter().filter(|c| &c.chunk_type == b"IDAT").flat_map(|c| c.data.clone()).collect();
+            let filtered = zlib_decompress(&idat, (48 * 3 + 1) * 32).unwrap();
+            (idat.len(), filtered.chunks_exact(48 * 3 + 1).map(|row| row[0]).collect::<Vec<u8>>())
+        };
+        let (original_size, original_filters) = image_data(&original);
+        assert_eq!(original_filters[..4], [1, 4, 4, 2]);
+
+        for (message, encoding_method) in [(vec![0x5Au8; 20], FileEncodingMethod::LeftToRight), (vec![0xC3u8; 400], FileEncodingMethod::CosWave)] {
+            let mut carrier = carrier_from_bytes(&original).unwrap();
+            carrier.parse_file().unwrap();
+            carrier.embed_data(&mut message.clone(), FileEncoding::Lsb, encoding_method, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+            let mut written = Vec::new();
+            carrier.write_to(&mut written).unwrap();
+
+            // Same filters, image data within 1% (or 16 bytes) of the original
+            let (size, filters) = image_data(&written);
+            assert_eq!(filters, original_filters);
+            assert!(size.abs_diff(original_size) <= (original_size / 100).max(16), "{size} vs {original_size}");
+
+            let mut carrier = carrier_from_bytes(&written).unwrap();
+            carrier.parse_file().unwrap();
+            assert_eq!(carrier.retrieve_data(FileEncoding::Lsb, encoding_method, FileEncodingFunctionDerivation::Unkeyed).unwrap(), message);
+        }
 
+        // A level of its own from the command line picks the filters again
+        let input = "src/filetype_support/assets/zlib-paeth-48x32.png";
+        let output = std::env::temp_dir().join("veritasobscura-compression-option.png").to_str().unwrap().to_string();
+        let args = |list: &[&str]| std::iter::once("maya").chain(list.iter().copied()).map(String::from).collect::<Vec<_>>();
+        let mut image_support = parse_arguments::<PngImageParser>(args(&["Lsb", "LeftRight", "embed", "level 9", input, &output, "--compression", "9"])).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Embedded);
+        let written = std::fs::read(&output).unwrap();
+        assert_ne!(image_data(&written).1, original_filters);
+        assert_eq!(&written[written.len() - 12..], &original[original.len() - 12..]);
+
+        for list in [
+            &["Lsb", "LeftRight", "embed", "level 10", input, &output, "--compression", "10"][..],
+            &["Lsb", "LeftRight", "embed", "runs", input, &output, "--rle", "keep"][..],
+        ] {
+            assert!(matches!(parse_arguments::<PngImageParser>(args(list)), Err(StegoError::Usage(_))), "{list:?}");
+        }
     }
 
     #[test]
-    fn test_bmp_color_retrieve_large_message_right_left(){
-        let mut bmp_image_parser = BmpImageParser::new("src/filetype_support/assets/sample-1024x1024-TEST_EMBED_COLOR_LARGE_RIGHT_LEFT.bmp");
-        bmp_image_parser.parse_file();
-        let message_vec : Vec<u8> = "This is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposesThis is a test embedding for testing purposes".as_bytes().to_vec();
+    fn test_png_encode_rejects_other_images(){
+        let mut parser = PngImageParser::from_bytes(&photo_png()).unwrap();
+        parser.parse_file().unwrap();
+        let image = parser.decode_image().unwrap();
+
+        let mut wider = image.clone();
+        wider.width += 1;
+        assert!(matches!(parser.encode_image(&wider), Err(StegoError::UnsupportedFormat(_))));
+        let mut too_big = image.clone();
+        too_big.samples[0] = 256;
+        assert!(matches!(parser.encode_image(&too_big), Err(StegoError::UnsupportedFormat(_))));
+        let mut grey = image.clone();
+        grey.layout = ChannelLayout::Gray;
+        assert!(matches!(parser.encode_image(&grey), Err(StegoError::UnsupportedFormat(_))));
+        assert!(matches!(encode_png(&image), Err(StegoError::UnsupportedFormat(_))));
+
+        let indexed = png_file(&ihdr(2, 1, 8, 3), &[chunk(b"PLTE", &[0; 6])], &[0, 0, 1]);
+        let mut image = decode_png(&indexed).unwrap();
+        image.palette[1] = [1, 2, 3];
+        assert!(matches!(encode_png(&image), Err(StegoError::UnsupportedFormat(_))));
+    }
 
-        let mut data_vec: Vec<u8> = vec![0];
-        match bmp_image_parser.pixel_size {
-            3 => {
-                data_vec = extract_color_data_right_left::<RgbPixel>( &mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64,(message_vec.len() * 8) as u64);
+    #[test]
+    fn test_png_sixteen_bit_pixels_are_big_endian(){
+        let bytes = [0x12, 0x34, 0xAB, 0xCD, 0xFF, 0x01, 0x80, 0x7F];
+        let mut pixel = Rgba64Pixel::from_bytes(&bytes, ());
+        assert_eq!((0..4).map(|channel| pixel.sample(channel)).collect::<Vec<_>>(), [0x1234, 0xABCD, 0xFF01, 0x807F]);
+        assert_eq!((pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()), (0x12, 0xAB, 0xFF, 0x80));
+        assert_eq!((pixel.first(), pixel.second(), pixel.third(), pixel.fourth()), (0x34, 0xCD, 0x01, 0x7F));
+
+        pixel.set_first(0x99);
+        pixel.set_fourth(0x00).unwrap();
+        pixel.set_blue(0x10);
+        let mut written = [0u8; 8];
+        pixel.write_bytes(&mut written);
+        assert_eq!(written, [0x12, 0x99, 0xAB, 0xCD, 0x10, 0x10, 0x80, 0x00]);
+        assert_eq!((Rgba64Pixel::SIZE, Rgba64Pixel::bit_depths(())), (8, vec![16; 4]));
+
+        let mut rgb = Rgb48Pixel::from_bytes(&bytes[..6], ());
+        assert_eq!(rgb.alpha(), 255);
+        assert!(matches!(rgb.set_alpha(1), Err(StegoError::InvalidChannel(_))));
+        assert!(matches!(rgb.set_fourth(1), Err(StegoError::InvalidChannel(_))));
+
+        let grey = Gray16Pixel::from_bytes(&bytes[2..4], ());
+        assert_eq!((grey.red(), grey.green(), grey.blue(), grey.fourth()), (0xAB, 0xAB, 0xAB, 255));
+        assert_eq!(Gray16Pixel::layout(()), Some(ChannelLayout::Gray));
+    }
+
+    #[test]
+    fn test_png_sixteen_bit_low_bits_round_trip(){
+        let (width, height) = (24usize, 20usize);
+        let message = b"sixteen bits per sample".to_vec();
+
+        for (color_type, channels) in [(0u8, 1usize), (2, 3), (4, 2), (6, 4)] {
+            let samples = test_samples(width * height * channels, 16);
+            let rows = pack_rows(&samples, width * channels, 16);
+            let original = png_file(&ihdr(width as u32, height as u32, 16, color_type), &[], &filter_rows(&rows, rows.len() / height, channels * 2));
+
+            for bits in 1..=8u8 {
+                let encoding = FileEncoding::LowBits(bits);
+                let derivation = FileEncodingFunctionDerivation::from_passphrase("deep");
+                let mut carrier = carrier_from_bytes(&original).unwrap();
+                carrier.parse_file().unwrap();
+                let report = carrier.capacity(encoding, FileEncodingMethod::TopToBottom).unwrap();
+                assert_eq!((report.total_bits, report.bits_per_pixel), ((width * height * channels) as u64 * bits as u64, channels as u64 * bits as u64));
+                carrier.embed_data(&mut message.clone(), encoding, FileEncodingMethod::TopToBottom, derivation).unwrap();
+                let mut written = Vec::new();
+                carrier.write_to(&mut written).unwrap();
+
+                // Only the lowest bits bits of a sample change, the high byte never does
+                let embedded = decode_png(&written).unwrap();
+                assert_eq!(embedded.bit_depths, vec![16; channels]);
+                assert!(embedded.samples.iter().zip(&samples).all(|(a, b)| a ^ b < 1 << bits), "{color_type} {bits}");
+                assert!(embedded.samples.iter().zip(&samples).any(|(a, b)| a ^ b > 1) || bits == 1);
+
+                let mut carrier = carrier_from_bytes(&written).unwrap();
+                carrier.parse_file().unwrap();
+                assert_eq!(carrier.retrieve_data(encoding, FileEncodingMethod::TopToBottom, derivation).unwrap(), message, "{color_type} {bits}");
+                assert!(matches!(
+                    carrier.retrieve_data(FileEncoding::LowBits(bits % 8 + 1), FileEncodingMethod::TopToBottom, derivation),
+                    Err(StegoError::IntegrityFailure(_))
+                ));
             }
+        }
 
-            4 => {
-                data_vec = extract_color_data_right_left::<RgbaPixel>(&mut bmp_image_parser.file_data[bmp_image_parser.pixel_map.pixel_map_start as usize..], bmp_image_parser.pixel_map.width as u64, bmp_image_parser.pixel_map.height as u64, bmp_image_parser.padding_size as u64, bmp_image_parser.pixel_size as u64, (message_vec.len() * 8) as u64);
+        // More than 8 bits, or all of an 8 bit sample, is refused
+        let mut parser = PngImageParser::from_bytes(&photo_png()).unwrap();
+        parser.parse_file().unwrap();
+        assert_eq!(parser.capacity(FileEncoding::LowBits(7), FileEncodingMethod::LeftToRight).unwrap().bits_per_pixel, 21);
+        for bits in [0, 8, 9] {
+            assert!(matches!(
+                parser.embed_data(&mut message.clone(), FileEncoding::LowBits(bits), FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed),
+                Err(StegoError::UnsupportedEncoding(_))
+            ));
+        }
+    }
+
+    // Premultiplied (red, green, blue, alpha) of an entry, what PalettePairs compares
+    fn seen_colour(image: &crate::file_encoding_support::image::Image, index: u16) -> [i64; 4] {
+        let alpha = image.palette_alpha.get(index as usize).copied().unwrap_or(255) as i64;
+        let [red, green, blue] = image.palette[index as usize].map(|value| (value as i64 * alpha + 127) / 255);
+        [red, green, blue, alpha]
+    }
+
+    #[test]
+    fn test_png_indexed_round_trip_keeps_colours_close(){
+        let (width, height) = (32usize, 24usize);
+        let message = b"palette png".to_vec();
+        // Close shades that pair up among themselves, a lone red and a fully transparent entry
+        let mut palette: Vec<[u8; 3]> = (0..5u8).map(|i| [40 + i * 6, 90 + i * 5, 200 - i * 7]).collect();
+        palette.extend([[250, 10, 10], [1, 2, 3]]);
+        let alpha = [255, 255, 200, 200, 255, 255, 0];
+
+        for depth in [1u8, 2, 4, 8] {
+            let entries = palette.len().min(1 << depth).min(if depth == 1 { 1 } else { usize::MAX });
+            let indices: Vec<u16> = (0..width * height).map(|i| ((i * 7 + i / width) % entries) as u16).collect();
+            let rows = pack_rows(&indices, width, depth as usize);
+            let plte: Vec<u8> = palette[..entries].iter().flatten().copied().collect();
+            let before_idat = [chunk(b"PLTE", &plte), chunk(b"tRNS", &alpha[..entries]), chunk(b"hIST", &[0, 1].repeat(entries))];
+            let original = png_file(&ihdr(width as u32, height as u32, depth, 3), &before_idat, &filter_rows(&rows, rows.len() / height, 1));
+            let decoded = decode_png(&original).unwrap();
+            assert_eq!((decoded.palette.len(), decoded.palette_alpha.len()), (entries, entries));
+
+            for derivation in [FileEncodingFunctionDerivation::Unkeyed, FileEncodingFunctionDerivation::from_passphrase("indexed")] {
+                let mut parser = PngImageParser::from_bytes(&original).unwrap();
+                parser.parse_file().unwrap();
+                let capacity = parser.capacity(FileEncoding::Lsb, FileEncodingMethod::CosWave).unwrap();
+                parser.embed_data(&mut message.clone(), FileEncoding::Lsb, FileEncodingMethod::CosWave, derivation).unwrap();
+                let mut written = Vec::new();
+                parser.write_to(&mut written).unwrap();
+
+                // The palette only grew, tRNS and hIST still cover it and no pixel moved further than the bound
+                let embedded = decode_png(&written).unwrap();
+                assert!(embedded.palette.starts_with(&decoded.palette) && embedded.palette.len() <= 1 << depth);
+                assert!(embedded.palette.len() > entries || depth == 2, "{depth}");
+                assert!(embedded.palette_alpha.starts_with(&decoded.palette_alpha) && embedded.palette_alpha.len() <= embedded.palette.len());
+                let chunks = read_chunks(&written).unwrap();
+                let hist = chunks.iter().find(|c| &c.chunk_type == b"hIST").unwrap();
+                assert_eq!(hist.data.len(), embedded.palette.len() * 2);
+                for (&before, &after) in decoded.samples.iter().zip(&embedded.samples) {
+                    let (before, after) = (seen_colour(&decoded, before), seen_colour(&embedded, after));
+                    let distance: i64 = (0..4).map(|channel| [3, 4, 2, 3][channel] * (before[channel] - after[channel]).pow(2)).sum();
+                    assert!(distance <= PalettePairs::MAX_DISTANCE as i64, "{depth} {before:?} {after:?}");
+                }
+
+                let mut parser = PngImageParser::from_bytes(&written).unwrap();
+                parser.parse_file().unwrap();
+                assert_eq!(parser.retrieve_data(FileEncoding::Lsb, FileEncodingMethod::CosWave, derivation).unwrap(), message, "{depth}");
+                assert!(parser.capacity(FileEncoding::Lsb, FileEncodingMethod::CosWave).unwrap().total_bits >= capacity.total_bits);
+            }
+
+            // Without twins PLTE and tRNS stay byte for byte, only pixels with a close partner carry bits
+            // (entries 0 to 3), and the single colour of the 1 bit image carries nothing
+            let args = |list: &[&str]| std::iter::once("maya").chain(list.iter().copied()).map(String::from).collect::<Vec<_>>();
+            let input = std::env::temp_dir().join(format!("veritasobscura-palette-twins-{depth}.png")).to_str().unwrap().to_string();
+            let output = std::env::temp_dir().join(format!("veritasobscura-palette-twins-{depth}-out.png")).to_str().unwrap().to_string();
+            std::fs::write(&input, &original).unwrap();
+            let mut image_support = parse_arguments::<PngImageParser>(args(&["Lsb", "LeftRight", "capacity", &input, "--palette-twins", "off"])).unwrap();
+            let Ok(OperationOutput::Capacity(capacity)) = image_support.run() else { panic!("{depth}") };
+            let mut image_support = parse_arguments::<PngImageParser>(args(&["Lsb", "LeftRight", "embed", "palette png", &input, &output, "--palette-twins", "off"])).unwrap();
+            let result = image_support.run();
+            if depth > 1 {
+                assert_eq!(result.unwrap(), OperationOutput::Embedded);
+                let written = std::fs::read(&output).unwrap();
+                let without_idat = |bytes: &[u8]| read_chunks(bytes).unwrap().into_iter().filter(|c| &c.chunk_type != b"IDAT").collect::<Vec<_>>();
+                assert_eq!(without_idat(&written), without_idat(&original));
+                let carrying = decoded.samples.iter().filter(|&&index| index < 4).count() as u64;
+                assert_eq!(capacity.total_bits, carrying);
+            } else {
+                assert!(matches!(result, Err(StegoError::InsufficientCapacity { .. })), "{depth}");
+            }
+            let result = parse_arguments::<PngImageParser>(args(&["Lsb", "LeftRight", "capacity", &input, "--palette-twins", "maybe"]));
+            assert!(matches!(result, Err(StegoError::Usage(_))));
+        }
+
+        // A tRNS that is longer than PLTE, or comes before it
+        let plte = chunk(b"PLTE", &[0; 6]);
+        assert!(matches!(decode_png(&png_file(&ihdr(2, 1, 8, 3), &[plte.clone(), chunk(b"tRNS", &[0; 3])], &[0, 0, 1])), Err(StegoError::MalformedHeader(_))));
+        assert!(matches!(decode_png(&png_file(&ihdr(2, 1, 8, 3), &[chunk(b"tRNS", &[0]), plte], &[0, 0, 1])), Err(StegoError::MalformedHeader(_))));
+    }
+
+    // The filtered data of an Adam7 interlaced image, written out pass by pass
+    fn adam7_filtered(samples: &[u16], width: usize, height: usize, channels: usize, depth: usize) -> Vec<u8> {
+        let passes = [(0, 0, 8, 8), (0, 4, 8, 8), (4, 0, 8, 4), (0, 2, 4, 4), (2, 0, 4, 2), (0, 1, 2, 2), (1, 0, 2, 1)];
+        let mut filtered = Vec::new();
+        for (first_row, first_col, row_step, col_step) in passes {
+            let pass: Vec<u16> = (first_row..height)
+                .step_by(row_step)
+                .flat_map(|row| (first_col..width).step_by(col_step).map(move |col| (row, col)))
+                .flat_map(|(row, col)| samples[(row * width + col) * channels..][..channels].to_vec())
+                .collect();
+            let pass_width = (first_col..width).step_by(col_step).count();
+            if pass.is_empty() {
+                continue;
             }
-            _ => {
-                println!("bmp test.rs Got bad value for pixel size, exiting ...");
-                exit(1);
+            let rows = pack_rows(&pass, pass_width * channels, depth);
+            let pass_height = pass.len() / (pass_width * channels);
+            filtered.extend(filter_rows(&rows, rows.len() / pass_height, (channels * depth).div_ceil(8)));
+        }
+        filtered
+    }
+
+    #[test]
+    fn test_png_adam7_decodes_to_final_coordinates(){
+        let cases: [(u8, u8, usize); 6] = [(0, 1, 1), (0, 16, 1), (3, 4, 1), (2, 8, 3), (4, 8, 2), (6, 16, 4)];
+        for (width, height) in [(1usize, 1usize), (3, 2), (8, 8), (13, 11), (2, 17)] {
+            for (color_type, depth, channels) in cases {
+                let samples = test_samples(width * height * channels, depth);
+                let before_idat = if color_type == 3 { vec![chunk(b"PLTE", &[9; 48])] } else { Vec::new() };
+                let mut interlaced_header = ihdr(width as u32, height as u32, depth, color_type);
+                interlaced_header[12] = 1;
+                let interlaced = png_file(&interlaced_header, &before_idat, &adam7_filtered(&samples, width, height, channels, depth as usize));
+
+                let image = decode_png(&interlaced).unwrap();
+                assert_eq!((image.width, image.height), (width, height));
+                assert_eq!(image.samples, samples, "{width}x{height} {color_type} {depth}");
+
+                // Written back as it was read, or converted either way
+                let encoded = encode_png(&image).unwrap();
+                assert_eq!(read_chunks(&encoded).unwrap()[0].data[12], 1);
+                assert_eq!(decode_png(&encoded).unwrap().samples, samples);
+                let mut parser = PngImageParser::from_bytes(&interlaced).unwrap();
+                parser.parse_file().unwrap();
+                parser.set_interlace(PngInterlace::NotInterlaced);
+                parser.encode_image(&image).unwrap();
+                let mut flat = Vec::new();
+                parser.write_to(&mut flat).unwrap();
+                assert_eq!(read_chunks(&flat).unwrap()[0].data[12], 0);
+                assert_eq!(decode_png(&flat).unwrap().samples, samples);
+                let mut parser = PngImageParser::from_bytes(&flat).unwrap();
+                parser.parse_file().unwrap();
+                parser.set_interlace(PngInterlace::Adam7);
+                parser.set_compression(PngCompression::Level(0));
+                parser.encode_image(&image).unwrap();
+                let mut again = Vec::new();
+                parser.write_to(&mut again).unwrap();
+                assert_eq!(read_chunks(&again).unwrap()[0].data[12], 1);
+                assert_eq!(decode_png(&again).unwrap().samples, samples);
             }
         }
 
-        /*
-            the data vec will have an extra byte. the slice is just to remove that extra byte so that the test will pass
-         */
-        assert_eq!(String::from_utf8(data_vec[0..(message_vec.len())].to_owned()), String::from_utf8(message_vec));
+        // Image data for the passes of a smaller image is too short
+        let samples = test_samples(9 * 9 * 3, 8);
+        let mut header = ihdr(9, 10, 8, 2);
+        header[12] = 1;
+        assert!(matches!(decode_png(&png_file(&header, &[], &adam7_filtered(&samples, 9, 9, 3, 8))), Err(StegoError::MalformedHeader(_))));
     }
 
+    #[test]
+    fn test_png_adam7_embeds_along_the_final_image(){
+        let (width, height) = (21usize, 19usize);
+        let samples = test_samples(width * height * 3, 8);
+        let rows = pack_rows(&samples, width * 3, 8);
+        let plain = png_file(&ihdr(width as u32, height as u32, 8, 2), &[], &filter_rows(&rows, width * 3, 3));
+        let mut header = ihdr(width as u32, height as u32, 8, 2);
+        header[12] = 1;
+        let interlaced = png_file(&header, &[], &adam7_filtered(&samples, width, height, 3, 8));
+        let message = b"interlaced".to_vec();
+
+        for method in [FileEncodingMethod::LeftToRight, FileEncodingMethod::TopToBottom, FileEncodingMethod::SinWave] {
+            for derivation in [FileEncodingFunctionDerivation::Unkeyed, FileEncodingFunctionDerivation::from_passphrase("adam7")] {
+                // The same pixels change whether the file is interlaced or not
+                let mut embedded = Vec::new();
+                for original in [&plain, &interlaced] {
+                    let mut carrier = carrier_from_bytes(original).unwrap();
+                    carrier.parse_file().unwrap();
+                    carrier.embed_data(&mut message.clone(), FileEncoding::Lsb, method, derivation).unwrap();
+                    let mut written = Vec::new();
+                    carrier.write_to(&mut written).unwrap();
+                    assert_eq!(read_chunks(&written).unwrap()[0].data[12], original[8 + 8 + 12]);
+                    embedded.push(written);
+                }
+                assert_eq!(decode_png(&embedded[0]).unwrap().samples, decode_png(&embedded[1]).unwrap().samples, "{method:?}");
+
+                // And the message survives a conversion to the other layout
+                let mut parser = PngImageParser::from_bytes(&embedded[1]).unwrap();
+                parser.parse_file().unwrap();
+                parser.set_interlace(PngInterlace::NotInterlaced);
+                parser.encode_image(&parser.decode_image().unwrap()).unwrap();
+                let mut converted = Vec::new();
+                parser.write_to(&mut converted).unwrap();
+                let mut carrier = carrier_from_bytes(&converted).unwrap();
+                carrier.parse_file().unwrap();
+                assert_eq!(carrier.retrieve_data(FileEncoding::Lsb, method, derivation).unwrap(), message);
+            }
+        }
+    }
 
+    #[test]
+    fn test_png_interlace_from_the_command_line(){
+        let (width, height) = (21usize, 19usize);
+        let samples = test_samples(width * height * 3, 8);
+        let plain = png_file(&ihdr(width as u32, height as u32, 8, 2), &[], &filter_rows(&pack_rows(&samples, width * 3, 8), width * 3, 3));
+        let mut header = ihdr(width as u32, height as u32, 8, 2);
+        header[12] = 1;
+        let interlaced = png_file(&header, &[], &adam7_filtered(&samples, width, height, 3, 8));
+        let args = |list: &[&str]| std::iter::once("maya").chain(list.iter().copied()).map(String::from).collect::<Vec<_>>();
+        let input = std::env::temp_dir().join("veritasobscura-interlace-option.png").to_str().unwrap().to_string();
+        let output = std::env::temp_dir().join("veritasobscura-interlace-option-out.png").to_str().unwrap().to_string();
+
+        for (original, value, flag) in [(&plain, "adam7", 1), (&plain, "keep", 0), (&interlaced, "none", 0), (&interlaced, "keep", 1)] {
+            std::fs::write(&input, original).unwrap();
+            let mut image_support = parse_arguments::<PngImageParser>(args(&["Lsb", "SinWave", "embed", "laid out", &input, &output, "--interlace", value])).unwrap();
+            assert_eq!(image_support.run().unwrap(), OperationOutput::Embedded);
+
+            let written = std::fs::read(&output).unwrap();
+            assert_eq!(read_chunks(&written).unwrap()[0].data[12], flag, "{value}");
+            let mut image_support = parse_arguments::<PngImageParser>(args(&["Lsb", "SinWave", "extract", &output])).unwrap();
+            assert_eq!(image_support.run().unwrap(), OperationOutput::Extracted(b"laid out".to_vec()));
+        }
 
+        let result = parse_arguments::<PngImageParser>(args(&["Lsb", "SinWave", "extract", &input, "--interlace", "adam8"]));
+        assert!(matches!(result, Err(StegoError::Usage(_))));
+    }
 
+    #[test]
+    fn test_png_colour_key_stays_transparent(){
+        let (width, height) = (48usize, 32usize);
+        for depth in [8u8, 16] {
+            let key: [u16; 3] = if depth == 8 { [200, 40, 90] } else { [0xC812, 0x2834, 0x5A56] };
+            // A third of the pixels are the key and a third are one low bit away from it
+            let mut samples = test_samples(width * height * 3, depth);
+            for (pixel, rgb) in samples.chunks_exact_mut(3).enumerate() {
+                if pixel % 3 < 2 {
+                    rgb.copy_from_slice(&key);
+                }
+                if pixel % 3 == 1 {
+                    rgb[pixel % 2 * 2] ^= 1;
+                }
+            }
+            let trns: Vec<u8> = key.iter().flat_map(|sample| sample.to_be_bytes()).collect();
+            let stride = 3 * depth as usize / 8;
+            let rows = pack_rows(&samples, width * 3, depth as usize);
+            let original = png_file(&ihdr(width as u32, height as u32, depth, 2), &[chunk(b"tRNS", &trns)], &filter_rows(&rows, width * stride, stride));
+            let decoded = decode_png(&original).unwrap();
+            assert_eq!(decoded.transparent_key, Some(key.to_vec()));
+
+            let encodings = if depth == 8 { vec![FileEncoding::Lsb, FileEncoding::ColorParity] } else { vec![FileEncoding::Lsb, FileEncoding::ColorParity, FileEncoding::LowBits(4)] };
+            for encoding in encodings {
+                // Only pixels whose bits above the changed ones differ from the key carry anything
+                let changed_bits = if let FileEncoding::LowBits(bits) = encoding { bits } else { 1 };
+                let carrying = decoded.samples.chunks_exact(3).filter(|rgb| rgb.iter().zip(&key).any(|(sample, key)| sample >> changed_bits != key >> changed_bits)).count() as u64;
+                assert!(carrying > 0 && carrying < (width * height) as u64 / 3 + 1, "{depth} {encoding:?}");
+
+                let mut parser = PngImageParser::from_bytes(&original).unwrap();
+                parser.parse_file().unwrap();
+                let report = parser.capacity(encoding, FileEncodingMethod::LeftToRight).unwrap();
+                assert_eq!(report.total_bits, carrying * report.bits_per_pixel, "{depth} {encoding:?}");
+
+                let message = vec![0xA5; report.usable_payload_bytes as usize];
+                parser.embed_data(&mut message.clone(), encoding, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+                let mut written = Vec::new();
+                parser.write_to(&mut written).unwrap();
+
+                // No pixel turned transparent and none stopped being transparent
+                let embedded = decode_png(&written).unwrap();
+                assert_ne!(embedded.samples, decoded.samples);
+                for (before, after) in decoded.samples.chunks_exact(3).zip(embedded.samples.chunks_exact(3)) {
+                    assert_eq!(before == key, after == key, "{depth} {encoding:?} {before:?} {after:?}");
+                }
+
+                let mut parser = PngImageParser::from_bytes(&written).unwrap();
+                parser.parse_file().unwrap();
+                assert_eq!(parser.retrieve_data(encoding, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap(), message, "{depth} {encoding:?}");
+            }
+        }
 
+        // A colour key has one sample per channel
+        let filtered = filter_rows(&[7; 12], 3, 3);
+        let bytes = png_file(&ihdr(1, 4, 8, 2), &[chunk(b"tRNS", &[0, 7, 0, 7])], &filtered);
+        assert!(matches!(decode_png(&bytes), Err(StegoError::MalformedHeader(_))));
+        let bytes = png_file(&ihdr(1, 4, 8, 0), &[chunk(b"tRNS", &[0, 7])], &[0, 7, 0, 7, 0, 7, 0, 7]);
+        assert_eq!(decode_png(&bytes).unwrap().transparent_key, Some(vec![7]));
+    }
 
+    fn idat_data(bytes: &[u8]) -> Vec<u8> {
+        read_chunks(bytes).unwrap().iter().filter(|c| &c.chunk_type == b"IDAT").flat_map(|c| c.data.clone()).collect()
+    }
 
+    #[test]
+    fn test_png_chunk_encoding_round_trip(){
+        let original = photo_png();
+        let message: Vec<u8> = (0..=255).collect();
+        let styles = [
+            ChunkStyle::Private(*b"orNt"),
+            ChunkStyle::Text("Comment".to_string()),
+            ChunkStyle::CompressedText("Software".to_string()),
+            ChunkStyle::InternationalText("Description".to_string()),
+        ];
+
+        for style in styles {
+            for placement in [ChunkPlacement::BeforeIdat, ChunkPlacement::AfterIdat] {
+                for derivation in [FileEncodingFunctionDerivation::Unkeyed, FileEncodingFunctionDerivation::from_passphrase("chunk")] {
+                    let embedding = ChunkEmbedding { style: style.clone(), placement };
+                    let mut parser = PngImageParser::from_bytes(&original).unwrap();
+                    parser.parse_file().unwrap();
+                    parser.set_chunk_embedding(embedding.clone());
+                    // Embedding twice leaves only the second payload behind
+                    parser.embed_data(&mut b"first".to_vec(), FileEncoding::Chunk, FileEncodingMethod::LeftToRight, derivation).unwrap();
+                    parser.embed_data(&mut message.clone(), FileEncoding::Chunk, FileEncodingMethod::LeftToRight, derivation).unwrap();
+                    let mut written = Vec::new();
+                    parser.write_to(&mut written).unwrap();
+
+                    // The pixel data is untouched and one chunk was added next to it
+                    assert_eq!(idat_data(&written), idat_data(&original));
+                    let chunks = read_chunks(&written).unwrap();
+                    assert_eq!(chunks.len(), read_chunks(&original).unwrap().len() + 1);
+                    assert!(chunks.iter().any(|c| c.data == b"Comment\0a test image"));
+                    let first_idat = chunks.iter().position(|c| &c.chunk_type == b"IDAT").unwrap();
+                    let last_idat = chunks.iter().rposition(|c| &c.chunk_type == b"IDAT").unwrap();
+                    let added = match placement {
+                        ChunkPlacement::BeforeIdat => &chunks[first_idat - 1],
+                        ChunkPlacement::AfterIdat => &chunks[last_idat + 1],
+                    };
+                    let is_text = !matches!(style, ChunkStyle::Private(_));
+                    assert_eq!(is_text, added.data.starts_with(b"Comment\0") || added.data.starts_with(b"Software\0") || added.data.starts_with(b"Description\0"));
+
+                    let mut parser = PngImageParser::from_bytes(&written).unwrap();
+                    parser.parse_file().unwrap();
+                    parser.set_chunk_embedding(embedding);
+                    assert_eq!(parser.retrieve_data(FileEncoding::Chunk, FileEncodingMethod::LeftToRight, derivation).unwrap(), message, "{style:?} {placement:?}");
+                    assert!(matches!(
+                        parser.retrieve_data(FileEncoding::Chunk, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::from_passphrase("other")),
+                        Err(StegoError::IntegrityFailure(_))
+                    ));
+                }
+            }
+        }
 
-}
+        // The default style works through the CLI carrier, and a different style finds nothing
+        let mut carrier = carrier_from_bytes(&original).unwrap();
+        carrier.parse_file().unwrap();
+        carrier.embed_data(&mut message.clone(), FileEncoding::Chunk, FileEncodingMethod::CosWave, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+        let mut written = Vec::new();
+        carrier.write_to(&mut written).unwrap();
+        let mut carrier = carrier_from_bytes(&written).unwrap();
+        carrier.parse_file().unwrap();
+        assert_eq!(carrier.retrieve_data(FileEncoding::Chunk, FileEncodingMethod::CosWave, FileEncodingFunctionDerivation::Unkeyed).unwrap(), message);
+        let mut parser = PngImageParser::from_bytes(&written).unwrap();
+        parser.parse_file().unwrap();
+        parser.set_chunk_embedding(ChunkEmbedding { style: ChunkStyle::Text("Comment".to_string()), placement: ChunkPlacement::AfterIdat });
+        assert!(matches!(
+            parser.retrieve_data(FileEncoding::Chunk, FileEncodingMethod::CosWave, FileEncodingFunctionDerivation::Unkeyed),
+            Err(StegoError::IntegrityFailure(_))
+        ));
+    }
+
+    #[test]
+    fn test_png_chunk_key_never_reuses_a_keystream(){
+        let original = photo_png();
+        let key = FileEncodingFunctionDerivation::from_passphrase("nonce");
+        let stored: Vec<Vec<u8>> = (0..2).map(|_| {
+            let mut parser = PngImageParser::from_bytes(&original).unwrap();
+            parser.parse_file().unwrap();
+            parser.set_chunk_embedding(ChunkEmbedding { style: ChunkStyle::Private(*b"orNt"), placement: ChunkPlacement::AfterIdat });
+            parser.embed_data(&mut vec![0u8; 64], FileEncoding::Chunk, FileEncodingMethod::LeftToRight, key).unwrap();
+            parser.chunks.iter().find(|c| &c.chunk_type == b"orNt").unwrap().data.clone()
+        }).collect();
+
+        // The same message under the same key, a nonce in front and nothing in common after it
+        assert_eq!(stored[0].len(), stored[1].len());
+        assert_ne!(stored[0][..12], stored[1][..12]);
+        let shared = stored[0][12..].iter().zip(&stored[1][12..]).filter(|(a, b)| a == b).count();
+        assert!(shared < stored[0].len() / 8, "{shared} bytes in common");
+    }
 
+    #[test]
+    fn test_png_chunk_options_from_the_command_line(){
+        let input = std::env::temp_dir().join("veritasobscura-chunk-option.png").to_str().unwrap().to_string();
+        let output = std::env::temp_dir().join("veritasobscura-chunk-option-out.png").to_str().unwrap().to_string();
+        std::fs::write(&input, photo_png()).unwrap();
+        let args = |list: &[&str]| std::iter::once("maya").chain(list.iter().copied()).map(String::from).collect::<Vec<_>>();
+
+        let options = ["--key", "chunky", "--chunk", "tEXt", "--chunk-keyword", "Author", "--chunk-placement", "before"];
+        let mut image_support = parse_arguments::<PngImageParser>(args(&[&["Chunk", "LeftRight", "embed", "in a chunk", &input, &output][..], &options].concat())).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Embedded);
+
+        let chunks = read_chunks(&std::fs::read(&output).unwrap()).unwrap();
+        let first_idat = chunks.iter().position(|c| &c.chunk_type == b"IDAT").unwrap();
+        assert_eq!(&chunks[first_idat - 1].chunk_type, b"tEXt");
+        assert!(chunks[first_idat - 1].data.starts_with(b"Author\0"));
+
+        let mut image_support = parse_arguments::<PngImageParser>(args(&[&["Chunk", "LeftRight", "extract", &output][..], &options[..6]].concat())).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Extracted(b"in a chunk".to_vec()));
+        let mut image_support = parse_arguments::<PngImageParser>(args(&["Chunk", "LeftRight", "extract", &output, "--key", "chunky"])).unwrap();
+        assert!(matches!(image_support.run(), Err(StegoError::IntegrityFailure(_))));
+
+        for list in [
+            &["Chunk", "LeftRight", "extract", &output, "--chunk", "Maya"][..],
+            &["Chunk", "LeftRight", "extract", &output, "--chunk", "zTXtx"][..],
+            &["Chunk", "LeftRight", "extract", &output, "--chunk", "orNt", "--chunk-keyword", "Author"][..],
+            &["Chunk", "LeftRight", "extract", &output, "--chunk-keyword", " Author"][..],
+            &["Chunk", "LeftRight", "extract", &output, "--chunk-placement", "middle"][..],
+        ] {
+            assert!(matches!(parse_arguments::<PngImageParser>(args(list)), Err(StegoError::Usage(_))), "{list:?}");
+        }
+    }
 
+    #[test]
+    fn test_png_chunk_styles_and_listing(){
+        let filtered = filter_rows(&[7; 12], 3, 3);
+        let bytes = png_file(&ihdr(1, 4, 8, 2), &[chunk(b"gAMA", &[0, 0, 0xB1, 0x8F]), chunk(b"prVt", b"private"), chunk(b"vpAg", &[0; 9])], &filtered);
+        let mut parser = PngImageParser::from_bytes(&bytes).unwrap();
+        parser.parse_file().unwrap();
+        // gAMA and the tEXt png_file adds are standard ancillary chunks, prVt and vpAg are not
+        let names: Vec<String> = parser.non_standard_chunks().iter().map(|c| c.name()).collect();
+        assert_eq!(names, ["prVt", "vpAg"]);
+
+        parser.set_chunk_embedding(ChunkEmbedding { style: ChunkStyle::Private(*b"maYa"), placement: ChunkPlacement::BeforeIdat });
+        parser.embed_data(&mut b"listed".to_vec(), FileEncoding::Chunk, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed).unwrap();
+        let names: Vec<String> = parser.chunks.iter().map(|c| c.name()).collect();
+        assert_eq!(names, ["IHDR", "gAMA", "prVt", "vpAg", "maYa", "IDAT", "IDAT", "tEXt", "IEND"]);
+        let names: Vec<String> = parser.non_standard_chunks().iter().map(|c| c.name()).collect();
+        assert_eq!(names, ["prVt", "vpAg", "maYa"]);
+
+        // The chunks operation lists the same from the command line
+        let input = std::env::temp_dir().join("veritasobscura-chunk-listing.png").to_str().unwrap().to_string();
+        let mut written = Vec::new();
+        parser.write_to(&mut written).unwrap();
+        std::fs::write(&input, &written).unwrap();
+        let args: Vec<String> = ["maya", "Chunk", "LeftRight", "chunks", &input].iter().map(|arg| arg.to_string()).collect();
+        let mut image_support = parse_arguments::<PngImageParser>(args).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Chunks(vec!["prVt".to_string(), "vpAg".to_string(), "maYa".to_string()]));
+        let report = parser.capacity(FileEncoding::Chunk, FileEncodingMethod::LeftToRight).unwrap();
+        assert_eq!((report.total_bits, report.bits_per_pixel), (0x7FFF_FFFF * 8, 0));
+
+        // Critical, public, reserved bit set or standard types and bad keywords are refused
+        let bad_styles = [
+            ChunkStyle::Private(*b"Maya"),
+            ChunkStyle::Private(*b"mAYa"),
+            ChunkStyle::Private(*b"maya"),
+            ChunkStyle::Private(*b"ma1A"),
+            ChunkStyle::Text(String::new()),
+            ChunkStyle::CompressedText(" Comment".to_string()),
+            ChunkStyle::InternationalText("two  spaces".to_string()),
+            ChunkStyle::Text("k".repeat(80)),
+            ChunkStyle::Text("caf\u{e9}".to_string()),
+        ];
+        for style in bad_styles {
+            parser.set_chunk_embedding(ChunkEmbedding { style: style.clone(), placement: ChunkPlacement::AfterIdat });
+            assert!(matches!(
+                parser.embed_data(&mut b"x".to_vec(), FileEncoding::Chunk, FileEncodingMethod::LeftToRight, FileEncodingFunctionDerivation::Unkeyed),
+                Err(StegoError::UnsupportedFormat(_))
+            ), "{style:?}");
+        }
 
+        // Chunk never reaches the pixels, other carriers get this from the engine
+        assert!(matches!(bits_per_pixel(&decode_png(&bytes).unwrap(), FileEncoding::Chunk), Err(StegoError::UnsupportedEncoding(_))));
+    }
+}
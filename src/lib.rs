@@ -0,0 +1,30 @@
+/*
+    Copyright (C) 2025 Dustyn Gibb
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, write to the Free Software
+Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
+
+ */
+/*
+    Each module lives in a directory of the same name (arg_handling/arg_handling.rs and so on). The
+    modules make up the library, the maya binary in main.rs is the command line on top of it.
+ */
+#![allow(clippy::module_inception)]
+pub mod filetype_support;
+pub mod file_encoding_support;
+pub mod arg_handling;
+pub mod compression;
+pub mod mathematics_support;
+#[cfg(test)]
+mod tests;
@@ -16,11 +16,11 @@ along with this program; if not, write to the Free Software
 Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
 
  */
-mod filetype_support;
-mod file_encoding_support;
-mod arg_handling;
-mod mathematics_support;
+use std::env;
+use std::process::exit;
+use veritasobscura::arg_handling::arg_handling::arg_handling;
 
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = env::args().collect();
+    exit(arg_handling::run(args));
 }
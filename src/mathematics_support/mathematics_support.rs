@@ -16,43 +16,130 @@
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
  */
 
-fn sin_wave(input: u64) -> f64 {
+pub fn sin_wave(input: u64) -> f64 {
     (input as f64).sin()
 }
 
-fn sin_scaled(input: u64, scale: f64) -> u64 {
+pub fn sin_scaled(input: u64, scale: f64) -> u64 {
     ((input as f64).sin() * scale) as u64
 }
 
-fn cos_wave(input: u64) -> f64 {
+pub fn cos_wave(input: u64) -> f64 {
     (input as f64).cos()
 }
 
-fn cos_scaled(input: u64, scale: f64) -> u64 {
+pub fn cos_scaled(input: u64, scale: f64) -> u64 {
     ((input as f64).cos() * scale) as u64
 }
 
-fn tan_wave(input: u64) -> f64 {
+pub fn tan_wave(input: u64) -> f64 {
     (input as f64).tan()
 }
 
-fn exp_wave(input: u64) -> f64 {
+pub fn exp_wave(input: u64) -> f64 {
     (input as f64).exp()
 }
 
-fn log_wave(input: u64) -> f64 {
+pub fn log_wave(input: u64) -> f64 {
     (input as f64).ln()
 }
 
-fn sqrt_wave(input: u64) -> f64 {
+pub fn sqrt_wave(input: u64) -> f64 {
     (input as f64).sqrt()
 }
 
-fn sqrt_scaled(input: u64, scale: f64) -> u64 {
+pub fn sqrt_scaled(input: u64, scale: f64) -> u64 {
     ((input as f64).sqrt() * scale) as u64
 }
 
-fn mod_wave(input: u64, modulus: u64) -> u64 {
+pub fn mod_wave(input: u64, modulus: u64) -> u64 {
     input % modulus
 }
 
+/*
+    CRC-32 (IEEE 802.3, reflected polynomial 0xEDB88320), the same one zlib and PNG use
+*/
+const CRC32_TABLE: [u32; 256] = build_crc32_table();
+
+const fn build_crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            if c & 1 != 0 {
+                c = 0xEDB88320 ^ (c >> 1);
+            } else {
+                c >>= 1;
+            }
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for byte in data {
+        c = CRC32_TABLE[((c ^ *byte as u32) & 0xFF) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+/*
+    Adler-32, the checksum at the end of a zlib stream
+*/
+pub fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    // The largest number of bytes that can be summed before b overflows a u32
+    const CHUNK: usize = 5552;
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(CHUNK) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+/*
+    Maps a distance along a Hilbert curve filling a side x side square (side a power of two) to its (x, y)
+    point, consecutive distances are always neighbouring points
+*/
+pub fn hilbert_point(side: usize, distance: usize) -> (usize, usize) {
+    let mut x = 0;
+    let mut y = 0;
+    let mut t = distance;
+    let mut s = 1;
+
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+
+    (x, y)
+}
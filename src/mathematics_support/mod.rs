@@ -16,4 +16,6 @@
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
  */
 
-pub mod mathematics_support;
\ No newline at end of file
+pub mod chacha20;
+pub mod mathematics_support;
+pub mod sha256;
@@ -14,4 +14,785 @@ GNU General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program; if not, write to the Free Software
 Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA
- */
\ No newline at end of file
+ */
+
+#[cfg(test)]
+mod cli_tests {
+    use crate::arg_handling::arg_handling::arg_handling::{parse_arguments, run, ERROR, INSUFFICIENT_CAPACITY, INTEGRITY_FAILURE, IO_ERROR, SUCCESS, UNSUPPORTED_ENCODING, UNSUPPORTED_FORMAT};
+    use crate::file_encoding_support::file_encoding_support::{CapacityReport, OperationOutput};
+    use crate::file_encoding_support::stego_error::StegoError;
+    use crate::filetype_support::bmp::BmpImageParser;
+
+    const SAMPLE: &str = "src/filetype_support/assets/sample-1024x1024.bmp";
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("maya").chain(list.iter().copied()).map(String::from).collect()
+    }
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(name).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_cli_embed_then_extract(){
+        let output = temp_path("veritasobscura-cli-embed.bmp");
+
+        let mut image_support = parse_arguments::<BmpImageParser>(args(&["Lsb", "LeftRight", "embed", "A message for the cli", SAMPLE, &output])).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Embedded);
+
+        let mut image_support = parse_arguments::<BmpImageParser>(args(&["Lsb", "LeftRight", "extract", &output])).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Extracted("A message for the cli".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn test_cli_capacity(){
+        let mut image_support = parse_arguments::<BmpImageParser>(args(&["Lsb", "LeftRight", "capacity", SAMPLE])).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Capacity(CapacityReport::new(1024 * 1024 * 3, 3)));
+
+        assert_eq!(run(args(&["Color", "RightLeft", "capacity", SAMPLE])), SUCCESS);
+    }
+
+    #[test]
+    fn test_cli_exit_codes(){
+        let output = temp_path("veritasobscura-cli-exit-codes.bmp");
+
+        assert_eq!(run(args(&["Lsb", "LeftRight", "embed", "exit codes", SAMPLE, &output])), SUCCESS);
+        assert_eq!(run(args(&["Lsb", "LeftRight", "extract", &output])), SUCCESS);
+        assert_eq!(run(args(&["Lsb", "LeftRight", "extract", "src/filetype_support/assets/does-not-exist.bmp"])), IO_ERROR);
+        assert_eq!(run(args(&["Lsb", "LeftRight", "extract", "Cargo.toml"])), UNSUPPORTED_FORMAT);
+        assert_eq!(run(args(&["Chunk", "LeftRight", "embed", "exit codes", SAMPLE, &output])), UNSUPPORTED_ENCODING);
+
+        let too_large = "x".repeat(1024 * 1024);
+        assert_eq!(run(args(&["Lsb", "LeftRight", "embed", &too_large, SAMPLE, &output])), INSUFFICIENT_CAPACITY);
+    }
+
+    #[test]
+    fn test_cli_usage_errors_are_returned(){
+        let output = temp_path("veritasobscura-cli-usage.bmp");
+
+        for bad in [
+            args(&[]),
+            args(&["Lsb", "LeftRight", "extract"]),
+            args(&["Nope", "LeftRight", "extract", SAMPLE]),
+            args(&["Hamming", "LeftRight", "embed", "message", SAMPLE, &output]),
+            args(&["PixelValueDifferencing", "LeftRight", "extract", SAMPLE]),
+            args(&["Lsb", "Nowhere", "extract", SAMPLE]),
+            args(&["Lsb", "LeftRight", "shred", SAMPLE]),
+            args(&["Lsb", "LeftRight", "embed", "message", SAMPLE, SAMPLE]),
+            args(&["Lsb", "LeftRight", "extract", SAMPLE, "--key"]),
+            args(&["Lsb", "LeftRight", "extract", SAMPLE, "--bits", "9"]),
+            args(&["Color", "LeftRight", "extract", SAMPLE, "--bits", "2"]),
+        ] {
+            assert!(matches!(parse_arguments::<BmpImageParser>(bad.clone()), Err(StegoError::Usage(_))), "{bad:?}");
+            assert_eq!(run(bad), ERROR);
+        }
+
+        assert_eq!(run(args(&["--version"])), SUCCESS);
+        assert_eq!(run(args(&["Lsb", "LeftRight", "embed", "message", SAMPLE, &output, "--bits", "0"])), ERROR);
+    }
+
+    #[test]
+    fn test_cli_key(){
+        let output = temp_path("veritasobscura-cli-key.bmp");
+
+        assert_eq!(run(args(&["Lsb", "LeftRight", "embed", "keyed message", SAMPLE, &output, "--key", "correct horse"])), SUCCESS);
+
+        let mut image_support = parse_arguments::<BmpImageParser>(args(&["--key", "correct horse", "Lsb", "LeftRight", "extract", &output])).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Extracted("keyed message".as_bytes().to_vec()));
+
+        assert_eq!(run(args(&["Lsb", "LeftRight", "extract", &output, "--key", "battery staple"])), INTEGRITY_FAILURE);
+        assert_eq!(run(args(&["Lsb", "LeftRight", "extract", &output])), INTEGRITY_FAILURE);
+    }
+
+    #[test]
+    fn test_cli_bits(){
+        let output = temp_path("veritasobscura-cli-bits.bmp");
+
+        let mut image_support = parse_arguments::<BmpImageParser>(args(&["Lsb", "LeftRight", "capacity", SAMPLE, "--bits", "3"])).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Capacity(CapacityReport::new(1024 * 1024 * 9, 9)));
+
+        assert_eq!(run(args(&["Lsb", "LeftRight", "embed", "three bits a sample", SAMPLE, &output, "--bits", "3"])), SUCCESS);
+        let mut image_support = parse_arguments::<BmpImageParser>(args(&["--bits", "3", "Lsb", "LeftRight", "extract", &output])).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Extracted("three bits a sample".as_bytes().to_vec()));
+        assert_eq!(run(args(&["Lsb", "LeftRight", "extract", &output])), INTEGRITY_FAILURE);
+
+        // 8 bits would leave nothing of an 8 bit sample
+        assert_eq!(run(args(&["Lsb", "LeftRight", "capacity", SAMPLE, "--bits", "8"])), UNSUPPORTED_ENCODING);
+    }
+
+    #[test]
+    fn test_cli_grow_gap(){
+        let output = temp_path("veritasobscura-cli-grow-gap.bmp");
+
+        // The sample has no gap and no row padding, Slack only has room once the gap is grown
+        let mut image_support = parse_arguments::<BmpImageParser>(args(&["Slack", "LeftRight", "capacity", SAMPLE])).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Capacity(CapacityReport::new(0, 0)));
+        let mut image_support = parse_arguments::<BmpImageParser>(args(&["Slack", "LeftRight", "capacity", SAMPLE, "--grow-gap", "64"])).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Capacity(CapacityReport::new(64 * 8, 0)));
+
+        assert_eq!(run(args(&["Slack", "LeftRight", "embed", "in the gap", SAMPLE, &output])), INSUFFICIENT_CAPACITY);
+        assert_eq!(run(args(&["Slack", "LeftRight", "embed", "in the gap", SAMPLE, &output, "--grow-gap", "64"])), SUCCESS);
+        assert_eq!(std::fs::metadata(&output).unwrap().len(), std::fs::metadata(SAMPLE).unwrap().len() + 64);
+
+        let mut image_support = parse_arguments::<BmpImageParser>(args(&["Slack", "LeftRight", "extract", &output])).unwrap();
+        assert_eq!(image_support.run().unwrap(), OperationOutput::Extracted("in the gap".as_bytes().to_vec()));
+
+        assert_eq!(run(args(&["Slack", "LeftRight", "capacity", SAMPLE, "--grow-gap", "-1"])), ERROR);
+    }
+}
+
+#[cfg(test)]
+mod payload_header_tests {
+    use crate::file_encoding_support::file_encoding_support::{FileEncoding, FileEncodingMethod, WaveFunction};
+    use crate::file_encoding_support::payload_header::{frame_payload, unframe_payload, PayloadHeader, PAYLOAD_HEADER_SIZE};
+    use crate::file_encoding_support::stego_error::StegoError;
+
+    fn stream_extractor(stream: &[u8]) -> impl FnMut(u64) -> Result<Vec<u8>, StegoError> + '_ {
+        move |bits| {
+            let bytes = bits.div_ceil(8) as usize;
+            if bytes > stream.len() {
+                return Err(StegoError::InsufficientCapacity { required_bits: bits, available_bits: stream.len() as u64 * 8 });
+            }
+            Ok(stream[0..bytes].to_vec())
+        }
+    }
+
+    #[test]
+    fn test_payload_header_round_trip(){
+        let payload = "header round trip".as_bytes();
+        let framed = frame_payload(payload, FileEncoding::Lsb, FileEncodingMethod::RightToLeft);
+        assert_eq!(framed.len(), PAYLOAD_HEADER_SIZE + payload.len());
+
+        let header = PayloadHeader::from_bytes(&framed).unwrap();
+        assert_eq!(header.payload_length, payload.len() as u64);
+        assert_eq!(header.encoding, FileEncoding::Lsb);
+        assert_eq!(header.encoding_method, FileEncodingMethod::RightToLeft);
+
+        let recovered = unframe_payload(stream_extractor(&framed), FileEncoding::Lsb, FileEncodingMethod::RightToLeft).unwrap();
+        assert_eq!(recovered, payload);
+
+        // The number of bits of LowBits goes in byte 7
+        let framed = frame_payload(payload, FileEncoding::LowBits(5), FileEncodingMethod::SinWave);
+        assert_eq!(framed[7], 5);
+        assert_eq!(PayloadHeader::from_bytes(&framed).unwrap().encoding, FileEncoding::LowBits(5));
+        let recovered = unframe_payload(stream_extractor(&framed), FileEncoding::LowBits(5), FileEncodingMethod::SinWave).unwrap();
+        assert_eq!(recovered, payload);
+        let mut too_many_bits = framed.clone();
+        too_many_bits[7] = 9;
+        assert!(matches!(PayloadHeader::from_bytes(&too_many_bits), Err(StegoError::IntegrityFailure(_))));
+
+        // Every wave traversal has an id of its own
+        let waves = [
+            WaveFunction::Horizontal,
+            WaveFunction::Vertical,
+            WaveFunction::DiagonalRight,
+            WaveFunction::DiagonalLeft,
+            WaveFunction::ZigZagHorizontal,
+            WaveFunction::ZigZagVertical,
+            WaveFunction::Sinusoidal,
+        ];
+        for wave_function in waves {
+            let framed = frame_payload(payload, FileEncoding::Lsb, FileEncodingMethod::Wave(wave_function));
+            assert_eq!(PayloadHeader::from_bytes(&framed).unwrap().encoding_method, FileEncodingMethod::Wave(wave_function));
+        }
+    }
+
+    #[test]
+    fn test_payload_header_detects_corruption(){
+        let mut framed = frame_payload("header corruption".as_bytes(), FileEncoding::Lsb, FileEncodingMethod::LeftToRight);
+        let last = framed.len() - 1;
+        framed[last] ^= 1;
+
+        let result = unframe_payload(stream_extractor(&framed), FileEncoding::Lsb, FileEncodingMethod::LeftToRight);
+        assert!(matches!(result, Err(StegoError::IntegrityFailure(_))));
+    }
+
+    #[test]
+    fn test_payload_header_rejects_mismatch_and_garbage(){
+        let framed = frame_payload("header mismatch".as_bytes(), FileEncoding::Lsb, FileEncodingMethod::LeftToRight);
+        let result = unframe_payload(stream_extractor(&framed), FileEncoding::ColorParity, FileEncodingMethod::LeftToRight);
+        assert!(matches!(result, Err(StegoError::IntegrityFailure(_))));
+
+        let garbage = vec![0x5Au8; 64];
+        let result = unframe_payload(stream_extractor(&garbage), FileEncoding::Lsb, FileEncodingMethod::LeftToRight);
+        assert!(matches!(result, Err(StegoError::IntegrityFailure(_))));
+
+        let mut oversized = framed.clone();
+        oversized[8..16].copy_from_slice(&u64::MAX.to_le_bytes()[..]);
+        let result = unframe_payload(stream_extractor(&oversized), FileEncoding::Lsb, FileEncodingMethod::LeftToRight);
+        assert!(matches!(result, Err(StegoError::IntegrityFailure(_))));
+    }
+}
+
+#[cfg(test)]
+mod pixel_grid_tests {
+    use crate::file_encoding_support::pixel::Pixel;
+    use crate::file_encoding_support::pixel_grid::{IndexedPixelGrid, PixelGrid, RowOrder};
+    use crate::file_encoding_support::stego_error::StegoError;
+    use crate::filetype_support::bmp::RgbPixel;
+
+    /*
+        3x2 image with 3 bytes of padding per row, every byte is its own offset so positions are easy to check
+     */
+    fn buffer() -> Vec<u8> {
+        (0..24u8).collect()
+    }
+
+    #[test]
+    fn test_pixel_grid_rejects_short_buffer(){
+        let mut data = buffer();
+        let result = PixelGrid::<RgbPixel>::new(&mut data[0..23], 3, 2, 3, RowOrder::TopDown);
+        assert!(matches!(result, Err(StegoError::MalformedHeader(_))));
+
+        let result = PixelGrid::<RgbPixel>::new(&mut data, usize::MAX, 2, 3, RowOrder::TopDown);
+        assert!(matches!(result, Err(StegoError::MalformedHeader(_))));
+    }
+
+    #[test]
+    fn test_pixel_grid_bounds_and_row_order(){
+        let mut data = buffer();
+        let grid = PixelGrid::<RgbPixel>::new(&mut data, 3, 2, 3, RowOrder::TopDown).unwrap();
+        assert_eq!(grid.get(0, 0).unwrap().blue(), 0);
+        assert_eq!(grid.get(0, 2).unwrap().blue(), 6);
+        assert_eq!(grid.get(1, 0).unwrap().blue(), 12);
+        assert!(grid.get(2, 0).is_none());
+        assert!(grid.get(0, 3).is_none());
+
+        let mut data = buffer();
+        let grid = PixelGrid::<RgbPixel>::new(&mut data, 3, 2, 3, RowOrder::BottomUp).unwrap();
+        assert_eq!(grid.get(0, 0).unwrap().blue(), 12);
+        assert_eq!(grid.get(1, 2).unwrap().blue(), 6);
+    }
+
+    #[test]
+    fn test_pixel_grid_iterators(){
+        let mut data = buffer();
+        let grid = PixelGrid::<RgbPixel>::new(&mut data, 3, 2, 3, RowOrder::TopDown).unwrap();
+
+        let row: Vec<u8> = grid.row(1).unwrap().map(|pixel| pixel.blue()).collect();
+        assert_eq!(row, vec![12, 15, 18]);
+
+        let column: Vec<u8> = grid.column(1).unwrap().map(|pixel| pixel.blue()).collect();
+        assert_eq!(column, vec![3, 15]);
+
+        assert!(grid.row(2).is_none());
+        assert!(grid.column(3).is_none());
+        assert_eq!(grid.rows().count(), 2);
+        assert_eq!(grid.columns().count(), 3);
+    }
+
+    #[test]
+    fn test_pixel_grid_get_mut_writes_back(){
+        let mut data = buffer();
+        {
+            let mut grid = PixelGrid::<RgbPixel>::new(&mut data, 3, 2, 3, RowOrder::TopDown).unwrap();
+            let mut pixel = grid.get_mut(1, 1).unwrap();
+            pixel.set_red(0xFF);
+            drop(pixel);
+            assert!(grid.get_mut(5, 5).is_none());
+            assert!(grid.set(0, 9, &RgbPixel::default()).is_err());
+        }
+
+        // red is the third byte of a BMP pixel, padding bytes are never touched
+        assert_eq!(data[17], 0xFF);
+        assert_eq!(&data[9..12], &[9, 10, 11]);
+    }
+
+    #[test]
+    fn test_indexed_pixel_grid_packing(){
+        // 4 bit indices, 3 pixels per row take 2 bytes and the second nibble of the last one is unused
+        let mut data = vec![0x12, 0x30, 0xFF, 0x45, 0x60, 0xFF];
+        let mut grid = IndexedPixelGrid::new(&mut data, 3, 2, 4, 1, RowOrder::BottomUp).unwrap();
+        assert_eq!(grid.get(0, 0), Some(4));
+        assert_eq!(grid.get(1, 2), Some(3));
+        assert!(grid.get(0, 3).is_none());
+
+        grid.set(1, 1, 0xA).unwrap();
+        assert!(matches!(grid.set(1, 1, 0x10), Err(StegoError::MalformedHeader(_))));
+        assert_eq!(data, vec![0x1A, 0x30, 0xFF, 0x45, 0x60, 0xFF]);
+
+        // 1 bit indices, most significant bit is the leftmost pixel
+        let mut data = vec![0b1000_0001, 0b1000_0000];
+        let mut grid = IndexedPixelGrid::new(&mut data, 9, 1, 1, 0, RowOrder::TopDown).unwrap();
+        assert_eq!((grid.get(0, 0), grid.get(0, 1), grid.get(0, 7), grid.get(0, 8)), (Some(1), Some(0), Some(1), Some(1)));
+        grid.set(0, 8, 0).unwrap();
+        assert_eq!(data, vec![0b1000_0001, 0]);
+
+        let mut data = vec![0u8; 5];
+        assert!(matches!(IndexedPixelGrid::new(&mut data, 3, 2, 4, 1, RowOrder::TopDown), Err(StegoError::MalformedHeader(_))));
+        assert!(matches!(IndexedPixelGrid::new(&mut data, 3, 1, 3, 0, RowOrder::TopDown), Err(StegoError::UnsupportedFormat(_))));
+    }
+}
+
+#[cfg(test)]
+mod embedding_engine_tests {
+    use crate::file_encoding_support::embedding_engine::{embed_encoded, extract_encoded, slot_sequence, unique_positions, PixelEmbedding};
+    use crate::file_encoding_support::image::{ChannelLayout, Image};
+    use crate::file_encoding_support::file_encoding_support::{FileEncoding, FileEncodingFunctionDerivation, FileEncodingMethod, WaveFunction};
+    use crate::file_encoding_support::palette::{add_palette_twins, PalettePairs};
+    use crate::file_encoding_support::pixel::{image_from_grid, write_image_to_grid, Pixel};
+    use crate::file_encoding_support::pixel_grid::{PixelGrid, RowOrder};
+    use crate::file_encoding_support::stego_error::StegoError;
+    use crate::filetype_support::bmp::{RgbPixel, RgbaPixel};
+
+    const WAVES: [WaveFunction; 7] = [
+        WaveFunction::Horizontal,
+        WaveFunction::Vertical,
+        WaveFunction::DiagonalRight,
+        WaveFunction::DiagonalLeft,
+        WaveFunction::ZigZagHorizontal,
+        WaveFunction::ZigZagVertical,
+        WaveFunction::Sinusoidal,
+    ];
+
+    const METHODS: [FileEncodingMethod; 14] = [
+        FileEncodingMethod::LeftToRight,
+        FileEncodingMethod::RightToLeft,
+        FileEncodingMethod::TopToBottom,
+        FileEncodingMethod::SinWave,
+        FileEncodingMethod::CosWave,
+        FileEncodingMethod::PolynomialFunction,
+        FileEncodingMethod::FractalFunction,
+        FileEncodingMethod::Wave(WaveFunction::Horizontal),
+        FileEncodingMethod::Wave(WaveFunction::Vertical),
+        FileEncodingMethod::Wave(WaveFunction::DiagonalRight),
+        FileEncodingMethod::Wave(WaveFunction::DiagonalLeft),
+        FileEncodingMethod::Wave(WaveFunction::ZigZagHorizontal),
+        FileEncodingMethod::Wave(WaveFunction::ZigZagVertical),
+        FileEncodingMethod::Wave(WaveFunction::Sinusoidal),
+    ];
+
+    /*
+        What the BMP parser does with its pixel rows, decode them through a PixelGrid, embed into the Image
+        and write it back
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn embed_into_rows<P: Pixel<Format = ()>>(
+        pixel_map: &mut [u8],
+        width: usize,
+        height: usize,
+        padding: usize,
+        order: &[(usize, usize)],
+        encoding: FileEncoding,
+        derivation: FileEncodingFunctionDerivation,
+        data: &[u8],
+    ) -> Result<(), StegoError> {
+        let mut grid = PixelGrid::<P>::new(pixel_map, width, height, padding, RowOrder::BottomUp)?;
+        let mut image = image_from_grid(&grid)?;
+        embed_encoded(&mut image, order, encoding, derivation, data)?;
+        write_image_to_grid(&image, &mut grid)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn extract_from_rows<P: Pixel<Format = ()>>(
+        pixel_map: &mut [u8],
+        width: usize,
+        height: usize,
+        padding: usize,
+        order: &[(usize, usize)],
+        encoding: FileEncoding,
+        derivation: FileEncodingFunctionDerivation,
+        embedded_bits: u64,
+    ) -> Result<Vec<u8>, StegoError> {
+        let grid = PixelGrid::<P>::new(pixel_map, width, height, padding, RowOrder::BottomUp)?;
+        extract_encoded(&image_from_grid(&grid)?, order, encoding, derivation, embedded_bits)
+    }
+
+    #[test]
+    fn test_every_method_visits_every_pixel_once(){
+        for (rows, cols) in [(1, 1), (7, 13), (13, 7), (16, 16), (3, 40)] {
+            for method in METHODS {
+                let order = method.traverse(rows, cols);
+                assert_eq!(order.len(), rows * cols, "{method:?} {rows}x{cols}");
+                assert_eq!(unique_positions(cols, rows, &order).unwrap().len(), rows * cols, "{method:?} {rows}x{cols}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_wave_functions_stay_inside_the_image(){
+        for wave_function in WAVES {
+            let order = wave_function.traverse(9, 5);
+            assert!(unique_positions(5, 9, &order).is_ok(), "{wave_function:?}");
+        }
+    }
+
+    #[test]
+    fn test_duplicate_points_are_skipped_and_outside_points_rejected(){
+        let order = [(0, 0), (0, 1), (0, 0), (1, 1), (0, 1)];
+        assert_eq!(unique_positions(2, 2, &order).unwrap(), vec![(0, 0), (0, 1), (1, 1)]);
+        let mut rgb = Image::new(2, 2, ChannelLayout::Rgb, vec![8; 3]).unwrap();
+        assert!(embed_encoded(&mut rgb, &order, FileEncoding::Lsb, FileEncodingFunctionDerivation::Unkeyed, &[0xFF]).is_ok());
+        let result = embed_encoded(&mut rgb, &order, FileEncoding::Lsb, FileEncodingFunctionDerivation::Unkeyed, &[0xFF, 0xFF]);
+        assert!(matches!(result, Err(StegoError::InsufficientCapacity { required_bits: 16, available_bits: 9 })));
+
+        assert!(matches!(unique_positions(2, 2, &[(0, 2)]), Err(StegoError::MalformedHeader(_))));
+    }
+
+    #[test]
+    fn test_wave_function_ordering_round_trip(){
+        // 6x4 image of 3 byte pixels, 2 bytes of padding to keep rows on 4 byte boundaries
+        let (width, height, padding) = (6usize, 4usize, 2usize);
+        let original: Vec<u8> = (0..(width * 3 + padding) * height).map(|i| (i * 37) as u8).collect();
+        let message = [0xA5u8, 0x3C];
+
+        for wave_function in WAVES {
+            // The wave itself comes first, whatever it leaves out after it
+            let order = FileEncodingMethod::Wave(wave_function).traverse(height, width);
+            assert!(order.starts_with(&unique_positions(width, height, &wave_function.traverse(height, width)).unwrap()), "{wave_function:?}");
+
+            for encoding in [FileEncoding::Lsb, FileEncoding::ColorParity] {
+                let mut pixel_map = original.clone();
+
+                embed_into_rows::<RgbPixel>(&mut pixel_map, width, height, padding, &order, encoding, FileEncodingFunctionDerivation::Unkeyed, &message).unwrap();
+                let extracted = extract_from_rows::<RgbPixel>(&mut pixel_map, width, height, padding, &order, encoding, FileEncodingFunctionDerivation::Unkeyed, 16).unwrap();
+                assert_eq!(extracted, message, "{wave_function:?} {encoding:?}");
+
+                // Padding is never written to
+                for row in 0..height {
+                    let end = (row + 1) * (width * 3 + padding);
+                    assert_eq!(pixel_map[end - padding..end], original[end - padding..end]);
+                }
+            }
+        }
+
+        // A single diagonal of a 6x4 image only holds 4 pixels worth of bits
+        let order = WaveFunction::DiagonalRight.traverse(height, width);
+        let mut pixel_map = original.clone();
+        let result = embed_into_rows::<RgbPixel>(&mut pixel_map, width, height, padding, &order, FileEncoding::ColorParity, FileEncodingFunctionDerivation::Unkeyed, &message);
+        assert!(matches!(result, Err(StegoError::InsufficientCapacity { required_bits: 16, available_bits: 4 })));
+    }
+
+    #[test]
+    fn test_keyed_slot_sequence_is_a_stable_permutation(){
+        let key = FileEncodingFunctionDerivation::from_passphrase("slots");
+
+        let mut all = slot_sequence(500, 500, key);
+        assert_eq!(slot_sequence(500, 40, key), all[0..40]);
+        assert_ne!(all[0..40], (0..40).collect::<Vec<u64>>());
+        assert_ne!(slot_sequence(500, 40, FileEncodingFunctionDerivation::from_passphrase("other")), all[0..40]);
+
+        all.sort();
+        assert_eq!(all, (0..500).collect::<Vec<u64>>());
+
+        assert_eq!(slot_sequence(500, 3, FileEncodingFunctionDerivation::Unkeyed), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_keyed_round_trip_needs_the_key(){
+        let (width, height, padding) = (16usize, 16usize, 0usize);
+        let original = vec![0x80u8; width * height * 4];
+        let message = "scattered".as_bytes();
+        let bits = message.len() as u64 * 8;
+        let key = FileEncodingFunctionDerivation::from_passphrase("round trip");
+        let order = FileEncodingMethod::LeftToRight.traverse(height, width);
+
+        for encoding in [FileEncoding::Lsb, FileEncoding::ColorParity] {
+            let mut pixel_map = original.clone();
+            embed_into_rows::<RgbaPixel>(&mut pixel_map, width, height, padding, &order, encoding, key, message).unwrap();
+
+            let extracted = extract_from_rows::<RgbaPixel>(&mut pixel_map, width, height, padding, &order, encoding, key, bits).unwrap();
+            assert_eq!(extracted, message, "{encoding:?}");
+
+            let unkeyed = extract_from_rows::<RgbaPixel>(&mut pixel_map, width, height, padding, &order, encoding, FileEncodingFunctionDerivation::Unkeyed, bits).unwrap();
+            assert_ne!(unkeyed, message, "{encoding:?}");
+
+            // Sequential embedding would only touch the start of the buffer, the keyed one lands all over it
+            let last_changed = pixel_map.iter().zip(&original).rposition(|(a, b)| a != b).unwrap();
+            assert!(last_changed > pixel_map.len() / 2, "{encoding:?}");
+        }
+    }
+
+    #[test]
+    fn test_key_is_not_shown_in_debug_output(){
+        let key = FileEncodingFunctionDerivation::from_passphrase("secret");
+        assert_eq!(format!("{key:?}"), "KeyBased(..)");
+    }
+
+    #[test]
+    fn test_palette_pairs_ignore_brightness_neighbours(){
+        // 0 and 3 are close, 1 and 2 are about as bright as each other but green and purple
+        let palette = [[200, 40, 40], [0, 170, 0], [170, 0, 170], [204, 44, 38]];
+        let pairs = PalettePairs::new(&palette, &[]);
+
+        assert_eq!((pairs.partner(0), pairs.partner(3)), (Some(3), Some(0)));
+        assert_eq!((pairs.partner(1), pairs.partner(2)), (None, None));
+        assert_eq!(pairs.partner(4), None);
+        assert_eq!(PalettePairs::new(&palette[..1], &[]).partner(0), None);
+    }
+
+    #[test]
+    fn test_palette_pairs_stay_close_and_see_alpha(){
+        // 0 and 2 are near, 1 and 3 are far apart in colour but both invisible, 4 is red at half alpha
+        let palette = [[100, 100, 100], [255, 0, 0], [104, 98, 101], [0, 0, 255], [255, 0, 0]];
+        let alpha = [255, 0, 255, 0, 128];
+        let pairs = PalettePairs::new(&palette, &alpha);
+        assert_eq!((0..5).map(|index| pairs.partner(index)).collect::<Vec<_>>(), [Some(2), Some(3), Some(0), Some(1), None]);
+
+        let mut image = Image::new(5, 1, ChannelLayout::Indexed, vec![8]).unwrap();
+        image.samples = vec![0, 1, 2, 3, 4];
+        image.palette = palette.to_vec();
+        image.palette_alpha = alpha.to_vec();
+        let order = FileEncodingMethod::LeftToRight.traverse(1, 5);
+        assert_eq!(pairs.positions(&image, &order), [(0, 0), (0, 1), (0, 2), (0, 3)]);
+
+        // The lone entry gets a copy with the same alpha, tRNS grows to reach it
+        assert_eq!(add_palette_twins(&mut image, 256), 1);
+        assert_eq!((image.palette[5], image.palette_alpha.clone()), ([255, 0, 0], vec![255, 0, 255, 0, 128, 128]));
+        let pairs = PalettePairs::new(&image.palette, &image.palette_alpha);
+        assert_eq!((pairs.partner(4), pairs.partner(5)), (Some(5), Some(4)));
+        assert_eq!(add_palette_twins(&mut image, 256), 0);
+
+        // The higher index of a pair is a 1, a pixel only ever moves to its partner
+        let mut samples = [0u16];
+        pairs.embed_bit(&mut samples, ChannelLayout::Indexed, 0, true).unwrap();
+        assert_eq!((samples, pairs.extract_bit(&samples, ChannelLayout::Indexed, 0).unwrap()), ([2], true));
+        pairs.embed_bit(&mut samples, ChannelLayout::Indexed, 0, true).unwrap();
+        assert_eq!(samples, [2]);
+        pairs.embed_bit(&mut samples, ChannelLayout::Indexed, 0, false).unwrap();
+        assert_eq!(samples, [0]);
+        let lone = PalettePairs::new(&palette[..2], &[]);
+        assert!(matches!(lone.extract_bit(&[1], ChannelLayout::Indexed, 0), Err(StegoError::MalformedHeader(_))));
+    }
+
+    #[test]
+    fn test_engine_runs_on_grey_images(){
+        let message = "grey".as_bytes();
+        let bits = message.len() as u64 * 8;
+        let order = FileEncodingMethod::TopToBottom.traverse(6, 7);
+
+        for (layout, bit_depths) in [(ChannelLayout::Gray, vec![8]), (ChannelLayout::GrayAlpha, vec![16, 16])] {
+            let mut original = Image::new(7, 6, layout, bit_depths).unwrap();
+            original.samples.iter_mut().enumerate().for_each(|(i, sample)| *sample = (i * 37 % 251) as u16);
+
+            for encoding in [FileEncoding::Lsb, FileEncoding::ColorParity] {
+                let mut image = original.clone();
+                embed_encoded(&mut image, &order, encoding, FileEncodingFunctionDerivation::Unkeyed, message).unwrap();
+                assert_eq!(extract_encoded(&image, &order, encoding, FileEncodingFunctionDerivation::Unkeyed, bits).unwrap(), message, "{layout:?} {encoding:?}");
+
+                // Only ever the lowest bit of a sample
+                assert!(image.samples.iter().zip(&original.samples).all(|(a, b)| a ^ b <= 1), "{layout:?} {encoding:?}");
+            }
+        }
+
+        // LowBits on 16 bit samples, bit n % bits of channel n / bits
+        let mut original = Image::new(7, 6, ChannelLayout::Rgb, vec![16; 3]).unwrap();
+        original.samples.iter_mut().enumerate().for_each(|(i, sample)| *sample = (i * 40_503) as u16);
+        for low_bits in [2u8, 5, 8] {
+            let mut image = original.clone();
+            embed_encoded(&mut image, &order, FileEncoding::LowBits(low_bits), FileEncodingFunctionDerivation::Unkeyed, message).unwrap();
+            assert_eq!(extract_encoded(&image, &order, FileEncoding::LowBits(low_bits), FileEncodingFunctionDerivation::Unkeyed, bits).unwrap(), message);
+            assert!(image.samples.iter().zip(&original.samples).all(|(a, b)| a ^ b < 1 << low_bits), "{low_bits}");
+            assert_eq!(image.samples[0] & ((1 << low_bits) - 1), message[0] as u16 & ((1 << low_bits) - 1), "{low_bits}");
+        }
+        let grey = Image::new(7, 6, ChannelLayout::Gray, vec![4]).unwrap();
+        assert!(matches!(extract_encoded(&grey, &order, FileEncoding::LowBits(4), FileEncodingFunctionDerivation::Unkeyed, 8), Err(StegoError::UnsupportedEncoding(_))));
+
+        let mut small = Image::new(2, 2, ChannelLayout::Gray, vec![8]).unwrap();
+        let order = FileEncodingMethod::LeftToRight.traverse(2, 2);
+        assert!(matches!(
+            embed_encoded(&mut small, &order, FileEncoding::Lsb, FileEncodingFunctionDerivation::Unkeyed, message),
+            Err(StegoError::InsufficientCapacity { .. })
+        ));
+    }
+}
+
+#[cfg(test)]
+mod image_tests {
+    use crate::file_encoding_support::image::{ChannelLayout, Image, MetadataBlock};
+    use crate::file_encoding_support::stego_error::StegoError;
+
+    #[test]
+    fn test_image_layout_and_samples(){
+        let mut image = Image::new(3, 2, ChannelLayout::Rgba, vec![5, 6, 5, 1]).unwrap();
+        assert_eq!(image.channels(), 4);
+        assert_eq!(image.samples.len(), 3 * 2 * 4);
+
+        image.pixel_mut(1, 2).unwrap().copy_from_slice(&[31, 63, 31, 1]);
+        assert_eq!(image.pixel(1, 2).unwrap(), &[31, 63, 31, 1]);
+        // Top row first, so the last pixel of the second row ends the buffer
+        assert_eq!(&image.samples[20..], &[31, 63, 31, 1]);
+
+        assert!(image.pixel(2, 0).is_none());
+        assert!(image.pixel(0, 3).is_none());
+        assert!(matches!(image.out_of_bounds(2, 0), StegoError::MalformedHeader(_)));
+
+        assert_eq!(ChannelLayout::from_channels(2, false), Some(ChannelLayout::GrayAlpha));
+        assert_eq!(ChannelLayout::from_channels(3, true), Some(ChannelLayout::Rgb));
+        assert_eq!(ChannelLayout::from_channels(3, false), None);
+    }
+
+    #[test]
+    fn test_image_rejects_bad_bit_depths(){
+        assert!(matches!(Image::new(1, 1, ChannelLayout::Rgb, vec![8, 8]), Err(StegoError::UnsupportedFormat(_))));
+        assert!(matches!(Image::new(1, 1, ChannelLayout::Gray, vec![0]), Err(StegoError::UnsupportedFormat(_))));
+        assert!(matches!(Image::new(1, 1, ChannelLayout::Gray, vec![17]), Err(StegoError::UnsupportedFormat(_))));
+        assert!(matches!(Image::new(usize::MAX, 2, ChannelLayout::Gray, vec![8]), Err(StegoError::MalformedHeader(_))));
+    }
+
+    #[test]
+    fn test_image_metadata_lookup(){
+        let mut image = Image::new(1, 1, ChannelLayout::Indexed, vec![4]).unwrap();
+        assert_eq!(image.metadata("BMP"), None);
+
+        image.metadata.push(MetadataBlock { name: "BMP".to_string(), data: vec![1, 2, 3] });
+        assert_eq!(image.metadata("BMP"), Some(&[1u8, 2, 3][..]));
+    }
+}
+
+#[cfg(test)]
+mod mathematics_support_tests {
+    use crate::mathematics_support::chacha20::{chacha20_block, ChaCha20Rng};
+    use crate::mathematics_support::sha256::sha256;
+    use crate::mathematics_support::mathematics_support::{adler32, crc32};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[test]
+    fn test_sha256_known_digests(){
+        assert_eq!(hex(&sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(hex(&sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(
+            hex(&sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_zlib_and_png_checksums(){
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        // Long enough that the sums have to be reduced along the way
+        assert_eq!(adler32(&[0xFF; 100_000]), 0x149A_302C);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn test_chacha20_known_keystream(){
+        // RFC 8439 appendix A.1, test vector 1
+        let block = chacha20_block(&[0u32; 8], 0, &[0u32; 3]);
+        assert_eq!(
+            hex(&block),
+            "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586"
+        );
+
+        let mut rng = ChaCha20Rng::new(&[0u8; 32], &[0u8; 12], 0);
+        let mut stream = [0u8; 70];
+        rng.fill_bytes(&mut stream);
+        assert_eq!(stream[0..64], block);
+        assert_eq!(stream[64..70], chacha20_block(&[0u32; 8], 1, &[0u32; 3])[0..6]);
+
+        for bound in [1, 2, 7, 1000] {
+            assert!(rng.next_below(bound) < bound);
+        }
+    }
+}
+
+#[cfg(test)]
+mod compression_tests {
+    use crate::compression::compression::{deflate, inflate, zlib_compress, zlib_compress_with, zlib_decompress, DeflateBlocks};
+    use crate::file_encoding_support::stego_error::StegoError;
+
+    // zlib.compress(..., 9) output, the first uses the fixed Huffman code and the second a dynamic one
+    const FIXED_STREAM: [u8; 28] = [
+        0x78, 0xda, 0x2b, 0x4b, 0x2d, 0xca, 0x2c, 0x49, 0x2c, 0x56, 0xc8, 0x4f, 0x2a, 0x4e, 0x2e, 0x2d, 0x4a, 0xd4, 0x51, 0x28,
+        0x23, 0x20, 0x00, 0x00, 0xd5, 0xec, 0x12, 0xc0,
+    ];
+    const DYNAMIC_STREAM: [u8; 64] = [
+        0x78, 0xda, 0xed, 0xcc, 0xb1, 0x11, 0x00, 0x31, 0x08, 0x03, 0xc1, 0x5a, 0x85, 0x00, 0x09, 0xfa, 0x2f, 0xc0, 0xef, 0xec,
+        0x8b, 0xf0, 0xc5, 0x37, 0x0b, 0x20, 0x98, 0xed, 0x8d, 0x32, 0x6a, 0xa8, 0x50, 0x38, 0xb7, 0xb9, 0x2a, 0x62, 0xac, 0xbe,
+        0xc9, 0x03, 0x96, 0x96, 0xbd, 0xe9, 0xef, 0x10, 0xa7, 0xe0, 0x8a, 0x75, 0x27, 0x03, 0xb7, 0xa7, 0x3c, 0xe5, 0xa7, 0x1c,
+        0x81, 0x57, 0xed, 0x3e,
+    ];
+
+    fn dynamic_text() -> Vec<u8> {
+        (0..600).map(|i| b"abcdefghij"[(i * i / 7) % 10]).collect()
+    }
+
+    #[test]
+    fn test_inflate_fixed_and_dynamic_blocks(){
+        let text = b"veritas obscura, veritas obscura, veritas obscura";
+        assert_eq!(zlib_decompress(&FIXED_STREAM, 1000).unwrap(), text);
+        assert_eq!(zlib_decompress(&DYNAMIC_STREAM, 1000).unwrap(), dynamic_text());
+
+        // The raw stream stops exactly where the Adler-32 checksum starts
+        let (inflated, used) = inflate(&DYNAMIC_STREAM[2..], 1000).unwrap();
+        assert_eq!(inflated.len(), 600);
+        assert_eq!(used, DYNAMIC_STREAM.len() - 6);
+    }
+
+    #[test]
+    fn test_inflate_stored_blocks(){
+        // Two stored blocks, the first not final, followed by an empty final fixed block
+        let mut stream = vec![0x00, 3, 0, !3, 0xFF, b'a', b'b', b'c'];
+        stream.extend_from_slice(&[0x00, 2, 0, !2, 0xFF, b'd', b'e']);
+        stream.extend_from_slice(&[0x03, 0x00]);
+        let (inflated, used) = inflate(&stream, 100).unwrap();
+        assert_eq!(inflated, b"abcde");
+        assert_eq!(used, stream.len());
+
+        stream[3] = 0;
+        assert!(matches!(inflate(&stream, 100), Err(StegoError::MalformedHeader(_))));
+    }
+
+    #[test]
+    fn test_zlib_rejects_bad_streams(){
+        let mut stream = DYNAMIC_STREAM;
+        stream[63] ^= 1;
+        assert!(matches!(zlib_decompress(&stream, 1000), Err(StegoError::IntegrityFailure(_))));
+
+        // Header check bits, a preset dictionary, a truncated stream and output over the limit
+        let mut stream = DYNAMIC_STREAM;
+        stream[1] ^= 1;
+        assert!(matches!(zlib_decompress(&stream, 1000), Err(StegoError::MalformedHeader(_))));
+        assert!(matches!(zlib_decompress(&[0x78, 0xbb, 0, 0, 0, 0, 0, 0], 1000), Err(StegoError::UnsupportedFormat(_))));
+        assert!(matches!(zlib_decompress(&DYNAMIC_STREAM[..30], 1000), Err(StegoError::MalformedHeader(_))));
+        assert!(matches!(zlib_decompress(&DYNAMIC_STREAM, 599), Err(StegoError::MalformedHeader(_))));
+
+        // Block type 3 is reserved
+        assert!(matches!(inflate(&[0x07], 100), Err(StegoError::MalformedHeader(_))));
+    }
+
+    #[test]
+    fn test_deflate_round_trips_at_every_level(){
+        let mut noise: Vec<u8> = (0..70_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+        noise.extend(std::iter::repeat_n(b'x', 300));
+        let inputs: [Vec<u8>; 5] = [Vec::new(), vec![42], b"abcabcabcabcabcabcabc".to_vec(), dynamic_text().repeat(20), noise];
+
+        for input in &inputs {
+            for level in 0..=9 {
+                let compressed = zlib_compress(input, level);
+                assert_eq!(zlib_decompress(&compressed, input.len()).unwrap(), *input, "level {level}, {} bytes", input.len());
+                let (inflated, used) = inflate(&deflate(input, level, DeflateBlocks::Smallest), input.len()).unwrap();
+                assert_eq!((inflated.len(), used), (input.len(), compressed.len() - 6));
+            }
+        }
+
+        // Repetitive data shrinks, and harder levels never do worse than the fastest one on it
+        let text = dynamic_text().repeat(20);
+        let fastest = zlib_compress(&text, 1).len();
+        assert!(fastest < text.len() / 4);
+        assert!(zlib_compress(&text, 9).len() <= fastest);
+        assert!(zlib_compress(&text, 0).len() > text.len());
+
+        // The header records the level the way zlib does
+        let headers: Vec<[u8; 2]> = [0, 1, 3, 6, 9].iter().map(|&level| zlib_compress(b"", level)[0..2].try_into().unwrap()).collect();
+        assert_eq!(headers, [[0x78, 0x01], [0x78, 0x01], [0x78, 0x5e], [0x78, 0x9c], [0x78, 0xda]]);
+    }
+
+    #[test]
+    fn test_deflate_fixed_blocks(){
+        let text = dynamic_text().repeat(20);
+        for level in 1..=9 {
+            let fixed = zlib_compress_with(&text, level, DeflateBlocks::Fixed);
+            assert_eq!(zlib_decompress(&fixed, text.len()).unwrap(), text, "level {level}");
+            // The first block header after the zlib header says fixed Huffman
+            assert_eq!((fixed[2] >> 1) & 0b11, 1);
+            assert!(fixed.len() > zlib_compress(&text, level).len());
+            assert!(fixed.len() < zlib_compress(&text, 0).len());
+        }
+        assert_eq!(zlib_decompress(&zlib_compress_with(b"", 6, DeflateBlocks::Fixed), 0).unwrap(), b"");
+    }
+}